@@ -1,5 +1,5 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::{parse_macro_input, Data, DeriveInput, Fields};
 
 /// Derive macro for automatic UbfStruct implementation
@@ -20,12 +20,53 @@ use syn::{parse_macro_input, Data, DeriveInput, Fields};
 ///     
 ///     #[ubf(field = 1004, default = "pending")]
 ///     status: String,
+///
+///     // Numeric value kept in a STRING field
+///     #[ubf(field = 1005, as = "string")]
+///     total: i64,
+///
+///     // Repeating group, one LineItem per occurrence; `field` is the
+///     // field whose occurrence count gives the number of items
+///     #[ubf(field = 1006)]
+///     items: Vec<LineItem>,
+///
+///     // Nested UbfStruct, read and written through its own field ids.
+///     // Records of the same shape share field ids; `occ_base` moves this
+///     // one to occurrences 10 and up so they don't collide
+///     billing: Address,
+///     #[ubf(occ_base = 10)]
+///     shipping: Address,
 /// }
 /// ```
+///
+/// Adding `#[ubf(debug)]` to the struct prints the generated `from_ubf_occ`
+/// and `update_ubf_occ` to stderr while it compiles, to check the mappings.
+///
+/// Writing a record at occurrence `n` fills missing lower occurrences of its
+/// fields with empty values, so an optional field left out of an earlier
+/// record reads back as `Some(0)`/`Some("")` once a later record sets it.
 #[proc_macro_derive(UbfStruct, attributes(ubf))]
 pub fn derive_ubf_struct(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
+    TokenStream::from(derive_ubf_struct_to(&input, &mut std::io::stderr()))
+}
+
+/// `derive_ubf_struct`, writing the `#[ubf(debug)]` listing to `out`
+fn derive_ubf_struct_to(
+    input: &DeriveInput,
+    out: &mut impl std::io::Write,
+) -> proc_macro2::TokenStream {
+    let expanded = expand_ubf_struct(input);
+    if let Some(dump) = debug_dump(input, &expanded) {
+        // Only a diagnostic, the expansion is returned either way
+        let _ = writeln!(out, "{}", dump);
+    }
+    expanded
+}
+
+/// Generated UbfStruct impl of a struct
+fn expand_ubf_struct(input: &DeriveInput) -> proc_macro2::TokenStream {
     let name = &input.ident;
 
     // Parse struct fields
@@ -39,55 +80,64 @@ pub fn derive_ubf_struct(input: TokenStream) -> TokenStream {
 
     // Generate from_ubf implementation
     let mut from_ubf_fields = Vec::new();
-    let mut to_ubf_fields = Vec::new();
+    let mut to_ubf_occ_fields = Vec::new();
+    let mut delete_occ_fields = Vec::new();
 
     for field in fields {
         let field_name = field.ident.as_ref().unwrap();
         let field_type = &field.ty;
 
-        // Parse #[ubf(field = ...)] attribute
-        let mut field_expr: Option<proc_macro2::TokenStream> = None;
-        let mut default_value: Option<String> = None;
-
-        for attr in &field.attrs {
-            if attr.path().is_ident("ubf") {
-                // Parse the meta list manually from tokens
-                let tokens_str = attr
-                    .meta
-                    .require_list()
-                    .expect("Expected meta list")
-                    .tokens
-                    .to_string();
-
-                // Split by comma and process each part
-                for part in tokens_str.split(',') {
-                    let part = part.trim();
-
-                    if part.starts_with("field") {
-                        // Parse "field = <expr>" where expr can be a constant or literal
-                        if let Some(eq_pos) = part.find('=') {
-                            let value_str = part[eq_pos + 1..].trim();
-                            // Store as token stream to support both literals and constants
-                            field_expr =
-                                Some(value_str.parse().expect("Failed to parse field expression"));
-                        }
-                    } else if part.starts_with("default") {
-                        // Parse "default = "value""
-                        if let Some(eq_pos) = part.find('=') {
-                            let value_str = part[eq_pos + 1..].trim();
-                            default_value = Some(value_str.trim_matches('"').to_string());
-                        }
-                    }
-                }
-            }
-        }
+        let FieldAttrs {
+            field: field_expr,
+            default: default_value,
+            as_string,
+            occ_base,
+            ..
+        } = parse_field_attrs(field);
 
-        let fid = field_expr.unwrap_or_else(|| {
-            panic!(
+        // A nested UbfStruct is read and written through its own field ids
+        let type_str = quote!(#field_type).to_string();
+        let nested =
+            !as_string && vec_inner_type(&type_str).is_none() && !is_scalar_type(&type_str);
+
+        let fid = match (field_expr, nested) {
+            (Some(_), true) => panic!(
+                "Field {}: #[ubf(field = ...)] is not used on a nested UbfStruct field, remove it",
+                field_name
+            ),
+            // Not referenced by the nested struct's code
+            (None, true) => proc_macro2::TokenStream::new(),
+            (Some(fid), false) => fid,
+            (None, false) => panic!(
                 "Field {} must have #[ubf(field = ...)] attribute",
                 field_name
-            )
-        });
+            ),
+        };
+
+        // Occurrence the nested struct's fields are read and written at
+        let occ = match occ_base {
+            Some(base) => {
+                if !nested {
+                    panic!(
+                        "Field {}: occ_base is only supported on nested UbfStruct fields",
+                        field_name
+                    );
+                }
+                quote!(occ + (#base))
+            }
+            None => quote!(occ),
+        };
+
+        if as_string {
+            from_ubf_fields.push(generate_string_encoded_getter(
+                field_name,
+                field_type,
+                fid.clone(),
+            ));
+            delete_occ_fields.push(delete_call(field_name, &fid));
+            to_ubf_occ_fields.push(generate_string_encoded_setter(field_name, field_type, fid));
+            continue;
+        }
 
         // Generate field reading code based on type
         let field_getter = generate_field_getter(
@@ -95,20 +145,27 @@ pub fn derive_ubf_struct(input: TokenStream) -> TokenStream {
             field_type,
             fid.clone(),
             default_value.as_deref(),
+            &occ,
         );
         from_ubf_fields.push(field_getter);
 
-        // Generate field writing code
-        let field_setter = generate_field_setter(field_name, field_type, fid);
-        to_ubf_fields.push(field_setter);
+        // Generate field writing code; fields are changed in place (Bchg) so
+        // writing a struct twice leaves a single occurrence
+        delete_occ_fields.push(generate_field_delete(field_name, field_type, &fid, &occ));
+        to_ubf_occ_fields.push(generate_field_setter(field_name, field_type, fid, &occ));
     }
 
     let field_names: Vec<_> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
 
     // Generate the implementation
-    let expanded = quote! {
+    quote! {
         impl ::endurox_sys::ubf_struct::UbfStruct for #name {
             fn from_ubf(buf: &::endurox_sys::ubf::UbfBuffer) -> Result<Self, ::endurox_sys::ubf_struct::UbfError> {
+                Self::from_ubf_occ(buf, 0)
+            }
+
+            #[allow(unused_variables)]
+            fn from_ubf_occ(buf: &::endurox_sys::ubf::UbfBuffer, occ: i32) -> Result<Self, ::endurox_sys::ubf_struct::UbfError> {
                 #(#from_ubf_fields)*
 
                 Ok(Self {
@@ -117,20 +174,282 @@ pub fn derive_ubf_struct(input: TokenStream) -> TokenStream {
             }
 
             fn to_ubf(&self) -> Result<::endurox_sys::ubf::UbfBuffer, ::endurox_sys::ubf_struct::UbfError> {
-                let mut buf = ::endurox_sys::ubf::UbfBuffer::new(2048)
+                let mut buf = ::endurox_sys::ubf::UbfBuffer::new(::endurox_sys::ubf::DEFAULT_BUFFER_SIZE)
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::AllocationError(e))?;
                 self.update_ubf(&mut buf)?;
                 Ok(buf)
             }
 
             fn update_ubf(&self, buf: &mut ::endurox_sys::ubf::UbfBuffer) -> Result<(), ::endurox_sys::ubf_struct::UbfError> {
-                #(#to_ubf_fields)*
+                self.update_ubf_occ(buf, 0)
+            }
+
+            #[allow(unused_variables)]
+            fn update_ubf_occ(&self, buf: &mut ::endurox_sys::ubf::UbfBuffer, occ: i32) -> Result<(), ::endurox_sys::ubf_struct::UbfError> {
+                #(#to_ubf_occ_fields)*
+                Ok(())
+            }
+
+            #[allow(unused_variables)]
+            fn delete_ubf_occ(buf: &mut ::endurox_sys::ubf::UbfBuffer, occ: i32) -> Result<(), ::endurox_sys::ubf_struct::UbfError> {
+                #(#delete_occ_fields)*
                 Ok(())
             }
         }
-    };
+    }
+}
 
-    TokenStream::from(expanded)
+/// Readable listing of `expanded` when the struct has `#[ubf(debug)]`
+fn debug_dump(input: &DeriveInput, expanded: &proc_macro2::TokenStream) -> Option<String> {
+    let mut debug = false;
+    for attr in &input.attrs {
+        if attr.path().is_ident("ubf") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("debug") {
+                    debug = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown ubf struct attribute"))
+                }
+            })
+            .unwrap_or_else(|e| panic!("Struct {}: {}", input.ident, e));
+        }
+    }
+    if !debug {
+        return None;
+    }
+
+    let mut dump = format!("UbfStruct expansion for {}:\n", input.ident);
+    let mut line = String::new();
+    dump_tokens(expanded.clone(), 0, &mut line, &mut dump);
+    flush_line(&mut line, 0, &mut dump);
+    Some(dump)
+}
+
+/// Writes one statement per line, indented by brace depth
+fn dump_tokens(
+    tokens: proc_macro2::TokenStream,
+    depth: usize,
+    line: &mut String,
+    out: &mut String,
+) {
+    use proc_macro2::{Delimiter, Spacing, TokenTree};
+
+    let mut joint = false;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => {
+                line.push_str(" {");
+                flush_line(line, depth, out);
+                dump_tokens(group.stream(), depth + 1, line, out);
+                flush_line(line, depth + 1, out);
+                line.push('}');
+                let ends_item = match tokens.peek() {
+                    Some(TokenTree::Punct(p)) => !matches!(p.as_char(), ';' | ','),
+                    _ => true,
+                };
+                if ends_item {
+                    flush_line(line, depth, out);
+                }
+                joint = false;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == ';' => {
+                line.push(';');
+                flush_line(line, depth, out);
+                joint = false;
+            }
+            token => {
+                if !joint && !line.is_empty() && !line.ends_with(['(', '[']) {
+                    line.push(' ');
+                }
+                joint = matches!(&token, TokenTree::Punct(p) if p.spacing() == Spacing::Joint);
+                line.push_str(&token.to_string());
+            }
+        }
+    }
+}
+
+fn flush_line(line: &mut String, depth: usize, out: &mut String) {
+    if !line.trim().is_empty() {
+        out.push_str(&"    ".repeat(depth));
+        out.push_str(line.trim());
+        out.push('\n');
+    }
+    line.clear();
+}
+
+/// Reads a numeric field stored as text (`as = "string"`)
+///
+/// The value is parsed with `FromStr`, so it survives the round trip through
+/// a STRING field unchanged instead of being stored as raw bytes.
+fn generate_string_encoded_getter(
+    field_name: &syn::Ident,
+    field_type: &syn::Type,
+    field_id: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let type_str = quote!(#field_type).to_string();
+
+    if let Some(inner_type) = option_inner_type(&type_str) {
+        quote! {
+            let #field_name = match buf.get_string(#field_id, occ) {
+                Ok(text) => Some(text.trim().parse::<#inner_type>()
+                    .map_err(|e| ::endurox_sys::ubf_struct::UbfError::InvalidValue(
+                        ::endurox_sys::ubf_struct::field_value_error(stringify!(#field_name), #field_id, "string", e)
+                    ))?),
+                Err(_) => None,
+            };
+        }
+    } else {
+        quote! {
+            let #field_name = buf.get_string(#field_id, occ)
+                .map_err(|e| ::endurox_sys::ubf_struct::UbfError::FieldNotFound(
+                    ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "string", e)
+                ))?
+                .trim()
+                .parse::<#field_type>()
+                .map_err(|e| ::endurox_sys::ubf_struct::UbfError::InvalidValue(
+                    ::endurox_sys::ubf_struct::field_value_error(stringify!(#field_name), #field_id, "string", e)
+                ))?;
+        }
+    }
+}
+
+/// Writes a numeric field as text (`as = "string"`)
+fn generate_string_encoded_setter(
+    field_name: &syn::Ident,
+    field_type: &syn::Type,
+    field_id: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let type_str = quote!(#field_type).to_string();
+
+    if option_inner_type(&type_str).is_some() {
+        let write = write_call("string", &field_id, quote!(&value.to_string()));
+        let delete = delete_call(field_name, &field_id);
+        quote! {
+            if let Some(ref value) = self.#field_name {
+                #write
+                    .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                        format!("Field {}: {}", stringify!(#field_name), e)
+                    ))?;
+            } else {
+                #delete
+            }
+        }
+    } else {
+        let write = write_call("string", &field_id, quote!(&self.#field_name.to_string()));
+        quote! {
+            #write
+                .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                    format!("Field {}: {}", stringify!(#field_name), e)
+                ))?;
+        }
+    }
+}
+
+/// `buf.change_<kind>(id, occ, value)`, replacing the occurrence if present
+fn write_call(
+    kind: &str,
+    field_id: &proc_macro2::TokenStream,
+    value: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let method = format_ident!("change_{}", kind);
+    quote!(buf.#method(#field_id, occ, #value))
+}
+
+/// Deletes occurrence `occ` of the field if present, e.g. for a `None`
+fn delete_call(
+    field_name: &syn::Ident,
+    field_id: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        if buf.is_present(#field_id, occ) {
+            buf.delete(#field_id, occ)
+                .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                    format!("Field {}: {}", stringify!(#field_name), e)
+                ))?;
+        }
+    }
+}
+
+/// Whether the field is written directly rather than as a nested UbfStruct
+fn is_scalar_type(type_str: &str) -> bool {
+    [
+        "String", "i64", "i32", "long", "f32", "f64", "double", "bool",
+    ]
+    .iter()
+    .any(|t| type_str.contains(t))
+}
+
+/// Body of `delete_ubf_occ` for one field
+fn generate_field_delete(
+    field_name: &syn::Ident,
+    field_type: &syn::Type,
+    field_id: &proc_macro2::TokenStream,
+    occ: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let type_str = quote!(#field_type).to_string();
+
+    if let Some(inner_type) = vec_inner_type(&type_str) {
+        return delete_records(field_name, &inner_type, field_id);
+    }
+    if is_scalar_type(&type_str) {
+        return delete_call(field_name, field_id);
+    }
+
+    let nested_type = option_inner_type(&type_str).unwrap_or_else(|| quote!(#field_type));
+    quote! {
+        <#nested_type as ::endurox_sys::ubf_struct::UbfStruct>::delete_ubf_occ(buf, #occ)?;
+    }
+}
+
+/// Deletes every record of a repeating group, last occurrence first
+fn delete_records(
+    field_name: &syn::Ident,
+    inner_type: &proc_macro2::TokenStream,
+    field_id: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    quote! {
+        let count = buf.occurrences(#field_id)
+            .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                format!("Field {}: {}", stringify!(#field_name), e)
+            ))?;
+        for i in (0..count).rev() {
+            <#inner_type as ::endurox_sys::ubf_struct::UbfStruct>::delete_ubf_occ(buf, i)?;
+        }
+    }
+}
+
+/// Inner type of `Vec<T>` from its token string
+fn vec_inner_type(type_str: &str) -> Option<proc_macro2::TokenStream> {
+    if !type_str.starts_with("Vec <") {
+        return None;
+    }
+
+    let inner_type_str = type_str
+        .trim_start_matches("Vec <")
+        .trim_end_matches('>')
+        .trim();
+    if infer_fd_type(inner_type_str).is_some() {
+        panic!(
+            "Vec<{}> is not supported, only Vec of UbfStruct records",
+            inner_type_str
+        );
+    }
+    Some(inner_type_str.parse().expect("Failed to parse inner type"))
+}
+
+/// Inner type of `Option<T>` from its token string
+fn option_inner_type(type_str: &str) -> Option<proc_macro2::TokenStream> {
+    if !type_str.starts_with("Option <") {
+        return None;
+    }
+
+    let inner_type_str = type_str
+        .trim_start_matches("Option <")
+        .trim_end_matches('>')
+        .trim();
+    Some(inner_type_str.parse().expect("Failed to parse inner type"))
 }
 
 fn generate_field_getter(
@@ -138,9 +457,25 @@ fn generate_field_getter(
     field_type: &syn::Type,
     field_id: proc_macro2::TokenStream,
     default_value: Option<&str>,
+    occ: &proc_macro2::TokenStream,
 ) -> proc_macro2::TokenStream {
     let type_str = quote!(#field_type).to_string();
 
+    // Repeating group: one record per occurrence of the key field
+    if let Some(inner_type) = vec_inner_type(&type_str) {
+        return quote! {
+            let #field_name = {
+                let count = buf.occurrences(#field_id)
+                    .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                        ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "repeating group", e)
+                    ))?;
+                (0..count)
+                    .map(|i| <#inner_type as ::endurox_sys::ubf_struct::UbfStruct>::from_ubf_occ(buf, i))
+                    .collect::<Result<Vec<_>, _>>()?
+            };
+        };
+    }
+
     // Check if it's an Option type
     let is_option = type_str.starts_with("Option <");
 
@@ -149,26 +484,28 @@ fn generate_field_getter(
         if type_str.contains("String") {
             // Option<String>
             quote! {
-                let #field_name = buf.get_string(#field_id, 0).ok();
+                let #field_name = buf.get_string(#field_id, occ).ok();
             }
         } else if type_str.contains("i64") || type_str.contains("i32") || type_str.contains("long")
         {
             // Option<i64/i32>
             quote! {
-                let #field_name = buf.get_long(#field_id, 0).ok().map(|v| v as _);
+                let #field_name = buf.get_long(#field_id, occ).ok().map(|v| v as _);
             }
-        } else if type_str.contains("f64")
-            || type_str.contains("f32")
-            || type_str.contains("double")
-        {
-            // Option<f64/f32>
+        } else if type_str.contains("f32") {
+            // Option<f32>
             quote! {
-                let #field_name = buf.get_double(#field_id, 0).ok().map(|v| v as _);
+                let #field_name = buf.get_float(#field_id, occ).ok();
+            }
+        } else if type_str.contains("f64") || type_str.contains("double") {
+            // Option<f64>
+            quote! {
+                let #field_name = buf.get_double(#field_id, occ).ok().map(|v| v as _);
             }
         } else if type_str.contains("bool") {
             // Option<bool>
             quote! {
-                let #field_name = if buf.is_present(#field_id, 0) { Some(true) } else { None };
+                let #field_name = if buf.is_present(#field_id, occ) { Some(true) } else { None };
             }
         } else {
             // Option<NestedStruct> - try to parse, return None if fails
@@ -181,7 +518,7 @@ fn generate_field_getter(
                 inner_type_str.parse().expect("Failed to parse inner type");
 
             quote! {
-                let #field_name = <#inner_type as ::endurox_sys::ubf_struct::UbfStruct>::from_ubf(buf).ok();
+                let #field_name = <#inner_type as ::endurox_sys::ubf_struct::UbfStruct>::from_ubf_occ(buf, #occ).ok();
             }
         }
     } else {
@@ -189,43 +526,47 @@ fn generate_field_getter(
         if type_str.contains("String") {
             if let Some(default) = default_value {
                 quote! {
-                    let #field_name = buf.get_string(#field_id, 0)
+                    let #field_name = buf.get_string(#field_id, occ)
                         .unwrap_or_else(|_| #default.to_string());
                 }
             } else {
                 quote! {
-                    let #field_name = buf.get_string(#field_id, 0)
+                    let #field_name = buf.get_string(#field_id, occ)
                         .map_err(|e| ::endurox_sys::ubf_struct::UbfError::FieldNotFound(
-                            format!("Field {} ({}): {}", stringify!(#field_name), #field_id, e)
+                            ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "string", e)
                         ))?;
                 }
             }
         } else if type_str.contains("i64") || type_str.contains("i32") || type_str.contains("long")
         {
             quote! {
-                let #field_name = buf.get_long(#field_id, 0)
+                let #field_name = buf.get_long(#field_id, occ)
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::FieldNotFound(
-                        format!("Field {} ({}): {}", stringify!(#field_name), #field_id, e)
+                        ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "long", e)
                     ))? as #field_type;
             }
-        } else if type_str.contains("f64")
-            || type_str.contains("f32")
-            || type_str.contains("double")
-        {
+        } else if type_str.contains("f32") {
             quote! {
-                let #field_name = buf.get_double(#field_id, 0)
+                let #field_name = buf.get_float(#field_id, occ)
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::FieldNotFound(
-                        format!("Field {} ({}): {}", stringify!(#field_name), #field_id, e)
+                        ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "float", e)
+                    ))?;
+            }
+        } else if type_str.contains("f64") || type_str.contains("double") {
+            quote! {
+                let #field_name = buf.get_double(#field_id, occ)
+                    .map_err(|e| ::endurox_sys::ubf_struct::UbfError::FieldNotFound(
+                        ::endurox_sys::ubf_struct::field_read_error(stringify!(#field_name), #field_id, "double", e)
                     ))? as #field_type;
             }
         } else if type_str.contains("bool") {
             quote! {
-                let #field_name = buf.is_present(#field_id, 0);
+                let #field_name = buf.is_present(#field_id, occ);
             }
         } else {
             // Assume it's a nested struct that implements UbfStruct
             quote! {
-                let #field_name = <#field_type as ::endurox_sys::ubf_struct::UbfStruct>::from_ubf(buf)?;
+                let #field_name = <#field_type as ::endurox_sys::ubf_struct::UbfStruct>::from_ubf_occ(buf, #occ)?;
             }
         }
     }
@@ -235,9 +576,28 @@ fn generate_field_setter(
     field_name: &syn::Ident,
     field_type: &syn::Type,
     field_id: proc_macro2::TokenStream,
+    occ: &proc_macro2::TokenStream,
 ) -> proc_macro2::TokenStream {
     let type_str = quote!(#field_type).to_string();
 
+    // Record i of a repeating group goes to occurrence i of its fields. The
+    // old records are deleted first, so a shorter Vec leaves none behind.
+    if let Some(inner_type) = vec_inner_type(&type_str) {
+        let delete = delete_records(field_name, &inner_type, &field_id);
+        return quote! {
+            {
+                #delete
+            }
+            for (i, item) in self.#field_name.iter().enumerate() {
+                item.update_ubf_occ(buf, i as i32)?;
+            }
+        };
+    }
+
+    let delete = delete_call(field_name, &field_id);
+
+    let nested_update = quote!(update_ubf_occ(buf, #occ));
+
     // Check if it's an Option type
     let is_option = type_str.starts_with("Option <");
 
@@ -245,99 +605,559 @@ fn generate_field_setter(
         // Handle all Option<T> types
         if type_str.contains("String") {
             // Option<String>
+            let write = write_call("string", &field_id, quote!(value));
             quote! {
                 if let Some(ref value) = self.#field_name {
-                    buf.add_string(#field_id, value)
+                    #write
                         .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                             format!("Field {}: {}", stringify!(#field_name), e)
                         ))?;
+                } else {
+                    #delete
                 }
             }
         } else if type_str.contains("i64") || type_str.contains("i32") || type_str.contains("long")
         {
             // Option<i64/i32>
+            let write = write_call("long", &field_id, quote!(value as i64));
             quote! {
                 if let Some(value) = self.#field_name {
-                    buf.add_long(#field_id, value as i64)
+                    #write
                         .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                             format!("Field {}: {}", stringify!(#field_name), e)
                         ))?;
+                } else {
+                    #delete
                 }
             }
-        } else if type_str.contains("f64")
-            || type_str.contains("f32")
-            || type_str.contains("double")
-        {
-            // Option<f64/f32>
+        } else if type_str.contains("f32") {
+            // Option<f32>
+            let write = write_call("float", &field_id, quote!(value));
             quote! {
                 if let Some(value) = self.#field_name {
-                    buf.add_double(#field_id, value as f64)
+                    #write
                         .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                             format!("Field {}: {}", stringify!(#field_name), e)
                         ))?;
+                } else {
+                    #delete
+                }
+            }
+        } else if type_str.contains("f64") || type_str.contains("double") {
+            // Option<f64>
+            let write = write_call("double", &field_id, quote!(value as f64));
+            quote! {
+                if let Some(value) = self.#field_name {
+                    #write
+                        .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                            format!("Field {}: {}", stringify!(#field_name), e)
+                        ))?;
+                } else {
+                    #delete
                 }
             }
         } else if type_str.contains("bool") {
             // Option<bool>
+            let write = write_call("long", &field_id, quote!(1));
             quote! {
-                if let Some(value) = self.#field_name {
-                    if value {
-                        buf.add_long(#field_id, 1)
-                            .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
-                                format!("Field {}: {}", stringify!(#field_name), e)
-                            ))?;
-                    }
+                if self.#field_name == Some(true) {
+                    #write
+                        .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                            format!("Field {}: {}", stringify!(#field_name), e)
+                        ))?;
+                } else {
+                    #delete
                 }
             }
         } else {
             // Option<NestedStruct>
+            let nested_type = option_inner_type(&type_str).expect("Option type");
             quote! {
                 if let Some(ref nested) = self.#field_name {
-                    nested.update_ubf(buf)?;
+                    nested.#nested_update?;
+                } else {
+                    <#nested_type as ::endurox_sys::ubf_struct::UbfStruct>::delete_ubf_occ(buf, #occ)?;
                 }
             }
         }
     } else {
         // Non-optional types
         if type_str.contains("String") {
+            let write = write_call("string", &field_id, quote!(&self.#field_name));
             quote! {
-                buf.add_string(#field_id, &self.#field_name)
+                #write
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                         format!("Field {}: {}", stringify!(#field_name), e)
                     ))?;
             }
         } else if type_str.contains("i64") || type_str.contains("i32") || type_str.contains("long")
         {
+            let write = write_call("long", &field_id, quote!(self.#field_name as i64));
             quote! {
-                buf.add_long(#field_id, self.#field_name as i64)
+                #write
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                         format!("Field {}: {}", stringify!(#field_name), e)
                     ))?;
             }
-        } else if type_str.contains("f64")
-            || type_str.contains("f32")
-            || type_str.contains("double")
-        {
+        } else if type_str.contains("f32") {
+            let write = write_call("float", &field_id, quote!(self.#field_name));
+            quote! {
+                #write
+                    .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
+                        format!("Field {}: {}", stringify!(#field_name), e)
+                    ))?;
+            }
+        } else if type_str.contains("f64") || type_str.contains("double") {
+            let write = write_call("double", &field_id, quote!(self.#field_name as f64));
             quote! {
-                buf.add_double(#field_id, self.#field_name as f64)
+                #write
                     .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                         format!("Field {}: {}", stringify!(#field_name), e)
                     ))?;
             }
         } else if type_str.contains("bool") {
+            let write = write_call("long", &field_id, quote!(1));
             quote! {
                 if self.#field_name {
-                    buf.add_long(#field_id, 1)
+                    #write
                         .map_err(|e| ::endurox_sys::ubf_struct::UbfError::TypeError(
                             format!("Field {}: {}", stringify!(#field_name), e)
                         ))?;
+                } else {
+                    #delete
                 }
             }
         } else {
             // Assume it's a nested struct that implements UbfStruct
             quote! {
-                self.#field_name.update_ubf(buf)?;
+                self.#field_name.#nested_update?;
             }
         }
     }
 }
+
+/// Derive macro for generating a UBF field table from a struct
+///
+/// `field` is the absolute field number. `type` defaults to the type implied
+/// by the Rust field type and `name` to `T_<FIELD>_FLD`.
+///
+/// # Example
+///
+/// ```ignore
+/// #[derive(UbfFieldTable)]
+/// #[ubf_table(name = "test", base = 1000)]
+/// struct Transaction {
+///     #[ubf(field = 1002, name = "T_NAME_FLD")]
+///     name: String,
+///
+///     #[ubf(field = 1012, type = "long")]
+///     id: i64,
+/// }
+///
+/// std::fs::write("test.fd.h", Transaction::fd_header())?;
+/// ```
+#[proc_macro_derive(UbfFieldTable, attributes(ubf, ubf_table))]
+pub fn derive_ubf_field_table(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => panic!("UbfFieldTable only supports named fields"),
+        },
+        _ => panic!("UbfFieldTable only supports structs"),
+    };
+
+    // Parse #[ubf_table(name = "...", base = ...)]
+    let mut table_name = name.to_string().to_lowercase();
+    let mut base: proc_macro2::TokenStream = quote!(0);
+
+    for attr in &input.attrs {
+        if attr.path().is_ident("ubf_table") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("name") {
+                    table_name = meta.value()?.parse::<syn::LitStr>()?.value();
+                } else if meta.path.is_ident("base") {
+                    let expr: syn::Expr = meta.value()?.parse()?;
+                    base = quote!(#expr);
+                } else {
+                    return Err(meta.error("unknown ubf_table attribute"));
+                }
+                Ok(())
+            })
+            .unwrap_or_else(|e| panic!("Invalid #[ubf_table] attribute: {}", e));
+        }
+    }
+
+    let mut field_defs = Vec::new();
+
+    for field in fields {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_type = &field.ty;
+
+        let FieldAttrs {
+            field: number,
+            name,
+            fd_type,
+            ..
+        } = parse_field_attrs(field);
+        let fd_name =
+            name.unwrap_or_else(|| format!("T_{}_FLD", field_name.to_string().to_uppercase()));
+
+        let number = number.unwrap_or_else(|| {
+            panic!(
+                "Field {} must have #[ubf(field = ...)] attribute",
+                field_name
+            )
+        });
+
+        let fd_type = fd_type.unwrap_or_else(|| {
+            infer_fd_type(&quote!(#field_type).to_string()).unwrap_or_else(|| {
+                panic!(
+                    "Cannot infer UBF type of field {}, add #[ubf(type = \"...\")]",
+                    field_name
+                )
+            })
+        });
+
+        if !FD_TYPES.contains(&fd_type.as_str()) {
+            panic!("Unknown UBF type '{}' for field {}", fd_type, field_name);
+        }
+
+        field_defs.push(quote! {
+            ::endurox_sys::ubf_table::UbfFieldDef {
+                name: #fd_name,
+                number: #number,
+                field_type: #fd_type,
+            }
+        });
+    }
+
+    let expanded = quote! {
+        impl ::endurox_sys::ubf_table::UbfFieldTable for #name {
+            const TABLE_NAME: &'static str = #table_name;
+            const BASE: i32 = #base;
+
+            fn fields() -> &'static [::endurox_sys::ubf_table::UbfFieldDef] {
+                const FIELDS: &[::endurox_sys::ubf_table::UbfFieldDef] = &[
+                    #(#field_defs),*
+                ];
+                FIELDS
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+const FD_TYPES: [&str; 7] = [
+    "short", "long", "char", "float", "double", "string", "carray",
+];
+
+/// Values of a field's `#[ubf(...)]` attributes, shared by both derives
+#[derive(Default)]
+struct FieldAttrs {
+    field: Option<proc_macro2::TokenStream>,
+    default: Option<String>,
+    as_string: bool,
+    occ_base: Option<proc_macro2::TokenStream>,
+    name: Option<String>,
+    fd_type: Option<String>,
+}
+
+/// Parses `#[ubf(key = value, ...)]`; values are full expressions, so
+/// `field = id(a, b)` or `default = "a, b"` keep their commas
+fn parse_field_attrs(field: &syn::Field) -> FieldAttrs {
+    let field_name = field.ident.as_ref().unwrap();
+    let mut attrs = FieldAttrs::default();
+
+    for attr in &field.attrs {
+        if !attr.path().is_ident("ubf") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("field") {
+                let expr: syn::Expr = meta.value()?.parse()?;
+                attrs.field = Some(quote!(#expr));
+            } else if meta.path.is_ident("default") {
+                attrs.default = Some(match meta.value()?.parse::<syn::Lit>()? {
+                    syn::Lit::Str(lit) => lit.value(),
+                    lit => quote!(#lit).to_string(),
+                });
+            } else if meta.path.is_ident("as") {
+                let encoding = meta.value()?.parse::<syn::LitStr>()?;
+                if encoding.value() != "string" {
+                    return Err(syn::Error::new(
+                        encoding.span(),
+                        format!("unsupported encoding as = \"{}\"", encoding.value()),
+                    ));
+                }
+                attrs.as_string = true;
+            } else if meta.path.is_ident("occ_base") {
+                let expr: syn::Expr = meta.value()?.parse()?;
+                attrs.occ_base = Some(quote!(#expr));
+            } else if meta.path.is_ident("name") {
+                attrs.name = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            } else if meta.path.is_ident("type") {
+                attrs.fd_type = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+            } else {
+                return Err(meta.error("unknown ubf attribute"));
+            }
+            Ok(())
+        })
+        .unwrap_or_else(|e| panic!("Field {}: {}", field_name, e));
+    }
+
+    attrs
+}
+
+/// `.fd` type implied by a Rust field type
+fn infer_fd_type(type_str: &str) -> Option<String> {
+    let inner = type_str
+        .trim_start_matches("Option <")
+        .trim_end_matches('>')
+        .trim();
+
+    let fd_type = match inner {
+        "String" => "string",
+        "i64" | "i32" | "bool" => "long",
+        "i16" => "short",
+        "f64" => "double",
+        "f32" => "float",
+        "u8" | "char" => "char",
+        "Vec < u8 >" => "carray",
+        _ => return None,
+    };
+
+    Some(fd_type.to_string())
+}
+
+/// `key: value` entries of `endurox_server!`
+struct ServerSpec {
+    name: Option<syn::LitStr>,
+    services: Vec<(syn::LitStr, syn::Expr)>,
+    raw_services: Vec<(syn::LitStr, syn::Expr)>,
+    init: Option<syn::Expr>,
+    done: Option<syn::Expr>,
+}
+
+impl syn::parse::Parse for ServerSpec {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut spec = ServerSpec {
+            name: None,
+            services: Vec::new(),
+            raw_services: Vec::new(),
+            init: None,
+            done: None,
+        };
+
+        while !input.is_empty() {
+            let key: syn::Ident = input.parse()?;
+            input.parse::<syn::Token![:]>()?;
+
+            match key.to_string().as_str() {
+                "name" => spec.name = Some(input.parse()?),
+                "services" => spec.services = parse_service_map(input)?,
+                "raw_services" => spec.raw_services = parse_service_map(input)?,
+                "init" => spec.init = Some(input.parse()?),
+                "done" => spec.done = Some(input.parse()?),
+                other => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!("Unknown endurox_server key: {}", other),
+                    ))
+                }
+            }
+
+            if !input.is_empty() {
+                input.parse::<syn::Token![,]>()?;
+            }
+        }
+
+        Ok(spec)
+    }
+}
+
+/// Parses `{ "NAME" => handler, ... }`
+fn parse_service_map(input: syn::parse::ParseStream) -> syn::Result<Vec<(syn::LitStr, syn::Expr)>> {
+    let content;
+    syn::braced!(content in input);
+
+    let mut services = Vec::new();
+    while !content.is_empty() {
+        let name: syn::LitStr = content.parse()?;
+        content.parse::<syn::Token![=>]>()?;
+        let handler: syn::Expr = content.parse()?;
+        services.push((name, handler));
+
+        if !content.is_empty() {
+            content.parse::<syn::Token![,]>()?;
+        }
+    }
+
+    Ok(services)
+}
+
+/// Generates the entry points of an Enduro/X server binary
+///
+/// Expands to `tpsvrinit`, `tpsvrdone`, the service dispatcher and a `main`
+/// calling `run_server`. `services` handlers are registered on a
+/// `ServiceServer` built once in `tpsvrinit`; `raw_services` are
+/// `extern "C"` dispatchers advertised as they are. `init` becomes the
+/// `on_init` hook and `done` the `on_shutdown` hook run from `tpsvrdone`.
+/// `name` defaults to the package name.
+///
+/// # Example
+///
+/// ```ignore
+/// endurox_sys::endurox_server! {
+///     name: "samplesvr_rust",
+///     services: {
+///         "ECHO" => echo_service,
+///         "HELLO" => hello_service,
+///     },
+///     raw_services: {
+///         "STREAM" => stream_dispatcher,
+///     },
+///     init: |args| {
+///         tplog_info(&format!("app args: {:?}", args));
+///         Ok(())
+///     },
+///     done: || tplog_info("bye"),
+/// }
+/// ```
+#[proc_macro]
+pub fn endurox_server(input: TokenStream) -> TokenStream {
+    let spec = parse_macro_input!(input as ServerSpec);
+
+    let name = match &spec.name {
+        Some(name) => quote!(#name),
+        None => quote!(env!("CARGO_PKG_NAME")),
+    };
+
+    let services = spec
+        .services
+        .iter()
+        .map(|(service, handler)| quote!(.service(#service, #handler)));
+    let on_init = spec.init.as_ref().map(|init| quote!(.on_init(#init)));
+    let on_shutdown = spec.done.as_ref().map(|done| quote!(.on_shutdown(#done)));
+
+    let raw_services = spec.raw_services.iter().map(|(service, dispatcher)| {
+        quote! {
+            if let Err(e) = ::endurox_sys::server::advertise_service(#service, #dispatcher) {
+                ::endurox_sys::tplog_error(&format!("Failed to advertise {}: {}", #service, e));
+                return -1;
+            }
+        }
+    });
+
+    let expanded = quote! {
+        // Service registry, built once in tpsvrinit
+        static __ENDUROX_SERVER: ::std::sync::OnceLock<::endurox_sys::service::ServiceServer> =
+            ::std::sync::OnceLock::new();
+
+        extern "C" fn __endurox_service_dispatcher(rqst: *mut ::endurox_sys::TpSvcInfoRaw) {
+            match __ENDUROX_SERVER.get() {
+                Some(server) => unsafe { server.dispatch(rqst) },
+                None => {
+                    ::endurox_sys::tplog_error("Service registry not initialized");
+                    unsafe { ::endurox_sys::server::tpreturn_fail(rqst) }
+                }
+            }
+        }
+
+        #[no_mangle]
+        #[allow(clippy::not_unsafe_ptr_arg_deref)] // argv comes straight from ndrx_main
+        pub extern "C" fn tpsvrinit(
+            argc: ::std::os::raw::c_int,
+            argv: *mut *mut ::std::os::raw::c_char,
+        ) -> ::std::os::raw::c_int {
+            ::endurox_sys::tplog_info(&format!("{} starting...", #name));
+
+            let args = unsafe { ::endurox_sys::server::parse_args(argc, argv) };
+            let server = __ENDUROX_SERVER.get_or_init(|| {
+                ::endurox_sys::service::ServiceServer::builder(#name)
+                    #(#services)*
+                    #on_init
+                    #on_shutdown
+                    .build()
+            });
+
+            if let Err(e) = server.init(&args, __endurox_service_dispatcher) {
+                ::endurox_sys::tplog_error(&e);
+                return -1;
+            }
+
+            #(#raw_services)*
+
+            ::endurox_sys::tplog_info(&format!("{} initialized successfully", #name));
+            0
+        }
+
+        #[no_mangle]
+        pub extern "C" fn tpsvrdone() {
+            ::endurox_sys::tplog_info(&format!("{} shutting down...", #name));
+            if let Some(server) = __ENDUROX_SERVER.get() {
+                server.shutdown();
+            }
+        }
+
+        #[cfg_attr(test, allow(dead_code))] // the test harness has its own main
+        fn main() -> ! {
+            ::endurox_sys::server::run_server(tpsvrinit, tpsvrdone)
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_attribute_dumps_expansion() {
+        let input: DeriveInput = syn::parse_quote! {
+            #[ubf(debug)]
+            struct Payment {
+                #[ubf(field = 1002)]
+                account: String,
+                #[ubf(field = 1012)]
+                amount: i64,
+            }
+        };
+        let mut stderr = Vec::new();
+        let expanded = derive_ubf_struct_to(&input, &mut stderr);
+        let dump = String::from_utf8(stderr).unwrap();
+
+        assert_eq!(expanded.to_string(), expand_ubf_struct(&input).to_string());
+        assert!(dump.starts_with("UbfStruct expansion for Payment:\n"));
+        assert!(dump.contains("fn from_ubf_occ"));
+        assert!(dump.contains("fn update_ubf_occ"));
+        assert!(dump.contains("1002"));
+        assert!(dump.contains("1012"));
+        assert!(dump.lines().count() > 10);
+
+        let quiet: DeriveInput = syn::parse_quote! {
+            struct Payment {
+                #[ubf(field = 1002)]
+                account: String,
+            }
+        };
+        let mut stderr = Vec::new();
+        derive_ubf_struct_to(&quiet, &mut stderr);
+        assert!(stderr.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Field amount: unknown ubf attribute")]
+    fn test_unknown_field_attribute_is_rejected() {
+        let field: syn::Field = syn::parse_quote! {
+            #[ubf(field = 1012, defualt = "0")]
+            amount: i64
+        };
+        parse_field_attrs(&field);
+    }
+}
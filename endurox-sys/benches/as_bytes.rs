@@ -0,0 +1,65 @@
+//! Sending a UBF request from a `to_vec` copy vs the borrowed `as_bytes`
+//!
+//! Run with `cargo bench -p endurox-sys --features ubf --bench as_bytes`.
+
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+const ROUNDS: u32 = 100_000;
+
+/// System allocator counting the allocations made through it
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn bench(name: &str, mut send: impl FnMut()) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        send();
+    }
+    let per_round = start.elapsed() / ROUNDS;
+    let allocated = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<10} {:>8} ns/request {:>4} allocations/request",
+        name,
+        per_round.as_nanos(),
+        allocated as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let mut buf = UbfBuffer::new(4096).expect("tpalloc failed");
+    buf.add_string(T_TRANS_ID_FLD, "TXN-1").unwrap();
+    buf.add_string(T_ACCOUNT_FLD, "ACC-42").unwrap();
+    buf.add_string(T_CURRENCY_FLD, "EUR").unwrap();
+    buf.add_string(T_DESC_FLD, &"x".repeat(512)).unwrap();
+
+    // What the gateway did before handing the request to the client
+    bench("to_vec", || {
+        let data = buf.as_bytes().to_vec();
+        black_box(&data);
+    });
+
+    bench("as_bytes", || {
+        black_box(buf.as_bytes());
+    });
+}
@@ -0,0 +1,45 @@
+//! Reading string fields with `get_string` vs a reused scratch buffer
+//!
+//! Run with `cargo bench -p endurox-sys --features ubf --bench get_string`.
+
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ROUNDS: u32 = 100_000;
+const FIELDS: [i32; 4] = [T_NAME_FLD, T_TRANS_ID_FLD, T_ACCOUNT_FLD, T_CURRENCY_FLD];
+
+fn bench(name: &str, mut read: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        read();
+    }
+    let per_round = start.elapsed() / ROUNDS;
+    println!(
+        "{:<16} {:>8} ns/request ({} fields)",
+        name,
+        per_round.as_nanos(),
+        FIELDS.len()
+    );
+}
+
+fn main() {
+    let mut buf = UbfBuffer::new(4096).expect("tpalloc failed");
+    for (field, value) in FIELDS.iter().zip(["Alice", "TXN-1", "ACC-42", "EUR"]) {
+        buf.add_string(*field, value).unwrap();
+    }
+
+    bench("get_string", || {
+        for field in FIELDS {
+            black_box(buf.get_string(field, 0).unwrap());
+        }
+    });
+
+    let mut scratch = Vec::new();
+    bench("get_string_into", || {
+        for field in FIELDS {
+            black_box(buf.get_string_into(field, 0, &mut scratch).unwrap());
+        }
+    });
+}
@@ -1,19 +1,26 @@
 use std::env;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+#[path = "build/lib_dir.rs"]
+mod lib_dir;
+use lib_dir::library_exists;
+
+#[path = "build/ubf_fields.rs"]
+mod ubf_fields;
 
 fn main() {
     // Add Enduro/X library paths
     let ndrx_home = std::env::var("NDRX_HOME").unwrap_or_else(|_| "/opt/endurox".to_string());
+    let lib_dir = endurox_lib_dir(&ndrx_home);
 
-    println!("cargo:rustc-link-search=native={}/lib", ndrx_home);
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
 
     // Common libraries for both server and client
-    println!("cargo:rustc-link-lib=atmi");
-    println!("cargo:rustc-link-lib=ubf");
-    println!("cargo:rustc-link-lib=netproto");
-    println!("cargo:rustc-link-lib=nstd");
+    for lib in endurox_libs(&lib_dir) {
+        println!("cargo:rustc-link-lib={}", lib);
+    }
     println!("cargo:rustc-link-lib=pthread");
 
     // rt library only exists on Linux
@@ -27,12 +34,176 @@ fn main() {
 
     println!("cargo:rerun-if-env-changed=NDRX_HOME");
     println!("cargo:rerun-if-env-changed=NDRX_APPHOME");
+    println!("cargo:rerun-if-env-changed=ENDUROX_LIBS");
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=build/lib_dir.rs");
+    println!("cargo:rerun-if-changed=build/ubf_fields.rs");
+
+    detect_endurox_version(&ndrx_home);
+    detect_tpsvcinfo_size(&ndrx_home);
 
     // Generate UBF field constants from test.fd.h
     generate_ubf_constants();
 }
 
+/// Reads NDRX_VERSION from the installed headers
+///
+/// Enduro/X has no runtime version call, so the version of the install the
+/// crate is built against is baked in. Sets `cfg(endurox_version)` when found.
+fn detect_endurox_version(ndrx_home: &str) {
+    println!("cargo:rustc-check-cfg=cfg(endurox_version)");
+
+    let header = PathBuf::from(ndrx_home).join("include/ndrx_config.h");
+    println!("cargo:rerun-if-changed={}", header.display());
+
+    let Ok(content) = fs::read_to_string(&header) else {
+        return;
+    };
+
+    // #define NDRX_VERSION   "Enduro/X 8.0.10"
+    let version = content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some("#define") || parts.next() != Some("NDRX_VERSION") {
+            return None;
+        }
+        let value = parts.collect::<Vec<_>>().join(" ");
+        Some(value.trim_matches('"').to_string())
+    });
+
+    if let Some(version) = version.filter(|v| !v.is_empty()) {
+        println!("cargo:rustc-env=ENDUROX_VERSION={}", version);
+        println!("cargo:rustc-cfg=endurox_version");
+    }
+}
+
+/// Enduro/X library directory under `ndrx_home`
+///
+/// A wrong `NDRX_HOME` would otherwise surface as an unresolved symbol deep
+/// in the link. When set explicitly it has to hold libatmi and libubf; the
+/// `/opt/endurox` fallback only warns, as the libraries may still resolve
+/// from the system library path.
+fn endurox_lib_dir(ndrx_home: &str) -> PathBuf {
+    let multiarch = env::var("CARGO_CFG_TARGET_ARCH")
+        .ok()
+        .map(|arch| format!("{}-linux-gnu", arch));
+
+    match lib_dir::find_lib_dir(Path::new(ndrx_home), multiarch.as_deref()) {
+        Ok(dir) => dir,
+        Err(e) if env::var_os("NDRX_HOME").is_some() => panic!("{}", e),
+        Err(e) => {
+            println!("cargo:warning={}", e);
+            PathBuf::from(ndrx_home).join("lib")
+        }
+    }
+}
+
+/// Enduro/X libraries linked by default (stock 8.x packaging)
+const DEFAULT_ENDUROX_LIBS: &[&str] = &["atmi", "ubf", "netproto", "nstd"];
+
+/// Selects the Enduro/X link set
+///
+/// `ENDUROX_LIBS` (comma or space separated, e.g. `ndrxcore,atmi,ubf,nstd`)
+/// overrides the default set for non-standard packagings. The chosen set is
+/// checked against `lib_dir` when that directory exists.
+fn endurox_libs(lib_dir: &Path) -> Vec<String> {
+    let (libs, overridden) = match env::var("ENDUROX_LIBS") {
+        Ok(value) if !value.trim().is_empty() => {
+            let libs: Vec<String> = value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.strip_prefix("lib").unwrap_or(s).to_string())
+                .collect();
+            (libs, true)
+        }
+        _ => (
+            DEFAULT_ENDUROX_LIBS.iter().map(|s| s.to_string()).collect(),
+            false,
+        ),
+    };
+
+    if lib_dir.is_dir() {
+        let missing: Vec<&String> = libs
+            .iter()
+            .filter(|lib| !library_exists(lib_dir, lib))
+            .collect();
+
+        if !missing.is_empty() {
+            let names = missing
+                .iter()
+                .map(|lib| format!("lib{}", lib))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if overridden {
+                // An explicit override that doesn't match the install is a user error
+                panic!(
+                    "ENDUROX_LIBS selects libraries not found in {}: {}",
+                    lib_dir.display(),
+                    names
+                );
+            }
+
+            // The default set may still resolve from the system library path
+            println!(
+                "cargo:warning=Enduro/X libraries not found in {}: {} (set ENDUROX_LIBS to override)",
+                lib_dir.display(),
+                names
+            );
+        }
+    }
+
+    libs
+}
+
+/// Computes sizeof(TPSVCINFO) from the installed headers
+///
+/// The layout follows xatmi.h; only the array sizes vary between installs.
+/// Sets `ENDUROX_TPSVCINFO_SIZE` for `server::verify_abi` when both
+/// constants are found.
+fn detect_tpsvcinfo_size(ndrx_home: &str) {
+    let include = PathBuf::from(ndrx_home).join("include");
+    let xatmi_h = include.join("xatmi.h");
+    let ndrstandard_h = include.join("ndrstandard.h");
+    println!("cargo:rerun-if-changed={}", xatmi_h.display());
+    println!("cargo:rerun-if-changed={}", ndrstandard_h.display());
+
+    let (Some(maxtident), Some(max_id_size)) = (
+        header_define(&xatmi_h, "MAXTIDENT"),
+        header_define(&ndrstandard_h, "NDRX_MAX_ID_SIZE"),
+    ) else {
+        return;
+    };
+
+    // char * and long are pointer sized on the supported (LP64/ILP32) targets
+    let word: usize = env::var("CARGO_CFG_TARGET_POINTER_WIDTH")
+        .ok()
+        .and_then(|w| w.parse::<usize>().ok())
+        .map_or(8, |bits| bits / 8);
+    let align = |offset: usize, to: usize| offset.div_ceil(to) * to;
+
+    let mut size = maxtident + 1; // name[XATMI_SERVICE_NAME_LENGTH+1]
+    size = align(size, word) + 3 * word; // data, len, flags
+    size += 4; // cd
+    size = align(size, word) + word; // appkey
+    size += max_id_size; // cltid.clientdata[NDRX_MAX_ID_SIZE]
+    size += maxtident + 1; // fname[XATMI_SERVICE_NAME_LENGTH+1]
+    size = align(size, word);
+
+    println!("cargo:rustc-env=ENDUROX_TPSVCINFO_SIZE={}", size);
+}
+
+/// Numeric value of `#define NAME <number>` in a header
+fn header_define(path: &Path, name: &str) -> Option<usize> {
+    let content = fs::read_to_string(path).ok()?;
+    content.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next() != Some("#define") || parts.next() != Some(name) {
+            return None;
+        }
+        parts.next()?.parse().ok()
+    })
+}
+
 fn generate_ubf_constants() {
     // Try NDRX_APPHOME first (for deployed apps), fall back to CARGO_MANIFEST_DIR (for development)
     let ubftab_dir = if let Ok(apphome) = env::var("NDRX_APPHOME") {
@@ -49,44 +220,41 @@ fn generate_ubf_constants() {
         println!("cargo:warning=ubftab directory not found, skipping UBF constants generation");
         // Still create an empty file so include! doesn't fail
     } else {
-        let mut found_files = false;
+        let mut tables = Vec::new();
 
         // Read all .fd.h files in ubftab directory
         if let Ok(entries) = fs::read_dir(&ubftab_dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
+                let Some(filename) = path.file_name().and_then(|f| f.to_str()) else {
+                    continue;
+                };
 
                 // Process only *.fd.h files
-                if let Some(ext) = path.extension() {
-                    if ext == "h"
-                        && path
-                            .file_name()
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .ends_with(".fd.h")
-                    {
-                        found_files = true;
-
-                        let filename = path.file_name().unwrap().to_str().unwrap();
-                        println!("cargo:rerun-if-changed=../ubftab/{}", filename);
-
-                        rust_code.push_str(&format!("\n// Fields from {}\n", filename));
-
-                        if let Ok(content) = fs::read_to_string(&path) {
-                            parse_ubf_header(&content, &mut rust_code);
-                        }
+                if filename.ends_with(".fd.h") {
+                    println!("cargo:rerun-if-changed=../ubftab/{}", filename);
+
+                    if let Ok(content) = fs::read_to_string(&path) {
+                        tables.push((filename.to_string(), content));
                     }
                 }
             }
         }
 
-        if !found_files {
+        if tables.is_empty() {
             println!(
                 "cargo:warning=No *.fd.h files found in ubftab/, skipping UBF constants generation"
             );
         }
 
+        // read_dir order is unspecified, keep the output (and conflict reports) stable
+        tables.sort();
+        let (code, conflicts) = ubf_fields::render_ubf_constants(&tables);
+        for conflict in &conflicts {
+            println!("cargo:warning={}", conflict);
+        }
+        rust_code.push_str(&code);
+
         // Watch for changes in ubftab directory
         println!("cargo:rerun-if-changed=../ubftab");
     }
@@ -97,35 +265,3 @@ fn generate_ubf_constants() {
     file.write_all(rust_code.as_bytes())
         .expect("Failed to write ubf_fields.rs");
 }
-
-fn parse_ubf_header(content: &str, rust_code: &mut String) {
-    for line in content.lines() {
-        if line.trim().starts_with("#define") && line.contains("((BFLDID32)") {
-            // Parse line like:
-            // #define	T_NAME_FLD	((BFLDID32)167773162)	/* number: 1002	 type: string */
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() >= 3 {
-                let name = parts[1];
-                let value_part = parts[2];
-
-                // Extract number from ((BFLDID32)167773162)
-                if let Some(start) = value_part.find("((BFLDID32)") {
-                    let num_start = start + 11; // length of "((BFLDID32)"
-                    if let Some(end) = value_part[num_start..].find(')') {
-                        let value = &value_part[num_start..num_start + end];
-
-                        // Extract comment for documentation
-                        if let Some(comment_start) = line.find("/*") {
-                            if let Some(comment_end) = line.find("*/") {
-                                let _comment = line[comment_start + 2..comment_end].trim();
-                                //rust_code.push_str(&format!("/// {}\n", comment));
-                            }
-                        }
-
-                        rust_code.push_str(&format!("pub const {}: i32 = {};\n\n", name, value));
-                    }
-                }
-            }
-        }
-    }
-}
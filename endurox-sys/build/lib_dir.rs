@@ -0,0 +1,65 @@
+//! Locates the Enduro/X libraries under `NDRX_HOME`
+//!
+//! Shared by build.rs and `tests/lib_dir_build_test.rs`.
+
+use std::path::{Path, PathBuf};
+
+/// Libraries every Enduro/X install has, whatever the packaging
+pub const CORE_LIBS: &[&str] = &["atmi", "ubf"];
+
+/// Directories under `ndrx_home` the libraries may be installed in
+///
+/// `lib` for tarball installs, `lib64` for RPMs and the multiarch directory
+/// (e.g. `lib/x86_64-linux-gnu`) for Debian packages.
+pub fn lib_dir_candidates(ndrx_home: &Path, multiarch: Option<&str>) -> Vec<PathBuf> {
+    let mut dirs = vec![ndrx_home.join("lib"), ndrx_home.join("lib64")];
+    if let Some(triple) = multiarch {
+        dirs.push(ndrx_home.join("lib").join(triple));
+    }
+    dirs
+}
+
+/// First candidate directory holding all of `CORE_LIBS`
+///
+/// The error names the `NDRX_HOME`, the directories searched and the
+/// libraries found in none of them.
+pub fn find_lib_dir(ndrx_home: &Path, multiarch: Option<&str>) -> Result<PathBuf, String> {
+    let candidates = lib_dir_candidates(ndrx_home, multiarch);
+    if let Some(dir) = candidates
+        .iter()
+        .find(|dir| CORE_LIBS.iter().all(|lib| library_exists(dir, lib)))
+    {
+        return Ok(dir.clone());
+    }
+
+    let missing = CORE_LIBS
+        .iter()
+        .filter(|lib| !candidates.iter().any(|dir| library_exists(dir, lib)))
+        .map(|lib| format!("lib{}", lib))
+        .collect::<Vec<_>>();
+    let searched = candidates
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let what = if missing.is_empty() {
+        // Each found, but not side by side
+        "libatmi and libubf are not in the same directory".to_string()
+    } else {
+        format!("missing {}", missing.join(", "))
+    };
+    Err(format!(
+        "NDRX_HOME={} does not look like an Enduro/X install: {} (searched {}). \
+         Set NDRX_HOME to the Enduro/X installation prefix",
+        ndrx_home.display(),
+        what,
+        searched
+    ))
+}
+
+pub fn library_exists(lib_dir: &Path, lib: &str) -> bool {
+    ["so", "a", "dylib"]
+        .iter()
+        .any(|ext| lib_dir.join(format!("lib{}.{}", lib, ext)).exists())
+}
@@ -0,0 +1,80 @@
+//! Generates `pub const` field ids from `*.fd.h` tables
+//!
+//! Shared by build.rs and `tests/ubf_fields_build_test.rs`.
+
+use std::collections::BTreeMap;
+
+/// `(name, id)` of every `#define NAME ((BFLDID32)id)` in a header
+pub fn parse_ubf_header(content: &str) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    for line in content.lines() {
+        if line.trim().starts_with("#define") && line.contains("((BFLDID32)") {
+            // Parse line like:
+            // #define	T_NAME_FLD	((BFLDID32)167773162)	/* number: 1002	 type: string */
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() >= 3 {
+                let name = parts[1];
+                let value_part = parts[2];
+
+                // Extract number from ((BFLDID32)167773162)
+                if let Some(start) = value_part.find("((BFLDID32)") {
+                    let num_start = start + 11; // length of "((BFLDID32)"
+                    if let Some(end) = value_part[num_start..].find(')') {
+                        let value = &value_part[num_start..num_start + end];
+                        fields.push((name.to_string(), value.to_string()));
+                    }
+                }
+            }
+        }
+    }
+    fields
+}
+
+/// Renders the constants of `(file name, header content)` tables
+///
+/// A name defined again with the same id is emitted once. A name defined
+/// with different ids becomes a `compile_error!` naming both tables, since
+/// two `pub const` lines would fail with an opaque duplicate definition.
+/// An id that doesn't fit `i32` becomes a `compile_error!` naming the field
+/// instead of a constant that wraps to another field. Also returns one
+/// message per such error.
+pub fn render_ubf_constants(tables: &[(String, String)]) -> (String, Vec<String>) {
+    let mut code = String::new();
+    let mut conflicts = Vec::new();
+    // name -> (id, file) of the first definition
+    let mut seen: BTreeMap<String, (String, String)> = BTreeMap::new();
+
+    for (filename, content) in tables {
+        code.push_str(&format!("\n// Fields from {}\n", filename));
+
+        for (name, value) in parse_ubf_header(content) {
+            if value.parse::<i32>().is_err() {
+                let msg = format!(
+                    "UBF field {} in {} has id {}, which doesn't fit i32",
+                    name, filename, value
+                );
+                code.push_str(&format!("compile_error!({:?});\n\n", msg));
+                conflicts.push(msg);
+                continue;
+            }
+
+            match seen.get(&name) {
+                Some((id, _)) if *id == value => {}
+                Some((id, first_file)) => {
+                    let msg = format!(
+                        "UBF field {} is {} in {} but {} in {}",
+                        name, id, first_file, value, filename
+                    );
+                    code.push_str(&format!("compile_error!({:?});\n\n", msg));
+                    conflicts.push(msg);
+                }
+                None => {
+                    code.push_str(&format!("pub const {}: i32 = {};\n\n", name, value));
+                    seen.insert(name, (value, filename.clone()));
+                }
+            }
+        }
+    }
+
+    (code, conflicts)
+}
@@ -1,141 +1,315 @@
 //! Client API - safe wrappers for client functions
 
+use crate::client_stats::{CallRecorder, ClientStats};
+use crate::context::AtmiContext;
+#[cfg(feature = "ubf")]
+use crate::error::conversation_event;
+use crate::error::{c_string, context_lost, record_errno, tperrno, TpError};
 use crate::ffi;
+use crate::flags::TpFlags;
+#[cfg(feature = "ubf")]
+use crate::tplog_warn;
+#[cfg(feature = "ubf")]
+use crate::typed_buffer::TypedBuffer;
+#[cfg(feature = "ubf")]
+use crate::ubf::{buffer_type, reply_buffer, UbfBuffer};
 use crate::{tplog_error, tplog_info};
-use libc::{c_char, c_long};
+use libc::{c_char, c_int, c_long};
+#[cfg(feature = "ubf")]
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+#[cfg(feature = "ubf")]
+use std::marker::PhantomData;
 use std::ptr;
+#[cfg(feature = "ubf")]
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
-/// Enduro/X client
-pub struct EnduroxClient {
-    initialized: bool,
+/// tpcall flags used by `call_ubf_no_timeout`
+const NO_TIMEOUT_FLAGS: TpFlags = TpFlags::NOTIME;
+
+/// How often `call_ubf_deadline` polls for the reply
+const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How long `is_service_available` trusts a looked up service state
+#[cfg(feature = "ubf")]
+const AVAILABILITY_TTL: Duration = Duration::from_secs(5);
+
+// tm_mib(5) fields from the Excompat table (base 2000)
+#[cfg(feature = "ubf")]
+mod tmib {
+    pub const TA_CLASS: i32 = 167774250; // string 90
+    pub const TA_CURSOR: i32 = 167774339; // string 179
+    pub const TA_ERROR: i32 = 33556893; // long 461
+    pub const TA_MORE: i32 = 33557282; // long 850
+    pub const TA_OPERATION: i32 = 167775071; // string 911
+    pub const TA_SERVICENAME: i32 = 167775229; // string 1069
+    pub const TA_STATE: i32 = 167775265; // string 1105
 }
 
-impl EnduroxClient {
-    /// Creates and initializes the client
-    pub fn new() -> Result<Self, String> {
-        unsafe {
-            tplog_info("Calling tpinit...");
-            let ret = ffi::tpinit(ptr::null_mut());
-            if ret == -1 {
-                let tperrno = *ffi::_exget_tperrno_addr();
-                let err_ptr = ffi::tpstrerror(tperrno);
-                let err_msg = if !err_ptr.is_null() {
-                    CStr::from_ptr(err_ptr).to_string_lossy().into_owned()
-                } else {
-                    "Unknown error".to_string()
-                };
-                tplog_error(&format!(
-                    "tpinit failed: ret={}, tperrno={}, msg={}",
-                    ret, tperrno, err_msg
-                ));
-                return Err(format!("tpinit failed: {}", err_msg));
-            }
-            tplog_info(&format!("tpinit succeeded: ret={}", ret));
+/// Service states looked up recently, valid for `ttl`
+#[cfg(feature = "ubf")]
+struct AvailabilityCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (bool, Instant)>>,
+}
+
+#[cfg(feature = "ubf")]
+impl AvailabilityCache {
+    fn new(ttl: Duration) -> Self {
+        AvailabilityCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
         }
+    }
 
-        Ok(EnduroxClient { initialized: true })
+    fn get(&self, service: &str) -> Option<bool> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(service)
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .map(|(available, _)| *available)
     }
 
-    /// Calls a service (blocking)
-    pub fn call_service_blocking(&self, service: &str, data: &str) -> Result<String, String> {
-        unsafe {
-            tplog_info(&format!(
-                "call_service_blocking: service={}, data_len={}",
-                service,
-                data.len()
-            ));
+    fn insert(&self, service: &str, available: bool) {
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(service.to_string(), (available, Instant::now()));
+    }
+}
 
-            // Allocate STRING buffer for input
-            let string_type = CString::new("STRING").map_err(|e| e.to_string())?;
-            let send_buf = ffi::tpalloc(
-                string_type.as_ptr(),
-                ptr::null(),
-                (data.len() + 1) as c_long,
-            );
+/// Client identity and flags passed to tpinit
+///
+/// The default config calls `tpinit(NULL)`, same as `EnduroxClient::new`.
+#[derive(Clone, Default)]
+pub struct ClientConfig {
+    pub user_name: Option<String>,
+    pub client_name: Option<String>,
+    pub password: Option<String>,
+    pub group: Option<String>,
+    pub flags: c_long,
+}
 
-            if send_buf.is_null() {
-                let tperrno = *ffi::_exget_tperrno_addr();
-                let err_msg = format!("Failed to allocate send buffer, tperrno={}", tperrno);
-                tplog_error(&err_msg);
-                return Err(err_msg);
-            }
+impl ClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-            // Copy data to buffer
-            let c_data = CString::new(data).map_err(|e| e.to_string())?;
-            ptr::copy_nonoverlapping(c_data.as_ptr(), send_buf, data.len() + 1);
+    pub fn user_name(mut self, name: &str) -> Self {
+        self.user_name = Some(name.to_string());
+        self
+    }
 
-            // Make synchronous call with tpcall
-            let c_service = CString::new(service).map_err(|e| e.to_string())?;
-            let mut recv_buf: *mut c_char = ptr::null_mut();
-            let mut recv_len: c_long = 0;
+    pub fn client_name(mut self, name: &str) -> Self {
+        self.client_name = Some(name.to_string());
+        self
+    }
 
-            tplog_info(&format!("Calling tpcall for service: {}", service));
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
 
-            let ret = ffi::tpcall(
-                c_service.as_ptr(),
-                send_buf,
-                (data.len() + 1) as c_long,
-                &mut recv_buf,
-                &mut recv_len,
-                0, // Try with no flags first
-            );
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
 
-            ffi::tpfree(send_buf);
+    /// TPINIT flags, e.g. `ffi::TPMULTICONTEXTS`
+    pub fn flags(mut self, flags: c_long) -> Self {
+        self.flags = flags;
+        self
+    }
 
-            tplog_info(&format!(
-                "tpcall returned: ret={}, recv_buf={:?}, recv_len={}",
-                ret, recv_buf, recv_len
-            ));
+    pub(crate) fn is_default(&self) -> bool {
+        self.user_name.is_none()
+            && self.client_name.is_none()
+            && self.password.is_none()
+            && self.group.is_none()
+            && self.flags == 0
+    }
 
-            if ret == -1 {
-                if !recv_buf.is_null() {
-                    ffi::tpfree(recv_buf);
-                }
-                let tperrno = *ffi::_exget_tperrno_addr();
-                let err_ptr = ffi::tpstrerror(tperrno);
-                let err_msg = if !err_ptr.is_null() {
-                    CStr::from_ptr(err_ptr).to_string_lossy().into_owned()
-                } else {
-                    "Unknown error".to_string()
-                };
-                tplog_error(&format!(
-                    "tpcall failed: ret={}, tperrno={}, msg={}",
-                    ret, tperrno, err_msg
-                ));
-                return Err(format!("tpcall failed: {}: {}", tperrno, err_msg));
-            }
+    /// Builds the TPINIT structure, checking identifier lengths
+    pub fn to_raw(&self) -> Result<ffi::TpInitRaw, String> {
+        let mut raw = ffi::TpInitRaw {
+            usrname: [0; ffi::MAXTIDENT + 2],
+            cltname: [0; ffi::MAXTIDENT + 2],
+            passwd: [0; ffi::MAXTIDENT + 2],
+            grpname: [0; ffi::MAXTIDENT + 2],
+            flags: self.flags,
+            datalen: 0,
+            data: 0,
+        };
 
-            // Convert response to string
-            let response = if !recv_buf.is_null() && recv_len > 0 {
-                let c_str = CStr::from_ptr(recv_buf);
-                let result = c_str.to_string_lossy().into_owned();
-                ffi::tpfree(recv_buf);
-                result
-            } else {
-                if !recv_buf.is_null() {
-                    ffi::tpfree(recv_buf);
-                }
-                String::new()
-            };
+        copy_ident(&mut raw.usrname, "user name", &self.user_name)?;
+        copy_ident(&mut raw.cltname, "client name", &self.client_name)?;
+        copy_ident(&mut raw.passwd, "password", &self.password)?;
+        copy_ident(&mut raw.grpname, "group", &self.group)?;
 
-            Ok(response)
+        Ok(raw)
+    }
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("user_name", &self.user_name)
+            .field("client_name", &self.client_name)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("group", &self.group)
+            .field("flags", &self.flags)
+            .finish()
+    }
+}
+
+fn copy_ident(dst: &mut [c_char], what: &str, value: &Option<String>) -> Result<(), String> {
+    let Some(value) = value else {
+        return Ok(());
+    };
+
+    if value.len() > ffi::MAXTIDENT {
+        return Err(format!("{} is longer than {} bytes", what, ffi::MAXTIDENT));
+    }
+
+    if value.as_bytes().contains(&0) {
+        return Err(format!("{} contains a NUL byte", what));
+    }
+
+    for (d, b) in dst.iter_mut().zip(value.as_bytes()) {
+        *d = *b as c_char;
+    }
+
+    Ok(())
+}
+
+/// Descriptor of a call sent with `EnduroxClient::acall`
+///
+/// Only valid on the thread (ATMI context) that made the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CallDescriptor(c_int);
+
+impl CallDescriptor {
+    /// Descriptor as returned by tpacall
+    pub fn raw(self) -> c_int {
+        self.0
+    }
+}
+
+/// Enduro/X client
+///
+/// Owns the `AtmiContext` of the thread it was created on; `tpterm` runs
+/// when the client is dropped.
+pub struct EnduroxClient {
+    context: AtmiContext,
+    stats: CallRecorder,
+    #[cfg(feature = "ubf")]
+    availability: AvailabilityCache,
+}
+
+impl EnduroxClient {
+    /// Creates and initializes the client
+    pub fn new() -> Result<Self, String> {
+        Self::with_config(ClientConfig::default())
+    }
+
+    /// Creates and initializes the client with tpinit parameters
+    ///
+    /// Fails if the thread already has a client or `AtmiContext`.
+    pub fn with_config(config: ClientConfig) -> Result<Self, String> {
+        let context =
+            AtmiContext::init_with(&config).map_err(|e| format!("tpinit failed: {}", e))?;
+
+        Ok(EnduroxClient {
+            context,
+            stats: CallRecorder::default(),
+            #[cfg(feature = "ubf")]
+            availability: AvailabilityCache::new(AVAILABILITY_TTL),
+        })
+    }
+
+    /// ATMI context owned by the client
+    pub fn context(&self) -> &AtmiContext {
+        &self.context
+    }
+
+    /// Call counts, error counts and latency percentiles per service
+    ///
+    /// Covers the blocking calls made through this client, including the
+    /// deadline and raw UBF variants; `acall` replies are not timed.
+    pub fn stats(&self) -> ClientStats {
+        self.stats.snapshot()
+    }
+
+    /// Fails with `TpError::NotInitialized` unless this thread can make calls
+    ///
+    /// Without a context `tpcall` fails with a cryptic `TPEPROTO`, e.g. when
+    /// the client is used from a thread other than the one it was created on.
+    /// Only the owner thread is compared: probing with `tpgetctxt` would
+    /// suspend a global transaction of the thread.
+    fn ensure_context(&self) -> Result<(), TpError> {
+        self.context.check_thread()
+    }
+
+    /// Checks that calls can still be made with this client
+    ///
+    /// Fails without a context on this thread, or once a call on this thread
+    /// failed with `TPESYSTEM`/`TPEOS`, e.g. after an Enduro/X restart. Such
+    /// a context stays broken until the client is dropped and recreated.
+    pub fn ping(&self) -> Result<(), TpError> {
+        self.ensure_context()?;
+        match context_lost() {
+            Some(errno) => Err(TpError::from_errno(errno)),
+            None => Ok(()),
         }
     }
 
+    /// Calls a service (blocking)
+    pub fn call_service_blocking(&self, service: &str, data: &str) -> Result<String, String> {
+        self.call_service_blocking_with_flags(service, data, TpFlags::empty())
+    }
+
+    /// Calls a service (blocking) with tpcall `flags`, e.g. `TpFlags::NOTIME`
+    pub fn call_service_blocking_with_flags(
+        &self,
+        service: &str,
+        data: &str,
+        flags: TpFlags,
+    ) -> Result<String, String> {
+        self.ensure_context().map_err(|e| e.to_string())?;
+        self.stats
+            .time(service, || tpcall_string(service, data, flags))
+    }
+
     /// Call service with UBF buffer (blocking)
     pub fn call_service_ubf_blocking(
         &self,
         service: &str,
         buffer_data: &[u8],
     ) -> Result<Vec<u8>, String> {
-        unsafe {
+        self.call_service_ubf_bounded(service, buffer_data, usize::MAX)
+    }
+
+    /// Like `call_service_ubf_blocking`, but fails on a reply whose `Bused`
+    /// exceeds `max_reply_len`, without copying it
+    pub fn call_service_ubf_bounded(
+        &self,
+        service: &str,
+        buffer_data: &[u8],
+        max_reply_len: usize,
+    ) -> Result<Vec<u8>, String> {
+        self.ensure_context().map_err(|e| e.to_string())?;
+
+        self.stats.time(service, || unsafe {
             tplog_info(&format!(
                 "call_service_ubf_blocking: service={}, data_len={}",
                 service,
                 buffer_data.len()
             ));
 
+            let c_service = c_string(service, "Service name")?;
+
             // Allocate UBF buffer for input
             let ubf_type = CString::new("UBF").map_err(|e| e.to_string())?;
             let send_buf =
@@ -152,7 +326,6 @@ impl EnduroxClient {
             ptr::copy_nonoverlapping(buffer_data.as_ptr(), send_buf as *mut u8, buffer_data.len());
 
             // Make synchronous call with tpcall
-            let c_service = CString::new(service).map_err(|e| e.to_string())?;
             let mut recv_buf: *mut c_char = send_buf;
             let mut recv_len: c_long = 0;
 
@@ -179,6 +352,7 @@ impl EnduroxClient {
                     ffi::tpfree(send_buf);
                 }
                 let tperrno = *ffi::_exget_tperrno_addr();
+                record_errno(tperrno);
                 let err_ptr = ffi::tpstrerror(tperrno);
                 let err_msg = if !err_ptr.is_null() {
                     CStr::from_ptr(err_ptr).to_string_lossy().into_owned()
@@ -199,6 +373,16 @@ impl EnduroxClient {
                 0
             };
 
+            if used_size > max_reply_len {
+                ffi::tpfree(recv_buf);
+                let err_msg = format!(
+                    "Reply of {} bytes from {} exceeds the limit of {} bytes",
+                    used_size, service, max_reply_len
+                );
+                tplog_error(&err_msg);
+                return Err(err_msg);
+            }
+
             let response = if !recv_buf.is_null() && used_size > 0 {
                 let data = std::slice::from_raw_parts(recv_buf as *const u8, used_size).to_vec();
                 ffi::tpfree(recv_buf);
@@ -211,9 +395,230 @@ impl EnduroxClient {
             };
 
             Ok(response)
+        })
+    }
+
+    /// Call service with UBF buffer and a millisecond-precision deadline
+    ///
+    /// `tpsblktime` only has whole-second granularity, so the call is issued
+    /// with `tpacall` and the reply is polled with `tpgetrply(TPNOBLOCK)` until
+    /// it arrives or `timeout` elapses, at which point the call descriptor is
+    /// `tpcancel`ed and `TpError::Timeout` is returned.
+    ///
+    /// No helper thread is involved: call descriptors belong to the ATMI
+    /// context of the thread that issued `tpacall`, so the wait has to happen
+    /// on the calling thread. The cost is a wake-up every
+    /// `DEADLINE_POLL_INTERVAL` while the call is outstanding. A late reply to
+    /// a cancelled call is discarded by Enduro/X.
+    #[cfg(feature = "ubf")]
+    pub fn call_ubf_deadline(
+        &self,
+        service: &str,
+        buf: &UbfBuffer,
+        timeout: Duration,
+    ) -> Result<UbfBuffer, TpError> {
+        self.ensure_context()?;
+        self.stats
+            .time(service, || self.ubf_deadline(service, buf, timeout))
+    }
+
+    #[cfg(feature = "ubf")]
+    fn ubf_deadline(
+        &self,
+        service: &str,
+        buf: &UbfBuffer,
+        timeout: Duration,
+    ) -> Result<UbfBuffer, TpError> {
+        let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+        let deadline = Instant::now() + timeout;
+
+        tplog_info(&format!(
+            "call_ubf_deadline: service={}, timeout={:?}",
+            service, timeout
+        ));
+
+        let cd = unsafe { ffi::tpacall(c_service.as_ptr(), buf.as_ptr(), 0, 0) };
+        if cd == -1 {
+            let err = TpError::last();
+            tplog_error(&format!("tpacall failed: {}", err));
+            return Err(err);
+        }
+
+        loop {
+            let mut reply_cd = cd;
+            let mut recv_buf: *mut c_char = ptr::null_mut();
+            let mut recv_len: c_long = 0;
+
+            let ret = unsafe {
+                ffi::tpgetrply(
+                    &mut reply_cd,
+                    &mut recv_buf,
+                    &mut recv_len,
+                    TpFlags::NOBLOCK.raw(),
+                )
+            };
+
+            if ret != -1 {
+                return unsafe { reply_buffer(recv_buf) };
+            }
+
+            if !recv_buf.is_null() {
+                unsafe { ffi::tpfree(recv_buf) };
+            }
+
+            let errno = tperrno();
+            if errno != ffi::TPEBLOCK {
+                let err = TpError::from_errno(errno);
+                tplog_error(&format!("tpgetrply failed: {}", err));
+                return Err(err);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                unsafe { ffi::tpcancel(cd) };
+                tplog_error(&format!(
+                    "call_ubf_deadline: {} timed out after {:?}",
+                    service, timeout
+                ));
+                return Err(TpError::Timeout);
+            }
+
+            thread::sleep(DEADLINE_POLL_INTERVAL.min(deadline - now));
         }
     }
 
+    /// Sends `buf` to `service` without waiting for the reply (tpacall)
+    ///
+    /// Collect the reply with `drain_replies`, or drop it with `cancel`.
+    #[cfg(feature = "ubf")]
+    pub fn acall(&self, service: &str, buf: &UbfBuffer) -> Result<CallDescriptor, TpError> {
+        self.ensure_context()?;
+        let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+
+        let cd = unsafe { ffi::tpacall(c_service.as_ptr(), buf.as_ptr(), 0, 0) };
+        if cd == -1 {
+            let err = TpError::last();
+            tplog_error(&format!("tpacall to {} failed: {}", service, err));
+            return Err(err);
+        }
+        Ok(CallDescriptor(cd))
+    }
+
+    /// Cancels an outstanding call, Enduro/X discards its reply (tpcancel)
+    pub fn cancel(&self, cd: CallDescriptor) -> Result<(), TpError> {
+        self.ensure_context()?;
+        if unsafe { ffi::tpcancel(cd.0) } == -1 {
+            return Err(TpError::last());
+        }
+        Ok(())
+    }
+
+    /// Collects the replies of `descriptors` until all arrived or `timeout`
+    /// elapses
+    ///
+    /// Replies are taken in arrival order with `tpgetrply(TPGETANY)`, polled
+    /// like `call_ubf_deadline`. Calls still outstanding at the deadline are
+    /// cancelled and get `TpError::Timeout`. Results come back in the order
+    /// of `descriptors`, each with the raw reply bytes.
+    ///
+    /// `TPGETANY` picks up replies to any call of this thread, so other
+    /// outstanding calls should be part of `descriptors`; their replies are
+    /// discarded otherwise.
+    #[cfg(feature = "ubf")]
+    pub fn drain_replies(
+        &self,
+        descriptors: Vec<CallDescriptor>,
+        timeout: Duration,
+    ) -> Vec<(CallDescriptor, Result<Vec<u8>, TpError>)> {
+        if let Err(e) = self.ensure_context() {
+            return descriptors
+                .into_iter()
+                .map(|cd| (cd, Err(e.clone())))
+                .collect();
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut pending = descriptors.clone();
+        let mut results = HashMap::new();
+
+        while !pending.is_empty() {
+            let mut reply_cd: c_int = 0;
+            let mut recv_buf: *mut c_char = ptr::null_mut();
+            let mut recv_len: c_long = 0;
+
+            let ret = unsafe {
+                ffi::tpgetrply(
+                    &mut reply_cd,
+                    &mut recv_buf,
+                    &mut recv_len,
+                    (TpFlags::GETANY | TpFlags::NOBLOCK).raw(),
+                )
+            };
+            let cd = CallDescriptor(reply_cd);
+
+            let result = if ret != -1 {
+                Ok(unsafe { take_reply_bytes(recv_buf, recv_len) })
+            } else {
+                if !recv_buf.is_null() {
+                    unsafe { ffi::tpfree(recv_buf) };
+                }
+
+                let errno = tperrno();
+                if errno == ffi::TPEBLOCK {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    thread::sleep(DEADLINE_POLL_INTERVAL.min(deadline - now));
+                    continue;
+                }
+
+                let err = TpError::from_errno(errno);
+                if !pending.contains(&cd) {
+                    // Not tied to one of our calls, nothing more will arrive
+                    tplog_error(&format!("drain_replies: tpgetrply failed: {}", err));
+                    for cd in pending.drain(..) {
+                        unsafe { ffi::tpcancel(cd.0) };
+                        results.insert(cd, Err(err.clone()));
+                    }
+                    break;
+                }
+                Err(err)
+            };
+
+            match pending.iter().position(|p| *p == cd) {
+                Some(index) => {
+                    pending.swap_remove(index);
+                    results.insert(cd, result);
+                }
+                None => tplog_warn(&format!(
+                    "drain_replies: discarding reply to unknown descriptor {}",
+                    reply_cd
+                )),
+            }
+        }
+
+        if !pending.is_empty() {
+            tplog_error(&format!(
+                "drain_replies: cancelling {} call(s) still outstanding after {:?}",
+                pending.len(),
+                timeout
+            ));
+        }
+        for cd in pending {
+            unsafe { ffi::tpcancel(cd.0) };
+            results.insert(cd, Err(TpError::Timeout));
+        }
+
+        descriptors
+            .into_iter()
+            .map(|cd| {
+                let result = results.remove(&cd).unwrap_or(Err(TpError::Timeout));
+                (cd, result)
+            })
+            .collect()
+    }
+
     /// Call service with raw buffer (for UBF)
     ///
     /// # Safety
@@ -224,10 +629,12 @@ impl EnduroxClient {
         service: &str,
         send_buf: *mut c_char,
     ) -> Result<*mut c_char, String> {
+        self.ensure_context().map_err(|e| e.to_string())?;
+
         unsafe {
             tplog_info(&format!("call_service_raw: service={}", service));
 
-            let c_service = CString::new(service).map_err(|e| e.to_string())?;
+            let c_service = c_string(service, "Service name")?;
             let mut recv_buf: *mut c_char = send_buf;
             let mut recv_len: c_long = 0;
 
@@ -258,14 +665,593 @@ impl EnduroxClient {
             Ok(recv_buf)
         }
     }
+
+    /// Call service with a UBF buffer, receiving the reply in place
+    ///
+    /// Safe counterpart of `call_service_raw`. The send buffer is handed to
+    /// `tpcall` as the receive buffer too; Enduro/X may grow it with
+    /// `tprealloc`, which frees the original, so afterwards only the returned
+    /// pointer is valid. It is owned exactly once: by the returned `UbfBuffer`
+    /// on success, and freed here on error.
+    #[cfg(feature = "ubf")]
+    pub fn call_raw_ubf(&self, service: &str, send: UbfBuffer) -> Result<UbfBuffer, TpError> {
+        self.call_raw_ubf_with_flags(service, send, TpFlags::empty())
+    }
+
+    /// Call service with a UBF buffer without the block timeout
+    ///
+    /// Passes `TPNOTIME`, for services that legitimately run longer than
+    /// `NDRX_TOUT`. Only the block timeout is disabled; a global transaction
+    /// still times out after its own transaction timeout.
+    #[cfg(feature = "ubf")]
+    pub fn call_ubf_no_timeout(
+        &self,
+        service: &str,
+        buf: &UbfBuffer,
+    ) -> Result<UbfBuffer, TpError> {
+        let send = UbfBuffer::from_bytes(buf.as_bytes()).map_err(TpError::InvalidArgument)?;
+        self.call_raw_ubf_with_flags(service, send, NO_TIMEOUT_FLAGS)
+    }
+
+    /// Call service with a UBF buffer without waiting for queue space
+    ///
+    /// Passes `TPNOBLOCK`: when the service queue is full the call fails at
+    /// once with `TpError::WouldBlock`, so the caller can back off or shed
+    /// the request. For a service behind a bridge (`tpbridge`), the flag
+    /// applies to the local queue only: a call accepted by the bridge still
+    /// waits for the remote domain, up to the block timeout.
+    #[cfg(feature = "ubf")]
+    pub fn call_ubf_nonblock(&self, service: &str, buf: &UbfBuffer) -> Result<UbfBuffer, TpError> {
+        let send = UbfBuffer::from_bytes(buf.as_bytes()).map_err(TpError::InvalidArgument)?;
+        self.call_raw_ubf_with_flags(service, send, TpFlags::NOBLOCK)
+    }
+
+    /// `call_raw_ubf` with tpcall `flags`
+    #[cfg(feature = "ubf")]
+    pub fn call_raw_ubf_with_flags(
+        &self,
+        service: &str,
+        send: UbfBuffer,
+        flags: TpFlags,
+    ) -> Result<UbfBuffer, TpError> {
+        self.ensure_context()?;
+        self.stats
+            .time(service, || tpcall_ubf(service, send, flags))
+    }
+
+    /// Call service with a CARRAY buffer, returning the CARRAY reply
+    ///
+    /// The reply is read to its full tpcall length, NUL bytes included, so
+    /// binary replies of any size come back whole. A reply of another type
+    /// fails with `TPEOTYPE`.
+    #[cfg(feature = "ubf")]
+    pub fn call_carray(&self, service: &str, data: &[u8]) -> Result<Vec<u8>, TpError> {
+        let (reply, _) = self.call_typed_reply(service, TypedBuffer::Carray(data.to_vec()))?;
+        match reply {
+            TypedBuffer::Carray(bytes) => Ok(bytes),
+            other => Err(TpError::Xatmi {
+                errno: ffi::TPEOTYPE,
+                message: format!("Expected a CARRAY reply, got {}", other.type_name()),
+            }),
+        }
+    }
+
+    /// Call service with a buffer of any type, returning the reply with its type
+    ///
+    /// The reply variant comes from `tptypes` on the received buffer, so a
+    /// service answering STRING to a UBF request is not misread. Also returns
+    /// the user return code (`tpurcode`) the service passed to `tpreturn`.
+    /// Buffer ownership is as in `call_raw_ubf`.
+    #[cfg(feature = "ubf")]
+    pub fn call_typed_reply(
+        &self,
+        service: &str,
+        send: TypedBuffer,
+    ) -> Result<(TypedBuffer, i64), TpError> {
+        self.ensure_context()?;
+        self.stats
+            .time(service, || tpcall_typed(service, send, TpFlags::empty()))
+    }
+
+    /// Opens a conversation with `service` and receives the buffers it streams
+    ///
+    /// The service gets send control (`TPRECVONLY` here) and is expected to
+    /// send rows with `server::send_ubf_stream` before its `tpreturn`. The
+    /// stream ends after the last row; a failed service yields one error.
+    /// Dropping the stream early disconnects the conversation.
+    #[cfg(feature = "ubf")]
+    pub fn stream_ubf(&self, service: &str, request: &UbfBuffer) -> Result<UbfStream<'_>, TpError> {
+        self.ensure_context()?;
+        let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+
+        tplog_info(&format!("stream_ubf: service={}", service));
+
+        let cd = unsafe {
+            ffi::tpconnect(
+                c_service.as_ptr(),
+                request.as_ptr(),
+                0,
+                TpFlags::RECVONLY.raw(),
+            )
+        };
+        if cd == -1 {
+            let err = TpError::last();
+            tplog_error(&format!("tpconnect failed: {}", err));
+            return Err(err);
+        }
+
+        Ok(UbfStream {
+            cd,
+            open: true,
+            done: false,
+            _client: PhantomData,
+        })
+    }
+
+    /// Checks whether any server currently advertises `service`
+    ///
+    /// Looks the service up in the `.TMIB` service table (T_SERVICE class),
+    /// so `tpadmsv` must be running. The answer is cached for
+    /// `AVAILABILITY_TTL`. If the lookup itself fails the service is reported
+    /// available and the real call gets to report the error.
+    #[cfg(feature = "ubf")]
+    pub fn is_service_available(&self, service: &str) -> bool {
+        if let Some(available) = self.availability.get(service) {
+            return available;
+        }
+
+        match self.lookup_service_state(service) {
+            Ok(available) => {
+                self.availability.insert(service, available);
+                available
+            }
+            Err(e) => {
+                tplog_warn(&format!(
+                    "is_service_available: lookup of {} failed: {}",
+                    service, e
+                ));
+                true
+            }
+        }
+    }
+
+    /// Pages through the T_SERVICE objects until `service` is found
+    ///
+    /// Stopping early leaves the cursor to expire in tpadmsv.
+    #[cfg(feature = "ubf")]
+    fn lookup_service_state(&self, service: &str) -> Result<bool, String> {
+        let mut request = UbfBuffer::new(1024)?;
+        request.add_string(tmib::TA_CLASS, "T_SERVICE")?;
+        request.add_string(tmib::TA_OPERATION, "GET")?;
+
+        loop {
+            let reply = self
+                .call_raw_ubf(".TMIB", request)
+                .map_err(|e| e.to_string())?;
+
+            let error = reply.get_long(tmib::TA_ERROR, 0).unwrap_or(0);
+            if error != 0 {
+                return Err(format!(".TMIB returned TA_ERROR {}", error));
+            }
+
+            for occ in 0..reply.occurrences(tmib::TA_SERVICENAME)? {
+                if reply.get_string(tmib::TA_SERVICENAME, occ)? == service {
+                    return Ok(reply.get_string(tmib::TA_STATE, occ)? == "ACT");
+                }
+            }
+
+            if reply.get_long(tmib::TA_MORE, 0).unwrap_or(0) <= 0 {
+                return Ok(false);
+            }
+
+            request = UbfBuffer::new(1024)?;
+            request.add_string(tmib::TA_CLASS, "T_SERVICE")?;
+            request.add_string(tmib::TA_OPERATION, "GETNEXT")?;
+            request.add_string(tmib::TA_CURSOR, &reply.get_string(tmib::TA_CURSOR, 0)?)?;
+        }
+    }
 }
 
-impl Drop for EnduroxClient {
+/// Buffers streamed by a conversational service, see `EnduroxClient::stream_ubf`
+#[cfg(feature = "ubf")]
+pub struct UbfStream<'a> {
+    cd: c_int,
+    /// The conversation still needs `tpdiscon`
+    open: bool,
+    done: bool,
+    _client: PhantomData<&'a EnduroxClient>,
+}
+
+#[cfg(feature = "ubf")]
+impl Iterator for UbfStream<'_> {
+    type Item = Result<UbfBuffer, TpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut recv_buf: *mut c_char = ptr::null_mut();
+        let mut recv_len: c_long = 0;
+        let mut revent: c_long = 0;
+
+        let ret = unsafe { ffi::tprecv(self.cd, &mut recv_buf, &mut recv_len, 0, &mut revent) };
+        if ret != -1 {
+            return Some(unsafe { reply_buffer(recv_buf) });
+        }
+
+        // The tpreturn reply of the service, if any, is not part of the stream
+        if !recv_buf.is_null() {
+            unsafe { ffi::tpfree(recv_buf) };
+        }
+        self.done = true;
+
+        let errno = tperrno();
+        if errno != ffi::TPEEVENT {
+            let err = TpError::from_errno(errno);
+            tplog_error(&format!("tprecv failed: {}", err));
+            return Some(Err(err));
+        }
+
+        // Every event but TPEV_SENDONLY ends the conversation
+        self.open = revent == ffi::TPEV_SENDONLY;
+        conversation_event(revent).map(Err)
+    }
+}
+
+#[cfg(feature = "ubf")]
+impl Drop for UbfStream<'_> {
     fn drop(&mut self) {
-        if self.initialized {
-            unsafe {
-                ffi::tpterm();
+        if self.open {
+            unsafe { ffi::tpdiscon(self.cd) };
+        }
+    }
+}
+
+/// tpcall with a STRING buffer on the current thread's context
+pub(crate) fn tpcall_string(service: &str, data: &str, flags: TpFlags) -> Result<String, String> {
+    unsafe {
+        tplog_info(&format!(
+            "call_service_blocking: service={}, data_len={}, flags={:#x}",
+            service,
+            data.len(),
+            flags.raw()
+        ));
+
+        // Convert before allocating so a bad argument can't leak the buffer
+        let c_data = c_string(data, "Request data")?;
+        let c_service = c_string(service, "Service name")?;
+
+        // Allocate STRING buffer for input
+        let string_type = CString::new("STRING").map_err(|e| e.to_string())?;
+        let send_buf = ffi::tpalloc(
+            string_type.as_ptr(),
+            ptr::null(),
+            (data.len() + 1) as c_long,
+        );
+
+        if send_buf.is_null() {
+            let tperrno = *ffi::_exget_tperrno_addr();
+            let err_msg = format!("Failed to allocate send buffer, tperrno={}", tperrno);
+            tplog_error(&err_msg);
+            return Err(err_msg);
+        }
+
+        // Copy data to buffer
+        ptr::copy_nonoverlapping(c_data.as_ptr(), send_buf, data.len() + 1);
+
+        // Make synchronous call with tpcall
+        let mut recv_buf: *mut c_char = ptr::null_mut();
+        let mut recv_len: c_long = 0;
+
+        tplog_info(&format!("Calling tpcall for service: {}", service));
+
+        let ret = ffi::tpcall(
+            c_service.as_ptr(),
+            send_buf,
+            (data.len() + 1) as c_long,
+            &mut recv_buf,
+            &mut recv_len,
+            flags.raw(),
+        );
+
+        ffi::tpfree(send_buf);
+
+        tplog_info(&format!(
+            "tpcall returned: ret={}, recv_buf={:?}, recv_len={}",
+            ret, recv_buf, recv_len
+        ));
+
+        if ret == -1 {
+            if !recv_buf.is_null() {
+                ffi::tpfree(recv_buf);
+            }
+            let tperrno = *ffi::_exget_tperrno_addr();
+            record_errno(tperrno);
+            let err_ptr = ffi::tpstrerror(tperrno);
+            let err_msg = if !err_ptr.is_null() {
+                CStr::from_ptr(err_ptr).to_string_lossy().into_owned()
+            } else {
+                "Unknown error".to_string()
+            };
+            tplog_error(&format!(
+                "tpcall failed: ret={}, tperrno={}, msg={}",
+                ret, tperrno, err_msg
+            ));
+            return Err(format!("tpcall failed: {}: {}", tperrno, err_msg));
+        }
+
+        // Convert response to string
+        let response = if !recv_buf.is_null() && recv_len > 0 {
+            let result = reply_string(recv_buf, recv_len);
+            ffi::tpfree(recv_buf);
+            result
+        } else {
+            if !recv_buf.is_null() {
+                ffi::tpfree(recv_buf);
+            }
+            String::new()
+        };
+
+        Ok(response)
+    }
+}
+
+/// Text of a STRING reply, read within its `recv_len`
+///
+/// The reply length includes the terminating NUL; a buffer without one is
+/// not scanned past its end.
+unsafe fn reply_string(recv_buf: *const c_char, recv_len: c_long) -> String {
+    let bytes = std::slice::from_raw_parts(recv_buf as *const u8, recv_len.max(0) as usize);
+    let text = bytes.split(|&b| b == 0).next().unwrap_or_default();
+    String::from_utf8_lossy(text).into_owned()
+}
+
+/// Copies a reply out of its XATMI buffer and frees the buffer
+///
+/// UBF replies are sized with `Bused`, their `len` may be 0.
+#[cfg(feature = "ubf")]
+unsafe fn take_reply_bytes(recv_buf: *mut c_char, recv_len: c_long) -> Vec<u8> {
+    if recv_buf.is_null() {
+        return Vec::new();
+    }
+
+    let len = if buffer_type(recv_buf).as_deref() == Some("UBF") {
+        ffi::Bused(recv_buf).max(0) as usize
+    } else {
+        recv_len.max(0) as usize
+    };
+    let bytes = std::slice::from_raw_parts(recv_buf as *const u8, len).to_vec();
+    ffi::tpfree(recv_buf);
+    bytes
+}
+
+/// tpcall with a UBF buffer on the current thread's context, see
+/// `EnduroxClient::call_raw_ubf` for buffer ownership
+#[cfg(feature = "ubf")]
+pub(crate) fn tpcall_ubf(
+    service: &str,
+    send: UbfBuffer,
+    flags: TpFlags,
+) -> Result<UbfBuffer, TpError> {
+    tpcall_ubf_via(ffi::tpcall, service, send, flags)
+}
+
+#[cfg(feature = "ubf")]
+type Tpcall = unsafe extern "C" fn(
+    *const c_char,
+    *mut c_char,
+    c_long,
+    *mut *mut c_char,
+    *mut c_long,
+    c_long,
+) -> c_int;
+
+// `tpcall_ubf` with the tpcall passed in; unit tests can't reach a service
+#[cfg(feature = "ubf")]
+fn tpcall_ubf_via(
+    tpcall: Tpcall,
+    service: &str,
+    send: UbfBuffer,
+    flags: TpFlags,
+) -> Result<UbfBuffer, TpError> {
+    let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+
+    tplog_info(&format!(
+        "call_raw_ubf: service={}, flags={:#x}",
+        service,
+        flags.raw()
+    ));
+
+    let send_buf = send.into_raw();
+    let mut recv_buf: *mut c_char = send_buf;
+    let mut recv_len: c_long = 0;
+
+    let ret = unsafe {
+        tpcall(
+            c_service.as_ptr(),
+            send_buf,
+            0, // 0 for UBF - length determined automatically
+            &mut recv_buf,
+            &mut recv_len,
+            flags.raw(),
+        )
+    };
+
+    if ret == -1 {
+        let err = TpError::last();
+        if !recv_buf.is_null() {
+            unsafe { ffi::tpfree(recv_buf) };
+        }
+        tplog_error(&format!("tpcall failed: {}", err));
+        return Err(err);
+    }
+
+    unsafe { reply_buffer(recv_buf) }
+}
+
+/// tpcall with a buffer of any type on the current thread's context
+#[cfg(feature = "ubf")]
+pub(crate) fn tpcall_typed(
+    service: &str,
+    send: TypedBuffer,
+    flags: TpFlags,
+) -> Result<(TypedBuffer, i64), TpError> {
+    let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+
+    tplog_info(&format!(
+        "call_typed_reply: service={}, type={}, flags={:#x}",
+        service,
+        send.type_name(),
+        flags.raw()
+    ));
+
+    let (send_buf, send_len) = send.into_raw().map_err(TpError::InvalidArgument)?;
+    let mut recv_buf: *mut c_char = send_buf;
+    let mut recv_len: c_long = 0;
+
+    let ret = unsafe {
+        ffi::tpcall(
+            c_service.as_ptr(),
+            send_buf,
+            send_len,
+            &mut recv_buf,
+            &mut recv_len,
+            flags.raw(),
+        )
+    };
+
+    if ret == -1 {
+        let err = TpError::last();
+        if !recv_buf.is_null() {
+            unsafe { ffi::tpfree(recv_buf) };
+        }
+        tplog_error(&format!("tpcall failed: {}", err));
+        return Err(err);
+    }
+
+    #[allow(clippy::unnecessary_cast)] // c_long is 32-bit on some targets
+    let urcode = unsafe { *ffi::_exget_tpurcode_addr() } as i64;
+    let reply = unsafe { TypedBuffer::from_reply(recv_buf, recv_len) }?;
+    Ok((reply, urcode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_timeout_flags() {
+        // xatmi.h: #define TPNOTIME 0x00000020
+        assert_eq!(ffi::TPNOTIME, 0x20);
+        assert_eq!(NO_TIMEOUT_FLAGS.raw(), ffi::TPNOTIME);
+    }
+
+    #[test]
+    #[cfg(feature = "ubf")]
+    fn test_availability_cache_expires() {
+        let cache = AvailabilityCache::new(Duration::from_millis(20));
+        assert_eq!(cache.get("ECHO"), None);
+
+        cache.insert("ECHO", true);
+        cache.insert("MISSING", false);
+        assert_eq!(cache.get("ECHO"), Some(true));
+        assert_eq!(cache.get("MISSING"), Some(false));
+
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(cache.get("ECHO"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "ubf")]
+    fn test_would_block_is_distinct() {
+        // Full service queue: the call fails at once with TPEBLOCK
+        unsafe extern "C" fn fake_tpcall(
+            _service: *const c_char,
+            _send_buf: *mut c_char,
+            _send_len: c_long,
+            _recv_buf: *mut *mut c_char,
+            _recv_len: *mut c_long,
+            flags: c_long,
+        ) -> c_int {
+            assert_eq!(flags, TpFlags::NOBLOCK.raw());
+            *(ffi::_exget_tperrno_addr() as *mut c_int) = ffi::TPEBLOCK;
+            -1
+        }
+
+        let send = UbfBuffer::new(128).unwrap();
+        let err = tpcall_ubf_via(fake_tpcall, "BUSY", send, TpFlags::NOBLOCK).unwrap_err();
+        assert_eq!(err, TpError::WouldBlock);
+        assert!(!err.is_context_lost());
+        assert!(matches!(
+            TpError::from_errno(ffi::TPEEVENT),
+            TpError::Xatmi { .. }
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "ubf")]
+    fn test_conversation_events() {
+        assert_eq!(conversation_event(ffi::TPEV_SVCSUCC), None);
+
+        match conversation_event(ffi::TPEV_SVCFAIL) {
+            Some(TpError::Xatmi { errno, message }) => {
+                assert_eq!(errno, ffi::TPEEVENT);
+                assert!(message.contains("TPEV_SVCFAIL"));
             }
+            other => panic!("unexpected {:?}", other),
         }
+        assert!(conversation_event(ffi::TPEV_DISCONIMM).is_some());
+    }
+
+    #[test]
+    fn test_reply_string_stays_within_len() {
+        let reply = b"Hello\0stale";
+        let text = unsafe { reply_string(reply.as_ptr() as *const c_char, reply.len() as c_long) };
+        assert_eq!(text, "Hello");
+
+        // No NUL within the reply length
+        let text = unsafe { reply_string(reply.as_ptr() as *const c_char, 3) };
+        assert_eq!(text, "Hel");
+    }
+
+    #[test]
+    fn test_tpinit_layout() {
+        let long = std::mem::size_of::<c_long>();
+        assert_eq!(
+            std::mem::size_of::<ffi::TpInitRaw>(),
+            4 * (ffi::MAXTIDENT + 2) + 3 * long
+        );
+    }
+
+    #[test]
+    fn test_client_config_to_raw() {
+        let config = ClientConfig::new()
+            .client_name("gateway")
+            .user_name("app")
+            .password("secret")
+            .group("grp1")
+            .flags(ffi::TPMULTICONTEXTS);
+
+        let raw = config.to_raw().unwrap();
+        let cltname = unsafe { CStr::from_ptr(raw.cltname.as_ptr()) };
+        let usrname = unsafe { CStr::from_ptr(raw.usrname.as_ptr()) };
+        let grpname = unsafe { CStr::from_ptr(raw.grpname.as_ptr()) };
+        assert_eq!(cltname.to_str().unwrap(), "gateway");
+        assert_eq!(usrname.to_str().unwrap(), "app");
+        assert_eq!(grpname.to_str().unwrap(), "grp1");
+        assert_eq!(raw.flags, ffi::TPMULTICONTEXTS);
+        assert!(!format!("{:?}", config).contains("secret"));
+    }
+
+    #[test]
+    fn test_client_config_default_and_limits() {
+        assert!(ClientConfig::default().is_default());
+
+        let long_name = "x".repeat(ffi::MAXTIDENT + 1);
+        assert!(ClientConfig::new()
+            .client_name(&long_name)
+            .to_raw()
+            .is_err());
     }
 }
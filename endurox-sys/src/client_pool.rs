@@ -0,0 +1,170 @@
+//! Client slot that replaces dead ATMI contexts on checkout
+//!
+//! An ATMI context belongs to the thread that ran `tpinit`, so a pool hands
+//! out the one client of the current thread; keep it in a `thread_local!`.
+//! Once a call fails with `TPESYSTEM`/`TPEOS`, e.g. because Enduro/X was
+//! restarted, the context stays broken. `ClientPool::checkout` pings the
+//! client first and replaces a dead one with a fresh `tpinit`, so worker
+//! threads recover without being restarted.
+
+use crate::client::EnduroxClient;
+use crate::error::TpError;
+use crate::{tplog_info, tplog_warn};
+use std::cell::{Cell, Ref, RefCell};
+
+/// Health check run on every checkout
+pub trait Ping {
+    fn ping(&self) -> Result<(), TpError>;
+}
+
+impl Ping for EnduroxClient {
+    fn ping(&self) -> Result<(), TpError> {
+        EnduroxClient::ping(self)
+    }
+}
+
+type Connect<C> = Box<dyn Fn() -> Result<C, String>>;
+
+/// Current thread's client, recreated when its context dies
+pub struct ClientPool<C: Ping = EnduroxClient> {
+    connect: Connect<C>,
+    client: RefCell<Option<C>>,
+    recycled: Cell<u64>,
+}
+
+impl ClientPool<EnduroxClient> {
+    /// Pool creating clients with `EnduroxClient::new`
+    pub fn atmi() -> Self {
+        Self::new(EnduroxClient::new)
+    }
+}
+
+impl<C: Ping> ClientPool<C> {
+    /// Pool creating clients with `connect`, on first checkout
+    pub fn new<F>(connect: F) -> Self
+    where
+        F: Fn() -> Result<C, String> + 'static,
+    {
+        ClientPool {
+            connect: Box::new(connect),
+            client: RefCell::new(None),
+            recycled: Cell::new(0),
+        }
+    }
+
+    /// Returns a client that passed `ping`, reconnecting if needed
+    ///
+    /// The dead client is dropped (tpterm) before the new one is created.
+    /// Don't check out again while holding the returned reference.
+    pub fn checkout(&self) -> Result<Ref<'_, C>, String> {
+        {
+            let mut slot = self.client.borrow_mut();
+
+            if let Some(Err(e)) = slot.as_ref().map(Ping::ping) {
+                tplog_warn(&format!("Replacing dead ATMI client: {}", e));
+                *slot = None;
+                self.recycled.set(self.recycled.get() + 1);
+            }
+
+            if slot.is_none() {
+                *slot = Some((self.connect)()?);
+                tplog_info("ATMI client connected");
+            }
+        }
+
+        Ok(Ref::map(self.client.borrow(), |slot| {
+            slot.as_ref().expect("client connected above")
+        }))
+    }
+
+    /// Number of dead clients replaced so far
+    pub fn recycled(&self) -> u64 {
+        self.recycled.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ffi;
+    use std::rc::Rc;
+
+    struct FakeClient {
+        id: u32,
+        alive: Rc<Cell<bool>>,
+    }
+
+    impl Ping for FakeClient {
+        fn ping(&self) -> Result<(), TpError> {
+            if self.alive.get() {
+                Ok(())
+            } else {
+                Err(TpError::Xatmi {
+                    errno: ffi::TPESYSTEM,
+                    message: "System error".to_string(),
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn test_dead_client_replaced_on_checkout() {
+        let connects = Rc::new(Cell::new(0));
+        let alive = Rc::new(Cell::new(true));
+
+        let pool = {
+            let (connects, alive) = (connects.clone(), alive.clone());
+            ClientPool::new(move || {
+                connects.set(connects.get() + 1);
+                alive.set(true);
+                Ok(FakeClient {
+                    id: connects.get(),
+                    alive: alive.clone(),
+                })
+            })
+        };
+
+        assert_eq!(pool.checkout().unwrap().id, 1);
+        assert_eq!(pool.checkout().unwrap().id, 1);
+        assert_eq!(pool.recycled(), 0);
+
+        // Context dies, e.g. after an Enduro/X restart
+        alive.set(false);
+        assert_eq!(pool.checkout().unwrap().id, 2);
+        assert_eq!(pool.recycled(), 1);
+        assert_eq!(connects.get(), 2);
+    }
+
+    #[test]
+    fn test_failed_connect_retried_on_next_checkout() {
+        let attempts = Rc::new(Cell::new(0));
+        let pool = {
+            let attempts = attempts.clone();
+            ClientPool::new(move || {
+                attempts.set(attempts.get() + 1);
+                if attempts.get() == 1 {
+                    Err("tpinit failed".to_string())
+                } else {
+                    Ok(FakeClient {
+                        id: attempts.get(),
+                        alive: Rc::new(Cell::new(true)),
+                    })
+                }
+            })
+        };
+
+        assert!(pool.checkout().is_err());
+        assert_eq!(pool.checkout().unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_context_lost_errors() {
+        let lost = TpError::Xatmi {
+            errno: ffi::TPEOS,
+            message: "Operating system error".to_string(),
+        };
+        assert!(lost.is_context_lost());
+        assert!(TpError::NotInitialized.is_context_lost());
+        assert!(!TpError::Timeout.is_context_lost());
+    }
+}
@@ -0,0 +1,241 @@
+//! Per-service call statistics kept by `EnduroxClient`
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Sub-buckets per power of two; latencies are recorded within 1/8 (12.5%)
+const SUB_BUCKETS: usize = 8;
+
+/// Powers of two covered, in microseconds: up to ~2^40 us (~12 days)
+const EXPONENTS: usize = 40;
+
+const LATENCY_BUCKETS: usize = SUB_BUCKETS * (EXPONENTS + 1);
+
+/// Calls, errors and latency of one service as seen by the client
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceCallStats {
+    /// Calls made, failed ones included
+    pub calls: u64,
+    /// Calls that returned an error
+    pub errors: u64,
+    /// Median latency
+    pub p50: Duration,
+    /// 90th percentile latency
+    pub p90: Duration,
+    /// 99th percentile latency
+    pub p99: Duration,
+    /// Slowest call
+    pub max: Duration,
+}
+
+/// Snapshot of `EnduroxClient::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientStats {
+    pub services: HashMap<String, ServiceCallStats>,
+}
+
+impl ClientStats {
+    /// Statistics of `service`, `None` if it was never called
+    pub fn service(&self, service: &str) -> Option<&ServiceCallStats> {
+        self.services.get(service)
+    }
+}
+
+/// Log-linear latency histogram, like a coarse HDR histogram
+///
+/// Values are bucketed by power of two of their microseconds, each split
+/// into `SUB_BUCKETS` linear steps, so percentiles are within 12.5%.
+struct LatencySketch {
+    buckets: Vec<AtomicU64>,
+    max_us: AtomicU64,
+}
+
+impl LatencySketch {
+    fn new() -> Self {
+        LatencySketch {
+            buckets: (0..LATENCY_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            max_us: AtomicU64::new(0),
+        }
+    }
+
+    fn bucket(us: u64) -> usize {
+        if us < SUB_BUCKETS as u64 {
+            return us as usize;
+        }
+        let exponent = 63 - us.leading_zeros() as usize;
+        let sub = (us >> (exponent - 3)) as usize - SUB_BUCKETS;
+        ((exponent - 2) * SUB_BUCKETS + sub).min(LATENCY_BUCKETS - 1)
+    }
+
+    /// Largest value counted in `bucket`
+    fn upper_bound(bucket: usize) -> u64 {
+        if bucket < SUB_BUCKETS {
+            return bucket as u64;
+        }
+        let exponent = bucket / SUB_BUCKETS + 2;
+        let sub = (bucket % SUB_BUCKETS + SUB_BUCKETS) as u64;
+        ((sub + 1) << (exponent - 3)) - 1
+    }
+
+    fn record(&self, latency: Duration) {
+        let us = latency.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[Self::bucket(us)].fetch_add(1, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    /// Latency below which `quantile` of the `total` calls fall
+    fn percentile(&self, quantile: f64, total: u64) -> Duration {
+        if total == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((total as f64 * quantile).ceil() as u64).max(1);
+        let max_us = self.max_us.load(Ordering::Relaxed);
+
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count.load(Ordering::Relaxed);
+            if seen >= rank {
+                return Duration::from_micros(Self::upper_bound(bucket).min(max_us));
+            }
+        }
+        Duration::from_micros(max_us)
+    }
+}
+
+struct ServiceCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    latency: LatencySketch,
+}
+
+impl ServiceCounters {
+    fn new() -> Self {
+        ServiceCounters {
+            calls: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            latency: LatencySketch::new(),
+        }
+    }
+
+    fn load(&self) -> ServiceCallStats {
+        let calls = self.calls.load(Ordering::Relaxed);
+        ServiceCallStats {
+            calls,
+            errors: self.errors.load(Ordering::Relaxed),
+            p50: self.latency.percentile(0.50, calls),
+            p90: self.latency.percentile(0.90, calls),
+            p99: self.latency.percentile(0.99, calls),
+            max: Duration::from_micros(self.latency.max_us.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters behind `EnduroxClient::stats`
+#[derive(Default)]
+pub(crate) struct CallRecorder {
+    services: RwLock<HashMap<String, Arc<ServiceCounters>>>,
+}
+
+impl CallRecorder {
+    fn counters(&self, service: &str) -> Arc<ServiceCounters> {
+        if let Some(counters) = self.services.read().unwrap().get(service) {
+            return counters.clone();
+        }
+
+        self.services
+            .write()
+            .unwrap()
+            .entry(service.to_string())
+            .or_insert_with(|| Arc::new(ServiceCounters::new()))
+            .clone()
+    }
+
+    /// Counts one call of `service` that took `latency`
+    pub(crate) fn record(&self, service: &str, latency: Duration, ok: bool) {
+        let counters = self.counters(service);
+        counters.calls.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            counters.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        counters.latency.record(latency);
+    }
+
+    /// Runs the call `f` and records its outcome and duration
+    pub(crate) fn time<T, E>(
+        &self,
+        service: &str,
+        f: impl FnOnce() -> Result<T, E>,
+    ) -> Result<T, E> {
+        let started = Instant::now();
+        let result = f();
+        self.record(service, started.elapsed(), result.is_ok());
+        result
+    }
+
+    pub(crate) fn snapshot(&self) -> ClientStats {
+        ClientStats {
+            services: self
+                .services
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(service, counters)| (service.clone(), counters.load()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_increment_per_service() {
+        let recorder = CallRecorder::default();
+        for _ in 0..3 {
+            recorder.time("TXNSVC", || Ok::<_, String>(())).unwrap();
+        }
+        let _ = recorder.time("TXNSVC", || Err::<(), _>("failed".to_string()));
+        recorder.time("UBFTEST", || Ok::<_, String>(())).unwrap();
+
+        let stats = recorder.snapshot();
+        assert_eq!(stats.services.len(), 2);
+        assert_eq!(stats.service("TXNSVC").unwrap().calls, 4);
+        assert_eq!(stats.service("TXNSVC").unwrap().errors, 1);
+        assert_eq!(stats.service("UBFTEST").unwrap().calls, 1);
+        assert_eq!(stats.service("UBFTEST").unwrap().errors, 0);
+        assert!(stats.service("OTHER").is_none());
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let recorder = CallRecorder::default();
+        for ms in 1..=100 {
+            recorder.record("SVC", Duration::from_millis(ms), true);
+        }
+
+        let stats = recorder.snapshot();
+        let svc = stats.service("SVC").unwrap();
+        let within = |actual: Duration, expected_ms: u64| {
+            let expected = Duration::from_millis(expected_ms);
+            actual >= expected && actual <= expected + expected / 8
+        };
+        assert!(within(svc.p50, 50), "p50 = {:?}", svc.p50);
+        assert!(within(svc.p90, 90), "p90 = {:?}", svc.p90);
+        assert!(within(svc.p99, 99), "p99 = {:?}", svc.p99);
+        assert_eq!(svc.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_bucket_bounds_cover_values() {
+        for us in [0u64, 1, 7, 8, 9, 15, 16, 17, 1000, 123_456, 1 << 39] {
+            let bucket = LatencySketch::bucket(us);
+            assert!(LatencySketch::upper_bound(bucket) >= us, "{}", us);
+            if bucket > 0 {
+                assert!(LatencySketch::upper_bound(bucket - 1) < us, "{}", us);
+            }
+        }
+    }
+}
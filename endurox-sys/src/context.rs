@@ -0,0 +1,227 @@
+//! ATMI context token
+//!
+//! `AtmiContext::init` runs `tpinit` and returns a token owning the calling
+//! thread's ATMI context; dropping it runs `tpterm`. A thread holds at most
+//! one context, so a second `init` on the same thread fails instead of
+//! sharing (and later terminating) the first one.
+
+use crate::client::ClientConfig;
+use crate::error::{clear_context_lost, TpError};
+use crate::ffi;
+use crate::flags::TpFlags;
+#[cfg(feature = "ubf")]
+use crate::ubf::UbfBuffer;
+use crate::{tplog_error, tplog_info};
+use libc::{c_int, c_void};
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ptr;
+use std::thread::{self, ThreadId};
+
+thread_local! {
+    // Set while an AtmiContext of this thread is alive
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+type Tpinit = unsafe extern "C" fn(*mut c_void) -> c_int;
+type Tpterm = unsafe extern "C" fn() -> c_int;
+
+/// Initialized ATMI context of the thread that created it
+///
+/// The token is not `Send`, so it can't leave that thread. It can be shared,
+/// but calls made through it from another thread fail with
+/// `TpError::NotInitialized`.
+///
+/// ```compile_fail
+/// fn assert_send<T: Send>() {}
+/// assert_send::<endurox_sys::context::AtmiContext>();
+/// ```
+#[derive(Debug)]
+pub struct AtmiContext {
+    thread: ThreadId,
+    tpterm: Tpterm,
+    _not_send: PhantomData<*const ()>,
+}
+
+// Every &self method checks the thread first, only the owner reaches ATMI
+unsafe impl Sync for AtmiContext {}
+
+impl AtmiContext {
+    /// Runs `tpinit` with default parameters
+    pub fn init() -> Result<Self, TpError> {
+        Self::init_with(&ClientConfig::default())
+    }
+
+    /// Runs `tpinit` with `config`
+    ///
+    /// Fails with `TpError::AlreadyInitialized` if this thread already holds
+    /// a context.
+    pub fn init_with(config: &ClientConfig) -> Result<Self, TpError> {
+        Self::init_via(config, ffi::tpinit, ffi::tpterm)
+    }
+
+    fn init_via(config: &ClientConfig, tpinit: Tpinit, tpterm: Tpterm) -> Result<Self, TpError> {
+        if Self::is_active() {
+            tplog_error("ATMI context already initialized on this thread");
+            return Err(TpError::AlreadyInitialized);
+        }
+
+        let mut raw = if config.is_default() {
+            None
+        } else {
+            Some(config.to_raw().map_err(TpError::InvalidArgument)?)
+        };
+        let tpinfo = raw
+            .as_mut()
+            .map_or(ptr::null_mut(), |r| r as *mut ffi::TpInitRaw as *mut c_void);
+
+        tplog_info(&format!("Calling tpinit with {:?}...", config));
+        if unsafe { tpinit(tpinfo) } == -1 {
+            let err = TpError::last();
+            tplog_error(&format!("tpinit failed: {}", err));
+            return Err(err);
+        }
+        tplog_info("tpinit succeeded");
+
+        clear_context_lost();
+        ACTIVE.with(|active| active.set(true));
+        Ok(AtmiContext {
+            thread: thread::current().id(),
+            tpterm,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Whether the calling thread holds a live `AtmiContext`
+    pub fn is_active() -> bool {
+        ACTIVE.with(|active| active.get())
+    }
+
+    /// Fails unless called on the thread that owns the context
+    pub fn check_thread(&self) -> Result<(), TpError> {
+        if thread::current().id() != self.thread {
+            tplog_error("ATMI context used from another thread");
+            return Err(TpError::NotInitialized);
+        }
+        Ok(())
+    }
+
+    /// Calls a service with a STRING buffer
+    pub fn call(&self, service: &str, data: &str) -> Result<String, String> {
+        self.check_thread().map_err(|e| e.to_string())?;
+        crate::client::tpcall_string(service, data, TpFlags::empty())
+    }
+
+    /// Calls a service with a copy of `request`
+    #[cfg(feature = "ubf")]
+    pub fn call_ubf(&self, service: &str, request: &UbfBuffer) -> Result<UbfBuffer, TpError> {
+        self.check_thread()?;
+        let send = UbfBuffer::from_bytes(request.as_bytes()).map_err(TpError::InvalidArgument)?;
+        crate::client::tpcall_ubf(service, send, TpFlags::empty())
+    }
+}
+
+impl Drop for AtmiContext {
+    fn drop(&mut self) {
+        // tpterm acts on the current thread's context
+        if self.check_thread().is_err() {
+            tplog_error("AtmiContext dropped on another thread, context not terminated");
+            return;
+        }
+
+        unsafe { (self.tpterm)() };
+        ACTIVE.with(|active| active.set(false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // tpinit needs a running Enduro/X, the tests count the calls instead
+    thread_local! {
+        static TPINIT_CALLS: Cell<u32> = const { Cell::new(0) };
+        static TPTERM_CALLS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    unsafe extern "C" fn fake_tpinit(_tpinfo: *mut c_void) -> c_int {
+        TPINIT_CALLS.with(|calls| calls.set(calls.get() + 1));
+        0
+    }
+
+    unsafe extern "C" fn fake_tpterm() -> c_int {
+        TPTERM_CALLS.with(|calls| calls.set(calls.get() + 1));
+        0
+    }
+
+    fn init() -> Result<AtmiContext, TpError> {
+        AtmiContext::init_via(&ClientConfig::default(), fake_tpinit, fake_tpterm)
+    }
+
+    fn calls() -> (u32, u32) {
+        (
+            TPINIT_CALLS.with(|c| c.get()),
+            TPTERM_CALLS.with(|c| c.get()),
+        )
+    }
+
+    #[test]
+    fn test_init_and_drop() {
+        thread::spawn(|| {
+            assert!(!AtmiContext::is_active());
+
+            let context = init().unwrap();
+            assert!(AtmiContext::is_active());
+            assert_eq!(calls(), (1, 0));
+
+            drop(context);
+            assert!(!AtmiContext::is_active());
+            assert_eq!(calls(), (1, 1));
+
+            // The thread can be initialized again
+            let _context = init().unwrap();
+            assert_eq!(calls(), (2, 1));
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_double_init_rejected() {
+        thread::spawn(|| {
+            let context = init().unwrap();
+
+            let err = init().unwrap_err();
+            assert_eq!(err, TpError::AlreadyInitialized);
+            assert_eq!(calls(), (1, 0));
+
+            // The rejected init didn't touch the live context
+            assert!(AtmiContext::is_active());
+            drop(context);
+            assert_eq!(calls(), (1, 1));
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_context_bound_to_its_thread() {
+        let other = thread::spawn(|| thread::current().id()).join().unwrap();
+        // As if created on `other`, which the type system otherwise prevents
+        let context = AtmiContext {
+            thread: other,
+            tpterm: fake_tpterm,
+            _not_send: PhantomData,
+        };
+
+        assert_eq!(context.check_thread(), Err(TpError::NotInitialized));
+        assert_eq!(
+            context.call("ECHO", "x").unwrap_err(),
+            TpError::NotInitialized.to_string()
+        );
+
+        // Not terminated here, tpterm would act on this thread's context
+        drop(context);
+        assert_eq!(calls(), (0, 0));
+    }
+}
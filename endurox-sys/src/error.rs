@@ -0,0 +1,366 @@
+//! XATMI error type
+
+use crate::ffi;
+use std::cell::Cell;
+use std::ffi::{CStr, CString};
+use std::fmt;
+
+thread_local! {
+    // tperrno of the last call on this thread that left its context unusable
+    static CONTEXT_LOST: Cell<Option<i32>> = const { Cell::new(None) };
+}
+
+/// Notes `errno` of a failed call; `TPESYSTEM` and `TPEOS` mark this
+/// thread's ATMI context as lost until the next successful tpinit
+pub(crate) fn record_errno(errno: i32) {
+    if matches!(TpErrno::from(errno), TpErrno::System | TpErrno::Os) {
+        CONTEXT_LOST.with(|c| c.set(Some(errno)));
+    }
+}
+
+/// `tperrno` that marked this thread's context as lost, if any
+pub(crate) fn context_lost() -> Option<i32> {
+    CONTEXT_LOST.with(|c| c.get())
+}
+
+pub(crate) fn clear_context_lost() {
+    CONTEXT_LOST.with(|c| c.set(None));
+}
+
+/// `tperrno` codes of xatmi.h
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TpErrno {
+    /// Transaction was aborted (`TPEABORT`)
+    Abort,
+    /// Invalid call or connection descriptor (`TPEBADDESC`)
+    BadDesc,
+    /// Call would block (`TPNOBLOCK`) (`TPEBLOCK`)
+    Block,
+    /// Invalid arguments (`TPEINVAL`)
+    Inval,
+    /// Limit reached, e.g. too many outstanding calls (`TPELIMIT`)
+    Limit,
+    /// No such service or entry (`TPENOENT`)
+    NoEnt,
+    /// Operating system error (`TPEOS`)
+    Os,
+    /// Permission denied (`TPEPERM`)
+    Perm,
+    /// Call made in the wrong context (`TPEPROTO`)
+    Proto,
+    /// Service error, e.g. the service didn't tpreturn properly (`TPESVCERR`)
+    SvcErr,
+    /// Service returned `TPFAIL` (`TPESVCFAIL`)
+    SvcFail,
+    /// Enduro/X system error (`TPESYSTEM`)
+    System,
+    /// Timeout (`TPETIME`)
+    Time,
+    /// Transaction error (`TPETRAN`)
+    Tran,
+    /// Interrupted by a signal (`TPGOTSIG`)
+    GotSig,
+    /// Resource manager error (`TPERMERR`)
+    RmErr,
+    /// Input buffer type not accepted by the service (`TPEITYPE`)
+    IType,
+    /// Reply buffer type not accepted by the caller (`TPEOTYPE`)
+    OType,
+    /// Incompatible release (`TPERELEASE`)
+    Release,
+    /// Transaction outcome may be partial (heuristic hazard) (`TPEHAZARD`)
+    Hazard,
+    /// Transaction committed or rolled back heuristically (`TPEHEURISTIC`)
+    Heuristic,
+    /// Conversation event occurred (`TPEEVENT`)
+    Event,
+    /// Name already in use, e.g. advertised twice (`TPEMATCH`)
+    Match,
+    /// Queue operation failed, see the diagnostic (`TPEDIAGNOSTIC`)
+    Diagnostic,
+    /// Management information base error (`TPEMIB`)
+    Mib,
+    /// Code this crate doesn't know
+    Unknown(i32),
+}
+
+impl TpErrno {
+    /// Numeric `tperrno` value
+    pub fn code(self) -> i32 {
+        match self {
+            TpErrno::Abort => 1,
+            TpErrno::BadDesc => 2,
+            TpErrno::Block => 3,
+            TpErrno::Inval => 4,
+            TpErrno::Limit => 5,
+            TpErrno::NoEnt => 6,
+            TpErrno::Os => 7,
+            TpErrno::Perm => 8,
+            TpErrno::Proto => 9,
+            TpErrno::SvcErr => 10,
+            TpErrno::SvcFail => 11,
+            TpErrno::System => 12,
+            TpErrno::Time => 13,
+            TpErrno::Tran => 14,
+            TpErrno::GotSig => 15,
+            TpErrno::RmErr => 16,
+            TpErrno::IType => 17,
+            TpErrno::OType => 18,
+            TpErrno::Release => 19,
+            TpErrno::Hazard => 20,
+            TpErrno::Heuristic => 21,
+            TpErrno::Event => 22,
+            TpErrno::Match => 23,
+            TpErrno::Diagnostic => 24,
+            TpErrno::Mib => 25,
+            TpErrno::Unknown(code) => code,
+        }
+    }
+
+    /// Name of the C constant, e.g. `TPENOENT`
+    pub fn name(self) -> &'static str {
+        match self {
+            TpErrno::Abort => "TPEABORT",
+            TpErrno::BadDesc => "TPEBADDESC",
+            TpErrno::Block => "TPEBLOCK",
+            TpErrno::Inval => "TPEINVAL",
+            TpErrno::Limit => "TPELIMIT",
+            TpErrno::NoEnt => "TPENOENT",
+            TpErrno::Os => "TPEOS",
+            TpErrno::Perm => "TPEPERM",
+            TpErrno::Proto => "TPEPROTO",
+            TpErrno::SvcErr => "TPESVCERR",
+            TpErrno::SvcFail => "TPESVCFAIL",
+            TpErrno::System => "TPESYSTEM",
+            TpErrno::Time => "TPETIME",
+            TpErrno::Tran => "TPETRAN",
+            TpErrno::GotSig => "TPGOTSIG",
+            TpErrno::RmErr => "TPERMERR",
+            TpErrno::IType => "TPEITYPE",
+            TpErrno::OType => "TPEOTYPE",
+            TpErrno::Release => "TPERELEASE",
+            TpErrno::Hazard => "TPEHAZARD",
+            TpErrno::Heuristic => "TPEHEURISTIC",
+            TpErrno::Event => "TPEEVENT",
+            TpErrno::Match => "TPEMATCH",
+            TpErrno::Diagnostic => "TPEDIAGNOSTIC",
+            TpErrno::Mib => "TPEMIB",
+            TpErrno::Unknown(_) => "unknown",
+        }
+    }
+}
+
+impl From<i32> for TpErrno {
+    fn from(code: i32) -> Self {
+        match code {
+            1 => TpErrno::Abort,
+            2 => TpErrno::BadDesc,
+            3 => TpErrno::Block,
+            4 => TpErrno::Inval,
+            5 => TpErrno::Limit,
+            6 => TpErrno::NoEnt,
+            7 => TpErrno::Os,
+            8 => TpErrno::Perm,
+            9 => TpErrno::Proto,
+            10 => TpErrno::SvcErr,
+            11 => TpErrno::SvcFail,
+            12 => TpErrno::System,
+            13 => TpErrno::Time,
+            14 => TpErrno::Tran,
+            15 => TpErrno::GotSig,
+            16 => TpErrno::RmErr,
+            17 => TpErrno::IType,
+            18 => TpErrno::OType,
+            19 => TpErrno::Release,
+            20 => TpErrno::Hazard,
+            21 => TpErrno::Heuristic,
+            22 => TpErrno::Event,
+            23 => TpErrno::Match,
+            24 => TpErrno::Diagnostic,
+            25 => TpErrno::Mib,
+            _ => TpErrno::Unknown(code),
+        }
+    }
+}
+
+impl fmt::Display for TpErrno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TpErrno::Unknown(code) => write!(f, "tperrno {}", code),
+            errno => write!(f, "{}", errno.name()),
+        }
+    }
+}
+
+/// Error returned by XATMI calls
+#[derive(Debug, Clone, PartialEq)]
+pub enum TpError {
+    /// The call did not complete before its deadline
+    Timeout,
+    /// XATMI call failed, with `tperrno` and its `tpstrerror` text
+    Xatmi { errno: i32, message: String },
+    /// Invalid input detected before calling into Enduro/X
+    InvalidArgument(String),
+    /// The calling thread has no ATMI context (no tpinit, or the client was dropped)
+    NotInitialized,
+    /// The calling thread already holds an `AtmiContext`
+    AlreadyInitialized,
+    /// A `TPNOBLOCK` call found the request queue full (`TPEBLOCK`)
+    WouldBlock,
+}
+
+impl TpError {
+    /// Builds an error from the given `tperrno`
+    pub fn from_errno(errno: i32) -> Self {
+        record_errno(errno);
+        match TpErrno::from(errno) {
+            TpErrno::Time => return TpError::Timeout,
+            TpErrno::Block => return TpError::WouldBlock,
+            _ => {}
+        }
+
+        let err_ptr = unsafe { ffi::tpstrerror(errno) };
+        let message = if !err_ptr.is_null() {
+            unsafe { CStr::from_ptr(err_ptr) }
+                .to_string_lossy()
+                .into_owned()
+        } else {
+            "Unknown error".to_string()
+        };
+
+        TpError::Xatmi { errno, message }
+    }
+
+    /// `tperrno` behind the error, `None` for errors raised on the Rust side
+    pub fn errno(&self) -> Option<TpErrno> {
+        match self {
+            TpError::Timeout => Some(TpErrno::Time),
+            TpError::WouldBlock => Some(TpErrno::Block),
+            TpError::Xatmi { errno, .. } => Some(TpErrno::from(*errno)),
+            _ => None,
+        }
+    }
+
+    /// Whether the error means the ATMI context must be rebuilt with tpinit
+    pub fn is_context_lost(&self) -> bool {
+        match self {
+            TpError::NotInitialized => true,
+            _ => matches!(self.errno(), Some(TpErrno::System | TpErrno::Os)),
+        }
+    }
+
+    /// Builds an error from the current thread's `tperrno`
+    pub fn last() -> Self {
+        Self::from_errno(tperrno())
+    }
+}
+
+/// Error for a conversation that ended with `revent` other than `TPEV_SVCSUCC`
+pub(crate) fn conversation_event(revent: libc::c_long) -> Option<TpError> {
+    let what = match revent {
+        ffi::TPEV_SVCSUCC => return None,
+        ffi::TPEV_SVCFAIL => "service failed (TPEV_SVCFAIL)",
+        ffi::TPEV_SVCERR => "service error (TPEV_SVCERR)",
+        ffi::TPEV_DISCONIMM => "conversation disconnected (TPEV_DISCONIMM)",
+        ffi::TPEV_SENDONLY => "peer handed over send control (TPEV_SENDONLY)",
+        _ => "unexpected conversation event",
+    };
+    Some(TpError::Xatmi {
+        errno: ffi::TPEEVENT,
+        message: format!("{}, revent={:#x}", what, revent),
+    })
+}
+
+impl fmt::Display for TpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TpError::Timeout => write!(f, "Call timed out"),
+            TpError::Xatmi { errno, message } => write!(f, "XATMI error {}: {}", errno, message),
+            TpError::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg),
+            TpError::NotInitialized => write!(
+                f,
+                "ATMI context not initialized on this thread, create the EnduroxClient here"
+            ),
+            TpError::AlreadyInitialized => {
+                write!(f, "ATMI context already initialized on this thread")
+            }
+            TpError::WouldBlock => write!(f, "Call would block, service queue is full"),
+        }
+    }
+}
+
+impl std::error::Error for TpError {}
+
+/// Current thread's `tperrno`
+pub fn tperrno() -> i32 {
+    unsafe { *ffi::_exget_tperrno_addr() }
+}
+
+/// Converts `value` to a C string, naming `what` if it contains a NUL byte
+pub(crate) fn c_string(value: &str, what: &str) -> Result<CString, String> {
+    CString::new(value).map_err(|e| {
+        format!(
+            "{} contains a NUL byte at position {}",
+            what,
+            e.nul_position()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tperrno_codes_map_to_variants() {
+        let expected = [
+            (1, TpErrno::Abort),
+            (2, TpErrno::BadDesc),
+            (3, TpErrno::Block),
+            (4, TpErrno::Inval),
+            (5, TpErrno::Limit),
+            (6, TpErrno::NoEnt),
+            (7, TpErrno::Os),
+            (8, TpErrno::Perm),
+            (9, TpErrno::Proto),
+            (10, TpErrno::SvcErr),
+            (11, TpErrno::SvcFail),
+            (12, TpErrno::System),
+            (13, TpErrno::Time),
+            (14, TpErrno::Tran),
+            (15, TpErrno::GotSig),
+            (16, TpErrno::RmErr),
+            (17, TpErrno::IType),
+            (18, TpErrno::OType),
+            (19, TpErrno::Release),
+            (20, TpErrno::Hazard),
+            (21, TpErrno::Heuristic),
+            (22, TpErrno::Event),
+            (23, TpErrno::Match),
+            (24, TpErrno::Diagnostic),
+            (25, TpErrno::Mib),
+        ];
+
+        for (code, errno) in expected {
+            assert_eq!(TpErrno::from(code), errno);
+            assert_eq!(errno.code(), code);
+        }
+        assert_eq!(TpErrno::from(ffi::TPEOTYPE), TpErrno::OType);
+        assert_eq!(TpErrno::from(0), TpErrno::Unknown(0));
+        assert_eq!(TpErrno::from(99).code(), 99);
+        assert_eq!(TpErrno::NoEnt.to_string(), "TPENOENT");
+    }
+
+    #[test]
+    fn test_tp_error_exposes_errno() {
+        assert_eq!(TpError::Timeout.errno(), Some(TpErrno::Time));
+        assert_eq!(TpError::WouldBlock.errno(), Some(TpErrno::Block));
+        let err = TpError::Xatmi {
+            errno: ffi::TPESYSTEM,
+            message: "system error".to_string(),
+        };
+        assert_eq!(err.errno(), Some(TpErrno::System));
+        assert!(err.is_context_lost());
+        assert_eq!(TpError::NotInitialized.errno(), None);
+    }
+}
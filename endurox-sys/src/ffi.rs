@@ -1,6 +1,6 @@
 //! Raw FFI bindings to Enduro/X C API
 
-use libc::{c_char, c_int, c_long, c_void};
+use libc::{c_char, c_int, c_long};
 
 // Return codes (from xatmi.h)
 pub const TPFAIL: c_int = 0x00000001;
@@ -8,9 +8,48 @@ pub const TPSUCCESS: c_int = 0x00000002;
 
 // Flags
 pub const TPNOBLOCK: c_long = 0x00000001;
+pub const TPSIGRSTRT: c_long = 0x00000002;
+pub const TPNOREPLY: c_long = 0x00000004;
 pub const TPNOTRAN: c_long = 0x00000008;
-pub const TPSIGRSTRT: c_long = 0x00000010;
+pub const TPTRAN: c_long = 0x00000010;
 pub const TPNOTIME: c_long = 0x00000020;
+pub const TPABSOLUTE: c_long = 0x00000040;
+pub const TPGETANY: c_long = 0x00000080;
+pub const TPNOCHANGE: c_long = 0x00000100;
+pub const TPACK: c_long = 0x00002000;
+pub const TPTRANSUSPEND: c_long = 0x00040000;
+pub const TPSOFTTIMEOUT: c_long = 0x00080000;
+pub const TPSOFTENOENT: c_long = 0x00100000;
+pub const TPNOAUTBUF: c_long = 0x00200000;
+pub const TPREGEXMATCH: c_long = 0x00800000;
+pub const TPNOABORT: c_long = 0x08000000;
+
+// TPINIT.flags
+pub const TPMULTICONTEXTS: c_long = 0x00000040;
+
+// Conversational flags
+pub const TPCONV: c_long = 0x00000400;
+pub const TPSENDONLY: c_long = 0x00000800;
+pub const TPRECVONLY: c_long = 0x00001000;
+
+// Conversation events (revent of tpsend/tprecv, with tperrno TPEEVENT)
+pub const TPEV_DISCONIMM: c_long = 0x0001;
+pub const TPEV_SVCERR: c_long = 0x0002;
+pub const TPEV_SVCFAIL: c_long = 0x0004;
+pub const TPEV_SVCSUCC: c_long = 0x0008;
+pub const TPEV_SENDONLY: c_long = 0x0020;
+
+// Unsolicited notification flags for TPINIT.flags
+pub const TPU_DIP: c_long = 0x00000002;
+pub const TPU_IGN: c_long = 0x00000004;
+
+// Error codes (tperrno)
+pub const TPEBLOCK: c_int = 3;
+pub const TPEOS: c_int = 7;
+pub const TPESYSTEM: c_int = 12;
+pub const TPETIME: c_int = 13;
+pub const TPEOTYPE: c_int = 18;
+pub const TPEEVENT: c_int = 22;
 
 // Service info structure  - must match C TPSVCINFO layout
 // typedef struct {
@@ -35,6 +74,39 @@ pub struct TpSvcInfoRaw {
     pub fname: [c_char; 32], // XATMI_SERVICE_NAME_LENGTH+1 (31, padded to 32)
 }
 
+// UBF error codes (Berror)
+pub const BNOSPACE: c_int = 4;
+pub const BNOTPRES: c_int = 5;
+pub const BBADFLD: c_int = 6;
+pub const BTYPERR: c_int = 7;
+
+// Max length of identifiers in TPINIT (from xatmi.h)
+pub const MAXTIDENT: usize = 30;
+
+// Max length of a service name, longer names are truncated (from xatmi.h)
+pub const XATMI_SERVICE_NAME_LENGTH: usize = MAXTIDENT;
+
+// Client init structure - must match C TPINIT layout
+// struct tpinfo_t {
+//     char usrname[MAXTIDENT+2];
+//     char cltname[MAXTIDENT+2];
+//     char passwd[MAXTIDENT+2];
+//     char grpname[MAXTIDENT+2];
+//     long flags;
+//     long datalen;
+//     long data;
+// };
+#[repr(C)]
+pub struct TpInitRaw {
+    pub usrname: [c_char; MAXTIDENT + 2],
+    pub cltname: [c_char; MAXTIDENT + 2],
+    pub passwd: [c_char; MAXTIDENT + 2],
+    pub grpname: [c_char; MAXTIDENT + 2],
+    pub flags: c_long,
+    pub datalen: c_long,
+    pub data: c_long,
+}
+
 extern "C" {
     // Server functions
     #[cfg(feature = "server")]
@@ -50,14 +122,36 @@ extern "C" {
     #[cfg(feature = "server")]
     pub fn tpreturn(rval: c_int, rcode: c_long, data: *mut c_char, len: c_long, flags: c_long);
 
+    #[cfg(feature = "server")]
+    pub fn tpforward(svc: *mut c_char, data: *mut c_char, len: c_long, flags: c_long);
+
+    #[cfg(feature = "server")]
+    pub fn tpgetsrvid() -> c_int;
+
+    #[cfg(feature = "server")]
+    pub fn tpgetnodeid() -> c_long;
+
+    // Transaction control, available to servers and clients alike
+    #[cfg(any(feature = "server", feature = "client"))]
+    pub fn tpgetlev() -> c_int;
+
+    #[cfg(any(feature = "server", feature = "client"))]
+    pub fn tpbegin(timeout: libc::c_ulong, flags: c_long) -> c_int;
+
+    #[cfg(any(feature = "server", feature = "client"))]
+    pub fn tpcommit(flags: c_long) -> c_int;
+
+    #[cfg(any(feature = "server", feature = "client"))]
+    pub fn tpabort(flags: c_long) -> c_int;
+
     // Client functions
     #[cfg(feature = "client")]
-    pub fn tpinit(tpinfo: *mut c_void) -> c_int;
+    pub fn tpinit(tpinfo: *mut libc::c_void) -> c_int;
 
     #[cfg(feature = "client")]
     pub fn tpterm() -> c_int;
 
-    #[cfg(feature = "client")]
+    #[cfg(any(feature = "client", feature = "server"))]
     pub fn tpcall(
         svc: *const c_char,
         idata: *mut c_char,
@@ -78,14 +172,41 @@ extern "C" {
         flags: c_long,
     ) -> c_int;
 
+    #[cfg(feature = "client")]
+    pub fn tpcancel(cd: c_int) -> c_int;
+
+    // Conversations
+    pub fn tpconnect(svc: *const c_char, data: *mut c_char, len: c_long, flags: c_long) -> c_int;
+    pub fn tpdiscon(cd: c_int) -> c_int;
+    pub fn tpsend(
+        cd: c_int,
+        data: *mut c_char,
+        len: c_long,
+        flags: c_long,
+        revent: *mut c_long,
+    ) -> c_int;
+    pub fn tprecv(
+        cd: c_int,
+        data: *mut *mut c_char,
+        len: *mut c_long,
+        flags: c_long,
+        revent: *mut c_long,
+    ) -> c_int;
+
     // Buffer management
     pub fn tpalloc(typ: *const c_char, subtyp: *const c_char, size: c_long) -> *mut c_char;
     pub fn tprealloc(ptr: *mut c_char, size: c_long) -> *mut c_char;
     pub fn tpfree(ptr: *mut c_char);
+    pub fn tptypes(ptr: *mut c_char, typ: *mut c_char, subtyp: *mut c_char) -> c_long;
+
+    // Enduro/X JSON representation of UBF
+    pub fn tpubftojson(p_ub: *mut c_char, buffer: *mut c_char, bufsize: c_int) -> c_int;
+    pub fn tpjsontoubf(p_ub: *mut c_char, buffer: *mut c_char) -> c_int;
 
     // Error handling
     pub fn tpstrerror(err: c_int) -> *const c_char;
     pub fn _exget_tperrno_addr() -> *const c_int;
+    pub fn _exget_tpurcode_addr() -> *mut c_long;
 
     // Logging
     pub fn tplog(lev: c_int, format: *const c_char, ...);
@@ -98,6 +219,25 @@ extern "C" {
     #[cfg(feature = "ubf")]
     pub fn Badd(p_ub: *mut c_char, bfldid: c_int, buf: *const c_char, len: c_int) -> c_int;
 
+    #[cfg(feature = "ubf")]
+    pub fn CBadd(
+        p_ub: *mut c_char,
+        bfldid: c_int,
+        buf: *const c_char,
+        len: c_int,
+        usrtype: c_int,
+    ) -> c_int;
+
+    #[cfg(feature = "ubf")]
+    pub fn CBchg(
+        p_ub: *mut c_char,
+        bfldid: c_int,
+        occ: c_int,
+        buf: *const c_char,
+        len: c_int,
+        usrtype: c_int,
+    ) -> c_int;
+
     #[cfg(feature = "ubf")]
     pub fn Bchg(
         p_ub: *mut c_char,
@@ -129,6 +269,9 @@ extern "C" {
     #[cfg(feature = "ubf")]
     pub fn Bpres(p_ub: *mut c_char, bfldid: c_int, occ: c_int) -> c_int;
 
+    #[cfg(feature = "ubf")]
+    pub fn Boccur(p_ub: *mut c_char, bfldid: c_int) -> c_int;
+
     #[cfg(feature = "ubf")]
     pub fn Bdel(p_ub: *mut c_char, bfldid: c_int, occ: c_int) -> c_int;
 
@@ -136,7 +279,7 @@ extern "C" {
     pub fn Bproj(p_ub: *mut c_char, fldlist: *const c_int) -> c_int;
 
     #[cfg(feature = "ubf")]
-    pub fn Bfprint(p_ub: *mut c_char, outf: *mut c_void) -> c_int;
+    pub fn Bfprint(p_ub: *mut c_char, outf: *mut libc::c_void) -> c_int;
 
     #[cfg(feature = "ubf")]
     pub fn Bprint(p_ub: *mut c_char) -> c_int;
@@ -147,12 +290,30 @@ extern "C" {
     #[cfg(feature = "ubf")]
     pub fn Bused(p_ub: *mut c_char) -> c_long;
 
+    #[cfg(feature = "ubf")]
+    pub fn Bisubf(p_ub: *mut c_char) -> c_int;
+
     #[cfg(feature = "ubf")]
     pub fn Bunused(p_ub: *mut c_char) -> c_long;
 
     #[cfg(feature = "ubf")]
     pub fn Bsizeof(p_ub: *mut c_char) -> c_long;
 
+    #[cfg(feature = "ubf")]
+    pub fn Bcpy(p_ub_dst: *mut c_char, p_ub_src: *mut c_char) -> c_int;
+
+    #[cfg(feature = "ubf")]
+    pub fn Bneeded(nrfields: c_int, totsize: c_int) -> c_long;
+
+    #[cfg(feature = "ubf")]
+    pub fn Bindex(p_ub: *mut c_char, occ: c_int) -> c_int;
+
+    #[cfg(feature = "ubf")]
+    pub fn Bunindex(p_ub: *mut c_char) -> c_int;
+
+    #[cfg(feature = "ubf")]
+    pub fn Bidxused(p_ub: *mut c_char) -> c_long;
+
     #[cfg(feature = "ubf")]
     pub fn Bfldid(fldname: *const c_char) -> c_int;
 
@@ -162,6 +323,12 @@ extern "C" {
     #[cfg(feature = "ubf")]
     pub fn Bfldtype(bfldid: c_int) -> c_int;
 
+    #[cfg(feature = "ubf")]
+    pub fn ndrx_Bget_Ferror_addr() -> *mut c_int;
+
+    #[cfg(feature = "ubf")]
+    pub fn Bstrerror(err: c_int) -> *const c_char;
+
     #[cfg(feature = "ubf")]
     pub fn Bnext(
         p_ub: *mut c_char,
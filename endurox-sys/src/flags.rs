@@ -0,0 +1,73 @@
+//! Typed ATMI call flags
+
+use crate::ffi;
+use bitflags::bitflags;
+use libc::c_long;
+
+bitflags! {
+    /// Flags of tpcall, tpacall, tpgetrply, tpforward and the conversational
+    /// calls (xatmi.h `TP*`)
+    ///
+    /// TPINIT flags such as `TPMULTICONTEXTS` share bits with these and are
+    /// not part of the set.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct TpFlags: c_long {
+        const NOBLOCK = ffi::TPNOBLOCK;
+        const SIGRSTRT = ffi::TPSIGRSTRT;
+        const NOREPLY = ffi::TPNOREPLY;
+        const NOTRAN = ffi::TPNOTRAN;
+        const TRAN = ffi::TPTRAN;
+        const NOTIME = ffi::TPNOTIME;
+        const ABSOLUTE = ffi::TPABSOLUTE;
+        const GETANY = ffi::TPGETANY;
+        const NOCHANGE = ffi::TPNOCHANGE;
+        const CONV = ffi::TPCONV;
+        const SENDONLY = ffi::TPSENDONLY;
+        const RECVONLY = ffi::TPRECVONLY;
+        const ACK = ffi::TPACK;
+        const TRANSUSPEND = ffi::TPTRANSUSPEND;
+        const SOFTTIMEOUT = ffi::TPSOFTTIMEOUT;
+        const SOFTENOENT = ffi::TPSOFTENOENT;
+        const NOAUTBUF = ffi::TPNOAUTBUF;
+        const REGEXMATCH = ffi::TPREGEXMATCH;
+        const NOABORT = ffi::TPNOABORT;
+    }
+}
+
+impl TpFlags {
+    /// Value passed to the C API
+    pub fn raw(self) -> c_long {
+        self.bits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compose_flags() {
+        let flags = TpFlags::NOTIME | TpFlags::NOTRAN;
+        assert_eq!(flags.raw(), 0x28);
+        assert!(flags.contains(TpFlags::NOTIME));
+        assert!(!flags.contains(TpFlags::NOBLOCK));
+
+        assert_eq!((flags - TpFlags::NOTRAN).raw(), ffi::TPNOTIME);
+        assert_eq!(TpFlags::default().raw(), 0);
+    }
+
+    #[test]
+    fn test_values_match_xatmi_h() {
+        assert_eq!(TpFlags::SIGRSTRT.raw(), 0x2);
+        assert_eq!(TpFlags::NOREPLY.raw(), 0x4);
+        assert_eq!(TpFlags::GETANY.raw(), 0x80);
+        assert_eq!(TpFlags::NOCHANGE.raw(), 0x100);
+        assert_eq!(TpFlags::RECVONLY.raw(), 0x1000);
+    }
+
+    #[test]
+    fn test_unknown_bits_rejected() {
+        assert_eq!(TpFlags::from_bits(0x20), Some(TpFlags::NOTIME));
+        assert_eq!(TpFlags::from_bits(0x4000), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Service codec registry for dynamic gateways
+//!
+//! Maps service names to UBF <-> JSON converters at runtime, so a gateway can
+//! expose new services from configuration instead of a compiled route per
+//! struct.
+
+use crate::ubf::UbfBuffer;
+use crate::ubf_struct::UbfStruct;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Converts a service reply buffer to JSON
+pub type UbfDecoder = Box<dyn Fn(&UbfBuffer) -> Result<Value, String> + Send + Sync>;
+
+/// Converts a JSON request to a service request buffer
+pub type UbfEncoder = Box<dyn Fn(&Value) -> Result<UbfBuffer, String> + Send + Sync>;
+
+struct ServiceCodec {
+    decoder: UbfDecoder,
+    encoder: UbfEncoder,
+}
+
+/// Registry of per-service decoders and encoders
+#[derive(Default)]
+pub struct GatewayRegistry {
+    services: HashMap<String, ServiceCodec>,
+}
+
+impl GatewayRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the codec for a service, replacing any previous one
+    pub fn register<D, E>(&mut self, service: &str, decoder: D, encoder: E)
+    where
+        D: Fn(&UbfBuffer) -> Result<Value, String> + Send + Sync + 'static,
+        E: Fn(&Value) -> Result<UbfBuffer, String> + Send + Sync + 'static,
+    {
+        self.services.insert(
+            service.to_string(),
+            ServiceCodec {
+                decoder: Box::new(decoder),
+                encoder: Box::new(encoder),
+            },
+        );
+    }
+
+    /// Registers a service whose request and reply map to the same `UbfStruct`
+    pub fn register_struct<T>(&mut self, service: &str)
+    where
+        T: UbfStruct + Serialize + DeserializeOwned + 'static,
+    {
+        self.register(
+            service,
+            |buf| {
+                let value = T::from_ubf(buf).map_err(|e| e.to_string())?;
+                serde_json::to_value(value).map_err(|e| e.to_string())
+            },
+            |json| {
+                let value: T = serde_json::from_value(json.clone()).map_err(|e| e.to_string())?;
+                value.to_ubf().map_err(|e| e.to_string())
+            },
+        );
+    }
+
+    /// Decodes a reply of `service` to JSON
+    pub fn decode(&self, service: &str, buf: &UbfBuffer) -> Result<Value, String> {
+        let codec = self.codec(service)?;
+        (codec.decoder)(buf)
+    }
+
+    /// Encodes a JSON request for `service`
+    pub fn encode(&self, service: &str, json: &Value) -> Result<UbfBuffer, String> {
+        let codec = self.codec(service)?;
+        (codec.encoder)(json)
+    }
+
+    pub fn contains(&self, service: &str) -> bool {
+        self.services.contains_key(service)
+    }
+
+    /// Names of all registered services
+    pub fn services(&self) -> Vec<&str> {
+        self.services.keys().map(|s| s.as_str()).collect()
+    }
+
+    fn codec(&self, service: &str) -> Result<&ServiceCodec, String> {
+        self.services
+            .get(service)
+            .ok_or_else(|| format!("No codec registered for service {}", service))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ubf_fields::*;
+    use serde_json::json;
+
+    fn hello_registry() -> GatewayRegistry {
+        let mut registry = GatewayRegistry::new();
+        registry.register(
+            "HELLO",
+            |buf| {
+                Ok(json!({
+                    "name": buf.get_string(T_NAME_FLD, 0)?,
+                    "id": buf.get_long(T_ID_FLD, 0).map_err(|e| e.to_string())?,
+                }))
+            },
+            |json| {
+                let mut buf = UbfBuffer::new(1024)?;
+                let name = json["name"].as_str().ok_or("name is required")?;
+                buf.add_string(T_NAME_FLD, name)?;
+                buf.add_long(T_ID_FLD, json["id"].as_i64().unwrap_or(0))?;
+                Ok(buf)
+            },
+        );
+        registry
+    }
+
+    #[test]
+    fn test_register_and_decode() {
+        let registry = hello_registry();
+        assert!(registry.contains("HELLO"));
+        assert_eq!(registry.services(), vec!["HELLO"]);
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "Alice").unwrap();
+        buf.add_long(T_ID_FLD, 7).unwrap();
+
+        let value = registry.decode("HELLO", &buf).unwrap();
+        assert_eq!(value, json!({"name": "Alice", "id": 7}));
+    }
+
+    #[test]
+    fn test_encode_and_unknown_service() {
+        let registry = hello_registry();
+
+        let buf = registry
+            .encode("HELLO", &json!({"name": "Bob", "id": 3}))
+            .unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "Bob");
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 3);
+
+        assert!(registry.encode("HELLO", &json!({"id": 3})).is_err());
+        assert!(registry.decode("MISSING", &buf).is_err());
+    }
+}
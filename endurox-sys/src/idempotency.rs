@@ -0,0 +1,166 @@
+//! Idempotency keys for safely retrying non-idempotent calls
+//!
+//! The client stamps a unique key into the request (`T_IDEMPOTENCY_KEY_FLD`)
+//! and reuses it on every retry. The server looks the key up in a
+//! `DedupStore` and replays the stored reply instead of running the handler
+//! again.
+
+use crate::ubf::UbfBuffer;
+use crate::ubf_fields::T_IDEMPOTENCY_KEY_FLD;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static KEY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Generates a key unique to this process and call
+pub fn new_idempotency_key() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let seq = KEY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{:x}-{:x}-{:x}", std::process::id(), nanos, seq)
+}
+
+/// Stamps `key` into the request, replacing any existing key
+pub fn stamp_idempotency_key(buf: &mut UbfBuffer, key: &str) -> Result<(), String> {
+    buf.set_string(T_IDEMPOTENCY_KEY_FLD, key)
+}
+
+/// Reads the idempotency key of a request, if any
+pub fn idempotency_key(buf: &UbfBuffer) -> Option<String> {
+    buf.get_string(T_IDEMPOTENCY_KEY_FLD, 0)
+        .ok()
+        .filter(|key| !key.is_empty())
+}
+
+/// Reply kept for replaying to duplicate requests
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedReply {
+    pub success: bool,
+    pub message: String,
+    /// Serialized UBF reply buffer
+    pub ubf_data: Option<Vec<u8>>,
+    pub rcode: i64,
+    pub json: bool,
+}
+
+/// Storage for replies of already processed requests
+///
+/// The in-memory store only deduplicates within one server process; servers
+/// running several copies should plug in a shared store (e.g. a DB table).
+pub trait DedupStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CachedReply>;
+    fn put(&self, key: &str, reply: CachedReply);
+}
+
+/// Bounded in-memory `DedupStore` with expiry
+pub struct InMemoryDedupStore {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<DedupEntries>,
+}
+
+#[derive(Default)]
+struct DedupEntries {
+    replies: HashMap<String, (Instant, CachedReply)>,
+    order: VecDeque<String>,
+}
+
+impl InMemoryDedupStore {
+    /// Keeps at most `capacity` replies, each for `ttl`
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        InMemoryDedupStore {
+            ttl,
+            capacity,
+            entries: Mutex::new(DedupEntries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.replies.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl DedupStore for InMemoryDedupStore {
+    fn get(&self, key: &str) -> Option<CachedReply> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .replies
+            .get(key)
+            .filter(|(stored, _)| stored.elapsed() < self.ttl)
+            .map(|(_, reply)| reply.clone())
+    }
+
+    fn put(&self, key: &str, reply: CachedReply) {
+        let Ok(mut entries) = self.entries.lock() else {
+            return;
+        };
+
+        if entries
+            .replies
+            .insert(key.to_string(), (Instant::now(), reply))
+            .is_none()
+        {
+            entries.order.push_back(key.to_string());
+        }
+
+        // Evict oldest entries beyond capacity
+        while entries.order.len() > self.capacity {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.replies.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(message: &str) -> CachedReply {
+        CachedReply {
+            success: true,
+            message: message.to_string(),
+            ubf_data: None,
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    #[test]
+    fn test_stamp_and_read_key() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        assert_eq!(idempotency_key(&buf), None);
+
+        let key = new_idempotency_key();
+        stamp_idempotency_key(&mut buf, &key).unwrap();
+        stamp_idempotency_key(&mut buf, &key).unwrap();
+
+        assert_eq!(idempotency_key(&buf), Some(key));
+        assert!(!buf.is_present(T_IDEMPOTENCY_KEY_FLD, 1));
+        assert_ne!(new_idempotency_key(), new_idempotency_key());
+    }
+
+    #[test]
+    fn test_in_memory_store_capacity_and_ttl() {
+        let store = InMemoryDedupStore::new(2, Duration::from_secs(60));
+        store.put("a", reply("1"));
+        store.put("b", reply("2"));
+        store.put("c", reply("3"));
+
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("a"), None);
+        assert_eq!(store.get("c"), Some(reply("3")));
+
+        let expired = InMemoryDedupStore::new(10, Duration::ZERO);
+        expired.put("a", reply("1"));
+        assert_eq!(expired.get("a"), None);
+    }
+}
@@ -6,18 +6,43 @@
 //! - `server` - Server API (tpsvrinit, tpsvrdone, ndrx_main)
 //! - `client` - Client API (tpinit, tpterm, tpacall, tpgetrply)
 //! - `ubf` - UBF (Unified Buffer Format) API
+//! - `derive` - `UbfStruct`/`UbfFieldTable` derives, and `endurox_server!` (with `server` + `ubf`)
+//! - `async` - `BlockingAtmiPool` and `LocalAtmi` for calling services from async runtimes
+//! - `chrono` - `UbfBuffer::add_datetime`/`get_datetime` for `chrono::NaiveDateTime`
+//! - `test` - `log::set_test_sink` for capturing log messages in tests
+//! - `test-utils` - `assert_ubf_eq!` for checking UBF buffers in tests
 //!
 //! ## Modules
 //! - `ffi` - Raw FFI bindings
+//! - `flags` - Typed ATMI call flags
 //! - `server` - Server API
 //! - `client` - Client API
+//! - `context` - `AtmiContext` token owning a thread's tpinit/tpterm (`client`)
+//! - `client_pool` - Per-thread client recreated after its context dies (`client`)
+//! - `client_stats` - Per-service call counts and latency of a client (`client`)
+//! - `xa` - Global transaction begin and read-only calls that stay out of it (`server` or `client`)
+//! - `pool` - Fixed pool of ATMI-initialized threads (`async`)
+//! - `local` - ATMI context pinned to a tokio `LocalSet` (`async`)
 //! - `ubf` - UBF API
+//! - `typed_buffer` - Buffers of any XATMI type, typed by `tptypes` (`ubf`)
+//! - `ubf_math` - Sums and min/max over repeating numeric fields (`ubf`)
+//! - `money` - `Money` amounts in integer minor units (`ubf`)
+//! - `ubf_datetime` - `NaiveDateTime` stored as ISO 8601 strings (`chrono`)
+//! - `ubf_table` - `.fd`/`.fd.h` field table generation
+//! - `gateway` - Runtime service codec registry (`ubf`)
+//! - `idempotency` - Idempotency keys and reply dedup store (`ubf`)
+//! - `service` - Service request/response types (`server` + `ubf`)
+//! - `test_utils` - UBF buffer assertions (`test-utils`)
+//! - `error` - XATMI error type
+//! - `version` - Enduro/X version the crate was built against
 //! - `log` - Logging functions
 
 #![allow(dead_code)]
 #![allow(static_mut_refs)]
 
+pub mod error;
 pub mod ffi;
+pub mod flags;
 pub mod log;
 
 #[cfg(feature = "server")]
@@ -26,22 +51,75 @@ pub mod server;
 #[cfg(feature = "client")]
 pub mod client;
 
+#[cfg(feature = "client")]
+pub mod client_pool;
+
+#[cfg(feature = "client")]
+pub mod client_stats;
+
+#[cfg(feature = "client")]
+pub mod context;
+
+#[cfg(any(feature = "server", feature = "client"))]
+pub mod xa;
+
+#[cfg(feature = "async")]
+pub mod pool;
+
+#[cfg(feature = "async")]
+pub mod local;
+
 #[cfg(feature = "ubf")]
 pub mod ubf;
 
+#[cfg(all(feature = "server", feature = "ubf"))]
+pub mod service;
+
+#[cfg(feature = "ubf")]
+pub mod typed_buffer;
+
+#[cfg(feature = "ubf")]
+pub mod ubf_math;
+
+#[cfg(feature = "ubf")]
+pub mod money;
+
+#[cfg(feature = "chrono")]
+pub mod ubf_datetime;
+
 #[cfg(feature = "ubf")]
 pub mod ubf_struct;
 
 #[cfg(feature = "ubf")]
 pub mod ubf_fields;
 
+#[cfg(feature = "ubf")]
+pub mod gateway;
+
+#[cfg(feature = "ubf")]
+pub mod idempotency;
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+
+pub mod ubf_table;
+
+pub mod version;
+
 // Re-export derive macro
 #[cfg(feature = "derive")]
-pub use endurox_derive::UbfStruct;
+pub use endurox_derive::{UbfFieldTable, UbfStruct};
+
+// Server scaffolding macro
+#[cfg(all(feature = "derive", feature = "server", feature = "ubf"))]
+pub use endurox_derive::endurox_server;
 
 // Re-export common types
+pub use error::{TpErrno, TpError};
 pub use ffi::{TpSvcInfoRaw, TPFAIL, TPSUCCESS};
-pub use log::{tplog_debug, tplog_error, tplog_info, tplog_warn};
+pub use flags::TpFlags;
+pub use log::{tplog_debug, tplog_error, tplog_info, tplog_warn, LogLevel};
+pub use version::endurox_version;
 
 #[cfg(feature = "server")]
 pub use server::*;
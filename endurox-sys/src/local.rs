@@ -0,0 +1,85 @@
+//! ATMI context pinned to a tokio `LocalSet`
+//!
+//! An alternative to `BlockingAtmiPool` for async-first programs: one OS
+//! thread holds one `tpinit` context and every ATMI-touching future runs on
+//! that thread via `spawn_local`.
+//!
+//! ATMI has no async API, so `LocalAtmi::call` still blocks the thread for
+//! the whole `tpcall`. While a call is in flight no other task of the
+//! `LocalSet` makes progress, calls are strictly serialized and a slow
+//! service stalls everything on the set. Use it when call rates are low or
+//! when context affinity matters more than throughput (e.g. a transaction
+//! spanning several calls); otherwise prefer `BlockingAtmiPool`, whose calls
+//! run in parallel on separate threads.
+
+use crate::client::EnduroxClient;
+use crate::error::TpError;
+use std::future::Future;
+use std::rc::Rc;
+use std::thread::{self, ThreadId};
+use tokio::task::LocalSet;
+
+/// Handle to the ATMI context of the thread running a `LocalSet`
+///
+/// Cheap to clone into `spawn_local` tasks. Not `Send`: the context must not
+/// leave its thread. The context is terminated when the last clone drops.
+#[derive(Clone)]
+pub struct LocalAtmi {
+    client: Rc<EnduroxClient>,
+    thread: ThreadId,
+}
+
+impl LocalAtmi {
+    /// Runs `tpinit` on the current thread, then drives `local` until the
+    /// future returned by `f` completes
+    ///
+    /// Must be awaited from the thread that will run the set, e.g. with
+    /// `Runtime::block_on` on a current-thread runtime.
+    ///
+    /// ```no_run
+    /// use endurox_sys::local::LocalAtmi;
+    /// use tokio::task::LocalSet;
+    ///
+    /// let rt = tokio::runtime::Builder::new_current_thread()
+    ///     .enable_all()
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let replies = rt.block_on(LocalAtmi::run(LocalSet::new(), |atmi| async move {
+    ///     let task = tokio::task::spawn_local({
+    ///         let atmi = atmi.clone();
+    ///         async move { atmi.call("STATUS", &[]).await }
+    ///     });
+    ///     (atmi.call("HELLO", &[]).await, task.await.unwrap())
+    /// }));
+    /// ```
+    pub async fn run<F, Fut>(local: LocalSet, f: F) -> Result<Fut::Output, String>
+    where
+        F: FnOnce(LocalAtmi) -> Fut,
+        Fut: Future,
+    {
+        let atmi = LocalAtmi {
+            client: Rc::new(EnduroxClient::new()?),
+            thread: thread::current().id(),
+        };
+
+        Ok(local.run_until(f(atmi)).await)
+    }
+
+    /// The client owning this thread's context
+    pub fn client(&self) -> &EnduroxClient {
+        &self.client
+    }
+
+    /// Calls a service with a UBF buffer on the context's thread
+    ///
+    /// Yields once so other ready tasks run first, then blocks in `tpcall`.
+    pub async fn call(&self, service: &str, buffer_data: &[u8]) -> Result<Vec<u8>, String> {
+        if thread::current().id() != self.thread {
+            return Err(TpError::NotInitialized.to_string());
+        }
+
+        tokio::task::yield_now().await;
+        self.client.call_service_ubf_blocking(service, buffer_data)
+    }
+}
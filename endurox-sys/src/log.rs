@@ -1,7 +1,11 @@
 //! Enduro/X logging functions
 
 use libc::c_int;
+use std::cell::RefCell;
 use std::ffi::CString;
+use std::fmt;
+#[cfg(any(test, feature = "test"))]
+use std::sync::{Arc, Mutex};
 
 // Log levels
 const LOG_ERROR: c_int = 1;
@@ -9,6 +13,118 @@ const LOG_WARN: c_int = 2;
 const LOG_INFO: c_int = 3;
 const LOG_DEBUG: c_int = 4;
 
+/// tplog level of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    fn from_raw(level: c_int) -> Self {
+        match level {
+            LOG_ERROR => LogLevel::Error,
+            LOG_WARN => LogLevel::Warn,
+            LOG_INFO => LogLevel::Info,
+            _ => LogLevel::Debug,
+        }
+    }
+}
+
+/// Captured log messages
+#[cfg(any(test, feature = "test"))]
+pub type LogSink = Arc<Mutex<Vec<(LogLevel, String)>>>;
+
+#[cfg(any(test, feature = "test"))]
+thread_local! {
+    static TEST_SINK: RefCell<Option<LogSink>> = const { RefCell::new(None) };
+}
+
+thread_local! {
+    static CORRELATION_ID: RefCell<Option<String>> = const { RefCell::new(None) };
+    // One entry per active `with_fields` call, outermost first
+    static LOG_FIELDS: RefCell<Vec<Vec<(String, String)>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f` with every message logged on this thread prefixed by `[id] `
+///
+/// The previous id is restored afterwards, so calls can nest.
+pub fn with_correlation_id<R>(id: Option<&str>, f: impl FnOnce() -> R) -> R {
+    let previous = CORRELATION_ID.with(|c| c.replace(id.map(String::from)));
+
+    // Restore on unwind too, the thread serves later requests
+    struct Restore(Option<String>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            CORRELATION_ID.with(|c| *c.borrow_mut() = previous);
+        }
+    }
+    let _restore = Restore(previous);
+
+    f()
+}
+
+/// Correlation id currently attached to this thread's log messages
+pub fn correlation_id() -> Option<String> {
+    CORRELATION_ID.with(|c| c.borrow().clone())
+}
+
+/// Runs `f` with every message logged on this thread prefixed by `key=value`
+/// pairs, e.g. `with_fields([("txn_id", id)], || ...)`
+///
+/// Nested calls add their fields after the outer ones; the fields are
+/// dropped when `f` returns or unwinds.
+pub fn with_fields<K, V, R>(fields: impl IntoIterator<Item = (K, V)>, f: impl FnOnce() -> R) -> R
+where
+    K: fmt::Display,
+    V: fmt::Display,
+{
+    let fields = fields
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    LOG_FIELDS.with(|stack| stack.borrow_mut().push(fields));
+
+    struct Pop;
+    impl Drop for Pop {
+        fn drop(&mut self) {
+            LOG_FIELDS.with(|stack| stack.borrow_mut().pop());
+        }
+    }
+    let _pop = Pop;
+
+    f()
+}
+
+/// `key=value ` prefix of the fields attached with `with_fields`
+fn fields_prefix() -> String {
+    LOG_FIELDS.with(|stack| {
+        stack
+            .borrow()
+            .iter()
+            .flatten()
+            .map(|(key, value)| format!("{}={} ", key, value))
+            .collect()
+    })
+}
+
+/// Redirects messages logged on the current thread to `sink` instead of tplog
+///
+/// The sink is per thread so parallel tests don't see each other's messages.
+#[cfg(any(test, feature = "test"))]
+pub fn set_test_sink(sink: LogSink) {
+    TEST_SINK.with(|s| *s.borrow_mut() = Some(sink));
+}
+
+/// Restores logging to tplog on the current thread
+#[cfg(any(test, feature = "test"))]
+pub fn clear_test_sink() {
+    TEST_SINK.with(|s| *s.borrow_mut() = None);
+}
+
 /// Log info message
 pub fn tplog_info(msg: &str) {
     log_message(LOG_INFO, msg);
@@ -30,9 +146,126 @@ pub fn tplog_debug(msg: &str) {
 }
 
 fn log_message(level: c_int, msg: &str) {
+    let fields = fields_prefix();
+    let prefixed;
+    let msg = match correlation_id() {
+        Some(id) => {
+            prefixed = format!("[{}] {}{}", id, fields, msg);
+            prefixed.as_str()
+        }
+        None if !fields.is_empty() => {
+            prefixed = format!("{}{}", fields, msg);
+            prefixed.as_str()
+        }
+        None => msg,
+    };
+
+    #[cfg(any(test, feature = "test"))]
+    {
+        let captured = TEST_SINK.with(|s| match s.borrow().as_ref() {
+            Some(sink) => {
+                if let Ok(mut messages) = sink.lock() {
+                    messages.push((LogLevel::from_raw(level), msg.to_string()));
+                }
+                true
+            }
+            None => false,
+        });
+        if captured {
+            return;
+        }
+    }
+
     if let Ok(c_msg) = CString::new(msg.to_string()) {
         unsafe {
             crate::ffi::tplog(level, c_msg.as_ptr());
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_captures_error() {
+        let sink: LogSink = Arc::new(Mutex::new(Vec::new()));
+        set_test_sink(sink.clone());
+
+        tplog_error("x");
+        tplog_info("y");
+
+        clear_test_sink();
+        tplog_error("not captured");
+
+        let messages = sink.lock().unwrap();
+        assert_eq!(
+            *messages,
+            vec![
+                (LogLevel::Error, "x".to_string()),
+                (LogLevel::Info, "y".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_correlation_id_prefix() {
+        let sink: LogSink = Arc::new(Mutex::new(Vec::new()));
+        set_test_sink(sink.clone());
+
+        with_correlation_id(Some("req-1"), || {
+            tplog_info("outer");
+            with_correlation_id(Some("req-2"), || tplog_info("inner"));
+            tplog_info("outer again");
+        });
+        tplog_info("plain");
+        clear_test_sink();
+
+        let messages: Vec<String> = sink
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, m)| m.clone())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[req-1] outer",
+                "[req-2] inner",
+                "[req-1] outer again",
+                "plain"
+            ]
+        );
+        assert_eq!(correlation_id(), None);
+    }
+
+    #[test]
+    fn test_with_fields_prefix() {
+        let sink: LogSink = Arc::new(Mutex::new(Vec::new()));
+        set_test_sink(sink.clone());
+
+        with_fields([("txn_id", "TXN-7")], || {
+            tplog_info("start");
+            with_fields([("account", 42)], || tplog_error("inner"));
+            with_correlation_id(Some("req-1"), || tplog_info("correlated"));
+        });
+        tplog_info("plain");
+        clear_test_sink();
+
+        let messages: Vec<String> = sink
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, m)| m.clone())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "txn_id=TXN-7 start",
+                "txn_id=TXN-7 account=42 inner",
+                "[req-1] txn_id=TXN-7 correlated",
+                "plain"
+            ]
+        );
+    }
+}
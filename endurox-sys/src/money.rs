@@ -0,0 +1,125 @@
+//! Money amounts in integer minor units
+//!
+//! `Money` keeps the amount as an `i64` of minor units (cents) next to its
+//! currency code, so amounts never pass through `f64` and stay exact beyond
+//! 2^53. In UBF it is a long `T_AMOUNT_FLD` plus a string `T_CURRENCY_FLD`.
+
+use crate::ubf::UbfBuffer;
+use crate::ubf_fields::{T_AMOUNT_FLD, T_CURRENCY_FLD};
+use crate::ubf_struct::{UbfError, UbfStruct};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Amount in minor units with its currency
+///
+/// Serializes as `{"amount": ..., "currency": ...}`; use `#[serde(flatten)]`
+/// to keep those keys at the level of the containing struct.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Money {
+    /// Minor units, e.g. cents
+    pub amount: i64,
+    /// ISO 4217 code, e.g. `USD`
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: i64, currency: &str) -> Self {
+        Money {
+            amount,
+            currency: currency.to_string(),
+        }
+    }
+
+    /// Sum of two amounts in the same currency, `None` on overflow or
+    /// currency mismatch
+    pub fn checked_add(&self, other: &Money) -> Option<Money> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(Money {
+            amount: self.amount.checked_add(other.amount)?,
+            currency: self.currency.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount, self.currency)
+    }
+}
+
+impl UbfStruct for Money {
+    fn from_ubf(buf: &UbfBuffer) -> Result<Self, UbfError> {
+        Self::from_ubf_occ(buf, 0)
+    }
+
+    fn to_ubf(&self) -> Result<UbfBuffer, UbfError> {
+        let mut buf = UbfBuffer::new(256).map_err(UbfError::AllocationError)?;
+        self.update_ubf(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn update_ubf(&self, buf: &mut UbfBuffer) -> Result<(), UbfError> {
+        self.update_ubf_occ(buf, 0)
+    }
+
+    fn from_ubf_occ(buf: &UbfBuffer, occ: i32) -> Result<Self, UbfError> {
+        let amount = buf
+            .get_long(T_AMOUNT_FLD, occ)
+            .map_err(|e| UbfError::FieldNotFound(format!("T_AMOUNT_FLD: {}", e)))?;
+
+        let currency = buf
+            .get_string(T_CURRENCY_FLD, occ)
+            .map_err(|e| UbfError::FieldNotFound(format!("T_CURRENCY_FLD: {}", e)))?;
+
+        Ok(Money { amount, currency })
+    }
+
+    fn update_ubf_occ(&self, buf: &mut UbfBuffer, occ: i32) -> Result<(), UbfError> {
+        buf.change_long(T_AMOUNT_FLD, occ, self.amount)
+            .map_err(|e| UbfError::TypeError(format!("amount: {}", e)))?;
+
+        buf.change_string(T_CURRENCY_FLD, occ, &self.currency)
+            .map_err(|e| UbfError::TypeError(format!("currency: {}", e)))?;
+
+        Ok(())
+    }
+
+    fn delete_ubf_occ(buf: &mut UbfBuffer, occ: i32) -> Result<(), UbfError> {
+        for field_id in [T_AMOUNT_FLD, T_CURRENCY_FLD] {
+            if buf.is_present(field_id, occ) {
+                buf.delete(field_id, occ).map_err(UbfError::TypeError)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_large_amount_round_trip() {
+        // Neither survives a round-trip through f64
+        for amount in [(1i64 << 53) + 1, i64::MAX - 1] {
+            assert_ne!(amount as f64 as i64, amount);
+
+            let money = Money::new(amount, "USD");
+            let buf = money.to_ubf().unwrap();
+            assert_eq!(Money::from_ubf(&buf).unwrap(), money);
+        }
+    }
+
+    #[test]
+    fn test_checked_add() {
+        let a = Money::new(150, "EUR");
+        assert_eq!(
+            a.checked_add(&Money::new(50, "EUR")),
+            Some(Money::new(200, "EUR"))
+        );
+        assert_eq!(a.checked_add(&Money::new(50, "USD")), None);
+        assert_eq!(Money::new(i64::MAX, "EUR").checked_add(&a), None);
+    }
+}
@@ -0,0 +1,147 @@
+//! Fixed pool of ATMI-initialized threads for async runtimes
+//!
+//! Every XATMI call must run on a thread that has its own `tpinit` context.
+//! Tokio's blocking pool grows and shrinks on demand, so a call routed through
+//! `spawn_blocking` can land on a thread that was never initialized and fail
+//! with `TPEPROTO`. `BlockingAtmiPool` instead owns a fixed set of threads,
+//! each holding an `EnduroxClient` for its whole lifetime, and hands calls to
+//! them over a shared queue.
+
+use crate::client::EnduroxClient;
+use crate::{tplog_error, tplog_info};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use tokio::sync::oneshot;
+
+type Job = Box<dyn FnOnce(&EnduroxClient) + Send + 'static>;
+
+/// Pool of threads with pinned ATMI contexts
+pub struct BlockingAtmiPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl BlockingAtmiPool {
+    /// Starts `size` threads and runs `tpinit` on each
+    ///
+    /// Fails if any thread cannot initialize its context.
+    pub fn new(size: usize) -> Result<Self, String> {
+        if size == 0 {
+            return Err("BlockingAtmiPool size must be at least 1".to_string());
+        }
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (init_tx, init_rx) = mpsc::channel::<Result<(), String>>();
+
+        let mut workers = Vec::with_capacity(size);
+        for id in 0..size {
+            let receiver = Arc::clone(&receiver);
+            let init_tx = init_tx.clone();
+
+            let handle = thread::Builder::new()
+                .name(format!("atmi-pool-{}", id))
+                .spawn(move || {
+                    let client = match EnduroxClient::new() {
+                        Ok(client) => {
+                            let _ = init_tx.send(Ok(()));
+                            client
+                        }
+                        Err(e) => {
+                            let _ = init_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    drop(init_tx);
+
+                    loop {
+                        // Hold the lock only while taking the next job
+                        let job = match receiver.lock() {
+                            Ok(rx) => rx.recv(),
+                            Err(_) => break,
+                        };
+
+                        match job {
+                            Ok(job) => job(&client),
+                            Err(_) => break, // Pool dropped
+                        }
+                    }
+                })
+                .map_err(|e| format!("Failed to spawn ATMI pool thread: {}", e))?;
+
+            workers.push(handle);
+        }
+        drop(init_tx);
+
+        let mut pool = BlockingAtmiPool {
+            sender: Some(sender),
+            workers,
+        };
+
+        for _ in 0..size {
+            match init_rx.recv() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    tplog_error(&format!("ATMI pool thread failed to initialize: {}", e));
+                    pool.shutdown();
+                    return Err(e);
+                }
+                Err(_) => {
+                    pool.shutdown();
+                    return Err("ATMI pool thread exited during initialization".to_string());
+                }
+            }
+        }
+
+        tplog_info(&format!("BlockingAtmiPool started with {} threads", size));
+        Ok(pool)
+    }
+
+    /// Number of pool threads
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Runs `f` on a pool thread and awaits its result
+    pub async fn execute<F, R>(&self, f: F) -> Result<R, String>
+    where
+        F: FnOnce(&EnduroxClient) -> Result<R, String> + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |client| {
+            let _ = tx.send(f(client));
+        });
+
+        self.sender
+            .as_ref()
+            .ok_or_else(|| "BlockingAtmiPool is shut down".to_string())?
+            .send(job)
+            .map_err(|_| "BlockingAtmiPool is shut down".to_string())?;
+
+        rx.await
+            .map_err(|_| "ATMI pool thread dropped the call".to_string())?
+    }
+
+    /// Calls a service with a UBF buffer on a pool thread
+    pub async fn call_async(&self, service: &str, buffer_data: Vec<u8>) -> Result<Vec<u8>, String> {
+        let service = service.to_string();
+        self.execute(move |client| client.call_service_ubf_blocking(&service, &buffer_data))
+            .await
+    }
+
+    fn shutdown(&mut self) {
+        // Closing the queue makes every idle thread leave its loop and tpterm
+        self.sender.take();
+        for handle in self.workers.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for BlockingAtmiPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
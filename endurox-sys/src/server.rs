@@ -1,9 +1,28 @@
 //! Server API - safe wrappers for server functions
 
+#[cfg(feature = "ubf")]
+use crate::error::{c_string, conversation_event, tperrno, TpError};
 use crate::ffi::{self, TpSvcInfoRaw, TPFAIL, TPSUCCESS};
+#[cfg(feature = "ubf")]
+use crate::flags::TpFlags;
+#[cfg(feature = "ubf")]
+use crate::ubf::{
+    base64_bytes, buffer_type, reply_buffer_via, FieldType, Tptypes, UbfBuffer, UbfValue,
+};
+#[cfg(feature = "ubf")]
+use crate::ubf_struct::UbfError;
 use libc::{c_char, c_int, c_long};
+#[cfg(feature = "ubf")]
+use serde_json::Value;
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
+use std::fmt;
 use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+pub use crate::xa::in_transaction;
 
 /// Buffer wrapper for automatic memory management
 pub struct TpBuffer {
@@ -59,6 +78,50 @@ impl TpBuffer {
         })
     }
 
+    /// Wraps a buffer allocated by tpalloc (e.g. the service request buffer)
+    ///
+    /// # Safety
+    /// Caller must ensure ptr was allocated by tpalloc with at least
+    /// `allocated_size` bytes and is not owned by another wrapper
+    pub unsafe fn from_raw(ptr: *mut c_char, allocated_size: usize) -> Self {
+        TpBuffer {
+            ptr,
+            len: 0,
+            allocated_size,
+        }
+    }
+
+    /// Resizes the buffer with tprealloc
+    ///
+    /// The buffer may move, so the stored pointer and size are updated together.
+    /// On failure the original buffer is left untouched.
+    pub fn realloc(&mut self, new_size: usize) -> Result<(), String> {
+        let new_ptr = unsafe { ffi::tprealloc(self.ptr, new_size as c_long) };
+
+        if new_ptr.is_null() {
+            return Err(format!("Failed to reallocate buffer to {} bytes", new_size));
+        }
+
+        self.ptr = new_ptr;
+        self.allocated_size = new_size;
+        Ok(())
+    }
+
+    /// Replaces the content with a NUL-terminated string, growing the buffer if needed
+    pub fn write_str(&mut self, content: &str) -> Result<(), String> {
+        let needed_size = content.len() + 1;
+        if needed_size > self.allocated_size {
+            self.realloc(needed_size)?;
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(content.as_ptr(), self.ptr as *mut u8, content.len());
+            *self.ptr.add(content.len()) = 0;
+        }
+        self.len = content.len();
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         self.len
     }
@@ -85,15 +148,68 @@ impl Drop for TpBuffer {
     }
 }
 
+/// Error of `advertise_service`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServerError {
+    /// Name longer than `XATMI_SERVICE_NAME_LENGTH`; Enduro/X would cut it
+    /// and advertise the service under a different name
+    NameTooLong(String),
+    /// Name containing a NUL byte
+    InvalidName(String),
+    /// `tpadvertise` failed, with the XATMI error message
+    Advertise(String),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerError::NameTooLong(name) => write!(
+                f,
+                "Service name '{}' is {} bytes, longer than the {} allowed",
+                name,
+                name.len(),
+                ffi::XATMI_SERVICE_NAME_LENGTH
+            ),
+            ServerError::InvalidName(name) => {
+                write!(f, "Service name {:?} contains a NUL byte", name)
+            }
+            ServerError::Advertise(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
 /// Registers a service
+///
+/// Names longer than `XATMI_SERVICE_NAME_LENGTH` are rejected rather than
+/// advertised truncated. Successfully advertised names are listed by
+/// `advertised_services`.
 pub fn advertise_service(
     name: &str,
     handler: extern "C" fn(*mut TpSvcInfoRaw),
-) -> Result<(), String> {
-    let c_name = CString::new(name).map_err(|e| e.to_string())?;
-    let c_funcname = CString::new("service_dispatcher").map_err(|e| e.to_string())?;
+) -> Result<(), ServerError> {
+    advertise_via(tpadvertise, name, handler)
+}
+
+/// tpadvertise of a service: name, dispatcher, function name
+pub(crate) type Tpadvertise = unsafe fn(&CStr, extern "C" fn(*mut TpSvcInfoRaw), &CStr) -> c_int;
+
+// `advertise_service` with the tpadvertise passed in; unit tests don't link
+// libatmisrv
+pub(crate) fn advertise_via(
+    tpadvertise: Tpadvertise,
+    name: &str,
+    handler: extern "C" fn(*mut TpSvcInfoRaw),
+) -> Result<(), ServerError> {
+    if name.len() > ffi::XATMI_SERVICE_NAME_LENGTH {
+        return Err(ServerError::NameTooLong(name.to_string()));
+    }
+    let c_name = CString::new(name).map_err(|_| ServerError::InvalidName(name.to_string()))?;
+    let c_funcname =
+        CString::new("service_dispatcher").map_err(|e| ServerError::Advertise(e.to_string()))?;
 
-    let result = unsafe { ffi::tpadvertise_full(c_name.as_ptr(), handler, c_funcname.as_ptr()) };
+    let result = unsafe { tpadvertise(&c_name, handler, &c_funcname) };
 
     if result == -1 {
         let err_msg = unsafe {
@@ -105,12 +221,33 @@ pub fn advertise_service(
                 "Unknown error".to_string()
             }
         };
-        return Err(err_msg);
+        return Err(ServerError::Advertise(err_msg));
+    }
+
+    let mut advertised = ADVERTISED_SERVICES.lock().unwrap();
+    if !advertised.iter().any(|s| s == name) {
+        advertised.push(name.to_string());
     }
 
     Ok(())
 }
 
+// Services advertised by this process, in advertising order
+static ADVERTISED_SERVICES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Names of the services this process has advertised
+pub fn advertised_services() -> Vec<String> {
+    ADVERTISED_SERVICES.lock().unwrap().clone()
+}
+
+pub(crate) unsafe fn tpadvertise(
+    name: &CStr,
+    handler: extern "C" fn(*mut TpSvcInfoRaw),
+    funcname: &CStr,
+) -> c_int {
+    ffi::tpadvertise_full(name.as_ptr(), handler, funcname.as_ptr())
+}
+
 /// Returns a successful result
 ///
 /// # Safety
@@ -151,6 +288,407 @@ pub unsafe fn tpreturn_success(rqst: *mut TpSvcInfoRaw, buffer: TpBuffer) {
     ffi::tpreturn(TPSUCCESS, 1, ret_ptr, len as c_long, 0);
 }
 
+/// Returns a string, reusing the request buffer when present
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+pub unsafe fn tpreturn_string(rqst: *mut TpSvcInfoRaw, rval: c_int, message: &str) {
+    let req = &*rqst;
+
+    let mut ret_buf = if req.data.is_null() {
+        match TpBuffer::new_string("") {
+            Ok(buf) => buf,
+            Err(e) => {
+                crate::tplog_error(&format!("Failed to allocate return buffer: {}", e));
+                tpreturn_fail(rqst);
+                return;
+            }
+        }
+    } else {
+        TpBuffer::from_raw(req.data, req.len.max(0) as usize)
+    };
+
+    if let Err(e) = ret_buf.write_str(message) {
+        crate::tplog_error(&format!("Failed to allocate return buffer: {}", e));
+        // The request buffer is still owned by Enduro/X
+        ret_buf.into_raw();
+        tpreturn_fail(rqst);
+        return;
+    }
+
+    let len = ret_buf.len();
+    ffi::tpreturn(rval, 0, ret_buf.into_raw(), len as c_long, 0);
+}
+
+/// Returns a JSON document in a new JSON buffer
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+pub unsafe fn tpreturn_json(rqst: *mut TpSvcInfoRaw, rval: c_int, json: &str) {
+    let ret_buf = match TpBuffer::new_json(json) {
+        Ok(buf) => buf,
+        Err(e) => {
+            crate::tplog_error(&format!("Failed to allocate JSON return buffer: {}", e));
+            tpreturn_fail(rqst);
+            return;
+        }
+    };
+
+    let len = ret_buf.len();
+    ffi::tpreturn(rval, 0, ret_buf.into_raw(), len as c_long, 0);
+}
+
+/// Returns a copy of a UBF buffer, reusing the request buffer when present
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+#[cfg(feature = "ubf")]
+pub unsafe fn tpreturn_ubf(rqst: *mut TpSvcInfoRaw, rval: c_int, buffer: &UbfBuffer) {
+    tpreturn_ubf_with(rqst, rval, 0, buffer);
+}
+
+/// `tpreturn_ubf` with a user return code (tpurcode on the caller side)
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+#[cfg(feature = "ubf")]
+pub unsafe fn tpreturn_ubf_with(
+    rqst: *mut TpSvcInfoRaw,
+    rval: c_int,
+    rcode: c_long,
+    buffer: &UbfBuffer,
+) {
+    let ret_buf = match outgoing_ubf(rqst, buffer) {
+        Ok(buf) => buf,
+        Err(e) => {
+            crate::tplog_error(&format!("Failed to allocate UBF return buffer: {}", e));
+            tpreturn_fail(rqst);
+            return;
+        }
+    };
+
+    let len = buffer.used();
+    ffi::tpreturn(rval, rcode, ret_buf.into_raw(), len as c_long, 0);
+}
+
+/// Forwards the request to another service with a UBF buffer
+///
+/// Like `tpreturn`, this ends the service call; the target service replies
+/// to the original caller.
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+#[cfg(feature = "ubf")]
+pub unsafe fn tpforward_ubf(rqst: *mut TpSvcInfoRaw, service: &str, buffer: &UbfBuffer) {
+    let c_service = match CString::new(service) {
+        Ok(s) => s,
+        Err(e) => {
+            crate::tplog_error(&format!("Invalid forward service name: {}", e));
+            tpreturn_fail(rqst);
+            return;
+        }
+    };
+
+    let fwd_buf = match outgoing_ubf(rqst, buffer) {
+        Ok(buf) => buf,
+        Err(e) => {
+            crate::tplog_error(&format!("Failed to allocate UBF forward buffer: {}", e));
+            tpreturn_fail(rqst);
+            return;
+        }
+    };
+
+    let len = buffer.used();
+    ffi::tpforward(
+        c_service.as_ptr() as *mut c_char,
+        fwd_buf.into_raw(),
+        len as c_long,
+        0,
+    );
+}
+
+/// Streams UBF buffers to the client of a conversational service
+///
+/// Each buffer goes out with its own `tpsend`, so the client can process
+/// rows while later ones are still being produced. The service still ends
+/// the conversation with `tpreturn`. Returns the number of buffers sent.
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+#[cfg(feature = "ubf")]
+pub unsafe fn send_ubf_stream<I>(rqst: *mut TpSvcInfoRaw, rows: I) -> Result<usize, TpError>
+where
+    I: IntoIterator<Item = UbfBuffer>,
+{
+    let req = &*rqst;
+    if !TpFlags::from_bits_truncate(req.flags).contains(TpFlags::CONV) {
+        return Err(TpError::InvalidArgument(
+            "service was not called with tpconnect".to_string(),
+        ));
+    }
+
+    let mut sent = 0;
+    for row in rows {
+        let mut revent: c_long = 0;
+        if ffi::tpsend(req.cd, row.as_ptr(), 0, 0, &mut revent) == -1 {
+            let errno = tperrno();
+            let err = match errno {
+                ffi::TPEEVENT => conversation_event(revent),
+                _ => None,
+            };
+            return Err(err.unwrap_or_else(|| TpError::from_errno(errno)));
+        }
+        sent += 1;
+    }
+
+    Ok(sent)
+}
+
+/// Runtime mapping of JSON keys to UBF fields, see `json_ubf_bridge`
+#[cfg(feature = "ubf")]
+#[derive(Debug, Clone, Default)]
+pub struct FieldMap {
+    fields: Vec<(String, i32, FieldType)>,
+}
+
+#[cfg(feature = "ubf")]
+impl FieldMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Maps the JSON key `name` to `field_id` of type `field_type`
+    pub fn field(mut self, name: &str, field_id: i32, field_type: FieldType) -> Self {
+        self.fields.retain(|(n, _, _)| n != name);
+        self.fields.push((name.to_string(), field_id, field_type));
+        self
+    }
+
+    /// Field id and type mapped to `name`
+    pub fn get(&self, name: &str) -> Option<(i32, FieldType)> {
+        self.fields
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .map(|&(_, id, field_type)| (id, field_type))
+    }
+
+    /// `(name, id, type)` in the order the fields were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, i32, FieldType)> {
+        self.fields
+            .iter()
+            .map(|(name, id, field_type)| (name.as_str(), *id, *field_type))
+    }
+}
+
+/// Converts a JSON object to a UBF buffer as described by `field_map`
+///
+/// An array value adds one occurrence per element. Carray values are base64
+/// text, char values one-character strings. Keys missing from the map are
+/// rejected rather than dropped.
+#[cfg(feature = "ubf")]
+pub fn json_ubf_bridge(req_json: &str, field_map: &FieldMap) -> Result<UbfBuffer, UbfError> {
+    let json: Value = serde_json::from_str(req_json)
+        .map_err(|e| UbfError::InvalidValue(format!("Invalid JSON: {}", e)))?;
+    let Value::Object(object) = json else {
+        return Err(UbfError::InvalidValue(
+            "JSON request must be an object".to_string(),
+        ));
+    };
+
+    let mut fields = Vec::new();
+    for (name, value) in &object {
+        let (field_id, field_type) = field_map
+            .get(name)
+            .ok_or_else(|| UbfError::FieldNotFound(name.clone()))?;
+
+        let items = match value {
+            Value::Array(items) => items.as_slice(),
+            single => std::slice::from_ref(single),
+        };
+        for item in items {
+            fields.push((field_id, json_to_ubf_value(name, item, field_type)?));
+        }
+    }
+
+    UbfBuffer::from_fields(&fields).map_err(UbfError::AllocationError)
+}
+
+/// Converts the fields of `field_map` in `buf` back to a JSON object
+///
+/// Absent fields are left out, a field with several occurrences becomes an
+/// array.
+#[cfg(feature = "ubf")]
+pub fn ubf_json_bridge(buf: &UbfBuffer, field_map: &FieldMap) -> Result<String, UbfError> {
+    let mut object = serde_json::Map::new();
+
+    for (name, field_id, _) in field_map.iter() {
+        let count = buf
+            .occurrences(field_id)
+            .map_err(|e| UbfError::FieldNotFound(format!("{}: {}", name, e)))?;
+
+        let mut values = Vec::with_capacity(count as usize);
+        for occ in 0..count {
+            let value = buf
+                .get_value(field_id, occ)
+                .map_err(|e| UbfError::TypeError(format!("{}: {}", name, e)))?;
+            values.push(ubf_value_to_json(value));
+        }
+
+        match values.len() {
+            0 => {}
+            1 => {
+                object.insert(name.to_string(), values.remove(0));
+            }
+            _ => {
+                object.insert(name.to_string(), Value::Array(values));
+            }
+        }
+    }
+
+    serde_json::to_string(&Value::Object(object)).map_err(|e| UbfError::InvalidValue(e.to_string()))
+}
+
+#[cfg(feature = "ubf")]
+fn json_to_ubf_value(
+    name: &str,
+    value: &Value,
+    field_type: FieldType,
+) -> Result<UbfValue, UbfError> {
+    let wrong_type =
+        || UbfError::TypeError(format!("{}: expected {}, got {}", name, field_type, value));
+
+    let ubf_value = match field_type {
+        FieldType::Short => UbfValue::Short(
+            value
+                .as_i64()
+                .and_then(|v| i16::try_from(v).ok())
+                .ok_or_else(wrong_type)?,
+        ),
+        FieldType::Long => UbfValue::Long(value.as_i64().ok_or_else(wrong_type)?),
+        FieldType::Char => {
+            let text = value.as_str().ok_or_else(wrong_type)?;
+            let mut chars = text.chars();
+            match (chars.next().map(u8::try_from), chars.next()) {
+                (Some(Ok(byte)), None) => UbfValue::Char(byte),
+                _ => return Err(wrong_type()),
+            }
+        }
+        FieldType::Float => UbfValue::Float(value.as_f64().ok_or_else(wrong_type)? as f32),
+        FieldType::Double => UbfValue::Double(value.as_f64().ok_or_else(wrong_type)?),
+        FieldType::String => UbfValue::String(value.as_str().ok_or_else(wrong_type)?.to_string()),
+        FieldType::Carray => {
+            let text = value.as_str().ok_or_else(wrong_type)?;
+            UbfValue::Carray(base64_bytes::decode(text).map_err(|_| wrong_type())?)
+        }
+    };
+    Ok(ubf_value)
+}
+
+#[cfg(feature = "ubf")]
+fn ubf_value_to_json(value: UbfValue) -> Value {
+    match value {
+        UbfValue::Short(v) => Value::from(v),
+        UbfValue::Long(v) => Value::from(v),
+        UbfValue::Char(v) => Value::from((v as char).to_string()),
+        UbfValue::Float(v) => Value::from(v as f64),
+        UbfValue::Double(v) => Value::from(v),
+        UbfValue::String(v) => Value::from(v),
+        UbfValue::Carray(v) => Value::from(base64_bytes::encode(&v)),
+    }
+}
+
+/// Calls `service` from inside a service handler and waits for the reply
+///
+/// Inside a global transaction the callee joins it unless `flags` has
+/// `TpFlags::NOTRAN`, which is what a non-transactional service such as an
+/// audit log needs. `buf` is copied, the caller keeps it.
+#[cfg(feature = "ubf")]
+pub fn call_out(service: &str, buf: &UbfBuffer, flags: TpFlags) -> Result<UbfBuffer, TpError> {
+    call_out_via(tpcall_ubf, buffer_type, service, buf, flags)
+}
+
+/// tpcall of a UBF request: service, send buffer, reply buffer, flags
+#[cfg(feature = "ubf")]
+type TpcallUbf = unsafe fn(&CStr, *mut c_char, *mut *mut c_char, c_long) -> c_int;
+
+// `call_out` with tpcall and tptypes passed in; unit tests can't reach a service
+#[cfg(feature = "ubf")]
+fn call_out_via(
+    tpcall: TpcallUbf,
+    tptypes: Tptypes,
+    service: &str,
+    buf: &UbfBuffer,
+    flags: TpFlags,
+) -> Result<UbfBuffer, TpError> {
+    let c_service = c_string(service, "Service name").map_err(TpError::InvalidArgument)?;
+    let send = UbfBuffer::from_bytes(buf.as_bytes()).map_err(TpError::InvalidArgument)?;
+
+    crate::tplog_info(&format!(
+        "call_out: service={}, flags={:#x}",
+        service,
+        flags.raw()
+    ));
+
+    let send_buf = send.into_raw();
+    let mut recv_buf: *mut c_char = send_buf;
+
+    let ret = unsafe { tpcall(&c_service, send_buf, &mut recv_buf, flags.raw()) };
+
+    if ret == -1 {
+        let err = TpError::last();
+        if !recv_buf.is_null() {
+            unsafe { ffi::tpfree(recv_buf) };
+        }
+        crate::tplog_error(&format!("call_out to {} failed: {}", service, err));
+        return Err(err);
+    }
+
+    unsafe { reply_buffer_via(tptypes, recv_buf) }
+}
+
+#[cfg(feature = "ubf")]
+unsafe fn tpcall_ubf(
+    service: &CStr,
+    send_buf: *mut c_char,
+    recv_buf: *mut *mut c_char,
+    flags: c_long,
+) -> c_int {
+    let mut recv_len: c_long = 0;
+    // 0 for UBF - length determined automatically
+    ffi::tpcall(
+        service.as_ptr(),
+        send_buf,
+        0,
+        recv_buf,
+        &mut recv_len,
+        flags,
+    )
+}
+
+/// Copies `buffer` into the request buffer, growing it as needed
+#[cfg(feature = "ubf")]
+unsafe fn outgoing_ubf(rqst: *mut TpSvcInfoRaw, buffer: &UbfBuffer) -> Result<UbfBuffer, String> {
+    let req = &*rqst;
+    let data = buffer.as_bytes();
+    // Keep the full buffer size so the copied UBF header matches the allocation
+    let needed_size = buffer.size();
+
+    let out_buf = if req.data.is_null() {
+        UbfBuffer::new(needed_size)?
+    } else {
+        let mut reused = UbfBuffer::from_raw(req.data);
+        if let Err(e) = reused.realloc(needed_size) {
+            // The request buffer is still owned by Enduro/X
+            reused.into_raw();
+            return Err(e);
+        }
+        reused
+    };
+
+    ptr::copy_nonoverlapping(data.as_ptr(), out_buf.as_ptr() as *mut u8, data.len());
+    Ok(out_buf)
+}
+
 /// Returns the same buffer that was received
 ///
 /// # Safety
@@ -166,8 +704,16 @@ pub unsafe fn tpreturn_echo(rqst: *mut TpSvcInfoRaw) {
 /// # Safety
 /// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
 pub unsafe fn tpreturn_fail(rqst: *mut TpSvcInfoRaw) {
+    tpreturn_fail_with(rqst, 0);
+}
+
+/// Returns an error with a user return code (tpurcode on the caller side)
+///
+/// # Safety
+/// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+pub unsafe fn tpreturn_fail_with(rqst: *mut TpSvcInfoRaw, rcode: c_long) {
     let req = &*rqst;
-    ffi::tpreturn(TPFAIL, 0, req.data, 0, 0);
+    ffi::tpreturn(TPFAIL, rcode, req.data, 0, 0);
 }
 
 /// Reads data from the request
@@ -200,11 +746,270 @@ pub unsafe fn get_service_name(rqst: *mut TpSvcInfoRaw) -> Result<String, String
     String::from_utf8(name_bytes).map_err(|e| e.to_string())
 }
 
+/// Upper bounds (inclusive, in bytes) of the payload size histogram buckets.
+/// Payloads larger than the last bound are counted in an extra overflow bucket.
+pub const SIZE_BUCKETS: [usize; 8] = [64, 256, 1024, 4096, 16384, 65536, 262144, 1048576];
+
+/// Number of histogram buckets including the overflow bucket
+pub const SIZE_BUCKET_COUNT: usize = SIZE_BUCKETS.len() + 1;
+
+/// Snapshot of payload size metrics for a single service
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceMetrics {
+    /// Number of requests received
+    pub requests: u64,
+    /// Total request payload bytes
+    pub request_bytes: u64,
+    /// Request count per size bucket (see `SIZE_BUCKETS`)
+    pub request_sizes: [u64; SIZE_BUCKET_COUNT],
+    /// Number of responses sent
+    pub responses: u64,
+    /// Total response payload bytes
+    pub response_bytes: u64,
+    /// Response count per size bucket (see `SIZE_BUCKETS`)
+    pub response_sizes: [u64; SIZE_BUCKET_COUNT],
+    /// Handler runs timed (`ServiceServerBuilder::with_timing`)
+    pub handled: u64,
+    /// Total wall-clock time of the timed handler runs
+    pub handler_time: Duration,
+    /// Slowest timed handler run
+    pub max_handler_time: Duration,
+}
+
+impl ServiceMetrics {
+    /// Mean time of the timed handler runs, zero before the first one
+    pub fn average_handler_time(&self) -> Duration {
+        if self.handled == 0 {
+            return Duration::ZERO;
+        }
+        let total_us = u64::try_from(self.handler_time.as_micros()).unwrap_or(u64::MAX);
+        Duration::from_micros(total_us / self.handled)
+    }
+}
+
+#[derive(Default)]
+struct SizeHistogram {
+    count: AtomicU64,
+    total: AtomicU64,
+    buckets: [AtomicU64; SIZE_BUCKET_COUNT],
+}
+
+impl SizeHistogram {
+    fn record(&self, bytes: usize) {
+        let bucket = SIZE_BUCKETS
+            .iter()
+            .position(|&bound| bytes <= bound)
+            .unwrap_or(SIZE_BUCKETS.len());
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn load(&self) -> (u64, u64, [u64; SIZE_BUCKET_COUNT]) {
+        let mut buckets = [0u64; SIZE_BUCKET_COUNT];
+        for (dst, src) in buckets.iter_mut().zip(self.buckets.iter()) {
+            *dst = src.load(Ordering::Relaxed);
+        }
+        (
+            self.count.load(Ordering::Relaxed),
+            self.total.load(Ordering::Relaxed),
+            buckets,
+        )
+    }
+}
+
+#[derive(Default)]
+struct HandlerTimes {
+    count: AtomicU64,
+    total_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl HandlerTimes {
+    fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+}
+
+#[derive(Default)]
+struct ServiceCounters {
+    requests: SizeHistogram,
+    responses: SizeHistogram,
+    handler: HandlerTimes,
+}
+
+type MetricsRegistry = RwLock<HashMap<String, Arc<ServiceCounters>>>;
+
+static SERVICE_METRICS: OnceLock<MetricsRegistry> = OnceLock::new();
+
+fn service_counters(service: &str) -> Arc<ServiceCounters> {
+    let registry = SERVICE_METRICS.get_or_init(Default::default);
+
+    if let Some(counters) = registry.read().unwrap().get(service) {
+        return counters.clone();
+    }
+
+    registry
+        .write()
+        .unwrap()
+        .entry(service.to_string())
+        .or_default()
+        .clone()
+}
+
+/// Records the size of a request received by a service
+pub fn record_request(service: &str, bytes: usize) {
+    service_counters(service).requests.record(bytes);
+}
+
+/// Records the size of a response sent by a service
+pub fn record_response(service: &str, bytes: usize) {
+    service_counters(service).responses.record(bytes);
+}
+
+/// Records the wall-clock time of one handler run
+pub fn record_handler_time(service: &str, elapsed: Duration) {
+    service_counters(service).handler.record(elapsed);
+}
+
+/// Returns payload size metrics for every service seen by the dispatcher
+pub fn metrics_snapshot() -> HashMap<String, ServiceMetrics> {
+    let registry = match SERVICE_METRICS.get() {
+        Some(registry) => registry,
+        None => return HashMap::new(),
+    };
+
+    registry
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(service, counters)| {
+            let (requests, request_bytes, request_sizes) = counters.requests.load();
+            let (responses, response_bytes, response_sizes) = counters.responses.load();
+            (
+                service.clone(),
+                ServiceMetrics {
+                    requests,
+                    request_bytes,
+                    request_sizes,
+                    responses,
+                    response_bytes,
+                    response_sizes,
+                    handled: counters.handler.count.load(Ordering::Relaxed),
+                    handler_time: Duration::from_micros(
+                        counters.handler.total_us.load(Ordering::Relaxed),
+                    ),
+                    max_handler_time: Duration::from_micros(
+                        counters.handler.max_us.load(Ordering::Relaxed),
+                    ),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Logs the request and response counts of every service, e.g. from a
+/// shutdown hook
+pub fn log_metrics_summary() {
+    let mut metrics: Vec<_> = metrics_snapshot().into_iter().collect();
+    metrics.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (service, m) in metrics {
+        crate::tplog_info(&format!(
+            "{}: {} requests ({} bytes), {} responses ({} bytes)",
+            service, m.requests, m.request_bytes, m.responses, m.response_bytes
+        ));
+        if m.handled > 0 {
+            crate::tplog_info(&format!(
+                "{}: {} timed runs, avg {:?}, max {:?}",
+                service,
+                m.handled,
+                m.average_handler_time(),
+                m.max_handler_time
+            ));
+        }
+    }
+}
+
+/// sizeof(TPSVCINFO) of Enduro/X 8.x on 64-bit targets, used when the
+/// headers were not found at build time
+#[cfg(target_pointer_width = "64")]
+const DOCUMENTED_TPSVCINFO_SIZE: Option<usize> = Some(200);
+#[cfg(not(target_pointer_width = "64"))]
+const DOCUMENTED_TPSVCINFO_SIZE: Option<usize> = None;
+
+fn expected_tpsvcinfo_size() -> Option<usize> {
+    option_env!("ENDUROX_TPSVCINFO_SIZE")
+        .and_then(|size| size.parse().ok())
+        .or(DOCUMENTED_TPSVCINFO_SIZE)
+}
+
+/// Checks that `TpSvcInfoRaw` matches the installed `TPSVCINFO` layout
+///
+/// A mismatch (e.g. a different `XATMI_SERVICE_NAME_LENGTH` or `CLIENTID`
+/// size) would make every service read garbage, so it is logged as an error.
+/// Called by `run_server` before `ndrx_main`.
+pub fn verify_abi() -> Result<(), String> {
+    let Some(expected) = expected_tpsvcinfo_size() else {
+        crate::tplog_warn("TPSVCINFO size unknown for this target, skipping ABI check");
+        return Ok(());
+    };
+
+    let actual = std::mem::size_of::<TpSvcInfoRaw>();
+    if actual != expected {
+        let msg = format!(
+            "ABI mismatch: TpSvcInfoRaw is {} bytes but the installed Enduro/X TPSVCINFO is {} bytes, \
+             service requests will be misread (rebuild endurox-sys against this install)",
+            actual, expected
+        );
+        crate::tplog_error(&msg);
+        return Err(msg);
+    }
+
+    Ok(())
+}
+
+/// Converts the `argc`/`argv` passed to `tpsvrinit` into owned strings
+///
+/// Null entries are skipped, invalid UTF-8 is replaced lossily.
+///
+/// # Safety
+/// Caller must ensure argv points to at least argc C strings (or is null)
+pub unsafe fn parse_args(argc: c_int, argv: *mut *mut c_char) -> Vec<String> {
+    if argv.is_null() || argc <= 0 {
+        return Vec::new();
+    }
+
+    (0..argc as usize)
+        .map(|i| *argv.add(i))
+        .filter(|arg| !arg.is_null())
+        .map(|arg| CStr::from_ptr(arg).to_string_lossy().into_owned())
+        .collect()
+}
+
+/// Server-specific arguments, i.e. everything after `--`
+///
+/// ndrxd starts servers as `<binary> <sysopt> -- <appopt>`, so the
+/// `<appopt>` of ndrxconfig.xml ends up here.
+pub fn app_args(args: &[String]) -> &[String] {
+    match args.iter().position(|arg| arg == "--") {
+        Some(pos) => &args[pos + 1..],
+        None => &[],
+    }
+}
+
 /// Entry point for server binary
 pub fn run_server(
     tpsvrinit: extern "C" fn(c_int, *mut *mut c_char) -> c_int,
     tpsvrdone: extern "C" fn(),
 ) -> ! {
+    // Logged loudly, the server still starts
+    let _ = verify_abi();
+
     // Export functions for libatmisrvnomain
     unsafe {
         G_tpsvrinit__ = tpsvrinit;
@@ -252,3 +1057,180 @@ pub static mut ndrx_G_tpsvrthrinit: TpsvrInitPtr = ptr::null_mut();
 
 #[no_mangle]
 pub static mut ndrx_G_tpsvrthrdone: TpsvrDonePtr = ptr::null_mut();
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Advertising always succeeds
+    pub(crate) unsafe fn fake_tpadvertise(
+        _name: &CStr,
+        _handler: extern "C" fn(*mut TpSvcInfoRaw),
+        _funcname: &CStr,
+    ) -> c_int {
+        0
+    }
+
+    #[test]
+    fn test_advertise_rejects_long_name() {
+        extern "C" fn noop(_rqst: *mut TpSvcInfoRaw) {}
+
+        let long = "TRANSACTION_HISTORY_EXPORT_SERVICE";
+        assert_eq!(
+            advertise_via(fake_tpadvertise, long, noop),
+            Err(ServerError::NameTooLong(long.to_string()))
+        );
+        assert!(!advertised_services().iter().any(|s| s == long));
+
+        let longest = "A".repeat(ffi::XATMI_SERVICE_NAME_LENGTH);
+        advertise_via(fake_tpadvertise, &longest, noop).unwrap();
+        assert!(advertised_services().contains(&longest));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let owned: Vec<CString> = [
+            "samplesvr_rust",
+            "-k",
+            "0myWI5nu",
+            "-i",
+            "1",
+            "--",
+            "-d",
+            "oracle",
+        ]
+        .iter()
+        .map(|arg| CString::new(*arg).unwrap())
+        .collect();
+        let mut argv: Vec<*mut c_char> = owned
+            .iter()
+            .map(|arg| arg.as_ptr() as *mut c_char)
+            .collect();
+        argv.push(ptr::null_mut());
+
+        let args = unsafe { parse_args(owned.len() as c_int, argv.as_mut_ptr()) };
+        assert_eq!(args.len(), 8);
+        assert_eq!(args[0], "samplesvr_rust");
+        assert_eq!(app_args(&args), ["-d", "oracle"]);
+
+        assert!(unsafe { parse_args(0, ptr::null_mut()) }.is_empty());
+        assert!(app_args(&args[..5]).is_empty());
+    }
+
+    #[test]
+    fn test_verify_abi() {
+        assert_eq!(verify_abi(), Ok(()));
+    }
+
+    #[cfg(feature = "ubf")]
+    #[test]
+    fn test_call_out_passes_flags_to_tpcall() {
+        use crate::ubf_fields::T_NAME_FLD;
+        use std::cell::Cell;
+
+        let mut buf = UbfBuffer::new(256).unwrap();
+        buf.add_string(T_NAME_FLD, "audit").unwrap();
+
+        thread_local! {
+            static TPCALL_FLAGS: Cell<Option<c_long>> = const { Cell::new(None) };
+        }
+
+        // Records the flags and replies with the request
+        unsafe fn fake_tpcall(
+            _service: &CStr,
+            send_buf: *mut c_char,
+            recv_buf: *mut *mut c_char,
+            flags: c_long,
+        ) -> c_int {
+            TPCALL_FLAGS.with(|f| f.set(Some(flags)));
+            *recv_buf = send_buf;
+            0
+        }
+
+        unsafe fn ubf_reply(_buf: *mut c_char) -> Option<String> {
+            Some("UBF".to_string())
+        }
+
+        let flags = TpFlags::NOTRAN | TpFlags::NOTIME;
+        let reply = call_out_via(fake_tpcall, ubf_reply, "AUDIT", &buf, flags).unwrap();
+        assert_eq!(
+            TPCALL_FLAGS.with(|f| f.get()),
+            Some(ffi::TPNOTRAN | ffi::TPNOTIME)
+        );
+        assert_eq!(reply.get_string(T_NAME_FLD, 0).unwrap(), "audit");
+        // The caller's buffer is untouched
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "audit");
+
+        assert!(call_out_via(fake_tpcall, ubf_reply, "BAD\0NAME", &buf, TpFlags::empty()).is_err());
+    }
+
+    #[test]
+    fn test_service_metrics() {
+        record_request("METRICS_TEST", 10);
+        record_request("METRICS_TEST", 2000);
+        record_response("METRICS_TEST", 5_000_000);
+
+        let snapshot = metrics_snapshot();
+        let metrics = snapshot.get("METRICS_TEST").expect("metrics recorded");
+
+        assert_eq!(metrics.requests, 2);
+        assert_eq!(metrics.request_bytes, 2010);
+        assert_eq!(metrics.request_sizes[0], 1);
+        assert_eq!(metrics.request_sizes[3], 1);
+        assert_eq!(metrics.responses, 1);
+        assert_eq!(metrics.response_bytes, 5_000_000);
+        assert_eq!(metrics.response_sizes[SIZE_BUCKET_COUNT - 1], 1);
+    }
+
+    #[test]
+    fn test_average_handler_time() {
+        let mut metrics = ServiceMetrics::default();
+        assert_eq!(metrics.average_handler_time(), Duration::ZERO);
+
+        metrics.handled = 4;
+        metrics.handler_time = Duration::from_millis(10);
+        assert_eq!(metrics.average_handler_time(), Duration::from_micros(2500));
+
+        // More runs than fit in a u32
+        metrics.handled = u64::from(u32::MAX) * 2;
+        metrics.handler_time = Duration::from_secs(u64::from(u32::MAX) * 6);
+        assert_eq!(metrics.average_handler_time(), Duration::from_secs(3));
+    }
+
+    #[test]
+    #[cfg(feature = "ubf")]
+    fn test_json_ubf_bridge_round_trip() {
+        use crate::ubf_fields::*;
+
+        let map = FieldMap::new()
+            .field("name", T_NAME_FLD, FieldType::String)
+            .field("amounts", T_AMOUNT_FLD, FieldType::Long)
+            .field("price", T_PRICE_FLD, FieldType::Double)
+            .field("flag", T_CHAR_FLD, FieldType::Char)
+            .field("payload", T_CARRAY_FLD, FieldType::Carray)
+            .field("missing", T_STATUS_FLD, FieldType::String);
+
+        let json =
+            r#"{"name":"alice","amounts":[10,20,30],"price":9.5,"flag":"Y","payload":"AAEC"}"#;
+        let buf = json_ubf_bridge(json, &map).unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "alice");
+        assert_eq!(buf.occurrences(T_AMOUNT_FLD).unwrap(), 3);
+        assert_eq!(buf.get_long(T_AMOUNT_FLD, 2).unwrap(), 30);
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 9.5);
+        assert_eq!(buf.get_carray(T_CARRAY_FLD, 0).unwrap(), vec![0, 1, 2]);
+
+        let back: Value = serde_json::from_str(&ubf_json_bridge(&buf, &map).unwrap()).unwrap();
+        assert_eq!(back, serde_json::from_str::<Value>(json).unwrap());
+
+        // Unmapped keys and mistyped values are rejected
+        assert!(matches!(
+            json_ubf_bridge(r#"{"other":1}"#, &map),
+            Err(UbfError::FieldNotFound(_))
+        ));
+        assert!(matches!(
+            json_ubf_bridge(r#"{"amounts":"ten"}"#, &map),
+            Err(UbfError::TypeError(_))
+        ));
+        assert!(json_ubf_bridge("[1, 2]", &map).is_err());
+    }
+}
@@ -0,0 +1,1775 @@
+//! Service request/response types shared by UBF servers
+
+use crate::ffi::{self, TpSvcInfoRaw, TPFAIL, TPSUCCESS};
+use crate::flags::TpFlags;
+use crate::idempotency::{self, CachedReply, DedupStore};
+use crate::log::with_correlation_id;
+use crate::server::{
+    advertise_via, advertised_services, app_args, record_handler_time, record_request,
+    record_response, tpadvertise, Tpadvertise,
+};
+use crate::server::{
+    tpforward_ubf, tpreturn_fail_with, tpreturn_json, tpreturn_string, tpreturn_ubf,
+    tpreturn_ubf_with,
+};
+use crate::ubf::{buffer_type, last_ubf_errno, GrowthPolicy, Tptypes, UbfBuffer};
+use crate::ubf_fields::T_TRANS_ID_FLD;
+use crate::ubf_struct::{UbfError, UbfStruct};
+use crate::{tplog_error, tplog_info, tplog_warn};
+use libc::{c_int, c_long};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Largest reply buffer `ServiceResult::ubf_sized` grows to
+pub const MAX_REPLY_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// User return code (tpurcode) of a call rejected by the `with_auth` check
+pub const AUTH_DENIED_RCODE: i64 = 403;
+
+/// User return code (tpurcode) of a `ServiceResult::logical_error_ubf` reply
+pub const LOGICAL_ERROR_RCODE: i64 = 422;
+
+/// Incoming service request
+#[derive(Debug)]
+pub struct ServiceRequest {
+    pub service_name: String,
+    pub ubf_buffer: Option<UbfBuffer>,
+    /// Application key of the caller (TPSVCINFO.appkey)
+    pub appkey: i64,
+    /// Tenant resolved from `appkey` by the `ServiceServer`
+    pub tenant: Option<String>,
+    /// Call mode flags (TPSVCINFO.flags)
+    pub flags: TpFlags,
+}
+
+impl ServiceRequest {
+    /// Parses the request from the raw service info
+    ///
+    /// # Safety
+    /// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+    pub unsafe fn from_raw(rqst: *mut TpSvcInfoRaw) -> Result<Self, String> {
+        Self::from_raw_via(buffer_type, rqst)
+    }
+
+    // `from_raw` with tptypes passed in; it needs ATMI config
+    unsafe fn from_raw_via(tptypes: Tptypes, rqst: *mut TpSvcInfoRaw) -> Result<Self, String> {
+        let req = &*rqst;
+
+        // Parse the service name from the TpSvcInfoRaw structure
+        let service_name = CStr::from_ptr(req.name.as_ptr())
+            .to_str()
+            .map_err(|e| format!("Invalid UTF-8 in service name: {}", e))?
+            .to_string();
+
+        // Try to get UBF buffer if data is present. For UBF the length is
+        // implicit and `len` is often 0, so Bused gives the real size.
+        let data_len = if req.data.is_null() {
+            0
+        } else if matches!(tptypes(req.data).as_deref(), Some("UBF" | "FML" | "FML32")) {
+            ffi::Bused(req.data).max(0) as usize
+        } else {
+            req.len.max(0) as usize
+        };
+
+        let ubf_buffer = if data_len > 0 {
+            let buffer_data = std::slice::from_raw_parts(req.data as *const u8, data_len);
+            UbfBuffer::from_bytes(buffer_data).ok()
+        } else {
+            None
+        };
+
+        #[allow(clippy::unnecessary_cast)] // c_long is 32-bit on some targets
+        let appkey = req.appkey as i64;
+
+        Ok(ServiceRequest {
+            service_name,
+            ubf_buffer,
+            appkey,
+            tenant: None,
+            flags: TpFlags::from_bits_truncate(req.flags),
+        })
+    }
+
+    pub fn service_name(&self) -> String {
+        self.service_name.clone()
+    }
+
+    /// Server id (`<srvid>` / `-i`) of the instance handling the request
+    ///
+    /// Requests are always handled by the calling process, so this reads
+    /// the process' own ATMI server id.
+    pub fn server_id(&self) -> i32 {
+        unsafe { ffi::tpgetsrvid() }
+    }
+
+    /// Cluster node the request is handled on
+    pub fn node_id(&self) -> i64 {
+        #[allow(clippy::unnecessary_cast)] // c_long is 32-bit on some targets
+        unsafe {
+            ffi::tpgetnodeid() as i64
+        }
+    }
+
+    /// Request opened a conversation (`TPCONV`)
+    pub fn is_conversational(&self) -> bool {
+        self.flags.contains(TpFlags::CONV)
+    }
+
+    /// Request runs in the caller's global transaction (`TPTRAN`)
+    pub fn is_transactional(&self) -> bool {
+        self.flags.contains(TpFlags::TRAN)
+    }
+
+    /// Tenant of the caller, if the server has a `TenantMap` covering its appkey
+    pub fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Idempotency key stamped by the client, if any
+    pub fn idempotency_key(&self) -> Option<String> {
+        self.ubf_buffer
+            .as_ref()
+            .and_then(idempotency::idempotency_key)
+    }
+
+    /// Transaction id (`T_TRANS_ID_FLD`) for log context, empty when missing
+    pub fn transaction_id(&self) -> String {
+        self.ubf_buffer
+            .as_ref()
+            .and_then(|buf| buf.get_string(T_TRANS_ID_FLD, 0).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Maps caller appkeys to tenant ids
+#[derive(Debug, Clone, Default)]
+pub struct TenantMap {
+    tenants: HashMap<i64, String>,
+}
+
+impl TenantMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a mapping from `appkey` to `tenant_id`
+    pub fn with_tenant(mut self, appkey: i64, tenant_id: &str) -> Self {
+        self.tenants.insert(appkey, tenant_id.to_string());
+        self
+    }
+
+    pub fn tenant_for(&self, appkey: i64) -> Option<&str> {
+        self.tenants.get(&appkey).map(|t| t.as_str())
+    }
+}
+
+/// Runs `handler` once per idempotency key
+///
+/// A request carrying a key already present in `store` gets the stored reply
+/// instead of running the handler again. Requests without a key always run.
+/// Only successful replies are stored: a failure may be transient (e.g. a
+/// database timeout), so a retry with the same key runs the handler again.
+pub fn call_idempotent<F>(
+    store: &dyn DedupStore,
+    request: &ServiceRequest,
+    handler: F,
+) -> ServiceResult
+where
+    F: FnOnce(&ServiceRequest) -> ServiceResult,
+{
+    let Some(key) = request.idempotency_key() else {
+        return handler(request);
+    };
+
+    if let Some(cached) = store.get(&key) {
+        tplog_info(&format!(
+            "Duplicate request {} for {}, replaying stored reply",
+            key, request.service_name
+        ));
+        match ServiceResult::from_cached(&cached) {
+            Ok(result) => return result,
+            Err(e) => tplog_error(&format!("Failed to replay reply for {}: {}", key, e)),
+        }
+    }
+
+    let result = handler(request);
+    // The reply of a forwarded call comes from the target service
+    if result.success && result.forward_to.is_none() {
+        store.put(&key, result.to_cached());
+    }
+    result
+}
+
+/// Outcome of a service handler
+#[derive(Debug)]
+pub struct ServiceResult {
+    pub success: bool,
+    pub message: String,
+    pub ubf_buffer: Option<UbfBuffer>,
+    /// Service the request is forwarded to instead of replying
+    pub forward_to: Option<String>,
+    /// User return code of a failed call (tpurcode on the caller side)
+    pub rcode: i64,
+    /// `message` is a JSON document, sent in a JSON buffer
+    pub json: bool,
+}
+
+impl ServiceResult {
+    pub fn success(message: &str) -> Self {
+        ServiceResult {
+            success: true,
+            message: message.to_string(),
+            ubf_buffer: None,
+            forward_to: None,
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    pub fn success_ubf(ubf_buffer: UbfBuffer) -> Self {
+        ServiceResult {
+            success: true,
+            message: String::new(),
+            ubf_buffer: Some(ubf_buffer),
+            forward_to: None,
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    pub fn error(message: &str) -> Self {
+        ServiceResult {
+            success: false,
+            message: message.to_string(),
+            ubf_buffer: None,
+            forward_to: None,
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    pub fn error_ubf(ubf_buffer: UbfBuffer) -> Self {
+        ServiceResult {
+            success: false,
+            message: String::new(),
+            ubf_buffer: Some(ubf_buffer),
+            forward_to: None,
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    /// Fails the call with `TPFAIL`, still sending `ubf_buffer` to the caller
+    ///
+    /// For business errors such as a rejected transaction. A service can
+    /// instead reply `success_ubf` with the error fields set:
+    ///
+    /// - `success_ubf`: the caller's `tpcall` succeeds and it has to read the
+    ///   status from the buffer; a global transaction is left to commit.
+    /// - `logical_error_ubf`: `tpcall` fails with `TPESVCFAIL` and tpurcode
+    ///   `LOGICAL_ERROR_RCODE`, the buffer still carries the details, and a
+    ///   global transaction becomes abort-only.
+    ///
+    /// `error_ubf` also sends `TPFAIL` with a buffer, but with tpurcode 0,
+    /// so callers can't tell a refused request from a broken service.
+    pub fn logical_error_ubf(ubf_buffer: UbfBuffer) -> Self {
+        ServiceResult {
+            rcode: LOGICAL_ERROR_RCODE,
+            ..ServiceResult::error_ubf(ubf_buffer)
+        }
+    }
+
+    /// Encodes `value` into a new buffer of `hint` bytes and succeeds with it
+    ///
+    /// When the struct doesn't fit, the buffer is allocated again at double
+    /// the size (up to `MAX_REPLY_BUFFER_SIZE`) instead of failing, so `hint`
+    /// only needs to cover the usual reply.
+    pub fn ubf_sized<T: UbfStruct>(value: &T, hint: usize) -> Self {
+        let mut size = hint.max(1);
+        loop {
+            let mut buf = match UbfBuffer::new(size) {
+                Ok(buf) => buf,
+                Err(e) => {
+                    tplog_error(&format!("Failed to allocate {} byte reply: {}", size, e));
+                    return ServiceResult::error(&e);
+                }
+            };
+
+            match value.update_ubf(&mut buf) {
+                Ok(()) => return ServiceResult::success_ubf(buf),
+                Err(_) if last_ubf_errno() == ffi::BNOSPACE && size < MAX_REPLY_BUFFER_SIZE => {
+                    size = GrowthPolicy::Double
+                        .next_size(size, size + 1)
+                        .min(MAX_REPLY_BUFFER_SIZE);
+                }
+                Err(e) => return ServiceResult::from(e),
+            }
+        }
+    }
+
+    /// Forwards the request to `service` with `ubf_buffer` (tpforward)
+    ///
+    /// The dispatcher performs the forward after the handler returns; the
+    /// target service replies to the original caller.
+    pub fn forward(service: &str, ubf_buffer: UbfBuffer) -> Self {
+        ServiceResult {
+            success: true,
+            message: String::new(),
+            ubf_buffer: Some(ubf_buffer),
+            forward_to: Some(service.to_string()),
+            rcode: 0,
+            json: false,
+        }
+    }
+
+    /// Snapshot of the result for a `DedupStore`
+    pub fn to_cached(&self) -> CachedReply {
+        CachedReply {
+            success: self.success,
+            message: self.message.clone(),
+            ubf_data: self.ubf_buffer.as_ref().map(|buf| buf.as_bytes().to_vec()),
+            rcode: self.rcode,
+            json: self.json,
+        }
+    }
+
+    /// Rebuilds a result from a `DedupStore` entry
+    pub fn from_cached(cached: &CachedReply) -> Result<Self, String> {
+        let ubf_buffer = match cached.ubf_data {
+            Some(ref data) => Some(UbfBuffer::from_bytes(data)?),
+            None => None,
+        };
+
+        Ok(ServiceResult {
+            success: cached.success,
+            message: cached.message.clone(),
+            ubf_buffer,
+            forward_to: None,
+            rcode: cached.rcode,
+            json: cached.json,
+        })
+    }
+
+    /// Reply the dispatcher sends for this result
+    pub fn into_response(self) -> Response {
+        match (self.success, self.ubf_buffer, self.forward_to) {
+            (_, Some(buf), Some(service)) => Response::Forward(service, buf),
+            (true, Some(buf), None) => Response::Ubf(buf),
+            (true, None, _) if self.json => Response::Json(self.message),
+            (true, None, _) => Response::String(self.message),
+            (false, Some(buf), None) if self.rcode == LOGICAL_ERROR_RCODE => {
+                Response::LogicalError(buf)
+            }
+            (false, Some(buf), None) => Response::FailUbf(buf),
+            (false, None, _) => Response::Fail(self.rcode),
+        }
+    }
+}
+
+impl From<Response> for ServiceResult {
+    fn from(response: Response) -> Self {
+        match response {
+            Response::Ubf(buf) => ServiceResult::success_ubf(buf),
+            Response::Json(body) => ServiceResult {
+                json: true,
+                ..ServiceResult::success(&body)
+            },
+            Response::String(message) => ServiceResult::success(&message),
+            Response::Fail(rcode) => ServiceResult {
+                rcode,
+                ..ServiceResult::error("")
+            },
+            Response::FailUbf(buf) => ServiceResult::error_ubf(buf),
+            Response::LogicalError(buf) => ServiceResult::logical_error_ubf(buf),
+            Response::Forward(service, buf) => ServiceResult::forward(&service, buf),
+        }
+    }
+}
+
+/// Reply of a service call
+///
+/// Handlers may return it directly; nothing is allocated for the reply until
+/// the dispatcher sends it, and the variant picks the buffer type and the
+/// single `tpreturn` or `tpforward`.
+#[derive(Debug)]
+pub enum Response {
+    /// `TPSUCCESS` with a UBF buffer
+    Ubf(UbfBuffer),
+    /// `TPSUCCESS` with a JSON buffer
+    Json(String),
+    /// `TPSUCCESS` with a STRING buffer
+    String(String),
+    /// `TPFAIL` with a user return code and no payload
+    Fail(i64),
+    /// `TPFAIL` with a UBF buffer, e.g. error details for the caller
+    FailUbf(UbfBuffer),
+    /// `TPFAIL` with a UBF buffer and `LOGICAL_ERROR_RCODE`, see
+    /// `ServiceResult::logical_error_ubf`
+    LogicalError(UbfBuffer),
+    /// Forwards the request to a service, which replies to the caller
+    Forward(String, UbfBuffer),
+}
+
+impl Response {
+    /// Size of the payload `send` returns
+    pub fn payload_len(&self) -> usize {
+        match self {
+            Response::Ubf(buf)
+            | Response::FailUbf(buf)
+            | Response::LogicalError(buf)
+            | Response::Forward(_, buf) => buf.used(),
+            Response::Json(body) | Response::String(body) => body.len(),
+            Response::Fail(_) => 0,
+        }
+    }
+
+    /// Ends the service call with tpreturn, or tpforward for `Forward`
+    ///
+    /// # Safety
+    /// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+    pub unsafe fn send(self, rqst: *mut TpSvcInfoRaw) {
+        self.send_to(&Xatmi, rqst);
+    }
+
+    unsafe fn send_to(self, sink: &impl ReplySink, rqst: *mut TpSvcInfoRaw) {
+        match self {
+            Response::Ubf(buf) => {
+                tplog_info("Service responded successfully with UBF buffer");
+                sink.ubf(rqst, TPSUCCESS, &buf);
+            }
+            Response::Json(body) => {
+                tplog_info("Service responded successfully with JSON buffer");
+                sink.json(rqst, TPSUCCESS, &body);
+            }
+            Response::String(message) => {
+                tplog_info(&format!("Service responded successfully: {}", message));
+                sink.string(rqst, TPSUCCESS, &message);
+            }
+            Response::Fail(rcode) => {
+                tplog_error(&format!("Service failed with rcode {}", rcode));
+                // c_long is 32-bit on some targets
+                #[allow(clippy::unnecessary_cast)]
+                sink.fail(rqst, rcode as c_long);
+            }
+            Response::FailUbf(buf) => {
+                tplog_error("Service responded with UBF error");
+                sink.ubf(rqst, TPFAIL, &buf);
+            }
+            Response::LogicalError(buf) => {
+                tplog_error("Service responded with logical error");
+                // c_long is 32-bit on some targets
+                #[allow(clippy::unnecessary_cast)]
+                sink.ubf_with(rqst, TPFAIL, LOGICAL_ERROR_RCODE as c_long, &buf);
+            }
+            Response::Forward(service, buf) => {
+                tplog_info(&format!("Service forwarding request to {}", service));
+                sink.forward(rqst, &service, &buf);
+            }
+        }
+    }
+}
+
+/// How a service call is ended; unit tests record the replies instead
+trait ReplySink {
+    unsafe fn ubf(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, buffer: &UbfBuffer);
+    unsafe fn ubf_with(
+        &self,
+        rqst: *mut TpSvcInfoRaw,
+        rval: c_int,
+        rcode: c_long,
+        buffer: &UbfBuffer,
+    );
+    unsafe fn json(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, json: &str);
+    unsafe fn string(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, message: &str);
+    unsafe fn fail(&self, rqst: *mut TpSvcInfoRaw, rcode: c_long);
+    unsafe fn forward(&self, rqst: *mut TpSvcInfoRaw, service: &str, buffer: &UbfBuffer);
+}
+
+/// tpreturn/tpforward
+struct Xatmi;
+
+impl ReplySink for Xatmi {
+    unsafe fn ubf(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, buffer: &UbfBuffer) {
+        tpreturn_ubf(rqst, rval, buffer);
+    }
+
+    unsafe fn ubf_with(
+        &self,
+        rqst: *mut TpSvcInfoRaw,
+        rval: c_int,
+        rcode: c_long,
+        buffer: &UbfBuffer,
+    ) {
+        tpreturn_ubf_with(rqst, rval, rcode, buffer);
+    }
+
+    unsafe fn json(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, json: &str) {
+        tpreturn_json(rqst, rval, json);
+    }
+
+    unsafe fn string(&self, rqst: *mut TpSvcInfoRaw, rval: c_int, message: &str) {
+        tpreturn_string(rqst, rval, message);
+    }
+
+    unsafe fn fail(&self, rqst: *mut TpSvcInfoRaw, rcode: c_long) {
+        tpreturn_fail_with(rqst, rcode);
+    }
+
+    unsafe fn forward(&self, rqst: *mut TpSvcInfoRaw, service: &str, buffer: &UbfBuffer) {
+        tpforward_ubf(rqst, service, buffer);
+    }
+}
+
+impl From<UbfError> for ServiceResult {
+    fn from(e: UbfError) -> Self {
+        tplog_error(&format!("Service failed: {}", e));
+        ServiceResult::error(&format!("{}: {}", e.code(), e))
+    }
+}
+
+/// Return type of a service handler
+///
+/// Handlers return either a `ServiceResult` or a `Result` whose error
+/// converts into one, so buffer operations can use `?`.
+pub trait IntoServiceResult {
+    fn into_service_result(self) -> ServiceResult;
+}
+
+impl IntoServiceResult for ServiceResult {
+    fn into_service_result(self) -> ServiceResult {
+        self
+    }
+}
+
+impl<E: Into<ServiceResult>> IntoServiceResult for Result<ServiceResult, E> {
+    fn into_service_result(self) -> ServiceResult {
+        self.unwrap_or_else(Into::into)
+    }
+}
+
+impl IntoServiceResult for Response {
+    fn into_service_result(self) -> ServiceResult {
+        self.into()
+    }
+}
+
+impl<E: Into<ServiceResult>> IntoServiceResult for Result<Response, E> {
+    fn into_service_result(self) -> ServiceResult {
+        self.map_or_else(Into::into, Into::into)
+    }
+}
+
+/// Service handler registered with a `ServiceServer`
+pub type ServiceHandler = Box<dyn Fn(&ServiceRequest) -> ServiceResult + Send + Sync>;
+
+/// Middleware wrapping every dispatch of a `ServiceServer`
+///
+/// Gets the request and `next`, which runs the rest of the chain and the
+/// handler. It can act before and after `next` or skip it to short-circuit.
+pub type Middleware = Box<
+    dyn Fn(&ServiceRequest, &dyn Fn(&ServiceRequest) -> ServiceResult) -> ServiceResult
+        + Send
+        + Sync,
+>;
+
+/// Middleware logging every request under the correlation id read from the
+/// string field `field_id`
+///
+/// All `tplog_*` calls made while the request is handled are prefixed with
+/// `[id] `, see `log::with_correlation_id`.
+pub fn correlation_id_middleware(
+    field_id: i32,
+) -> impl Fn(&ServiceRequest, &dyn Fn(&ServiceRequest) -> ServiceResult) -> ServiceResult + Send + Sync
+{
+    move |request, next| {
+        let id = request
+            .ubf_buffer
+            .as_ref()
+            .and_then(|buf| buf.get_string(field_id, 0).ok());
+
+        with_correlation_id(id.as_deref(), || next(request))
+    }
+}
+
+/// Authorization check registered with a `ServiceServer`, true to allow
+pub type AuthCheck = Box<dyn Fn(&ServiceRequest) -> bool + Send + Sync>;
+
+/// Init hook registered with a `ServiceServer`, gets the server's `app_args`
+pub type InitHandler = Box<dyn Fn(&[String]) -> Result<(), String> + Send + Sync>;
+
+/// Hooks run in registration order when the server shuts down
+///
+/// A hook that panics is logged and the remaining hooks still run.
+#[derive(Default)]
+pub struct ShutdownHooks {
+    hooks: Vec<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl ShutdownHooks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<F>(&mut self, hook: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Runs every hook
+    pub fn run(&self) {
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(hook)).is_err() {
+                tplog_error(&format!("Shutdown hook {} panicked", index));
+            }
+        }
+    }
+}
+
+struct RegisteredService {
+    name: String,
+    buffer_type: String,
+    handler: ServiceHandler,
+}
+
+/// Calls of one service allowed to run at once
+struct ConcurrencyLimit {
+    max: usize,
+    in_flight: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    fn new(max: usize) -> Self {
+        ConcurrencyLimit {
+            max,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes a slot, released when the guard drops; `None` when all are taken
+    fn try_acquire(&self) -> Option<InFlightGuard<'_>> {
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| InFlightGuard(&self.in_flight))
+    }
+}
+
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Services of one server process and their handlers
+///
+/// The same registry drives advertising, dispatching and the exported
+/// ndrxconfig fragment, so the three can't drift apart.
+pub struct ServiceServer {
+    name: String,
+    services: Vec<RegisteredService>,
+    tenants: TenantMap,
+    auth: Option<AuthCheck>,
+    on_init: Option<InitHandler>,
+    middleware: Vec<Middleware>,
+    slow_threshold: Option<Duration>,
+    shutdown_hooks: ShutdownHooks,
+    limits: HashMap<String, ConcurrencyLimit>,
+}
+
+/// Builder for `ServiceServer`
+pub struct ServiceServerBuilder {
+    name: String,
+    services: Vec<RegisteredService>,
+    tenants: TenantMap,
+    auth: Option<AuthCheck>,
+    on_init: Option<InitHandler>,
+    middleware: Vec<Middleware>,
+    slow_threshold: Option<Duration>,
+    shutdown_hooks: ShutdownHooks,
+    limits: HashMap<String, usize>,
+}
+
+impl ServiceServerBuilder {
+    /// Registers a service taking UBF buffers
+    ///
+    /// The handler returns a `ServiceResult` or a `Result` of one, see
+    /// `IntoServiceResult`.
+    pub fn service<F, R>(self, name: &str, handler: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> R + Send + Sync + 'static,
+        R: IntoServiceResult,
+    {
+        self.service_with_buffer(name, "UBF", handler)
+    }
+
+    /// Registers a service taking the given XATMI buffer type
+    ///
+    /// Registering a name again replaces the earlier handler.
+    pub fn service_with_buffer<F, R>(mut self, name: &str, buffer_type: &str, handler: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> R + Send + Sync + 'static,
+        R: IntoServiceResult,
+    {
+        let service = RegisteredService {
+            name: name.to_string(),
+            buffer_type: buffer_type.to_string(),
+            handler: Box::new(move |req| handler(req).into_service_result()),
+        };
+        match self.services.iter_mut().find(|s| s.name == name) {
+            Some(existing) => *existing = service,
+            None => self.services.push(service),
+        }
+        self
+    }
+
+    /// Resolves `ServiceRequest::tenant_id` from the caller's appkey
+    pub fn tenants(mut self, tenants: TenantMap) -> Self {
+        self.tenants = tenants;
+        self
+    }
+
+    /// Checks every request with `check` before the middleware and handler
+    ///
+    /// A request `check` rejects is logged and fails with `TPFAIL` and
+    /// `AUTH_DENIED_RCODE`, so callers can tell it from a service error.
+    /// The tenant is already resolved when `check` runs.
+    pub fn with_auth<F>(mut self, check: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + Send + Sync + 'static,
+    {
+        self.auth = Some(Box::new(check));
+        self
+    }
+
+    /// Wraps every dispatch in `middleware`
+    ///
+    /// The first middleware added is the outermost one.
+    pub fn with_middleware<F>(mut self, middleware: F) -> Self
+    where
+        F: Fn(&ServiceRequest, &dyn Fn(&ServiceRequest) -> ServiceResult) -> ServiceResult
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Times every handler run and logs the ones slower than `slow_threshold`
+    ///
+    /// Slow runs are logged with `tplog_warn`; every run is added to the
+    /// service's `server::metrics_snapshot` handler time. The time covers the
+    /// handler only, not the middleware around it or the reply.
+    pub fn with_timing(mut self, slow_threshold: Duration) -> Self {
+        self.slow_threshold = Some(slow_threshold);
+        self
+    }
+
+    /// Runs `hook` from `ServiceServer::init` with the arguments after `--`
+    ///
+    /// Use it to read per-instance configuration such as `-d oracle`.
+    pub fn on_init<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&[String]) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.on_init = Some(Box::new(hook));
+        self
+    }
+
+    /// Runs `hook` from `ServiceServer::shutdown` (tpsvrdone)
+    ///
+    /// Hooks run in the order they are added, e.g. to persist counters or
+    /// close connection pools.
+    pub fn on_shutdown<F>(mut self, hook: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.shutdown_hooks.add(hook);
+        self
+    }
+
+    /// Runs at most `max` calls of `service` at once
+    ///
+    /// In a multithreaded server, a call arriving while `max` are in flight
+    /// fails at once with a "Service busy" `TPFAIL` instead of waiting, so
+    /// callers back off before a backend such as the DB pool is exhausted.
+    pub fn max_concurrent(mut self, service: &str, max: usize) -> Self {
+        self.limits.insert(service.to_string(), max);
+        self
+    }
+
+    pub fn build(self) -> ServiceServer {
+        ServiceServer {
+            name: self.name,
+            services: self.services,
+            tenants: self.tenants,
+            auth: self.auth,
+            on_init: self.on_init,
+            middleware: self.middleware,
+            slow_threshold: self.slow_threshold,
+            shutdown_hooks: self.shutdown_hooks,
+            limits: self
+                .limits
+                .into_iter()
+                .map(|(service, max)| (service, ConcurrencyLimit::new(max)))
+                .collect(),
+        }
+    }
+}
+
+impl ServiceServer {
+    /// Starts building the service list of server `name`
+    pub fn builder(name: &str) -> ServiceServerBuilder {
+        ServiceServerBuilder {
+            name: name.to_string(),
+            services: Vec::new(),
+            tenants: TenantMap::new(),
+            auth: None,
+            on_init: None,
+            middleware: Vec::new(),
+            slow_threshold: None,
+            shutdown_hooks: ShutdownHooks::new(),
+            limits: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of all registered services, in registration order
+    pub fn services(&self) -> Vec<&str> {
+        self.services.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// Registered services that have been advertised
+    pub fn advertised_services(&self) -> Vec<String> {
+        let advertised = advertised_services();
+        self.services
+            .iter()
+            .filter(|s| advertised.contains(&s.name))
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    /// Advertises every registered service with `dispatcher` (call from tpsvrinit)
+    pub fn advertise(&self, dispatcher: extern "C" fn(*mut TpSvcInfoRaw)) -> Result<(), String> {
+        self.advertise_via(tpadvertise, dispatcher)
+    }
+
+    fn advertise_via(
+        &self,
+        tpadvertise: Tpadvertise,
+        dispatcher: extern "C" fn(*mut TpSvcInfoRaw),
+    ) -> Result<(), String> {
+        for service in &self.services {
+            advertise_via(tpadvertise, &service.name, dispatcher)
+                .map_err(|e| format!("Failed to advertise {}: {}", service.name, e))?;
+            tplog_info(&format!("Successfully advertised {}", service.name));
+        }
+        Ok(())
+    }
+
+    /// Runs the init hook with `app_args(args)`, then advertises (call from tpsvrinit)
+    ///
+    /// `args` is the `tpsvrinit` command line, see `server::parse_args`.
+    pub fn init(
+        &self,
+        args: &[String],
+        dispatcher: extern "C" fn(*mut TpSvcInfoRaw),
+    ) -> Result<(), String> {
+        self.run_init_hook(args)?;
+        self.advertise(dispatcher)
+    }
+
+    /// Runs the `on_init` hook, if any, with the arguments after `--`
+    pub fn run_init_hook(&self, args: &[String]) -> Result<(), String> {
+        match &self.on_init {
+            Some(hook) => {
+                hook(app_args(args)).map_err(|e| format!("{} init failed: {}", self.name, e))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the `on_shutdown` hooks (call from tpsvrdone)
+    pub fn shutdown(&self) {
+        tplog_info(&format!(
+            "{} running {} shutdown hook(s)",
+            self.name,
+            self.shutdown_hooks.len()
+        ));
+        self.shutdown_hooks.run();
+    }
+
+    /// Runs the handler registered for the request's service
+    ///
+    /// Sets the request's tenant from its appkey first, runs the `with_auth`
+    /// check, then goes through the middleware chain. Handlers are
+    /// request/response only, so conversational requests are rejected.
+    pub fn handle(&self, request: &mut ServiceRequest) -> ServiceResult {
+        if request.is_conversational() {
+            tplog_error(&format!(
+                "Conversational call to {} rejected",
+                request.service_name
+            ));
+            return ServiceResult::error("Conversational calls not supported");
+        }
+
+        request.tenant = self.tenants.tenant_for(request.appkey).map(String::from);
+
+        if let Some(check) = &self.auth {
+            if !check(request) {
+                tplog_error(&format!(
+                    "Call to {} denied for appkey {}",
+                    request.service_name, request.appkey
+                ));
+                return ServiceResult {
+                    rcode: AUTH_DENIED_RCODE,
+                    ..ServiceResult::error("Access denied")
+                };
+            }
+        }
+
+        self.run_middleware(0, request)
+    }
+
+    fn run_middleware(&self, index: usize, request: &ServiceRequest) -> ServiceResult {
+        match self.middleware.get(index) {
+            Some(middleware) => middleware(request, &|req| self.run_middleware(index + 1, req)),
+            None => self.run_handler(request),
+        }
+    }
+
+    fn run_handler(&self, request: &ServiceRequest) -> ServiceResult {
+        match self
+            .services
+            .iter()
+            .find(|s| s.name == request.service_name)
+        {
+            Some(service) => {
+                let _slot = match self.limits.get(&service.name) {
+                    Some(limit) => match limit.try_acquire() {
+                        Some(slot) => Some(slot),
+                        None => {
+                            tplog_error(&format!(
+                                "{} busy, {} calls in flight",
+                                service.name, limit.max
+                            ));
+                            return ServiceResult::error("Service busy");
+                        }
+                    },
+                    None => None,
+                };
+                match self.slow_threshold {
+                    Some(threshold) => self.run_timed(service, request, threshold),
+                    None => (service.handler)(request),
+                }
+            }
+            None => {
+                tplog_error(&format!("Unknown service: {}", request.service_name));
+                ServiceResult::error("Service not found")
+            }
+        }
+    }
+
+    fn run_timed(
+        &self,
+        service: &RegisteredService,
+        request: &ServiceRequest,
+        threshold: Duration,
+    ) -> ServiceResult {
+        let started = Instant::now();
+        let result = (service.handler)(request);
+        let elapsed = started.elapsed();
+
+        record_handler_time(&service.name, elapsed);
+        if elapsed > threshold {
+            tplog_warn(&format!(
+                "Slow handler: {} took {} ms (threshold {} ms)",
+                service.name,
+                elapsed.as_millis(),
+                threshold.as_millis()
+            ));
+        }
+        result
+    }
+
+    /// Parses the request, runs its handler and sends the reply
+    ///
+    /// # Safety
+    /// Caller must ensure rqst is a valid pointer to TpSvcInfoRaw
+    pub unsafe fn dispatch(&self, rqst: *mut TpSvcInfoRaw) {
+        self.dispatch_to(&Xatmi, rqst);
+    }
+
+    unsafe fn dispatch_to(&self, sink: &impl ReplySink, rqst: *mut TpSvcInfoRaw) {
+        let mut request = match ServiceRequest::from_raw(rqst) {
+            Ok(req) => req,
+            Err(e) => {
+                tplog_error(&format!("Failed to parse service request: {}", e));
+                sink.fail(rqst, 0);
+                return;
+            }
+        };
+
+        let request_len = request.ubf_buffer.as_ref().map_or(0, |buf| buf.used());
+        record_request(&request.service_name, request_len);
+
+        let result = self.handle(&mut request);
+        if !result.success && !result.message.is_empty() {
+            tplog_error(&format!("Service responded with error: {}", result.message));
+        }
+
+        let response = result.into_response();
+        record_response(&request.service_name, response.payload_len());
+        response.send_to(sink, rqst);
+    }
+
+    /// `<service>` entries for the `<services>` section of ndrxconfig.xml
+    ///
+    /// The expected buffer type is noted in a comment, ndrxconfig has no
+    /// attribute for it.
+    pub fn export_config(&self) -> String {
+        let mut out = format!("<!-- Services of {} -->\n", xml_escape(&self.name));
+        for service in &self.services {
+            out.push_str(&format!(
+                "<service svcnm=\"{}\"/> <!-- {} -->\n",
+                xml_escape(&service.name),
+                xml_escape(&service.buffer_type)
+            ));
+        }
+        out
+    }
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::idempotency::{stamp_idempotency_key, InMemoryDedupStore};
+    use crate::server::tests::fake_tpadvertise;
+    use crate::ubf_fields::*;
+    use crate::ubf_struct::UserData;
+    use libc::c_char;
+    use std::cell::{Cell, RefCell};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::time::Duration;
+
+    /// Records what the dispatcher sends instead of calling tpreturn
+    #[derive(Default)]
+    struct Recorder {
+        sent: RefCell<Vec<(&'static str, c_int, String)>>,
+    }
+
+    impl Recorder {
+        /// Takes the (call, rval, payload) tuples recorded so far
+        fn take(&self) -> Vec<(&'static str, c_int, String)> {
+            self.sent.take()
+        }
+
+        fn record(&self, call: &'static str, rval: c_int, payload: String) {
+            self.sent.borrow_mut().push((call, rval, payload));
+        }
+    }
+
+    fn name(buffer: &UbfBuffer) -> String {
+        buffer.get_string(T_NAME_FLD, 0).unwrap_or_default()
+    }
+
+    impl ReplySink for Recorder {
+        unsafe fn ubf(&self, _rqst: *mut TpSvcInfoRaw, rval: c_int, buffer: &UbfBuffer) {
+            self.record("tpreturn_ubf", rval, name(buffer));
+        }
+
+        unsafe fn ubf_with(
+            &self,
+            _rqst: *mut TpSvcInfoRaw,
+            rval: c_int,
+            rcode: c_long,
+            buffer: &UbfBuffer,
+        ) {
+            self.record(
+                "tpreturn_ubf_with",
+                rval,
+                format!("{} {}", rcode, name(buffer)),
+            );
+        }
+
+        unsafe fn json(&self, _rqst: *mut TpSvcInfoRaw, rval: c_int, json: &str) {
+            self.record("tpreturn_json", rval, json.to_string());
+        }
+
+        unsafe fn string(&self, _rqst: *mut TpSvcInfoRaw, rval: c_int, message: &str) {
+            self.record("tpreturn_string", rval, message.to_string());
+        }
+
+        unsafe fn fail(&self, _rqst: *mut TpSvcInfoRaw, rcode: c_long) {
+            self.record("tpreturn_fail", TPFAIL, rcode.to_string());
+        }
+
+        unsafe fn forward(&self, _rqst: *mut TpSvcInfoRaw, service: &str, buffer: &UbfBuffer) {
+            self.record("tpforward_ubf", 0, format!("{} {}", service, name(buffer)));
+        }
+    }
+
+    fn named(name: &str) -> UbfBuffer {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, name).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_dispatch_sends_each_response_variant() {
+        let server = ServiceServer::builder("testsvr")
+            .service("UBF", |_req| Response::Ubf(named("ubf")))
+            .service("JSON", |_req| Response::Json(r#"{"ok":true}"#.to_string()))
+            .service("STRING", |_req| Response::String("hello".to_string()))
+            .service("FAIL", |_req| Response::Fail(42))
+            .service("FAILUBF", |_req| Response::FailUbf(named("details")))
+            .service("LOGICAL", |_req| Response::LogicalError(named("refused")))
+            .service("FORWARD", |_req| {
+                Response::Forward("SLEEP".to_string(), named("fwd"))
+            })
+            .service("ERROR", |_req| -> Result<Response, UbfError> {
+                Err(UbfError::FieldNotFound("T_NAME_FLD".to_string()))
+            })
+            .build();
+
+        let expected = [
+            ("UBF", ("tpreturn_ubf", TPSUCCESS, "ubf")),
+            ("JSON", ("tpreturn_json", TPSUCCESS, r#"{"ok":true}"#)),
+            ("STRING", ("tpreturn_string", TPSUCCESS, "hello")),
+            ("FAIL", ("tpreturn_fail", TPFAIL, "42")),
+            ("FAILUBF", ("tpreturn_ubf", TPFAIL, "details")),
+            ("LOGICAL", ("tpreturn_ubf_with", TPFAIL, "422 refused")),
+            ("FORWARD", ("tpforward_ubf", 0, "SLEEP fwd")),
+            ("ERROR", ("tpreturn_fail", TPFAIL, "0")),
+            ("MISSING", ("tpreturn_fail", TPFAIL, "0")),
+        ];
+
+        let sent = Recorder::default();
+        for (service, (call, rval, payload)) in expected {
+            let mut info: TpSvcInfoRaw = unsafe { std::mem::zeroed() };
+            for (dst, src) in info.name.iter_mut().zip(service.as_bytes()) {
+                *dst = *src as c_char;
+            }
+
+            unsafe { server.dispatch_to(&sent, &mut info) };
+            assert_eq!(
+                sent.take(),
+                vec![(call, rval, payload.to_string())],
+                "{}",
+                service
+            );
+        }
+    }
+
+    #[test]
+    fn test_service_result_round_trips_through_response() {
+        let json: ServiceResult = Response::Json("{}".to_string()).into();
+        assert!(json.success && json.json);
+        assert!(matches!(json.into_response(), Response::Json(body) if body == "{}"));
+
+        let fail: ServiceResult = Response::Fail(7).into();
+        assert!(!fail.success);
+        assert!(matches!(fail.into_response(), Response::Fail(7)));
+
+        assert!(matches!(
+            ServiceResult::error_ubf(named("x")).into_response(),
+            Response::FailUbf(_)
+        ));
+        assert!(matches!(
+            ServiceResult::error("bad input").into_response(),
+            Response::Fail(0)
+        ));
+    }
+
+    #[test]
+    fn test_cached_reply_replays_same_response() {
+        let json: ServiceResult = Response::Json("{}".to_string()).into();
+        let replayed = ServiceResult::from_cached(&json.to_cached()).unwrap();
+        assert!(matches!(replayed.into_response(), Response::Json(body) if body == "{}"));
+
+        let fail: ServiceResult = Response::Fail(7).into();
+        let replayed = ServiceResult::from_cached(&fail.to_cached()).unwrap();
+        assert!(matches!(replayed.into_response(), Response::Fail(7)));
+    }
+
+    #[test]
+    fn test_logical_error_fails_call_only_when_chosen() {
+        let server = ServiceServer::builder("txnsvr")
+            .service("IN_BODY", |_req| ServiceResult::success_ubf(named("ERROR")))
+            .service("AS_FAIL", |_req| {
+                ServiceResult::logical_error_ubf(named("ERROR"))
+            })
+            .build();
+
+        let sent = Recorder::default();
+        for (service, expected) in [
+            ("IN_BODY", ("tpreturn_ubf", TPSUCCESS, "ERROR")),
+            ("AS_FAIL", ("tpreturn_ubf_with", TPFAIL, "422 ERROR")),
+        ] {
+            let mut info: TpSvcInfoRaw = unsafe { std::mem::zeroed() };
+            for (dst, src) in info.name.iter_mut().zip(service.as_bytes()) {
+                *dst = *src as c_char;
+            }
+
+            unsafe { server.dispatch_to(&sent, &mut info) };
+            let (call, rval, payload) = expected;
+            assert_eq!(sent.take(), vec![(call, rval, payload.to_string())]);
+        }
+
+        // A cached logical error replays as one
+        let cached = ServiceResult::logical_error_ubf(named("ERROR")).to_cached();
+        let replayed = ServiceResult::from_cached(&cached).unwrap();
+        assert!(matches!(
+            replayed.into_response(),
+            Response::LogicalError(_)
+        ));
+    }
+
+    fn create_request(key: Option<&str>) -> ServiceRequest {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_TRANS_ID_FLD, "TXN-1").unwrap();
+        if let Some(key) = key {
+            stamp_idempotency_key(&mut buf, key).unwrap();
+        }
+        ServiceRequest {
+            service_name: "CREATE_TXN".to_string(),
+            ubf_buffer: Some(buf),
+            appkey: 0,
+            tenant: None,
+            flags: TpFlags::empty(),
+        }
+    }
+
+    #[test]
+    #[ignore] // Run only with Enduro/X environment (tptypes needs ATMI config)
+    fn test_from_raw_ubf_with_zero_len() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "implicit length").unwrap();
+
+        let mut info: TpSvcInfoRaw = unsafe { std::mem::zeroed() };
+        for (dst, src) in info.name.iter_mut().zip(b"TESTSVC") {
+            *dst = *src as c_char;
+        }
+        info.data = buf.as_ptr();
+        info.len = 0;
+
+        let request = unsafe { ServiceRequest::from_raw(&mut info) }.unwrap();
+        assert_eq!(request.service_name(), "TESTSVC");
+
+        let ubf = request
+            .ubf_buffer
+            .expect("UBF request with len 0 was dropped");
+        assert_eq!(ubf.used(), buf.used());
+        assert_eq!(ubf.get_string(T_NAME_FLD, 0).unwrap(), "implicit length");
+    }
+
+    #[test]
+    fn test_from_raw_via_sizes_ubf_by_bused() {
+        unsafe fn ubf(_data: *mut c_char) -> Option<String> {
+            Some("UBF".to_string())
+        }
+        unsafe fn string(_data: *mut c_char) -> Option<String> {
+            Some("STRING".to_string())
+        }
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "implicit length").unwrap();
+
+        let mut info: TpSvcInfoRaw = unsafe { std::mem::zeroed() };
+        for (dst, src) in info.name.iter_mut().zip(b"TESTSVC") {
+            *dst = *src as c_char;
+        }
+        info.data = buf.as_ptr();
+        info.len = 0;
+
+        let request = unsafe { ServiceRequest::from_raw_via(ubf, &mut info) }.unwrap();
+        let copy = request
+            .ubf_buffer
+            .expect("UBF request with len 0 was dropped");
+        assert_eq!(copy.used(), buf.used());
+        assert_eq!(copy.get_string(T_NAME_FLD, 0).unwrap(), "implicit length");
+
+        // Other types go by len, which is 0 here
+        let request = unsafe { ServiceRequest::from_raw_via(string, &mut info) }.unwrap();
+        assert!(request.ubf_buffer.is_none());
+    }
+
+    #[test]
+    fn test_call_mode_from_flags() {
+        let mut info: TpSvcInfoRaw = unsafe { std::mem::zeroed() };
+        for (dst, src) in info.name.iter_mut().zip(b"TESTSVC") {
+            *dst = *src as c_char;
+        }
+
+        let request = unsafe { ServiceRequest::from_raw(&mut info) }.unwrap();
+        assert!(!request.is_conversational());
+        assert!(!request.is_transactional());
+
+        info.flags = ffi::TPCONV | ffi::TPSENDONLY;
+        let request = unsafe { ServiceRequest::from_raw(&mut info) }.unwrap();
+        assert!(request.is_conversational());
+        assert!(!request.is_transactional());
+
+        info.flags = ffi::TPTRAN | 0x40000000;
+        let request = unsafe { ServiceRequest::from_raw(&mut info) }.unwrap();
+        assert!(request.is_transactional());
+        assert_eq!(request.flags, TpFlags::TRAN);
+    }
+
+    #[test]
+    fn test_conversational_request_rejected() {
+        let server = ServiceServer::builder("testsvr")
+            .service("CREATE_TXN", |_req| ServiceResult::success("created"))
+            .build();
+
+        let mut request = create_request(None);
+        request.flags = TpFlags::CONV;
+        let result = server.handle(&mut request);
+        assert!(!result.success);
+
+        request.flags = TpFlags::TRAN;
+        assert_eq!(server.handle(&mut request).message, "created");
+    }
+
+    #[test]
+    fn test_shutdown_hooks_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+
+        let server = {
+            let (first, second) = (calls.clone(), calls.clone());
+            ServiceServer::builder("testsvr")
+                .on_shutdown(move || first.lock().unwrap().push("flush metrics"))
+                .on_shutdown(|| panic!("hook failed"))
+                .on_shutdown(move || second.lock().unwrap().push("close pool"))
+                .build()
+        };
+        assert!(calls.lock().unwrap().is_empty());
+
+        server.shutdown();
+        assert_eq!(*calls.lock().unwrap(), ["flush metrics", "close pool"]);
+    }
+
+    extern "C" fn noop_dispatcher(_rqst: *mut TpSvcInfoRaw) {}
+
+    #[test]
+    fn test_advertised_services_match_registry() {
+        let server = ServiceServer::builder("testsvr")
+            .service("ADV_ONE", |_req| ServiceResult::success("one"))
+            .service("ADV_TWO", |_req| ServiceResult::success("two"))
+            .build();
+        assert!(server.advertised_services().is_empty());
+
+        server
+            .advertise_via(fake_tpadvertise, noop_dispatcher)
+            .unwrap();
+        assert_eq!(server.advertised_services(), ["ADV_ONE", "ADV_TWO"]);
+
+        advertise_via(fake_tpadvertise, "ADV_RAW", noop_dispatcher).unwrap();
+        advertise_via(fake_tpadvertise, "ADV_RAW", noop_dispatcher).unwrap();
+        let all = advertised_services();
+        assert_eq!(all.iter().filter(|s| *s == "ADV_RAW").count(), 1);
+        assert!(all.iter().any(|s| s == "ADV_ONE"));
+    }
+
+    #[test]
+    fn test_export_config_lists_services() {
+        let server = ServiceServer::builder("samplesvr_rust")
+            .service("ECHO", |_| ServiceResult::success("echo"))
+            .service("HELLO", |_| ServiceResult::success("hello"))
+            .service_with_buffer("STATUS", "STRING", |_| ServiceResult::success("ok"))
+            .service("ECHO", |_| ServiceResult::success("echo v2"))
+            .build();
+
+        assert_eq!(server.services(), vec!["ECHO", "HELLO", "STATUS"]);
+        assert_eq!(
+            server.export_config(),
+            "<!-- Services of samplesvr_rust -->\n\
+             <service svcnm=\"ECHO\"/> <!-- UBF -->\n\
+             <service svcnm=\"HELLO\"/> <!-- UBF -->\n\
+             <service svcnm=\"STATUS\"/> <!-- STRING -->\n"
+        );
+
+        let result = server.handle(&mut create_request(None));
+        assert!(!result.success);
+
+        let mut request = create_request(None);
+        request.service_name = "ECHO".to_string();
+        assert_eq!(server.handle(&mut request).message, "echo v2");
+    }
+
+    #[test]
+    fn test_init_hook_gets_app_args() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        let server = ServiceServer::builder("txnsvr")
+            .on_init(move |args| {
+                *hook_seen.lock().unwrap() = args.to_vec();
+                match args {
+                    [flag, db] if flag == "-d" && db == "oracle" => Ok(()),
+                    _ => Err(format!("unsupported args {:?}", args)),
+                }
+            })
+            .build();
+
+        let args: Vec<String> = ["txnsvr", "-i", "1", "--", "-d", "oracle"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(server.run_init_hook(&args), Ok(()));
+        assert_eq!(*seen.lock().unwrap(), ["-d", "oracle"]);
+
+        let err = server.run_init_hook(&args[..3]).unwrap_err();
+        assert!(err.starts_with("txnsvr init failed"));
+    }
+
+    #[test]
+    fn test_concurrency_limit_rejects_excess_calls() {
+        let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+        let released = Arc::new((Mutex::new(false), Condvar::new()));
+
+        let gate = released.clone();
+        let server = ServiceServer::builder("txnsvr")
+            .service("CREATE_TXN", move |_req: &ServiceRequest| {
+                entered_tx.send(()).unwrap();
+                let (lock, cvar) = &*gate;
+                let _open = cvar
+                    .wait_while(lock.lock().unwrap(), |open| !*open)
+                    .unwrap();
+                ServiceResult::success("created")
+            })
+            .max_concurrent("CREATE_TXN", 2)
+            .build();
+
+        std::thread::scope(|scope| {
+            let calls: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| server.handle(&mut create_request(None)).success))
+                .collect();
+            for _ in 0..2 {
+                entered_rx.recv().unwrap();
+            }
+
+            // Both slots are taken
+            let result = server.handle(&mut create_request(None));
+            assert!(!result.success);
+            assert_eq!(result.message, "Service busy");
+
+            let (lock, cvar) = &*released;
+            *lock.lock().unwrap() = true;
+            cvar.notify_all();
+            for call in calls {
+                assert!(call.join().unwrap());
+            }
+        });
+
+        // Slots are freed once the calls return
+        assert!(server.handle(&mut create_request(None)).success);
+    }
+
+    #[test]
+    fn test_ubf_sized_grows_for_long_message() {
+        let user = UserData {
+            name: "x".repeat(5000),
+            id: 7,
+            balance: 1.5,
+            active: false,
+        };
+
+        // Doesn't fit the fixed 1024 byte buffer
+        let mut small = UbfBuffer::new(1024).unwrap();
+        assert!(user.update_ubf(&mut small).is_err());
+
+        let result = ServiceResult::ubf_sized(&user, 1024);
+        assert!(result.success, "{}", result.message);
+        let buf = result.ubf_buffer.unwrap();
+        assert!(buf.used() > 5000);
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_ubf_error_propagates_to_error_result() {
+        fn handler(request: &ServiceRequest) -> Result<ServiceResult, UbfError> {
+            let buf = request
+                .ubf_buffer
+                .as_ref()
+                .ok_or_else(|| UbfError::InvalidValue("no buffer".to_string()))?;
+            let name = buf
+                .get_string(T_NAME_FLD, 0)
+                .map_err(UbfError::FieldNotFound)?;
+            Ok(ServiceResult::success(&name))
+        }
+
+        let server = ServiceServer::builder("samplesvr_rust")
+            .service("NAME", handler)
+            .build();
+
+        let mut request = create_request(None);
+        request.service_name = "NAME".to_string();
+        let result = server.handle(&mut request);
+        assert!(!result.success);
+        assert!(result.message.starts_with("FIELD_NOT_FOUND: "));
+
+        request
+            .ubf_buffer
+            .as_mut()
+            .unwrap()
+            .add_string(T_NAME_FLD, "alice")
+            .unwrap();
+        let result = server.handle(&mut request);
+        assert!(result.success);
+        assert_eq!(result.message, "alice");
+    }
+
+    #[test]
+    fn test_middleware_wraps_handler() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let (outer, inner, handler) = (events.clone(), events.clone(), events.clone());
+
+        let server = ServiceServer::builder("samplesvr_rust")
+            .service("ECHO", move |_| {
+                handler.lock().unwrap().push("handler".to_string());
+                ServiceResult::success("echo")
+            })
+            .with_middleware(move |req, next| {
+                outer
+                    .lock()
+                    .unwrap()
+                    .push(format!("outer before {}", req.service_name));
+                let result = next(req);
+                outer
+                    .lock()
+                    .unwrap()
+                    .push(format!("outer after {}", result.message));
+                result
+            })
+            .with_middleware(move |req, next| {
+                inner.lock().unwrap().push("inner before".to_string());
+                let result = next(req);
+                inner.lock().unwrap().push("inner after".to_string());
+                result
+            })
+            .build();
+
+        let mut request = create_request(None);
+        request.service_name = "ECHO".to_string();
+        assert_eq!(server.handle(&mut request).message, "echo");
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "outer before ECHO",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after echo",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_correlation_id_middleware() {
+        use crate::log::{clear_test_sink, set_test_sink, LogSink};
+
+        let server = ServiceServer::builder("samplesvr_rust")
+            .service("ECHO", |_| {
+                tplog_info("handling");
+                ServiceResult::success("echo")
+            })
+            .with_middleware(correlation_id_middleware(T_STRING_FLD))
+            .build();
+
+        let mut buf = UbfBuffer::new(256).unwrap();
+        buf.add_string(T_STRING_FLD, "cid-42").unwrap();
+        let mut request = create_request(None);
+        request.service_name = "ECHO".to_string();
+        request.ubf_buffer = Some(buf);
+
+        let sink: LogSink = Arc::new(Mutex::new(Vec::new()));
+        set_test_sink(sink.clone());
+        server.handle(&mut request);
+        clear_test_sink();
+
+        let messages = sink.lock().unwrap();
+        assert!(messages.iter().any(|(_, m)| m == "[cid-42] handling"));
+    }
+
+    #[test]
+    fn test_timing_logs_slow_handler() {
+        use crate::log::{clear_test_sink, set_test_sink, LogSink};
+        use crate::server::metrics_snapshot;
+
+        let server = ServiceServer::builder("samplesvr_rust")
+            .service("TIMING_SLOW", |_| {
+                std::thread::sleep(Duration::from_millis(30));
+                ServiceResult::success("slow")
+            })
+            .service("TIMING_FAST", |_| ServiceResult::success("fast"))
+            .with_timing(Duration::from_millis(10))
+            .build();
+
+        let sink: LogSink = Arc::new(Mutex::new(Vec::new()));
+        set_test_sink(sink.clone());
+        for service in ["TIMING_SLOW", "TIMING_FAST"] {
+            let mut request = create_request(None);
+            request.service_name = service.to_string();
+            assert!(server.handle(&mut request).success);
+        }
+        clear_test_sink();
+
+        let messages = sink.lock().unwrap();
+        let slow: Vec<_> = messages
+            .iter()
+            .filter(|(_, m)| m.starts_with("Slow handler"))
+            .collect();
+        assert_eq!(slow.len(), 1, "{:?}", messages);
+        assert!(slow[0].1.contains("TIMING_SLOW"));
+
+        let metrics = metrics_snapshot();
+        let timed = &metrics["TIMING_SLOW"];
+        assert_eq!(timed.handled, 1);
+        assert!(timed.max_handler_time >= Duration::from_millis(30));
+        assert_eq!(metrics["TIMING_FAST"].handled, 1);
+    }
+
+    #[test]
+    fn test_tenant_from_appkey() {
+        let server = ServiceServer::builder("txnsvr")
+            .service("CREATE_TXN", |req| {
+                ServiceResult::success(req.tenant_id().unwrap_or("none"))
+            })
+            .tenants(
+                TenantMap::new()
+                    .with_tenant(10, "acme")
+                    .with_tenant(20, "globex"),
+            )
+            .build();
+
+        let mut request = create_request(None);
+        request.appkey = 10;
+        assert_eq!(server.handle(&mut request).message, "acme");
+
+        request.appkey = 20;
+        assert_eq!(server.handle(&mut request).message, "globex");
+        assert_eq!(request.tenant_id(), Some("globex"));
+
+        request.appkey = 30;
+        assert_eq!(server.handle(&mut request).message, "none");
+    }
+
+    #[test]
+    fn test_auth_denies_appkey() {
+        let server = ServiceServer::builder("txnsvr")
+            .service("CREATE_TXN", |_req| ServiceResult::success("created"))
+            .with_auth(|req| req.appkey != 13)
+            .build();
+
+        let mut request = create_request(None);
+        request.appkey = 12;
+        let allowed = server.handle(&mut request);
+        assert!(allowed.success);
+        assert_eq!(allowed.message, "created");
+
+        request.appkey = 13;
+        let denied = server.handle(&mut request);
+        assert!(!denied.success);
+        assert_eq!(denied.rcode, AUTH_DENIED_RCODE);
+        assert!(matches!(
+            denied.into_response(),
+            Response::Fail(AUTH_DENIED_RCODE)
+        ));
+    }
+
+    #[test]
+    fn test_transaction_id() {
+        assert_eq!(create_request(None).transaction_id(), "TXN-1");
+
+        let mut request = create_request(None);
+        request.ubf_buffer = None;
+        assert_eq!(request.transaction_id(), "");
+    }
+
+    #[test]
+    fn test_duplicate_request_is_deduplicated() {
+        let store = InMemoryDedupStore::new(16, Duration::from_secs(60));
+        let inserts = Cell::new(0);
+        let handler = |_: &ServiceRequest| {
+            inserts.set(inserts.get() + 1);
+            let mut reply = UbfBuffer::new(256).unwrap();
+            reply.add_string(T_STATUS_FLD, "SUCCESS").unwrap();
+            ServiceResult::success_ubf(reply)
+        };
+
+        let request = create_request(Some("key-1"));
+        assert_eq!(request.idempotency_key().as_deref(), Some("key-1"));
+
+        let first = call_idempotent(&store, &request, handler);
+        let retry = call_idempotent(&store, &create_request(Some("key-1")), handler);
+
+        assert_eq!(inserts.get(), 1);
+        assert!(retry.success);
+        assert_eq!(
+            retry
+                .ubf_buffer
+                .as_ref()
+                .unwrap()
+                .get_string(T_STATUS_FLD, 0)
+                .unwrap(),
+            "SUCCESS"
+        );
+        assert_eq!(first.to_cached(), retry.to_cached());
+
+        // A new key and a request without key both run the handler
+        call_idempotent(&store, &create_request(Some("key-2")), handler);
+        call_idempotent(&store, &create_request(None), handler);
+        call_idempotent(&store, &create_request(None), handler);
+        assert_eq!(inserts.get(), 4);
+    }
+
+    #[test]
+    fn test_failed_request_is_not_deduplicated() {
+        let store = InMemoryDedupStore::new(16, Duration::from_secs(60));
+        let attempts = Cell::new(0);
+        let handler = |_: &ServiceRequest| {
+            attempts.set(attempts.get() + 1);
+            if attempts.get() == 1 {
+                ServiceResult::error("database timeout")
+            } else {
+                ServiceResult::success("created")
+            }
+        };
+
+        let first = call_idempotent(&store, &create_request(Some("key-1")), handler);
+        let retry = call_idempotent(&store, &create_request(Some("key-1")), handler);
+        let replay = call_idempotent(&store, &create_request(Some("key-1")), handler);
+
+        assert!(!first.success);
+        assert!(retry.success && replay.success);
+        assert_eq!(attempts.get(), 2);
+    }
+}
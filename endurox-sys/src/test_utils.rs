@@ -0,0 +1,131 @@
+//! Assertions on UBF buffers for integration tests
+
+use crate::ubf::{UbfBuffer, UbfValue};
+
+/// Checks that `buf` holds the `expected` values at occurrence 0
+///
+/// Integer and floating point values compare across widths, so `5` matches
+/// a short or long field. With `exact`, any other field or occurrence in the
+/// buffer is an error too.
+pub fn check_ubf_fields(
+    buf: &UbfBuffer,
+    expected: &[(i32, UbfValue)],
+    exact: bool,
+) -> Result<(), String> {
+    let mut problems = Vec::new();
+
+    for (field_id, want) in expected {
+        match buf.get_value(*field_id, 0) {
+            Ok(got) if values_match(&got, want) => {}
+            Ok(got) => problems.push(format!(
+                "field {}: expected {:?}, got {:?}",
+                field_id, want, got
+            )),
+            Err(e) => problems.push(format!("field {}: {}", field_id, e)),
+        }
+    }
+
+    if exact {
+        for field in buf.fields() {
+            let listed = expected.iter().any(|(id, _)| *id == field.id);
+            if !listed || field.occ > 0 {
+                problems.push(format!(
+                    "unexpected field {} occurrence {}",
+                    field.id, field.occ
+                ));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("\n"))
+    }
+}
+
+fn values_match(got: &UbfValue, want: &UbfValue) -> bool {
+    match (as_integer(got), as_integer(want)) {
+        (Some(a), Some(b)) => return a == b,
+        (Some(_), None) | (None, Some(_)) => return false,
+        (None, None) => {}
+    }
+
+    match (got, want) {
+        // A float field only keeps f32 precision
+        (UbfValue::Float(a), UbfValue::Double(b)) | (UbfValue::Double(b), UbfValue::Float(a)) => {
+            *a == *b as f32
+        }
+        _ => got == want,
+    }
+}
+
+fn as_integer(value: &UbfValue) -> Option<i64> {
+    match *value {
+        UbfValue::Short(v) => Some(v as i64),
+        UbfValue::Long(v) => Some(v),
+        UbfValue::Char(v) => Some(v as i64),
+        _ => None,
+    }
+}
+
+/// Asserts that a UBF buffer holds the listed field values
+///
+/// ```ignore
+/// assert_ubf_eq!(buf, { T_NAME_FLD => "x", T_ID_FLD => 5 });
+/// // No other fields or occurrences allowed
+/// assert_ubf_eq!(buf, { T_NAME_FLD => "x" }, exact);
+/// ```
+#[macro_export]
+macro_rules! assert_ubf_eq {
+    ($buf:expr, { $($field:expr => $value:expr),* $(,)? }) => {
+        $crate::assert_ubf_eq!(@check $buf, false, $($field => $value),*)
+    };
+    ($buf:expr, { $($field:expr => $value:expr),* $(,)? }, exact) => {
+        $crate::assert_ubf_eq!(@check $buf, true, $($field => $value),*)
+    };
+    (@check $buf:expr, $exact:expr, $($field:expr => $value:expr),*) => {
+        if let Err(e) = $crate::test_utils::check_ubf_fields(
+            &$buf,
+            &[$(($field, $crate::ubf::UbfValue::from($value))),*],
+            $exact,
+        ) {
+            panic!("UBF buffer mismatch:\n{}", e);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ubf::UbfBuffer;
+    use crate::ubf_fields::*;
+
+    fn sample() -> UbfBuffer {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "x").unwrap();
+        buf.add_long(T_ID_FLD, 5).unwrap();
+        buf.add_double(T_PRICE_FLD, 1.5).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_assert_ubf_eq_matches() {
+        let buf = sample();
+        assert_ubf_eq!(buf, { T_NAME_FLD => "x", T_ID_FLD => 5 });
+        assert_ubf_eq!(buf, { T_NAME_FLD => "x", T_ID_FLD => 5, T_PRICE_FLD => 1.5 }, exact);
+    }
+
+    #[test]
+    #[should_panic(expected = "unexpected field")]
+    fn test_assert_ubf_eq_exact_rejects_extra() {
+        let buf = sample();
+        assert_ubf_eq!(buf, { T_NAME_FLD => "x", T_ID_FLD => 5 }, exact);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected String(\"y\")")]
+    fn test_assert_ubf_eq_value_mismatch() {
+        let buf = sample();
+        assert_ubf_eq!(buf, { T_NAME_FLD => "y" });
+    }
+}
@@ -0,0 +1,188 @@
+//! XATMI buffers of any type, told apart with `tptypes`
+
+use crate::error::TpError;
+use crate::ffi;
+use crate::ubf::{buffer_type, UbfBuffer};
+use libc::{c_char, c_long};
+use std::ffi::CString;
+use std::ptr;
+
+/// Request or reply buffer with its XATMI type
+#[derive(Debug)]
+pub enum TypedBuffer {
+    /// `UBF` (also `FML`/`FML32`)
+    Ubf(UbfBuffer),
+    /// `STRING`
+    String(String),
+    /// `JSON`
+    Json(String),
+    /// `CARRAY`
+    Carray(Vec<u8>),
+}
+
+impl TypedBuffer {
+    /// XATMI type name, as passed to tpalloc
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            TypedBuffer::Ubf(_) => "UBF",
+            TypedBuffer::String(_) => "STRING",
+            TypedBuffer::Json(_) => "JSON",
+            TypedBuffer::Carray(_) => "CARRAY",
+        }
+    }
+
+    /// Moves the content into a tpalloc'ed buffer, returned with its tpcall length
+    ///
+    /// The caller owns the returned buffer.
+    pub(crate) fn into_raw(self) -> Result<(*mut c_char, c_long), String> {
+        let typ = self.type_name();
+        let bytes = match self {
+            // 0 for UBF - length determined automatically
+            TypedBuffer::Ubf(buf) => return Ok((buf.into_raw(), 0)),
+            TypedBuffer::String(text) | TypedBuffer::Json(text) => CString::new(text)
+                .map_err(|_| format!("{} buffer contains a NUL byte", typ))?
+                .into_bytes_with_nul(),
+            TypedBuffer::Carray(bytes) => bytes,
+        };
+
+        let c_type = CString::new(typ).map_err(|e| e.to_string())?;
+        // tpalloc rejects a zero size, an empty CARRAY still gets a byte
+        let ptr =
+            unsafe { ffi::tpalloc(c_type.as_ptr(), ptr::null(), bytes.len().max(1) as c_long) };
+        if ptr.is_null() {
+            return Err(format!("Failed to allocate {} buffer", typ));
+        }
+
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, ptr, bytes.len()) };
+        Ok((ptr, bytes.len() as c_long))
+    }
+
+    /// Takes over a reply buffer, typed with `tptypes`
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a buffer allocated by tpalloc, owned by the caller, with
+    /// `len` the reply length from tpcall.
+    pub(crate) unsafe fn from_reply(ptr: *mut c_char, len: c_long) -> Result<Self, TpError> {
+        if ptr.is_null() {
+            return Err(TpError::Xatmi {
+                errno: ffi::TPEOTYPE,
+                message: "Null reply buffer".to_string(),
+            });
+        }
+
+        let typ = buffer_type(ptr);
+        Self::decode(typ.as_deref(), ptr, len)
+    }
+
+    /// `from_reply` with the buffer type already known
+    ///
+    /// Every buffer but UBF is copied out and freed.
+    unsafe fn decode(typ: Option<&str>, ptr: *mut c_char, len: c_long) -> Result<Self, TpError> {
+        let len = len.max(0) as usize;
+        let bytes = || std::slice::from_raw_parts(ptr as *const u8, len).to_vec();
+        // STRING and JSON end at the NUL, which may come before len
+        let text = || {
+            let mut bytes = bytes();
+            if let Some(nul) = bytes.iter().position(|&b| b == 0) {
+                bytes.truncate(nul);
+            }
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+
+        let reply = match typ {
+            Some("UBF" | "FML" | "FML32") => {
+                return Ok(TypedBuffer::Ubf(UbfBuffer::from_raw(ptr)));
+            }
+            Some("STRING") => Ok(TypedBuffer::String(text())),
+            Some("JSON") => Ok(TypedBuffer::Json(text())),
+            Some("CARRAY") => Ok(TypedBuffer::Carray(bytes())),
+            other => Err(TpError::Xatmi {
+                errno: ffi::TPEOTYPE,
+                message: format!("Unsupported reply buffer type {:?}", other),
+            }),
+        };
+
+        ffi::tpfree(ptr);
+        reply
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ubf_fields::*;
+
+    // tptypes needs ATMI config, so the tests give the type to decode
+
+    #[test]
+    fn test_string_reply_round_trip() {
+        let (ptr, len) = TypedBuffer::String("Hello".to_string()).into_raw().unwrap();
+        assert_eq!(len, 6);
+
+        match unsafe { TypedBuffer::decode(Some("STRING"), ptr, len) }.unwrap() {
+            TypedBuffer::String(text) => assert_eq!(text, "Hello"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ubf_reply_keeps_buffer() {
+        let mut buf = UbfBuffer::new(256).unwrap();
+        buf.add_string(T_STATUS_FLD, "OK").unwrap();
+
+        let (ptr, len) = TypedBuffer::Ubf(buf).into_raw().unwrap();
+        assert_eq!(len, 0);
+
+        match unsafe { TypedBuffer::decode(Some("UBF"), ptr, len) }.unwrap() {
+            TypedBuffer::Ubf(reply) => {
+                assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK")
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_json_and_carray_replies() {
+        let (ptr, len) = TypedBuffer::Json(r#"{"status":"OK"}"#.to_string())
+            .into_raw()
+            .unwrap();
+        match unsafe { TypedBuffer::decode(Some("JSON"), ptr, len) }.unwrap() {
+            TypedBuffer::Json(body) => assert_eq!(body, r#"{"status":"OK"}"#),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let (ptr, len) = TypedBuffer::Carray(vec![1, 0, 2]).into_raw().unwrap();
+        match unsafe { TypedBuffer::decode(Some("CARRAY"), ptr, len) }.unwrap() {
+            TypedBuffer::Carray(bytes) => assert_eq!(bytes, [1, 0, 2]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let (ptr, len) = TypedBuffer::String("x".to_string()).into_raw().unwrap();
+        let err = unsafe { TypedBuffer::decode(Some("VIEW"), ptr, len) }.unwrap_err();
+        assert!(matches!(
+            err,
+            TpError::Xatmi {
+                errno: ffi::TPEOTYPE,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_large_carray_reply_is_not_truncated() {
+        let data: Vec<u8> = (0..4 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+
+        let (ptr, len) = TypedBuffer::Carray(data.clone()).into_raw().unwrap();
+        assert_eq!(len as usize, data.len());
+        match unsafe { TypedBuffer::decode(Some("CARRAY"), ptr, len) }.unwrap() {
+            TypedBuffer::Carray(bytes) => assert!(bytes == data),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_string_with_nul_is_rejected() {
+        assert!(TypedBuffer::String("a\0b".to_string()).into_raw().is_err());
+    }
+}
@@ -3,19 +3,364 @@
 //! This module provides safe Rust wrappers around Enduro/X UBF API.
 //! UBF is a typed, self-describing buffer format for structured data.
 
+use crate::error::{c_string, TpError};
 use crate::ffi;
+use crate::ubf_struct::UbfError;
 use libc::{c_char, c_int, c_long};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::ffi::{CStr, CString};
 use std::fmt;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
 use std::ptr;
 
+/// Typed value of a UBF field
+///
+/// In JSON the value is tagged with its UBF type so it round-trips, e.g.
+/// `{"type":"long","value":5}`. A char is a one-character string (the byte
+/// as a Latin-1 character), a carray is standard base64 with padding.
+/// `Display` renders the bare value the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum UbfValue {
+    Short(i16),
+    Long(i64),
+    Char(#[serde(with = "char_byte")] u8),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Carray(#[serde(with = "base64_bytes")] Vec<u8>),
+}
+
+impl UbfValue {
+    /// Bytes of field data the value takes in a buffer (strings with NUL)
+    pub fn data_len(&self) -> usize {
+        match self {
+            UbfValue::Short(_) => std::mem::size_of::<i16>(),
+            UbfValue::Long(_) => std::mem::size_of::<c_long>(),
+            UbfValue::Char(_) => 1,
+            UbfValue::Float(_) => std::mem::size_of::<f32>(),
+            UbfValue::Double(_) => std::mem::size_of::<f64>(),
+            UbfValue::String(v) => v.len() + 1,
+            UbfValue::Carray(v) => v.len(),
+        }
+    }
+}
+
+/// Types encoded as a flat list of UBF fields, such as database rows
+///
+/// Implementors only list their fields; building the buffer is shared.
+pub trait ToUbf {
+    /// Fields in the order they are added, leaving out NULL columns
+    fn ubf_fields(&self) -> Vec<(i32, UbfValue)>;
+
+    /// Encodes `ubf_fields` into a new buffer sized for them
+    fn to_ubf_buffer(&self) -> Result<UbfBuffer, String> {
+        UbfBuffer::from_fields(&self.ubf_fields())
+    }
+}
+
+impl fmt::Display for UbfValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UbfValue::Short(v) => write!(f, "{}", v),
+            UbfValue::Long(v) => write!(f, "{}", v),
+            UbfValue::Char(v) => write!(f, "{}", *v as char),
+            UbfValue::Float(v) => write!(f, "{}", v),
+            UbfValue::Double(v) => write!(f, "{}", v),
+            UbfValue::String(v) => write!(f, "{}", v),
+            UbfValue::Carray(v) => write!(f, "{}", base64_bytes::encode(v)),
+        }
+    }
+}
+
+/// Type of a UBF field, encoded in its field id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Short,
+    Long,
+    Char,
+    Float,
+    Double,
+    String,
+    Carray,
+}
+
+impl FieldType {
+    /// Type for a `BFLD_*` code
+    pub fn from_code(code: c_int) -> Option<Self> {
+        match code {
+            ffi::BFLD_SHORT => Some(FieldType::Short),
+            ffi::BFLD_LONG => Some(FieldType::Long),
+            ffi::BFLD_CHAR => Some(FieldType::Char),
+            ffi::BFLD_FLOAT => Some(FieldType::Float),
+            ffi::BFLD_DOUBLE => Some(FieldType::Double),
+            ffi::BFLD_STRING => Some(FieldType::String),
+            ffi::BFLD_CARRAY => Some(FieldType::Carray),
+            _ => None,
+        }
+    }
+
+    /// `.fd` name of the type, e.g. `string`
+    pub fn name(self) -> &'static str {
+        crate::ubf_table::FIELD_TYPES[self as usize]
+    }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Problem found by `UbfBuffer::validate_against`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaViolation {
+    /// Required field has no occurrence
+    MissingRequired { field_id: i32 },
+    /// Field id in the buffer is not of the type the schema expects
+    WrongType {
+        field_id: i32,
+        expected: FieldType,
+        actual: Option<FieldType>,
+    },
+    /// Field present in the buffer but not listed in the schema
+    Unexpected { field_id: i32 },
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaViolation::MissingRequired { field_id } => {
+                write!(f, "Required field {} is missing", display_field(*field_id))
+            }
+            SchemaViolation::WrongType {
+                field_id,
+                expected,
+                actual,
+            } => match actual {
+                Some(actual) => write!(
+                    f,
+                    "Field {} is {}, expected {}",
+                    display_field(*field_id),
+                    actual,
+                    expected
+                ),
+                None => write!(
+                    f,
+                    "Field {} has an unknown type, expected {}",
+                    display_field(*field_id),
+                    expected
+                ),
+            },
+            SchemaViolation::Unexpected { field_id } => {
+                write!(f, "Unexpected field {}", display_field(*field_id))
+            }
+        }
+    }
+}
+
+/// Field name from the loaded table, or the bare id
+fn display_field(field_id: i32) -> String {
+    UbfBuffer::field_name(field_id).unwrap_or_else(|_| field_id.to_string())
+}
+
+/// Carray bytes as base64 text
+pub(crate) mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn encode(bytes: &[u8]) -> String {
+        STANDARD.encode(bytes)
+    }
+
+    pub fn decode(text: &str) -> Result<Vec<u8>, base64::DecodeError> {
+        STANDARD.decode(text)
+    }
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        decode(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// UBF char as a one-character string
+mod char_byte {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(byte: &u8, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(*byte as char)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+        let c = char::deserialize(deserializer)?;
+        u8::try_from(c)
+            .map_err(|_| serde::de::Error::custom(format!("char {:?} is not a single byte", c)))
+    }
+}
+
+impl From<i16> for UbfValue {
+    fn from(v: i16) -> Self {
+        UbfValue::Short(v)
+    }
+}
+
+impl From<i32> for UbfValue {
+    fn from(v: i32) -> Self {
+        UbfValue::Long(v as i64)
+    }
+}
+
+impl From<i64> for UbfValue {
+    fn from(v: i64) -> Self {
+        UbfValue::Long(v)
+    }
+}
+
+impl From<u8> for UbfValue {
+    fn from(v: u8) -> Self {
+        UbfValue::Char(v)
+    }
+}
+
+impl From<f32> for UbfValue {
+    fn from(v: f32) -> Self {
+        UbfValue::Float(v)
+    }
+}
+
+impl From<f64> for UbfValue {
+    fn from(v: f64) -> Self {
+        UbfValue::Double(v)
+    }
+}
+
+impl From<&str> for UbfValue {
+    fn from(v: &str) -> Self {
+        UbfValue::String(v.to_string())
+    }
+}
+
+impl From<String> for UbfValue {
+    fn from(v: String) -> Self {
+        UbfValue::String(v)
+    }
+}
+
+impl From<&[u8]> for UbfValue {
+    fn from(v: &[u8]) -> Self {
+        UbfValue::Carray(v.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for UbfValue {
+    fn from(v: Vec<u8>) -> Self {
+        UbfValue::Carray(v)
+    }
+}
+
 /// UBF Buffer - safe wrapper around Enduro/X UBF buffer
 pub struct UbfBuffer {
     ptr: *mut c_char,
     size: usize,
+    growth: GrowthPolicy,
+}
+
+/// Size of buffers created without an explicit size (`UbfBuffer::builder`,
+/// `#[derive(UbfStruct)]`'s `to_ubf`)
+pub const DEFAULT_BUFFER_SIZE: usize = 2048;
+
+/// Largest step `GrowthPolicy::Double` grows a buffer by at once
+pub const MAX_DOUBLING_STEP: usize = 1024 * 1024;
+
+/// How `UbfBuffer::reserve` picks the new allocation size
+///
+/// `Double` minimizes the number of reallocations, `Exact` minimizes memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GrowthPolicy {
+    /// Double the size, growing by at most `MAX_DOUBLING_STEP` at once
+    #[default]
+    Double,
+    /// Grow in steps of the given number of bytes
+    Fixed(usize),
+    /// Grow to exactly the required size
+    Exact,
+}
+
+impl GrowthPolicy {
+    /// New allocation size for a buffer of `current` bytes that needs `required`
+    pub fn next_size(&self, current: usize, required: usize) -> usize {
+        if required <= current {
+            return current;
+        }
+
+        match *self {
+            GrowthPolicy::Double => {
+                let step = current.clamp(1, MAX_DOUBLING_STEP);
+                required.max(current + step)
+            }
+            GrowthPolicy::Fixed(step) if step > 0 => {
+                let steps = (required - current).div_ceil(step);
+                current + steps * step
+            }
+            GrowthPolicy::Fixed(_) | GrowthPolicy::Exact => required,
+        }
+    }
+}
+
+/// Builder for `UbfBuffer`
+///
+/// ```no_run
+/// use endurox_sys::ubf::{GrowthPolicy, UbfBuffer};
+///
+/// let buf = UbfBuffer::builder()
+///     .size(4096)
+///     .growth(GrowthPolicy::Fixed(1024))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct UbfBufferBuilder {
+    size: usize,
+    growth: GrowthPolicy,
+}
+
+impl UbfBufferBuilder {
+    /// Initial allocation size, `DEFAULT_BUFFER_SIZE` if not set
+    pub fn size(mut self, size: usize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Growth policy used by `reserve`
+    pub fn growth(mut self, growth: GrowthPolicy) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    pub fn build(self) -> Result<UbfBuffer, String> {
+        let mut buf = UbfBuffer::new(self.size)?;
+        buf.set_growth(self.growth);
+        Ok(buf)
+    }
 }
 
 impl UbfBuffer {
+    /// Starts building a buffer of `DEFAULT_BUFFER_SIZE` bytes
+    pub fn builder() -> UbfBufferBuilder {
+        UbfBufferBuilder {
+            size: DEFAULT_BUFFER_SIZE,
+            growth: GrowthPolicy::default(),
+        }
+    }
+
     /// Allocate a new UBF buffer
     pub fn new(size: usize) -> Result<Self, String> {
         let ubf_type = CString::new("UBF").map_err(|e| e.to_string())?;
@@ -34,12 +379,58 @@ impl UbfBuffer {
             return Err("Failed to initialize UBF buffer".to_string());
         }
 
-        Ok(UbfBuffer { ptr, size })
+        Ok(UbfBuffer {
+            ptr,
+            size,
+            growth: GrowthPolicy::default(),
+        })
+    }
+
+    /// Allocates a buffer just big enough for `fields` and adds them in order
+    pub fn from_fields(fields: &[(i32, UbfValue)]) -> Result<Self, String> {
+        let data_len: usize = fields.iter().map(|(_, value)| value.data_len()).sum();
+        let needed = unsafe { ffi::Bneeded(fields.len() as c_int, data_len as c_int) };
+        if needed < 0 {
+            return Err(format!(
+                "Failed to size buffer for {} fields of {} bytes",
+                fields.len(),
+                data_len
+            ));
+        }
+
+        let mut buf = UbfBuffer::new(needed as usize)?;
+        buf.add_many(fields)?;
+        Ok(buf)
+    }
+
+    /// Builds a buffer from field names, resolved with `Bfldid`
+    ///
+    /// For tests and tools without a compiled struct. Fails naming every
+    /// field the loaded tables don't define. Values are added like
+    /// `add_value`, converted to each field's type.
+    pub fn from_map(map: &HashMap<&str, UbfValue>) -> Result<Self, String> {
+        let mut names: Vec<&str> = map.keys().copied().collect();
+        names.sort_unstable();
+
+        let mut fields = Vec::with_capacity(names.len());
+        let mut unknown = Vec::new();
+        for name in names {
+            match UbfBuffer::field_id(name) {
+                Ok(field_id) => fields.push((field_id, map[name].clone())),
+                Err(_) => unknown.push(name),
+            }
+        }
+
+        if !unknown.is_empty() {
+            return Err(format!("Unknown UBF field(s): {}", unknown.join(", ")));
+        }
+
+        UbfBuffer::from_fields(&fields)
     }
 
     /// Add a string field
     pub fn add_string(&mut self, field_id: i32, value: &str) -> Result<(), String> {
-        let c_value = CString::new(value).map_err(|e| e.to_string())?;
+        let c_value = field_c_string(field_id, value).map_err(|e| e.to_string())?;
         let result = unsafe {
             ffi::Badd(
                 self.ptr,
@@ -93,9 +484,148 @@ impl UbfBuffer {
         Ok(())
     }
 
+    /// Add a float field, converted (`CBadd`) if the field isn't BFLD_FLOAT
+    pub fn add_float(&mut self, field_id: i32, value: f32) -> Result<(), String> {
+        self.add_converted(field_id, &value as *const f32, ffi::BFLD_FLOAT)
+            .map_err(|_| format!("Failed to add float field {}", field_id))
+    }
+
+    /// Add a carray (binary) field
+    pub fn add_carray(&mut self, field_id: i32, value: &[u8]) -> Result<(), String> {
+        let result = unsafe {
+            ffi::Badd(
+                self.ptr,
+                field_id,
+                value.as_ptr() as *const c_char,
+                value.len() as c_int,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!("Failed to add carray field {}", field_id));
+        }
+
+        Ok(())
+    }
+
+    /// Add a field of any supported type
+    ///
+    /// Numeric and char values are converted by `CBadd` to the type of the
+    /// field, so e.g. a JSON integer can go into a double field.
+    pub fn add_value(&mut self, field_id: i32, value: &UbfValue) -> Result<(), String> {
+        match value {
+            UbfValue::Long(v) => {
+                let val = *v as c_long;
+                self.add_converted(field_id, &val as *const c_long, ffi::BFLD_LONG)
+            }
+            UbfValue::Double(v) => self.add_converted(field_id, v as *const f64, ffi::BFLD_DOUBLE),
+            UbfValue::String(v) => self.add_string(field_id, v),
+            UbfValue::Carray(v) => self.add_carray(field_id, v),
+            UbfValue::Short(v) => self.add_converted(field_id, v as *const i16, ffi::BFLD_SHORT),
+            UbfValue::Char(v) => self.add_converted(field_id, v as *const u8, ffi::BFLD_CHAR),
+            UbfValue::Float(v) => self.add_float(field_id, *v),
+        }
+    }
+
+    /// Add several fields in order
+    ///
+    /// Stops at the first failure; fields added before it stay in the buffer.
+    pub fn add_many(&mut self, fields: &[(i32, UbfValue)]) -> Result<(), String> {
+        for (field_id, value) in fields {
+            self.add_value(*field_id, value)
+                .map_err(|e| format!("add_many stopped at field {}: {}", field_id, e))?;
+        }
+        Ok(())
+    }
+
+    fn add_converted<T>(
+        &mut self,
+        field_id: i32,
+        value: *const T,
+        usrtype: c_int,
+    ) -> Result<(), String> {
+        let result = unsafe { ffi::CBadd(self.ptr, field_id, value as *const c_char, 0, usrtype) };
+
+        if result == -1 {
+            return Err(format!("Failed to add field {}", field_id));
+        }
+
+        Ok(())
+    }
+
+    /// Set the first occurrence of a field of any supported type
+    ///
+    /// Like `add_value`, the value is converted (`CBchg`) to the type of the
+    /// field.
+    pub fn set_value(&mut self, field_id: i32, value: &UbfValue) -> Result<(), String> {
+        // Converted values must outlive the CBchg call
+        let long_value: c_long;
+        let c_value: CString;
+
+        let (ptr, len, usrtype): (*const c_char, c_int, c_int) = match value {
+            UbfValue::Short(v) => (v as *const i16 as *const c_char, 0, ffi::BFLD_SHORT),
+            UbfValue::Long(v) => {
+                long_value = *v as c_long;
+                (
+                    &long_value as *const c_long as *const c_char,
+                    0,
+                    ffi::BFLD_LONG,
+                )
+            }
+            UbfValue::Char(v) => (v as *const u8 as *const c_char, 0, ffi::BFLD_CHAR),
+            UbfValue::Float(v) => (v as *const f32 as *const c_char, 0, ffi::BFLD_FLOAT),
+            UbfValue::Double(v) => (v as *const f64 as *const c_char, 0, ffi::BFLD_DOUBLE),
+            UbfValue::String(v) => {
+                c_value = field_c_string(field_id, v).map_err(|e| e.to_string())?;
+                (c_value.as_ptr(), 0, ffi::BFLD_STRING)
+            }
+            UbfValue::Carray(v) => (
+                v.as_ptr() as *const c_char,
+                v.len() as c_int,
+                ffi::BFLD_CARRAY,
+            ),
+        };
+
+        let result = unsafe { ffi::CBchg(self.ptr, field_id, 0, ptr, len, usrtype) };
+
+        if result == -1 {
+            return Err(format!("Failed to set field {}", field_id));
+        }
+
+        Ok(())
+    }
+
+    /// Set the first occurrence of a string field, adding it if absent
+    pub fn set_string(&mut self, field_id: i32, value: &str) -> Result<(), String> {
+        let c_value = field_c_string(field_id, value).map_err(|e| e.to_string())?;
+        self.set_raw(field_id, c_value.as_ptr())
+    }
+
+    /// Set the first occurrence of a long field, adding it if absent
+    pub fn set_long(&mut self, field_id: i32, value: i64) -> Result<(), String> {
+        let val = value as c_long;
+        self.set_raw(field_id, &val as *const c_long as *const c_char)
+    }
+
+    /// Set the first occurrence of a double field, adding it if absent
+    pub fn set_double(&mut self, field_id: i32, value: f64) -> Result<(), String> {
+        self.set_raw(field_id, &value as *const f64 as *const c_char)
+    }
+
+    // Bchg adds the occurrence when it is not present
+    fn set_raw(&mut self, field_id: i32, value: *const c_char) -> Result<(), String> {
+        let result = unsafe { ffi::Bchg(self.ptr, field_id, 0, value, 0) };
+
+        if result == -1 {
+            return Err(format!("Failed to set field {}", field_id));
+        }
+
+        Ok(())
+    }
+
     /// Change a string field at specific occurrence
     pub fn change_string(&mut self, field_id: i32, occ: i32, value: &str) -> Result<(), String> {
-        let c_value = CString::new(value).map_err(|e| e.to_string())?;
+        let c_value = field_c_string(field_id, value).map_err(|e| e.to_string())?;
         let result = unsafe { ffi::Bchg(self.ptr, field_id, occ, c_value.as_ptr(), 0) };
 
         if result == -1 {
@@ -108,36 +638,144 @@ impl UbfBuffer {
         Ok(())
     }
 
-    /// Get a string field
-    pub fn get_string(&self, field_id: i32, occ: i32) -> Result<String, String> {
-        let mut buf = vec![0u8; 1024];
-        let mut len = buf.len() as c_int;
+    /// Change a long field at specific occurrence
+    ///
+    /// Like `change_string`, missing occurrences up to `occ` are added.
+    pub fn change_long(&mut self, field_id: i32, occ: i32, value: i64) -> Result<(), String> {
+        let val = value as c_long;
+        let result = unsafe {
+            ffi::Bchg(
+                self.ptr,
+                field_id,
+                occ,
+                &val as *const c_long as *const c_char,
+                0,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!(
+                "Failed to change long field {} at occ {}",
+                field_id, occ
+            ));
+        }
+
+        Ok(())
+    }
 
+    /// Change a double field at specific occurrence
+    pub fn change_double(&mut self, field_id: i32, occ: i32, value: f64) -> Result<(), String> {
         let result = unsafe {
-            ffi::CBget(
+            ffi::Bchg(
                 self.ptr,
                 field_id,
                 occ,
-                buf.as_mut_ptr() as *mut c_char,
-                &mut len,
-                ffi::BFLD_STRING,
+                &value as *const f64 as *const c_char,
+                0,
             )
         };
 
         if result == -1 {
             return Err(format!(
-                "Failed to get string field {} at occ {}",
+                "Failed to change double field {} at occ {}",
                 field_id, occ
             ));
         }
 
-        // Convert C string to Rust String
-        let c_str = unsafe { CStr::from_ptr(buf.as_ptr() as *const c_char) };
-        Ok(c_str.to_string_lossy().into_owned())
+        Ok(())
+    }
+
+    /// Change a float field at specific occurrence
+    pub fn change_float(&mut self, field_id: i32, occ: i32, value: f32) -> Result<(), String> {
+        let result = unsafe {
+            ffi::CBchg(
+                self.ptr,
+                field_id,
+                occ,
+                &value as *const f32 as *const c_char,
+                0,
+                ffi::BFLD_FLOAT,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!(
+                "Failed to change float field {} at occ {}",
+                field_id, occ
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get a string field
+    pub fn get_string(&self, field_id: i32, occ: i32) -> Result<String, String> {
+        let mut buf = vec![0u8; 1024];
+        let len = self.read_string(field_id, occ, &mut buf)?;
+        Ok(String::from_utf8_lossy(&buf[..len]).into_owned())
+    }
+
+    /// Get a string field into `scratch`, reusing its allocation
+    ///
+    /// `scratch` only grows when a field doesn't fit, so reading many fields
+    /// through the same vector allocates once.
+    pub fn get_string_into<'a>(
+        &self,
+        field_id: i32,
+        occ: i32,
+        scratch: &'a mut Vec<u8>,
+    ) -> Result<&'a str, String> {
+        let len = self.read_string(field_id, occ, scratch)?;
+        std::str::from_utf8(&scratch[..len]).map_err(|e| {
+            format!(
+                "String field {} at occ {} is not UTF-8: {}",
+                field_id, occ, e
+            )
+        })
+    }
+
+    /// Reads a string field into `buf`, growing it on `BNOSPACE`; returns
+    /// the length without the terminating NUL
+    fn read_string(&self, field_id: i32, occ: i32, buf: &mut Vec<u8>) -> Result<usize, String> {
+        if buf.is_empty() {
+            buf.resize(buf.capacity().max(256), 0);
+        }
+
+        loop {
+            let mut len = buf.len() as c_int;
+            let result = unsafe {
+                ffi::CBget(
+                    self.ptr,
+                    field_id,
+                    occ,
+                    buf.as_mut_ptr() as *mut c_char,
+                    &mut len,
+                    ffi::BFLD_STRING,
+                )
+            };
+
+            if result != -1 {
+                break;
+            }
+            // No field can be longer than the buffer holding it
+            if last_ubf_errno() != ffi::BNOSPACE || buf.len() > self.size() {
+                return Err(format!(
+                    "Failed to get string field {} at occ {}",
+                    field_id, occ
+                ));
+            }
+            let needed = unsafe { ffi::Blen(self.ptr, field_id, occ) }.max(0) as usize + 1;
+            buf.resize(needed.max(buf.len() * 2), 0);
+        }
+
+        Ok(buf.iter().position(|&b| b == 0).unwrap_or(buf.len()))
     }
 
     /// Get a long field
-    pub fn get_long(&self, field_id: i32, occ: i32) -> Result<i64, String> {
+    ///
+    /// Returns `UbfError::NotPresent` when the field or occurrence is absent,
+    /// so occurrence loops can stop cleanly at the end.
+    pub fn get_long(&self, field_id: i32, occ: i32) -> Result<i64, UbfError> {
         let mut value: c_long = 0;
         let mut len = std::mem::size_of::<c_long>() as c_int;
 
@@ -153,10 +791,7 @@ impl UbfBuffer {
         };
 
         if result == -1 {
-            return Err(format!(
-                "Failed to get long field {} at occ {}",
-                field_id, occ
-            ));
+            return Err(last_ubf_error(field_id, occ));
         }
 
         Ok(value as i64)
@@ -188,22 +823,221 @@ impl UbfBuffer {
         Ok(value)
     }
 
-    /// Check if field is present
-    pub fn is_present(&self, field_id: i32, occ: i32) -> bool {
-        unsafe { ffi::Bpres(self.ptr, field_id, occ) == 1 }
+    /// Get a float field
+    pub fn get_float(&self, field_id: i32, occ: i32) -> Result<f32, String> {
+        self.get_converted::<f32>(field_id, occ, ffi::BFLD_FLOAT)
+            .map_err(|_| format!("Failed to get float field {} at occ {}", field_id, occ))
     }
 
-    /// Delete a field occurrence
-    pub fn delete(&mut self, field_id: i32, occ: i32) -> Result<(), String> {
-        let result = unsafe { ffi::Bdel(self.ptr, field_id, occ) };
+    /// Get a field's bytes exactly as stored, without type conversion (Bget)
+    ///
+    /// Numeric fields come back in native byte order; string fields include
+    /// the terminating NUL.
+    pub fn get_raw(&self, field_id: i32, occ: i32) -> Result<Vec<u8>, String> {
+        // A field can't be larger than the used part of the buffer
+        let mut buf = vec![0u8; self.used().max(1)];
+        let mut len = buf.len() as c_int;
 
-        if result == -1 {
-            return Err(format!(
-                "Failed to delete field {} at occ {}",
+        let result = unsafe {
+            ffi::Bget(
+                self.ptr,
+                field_id,
+                occ,
+                buf.as_mut_ptr() as *mut c_char,
+                &mut len,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!(
+                "Failed to get raw field {} at occ {}",
                 field_id, occ
             ));
         }
 
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// Get a field as the `UbfValue` variant of its type (from `Bfldtype`)
+    pub fn get_value(&self, field_id: i32, occ: i32) -> Result<UbfValue, String> {
+        let field_type = unsafe { ffi::Bfldtype(field_id) };
+
+        match field_type {
+            ffi::BFLD_SHORT => self
+                .get_converted::<i16>(field_id, occ, ffi::BFLD_SHORT)
+                .map(UbfValue::Short),
+            ffi::BFLD_LONG => self
+                .get_long(field_id, occ)
+                .map(UbfValue::Long)
+                .map_err(|e| e.to_string()),
+            ffi::BFLD_CHAR => self
+                .get_converted::<u8>(field_id, occ, ffi::BFLD_CHAR)
+                .map(UbfValue::Char),
+            ffi::BFLD_FLOAT => self.get_float(field_id, occ).map(UbfValue::Float),
+            ffi::BFLD_DOUBLE => self.get_double(field_id, occ).map(UbfValue::Double),
+            ffi::BFLD_STRING => self.get_string(field_id, occ).map(UbfValue::String),
+            ffi::BFLD_CARRAY => self.get_carray(field_id, occ).map(UbfValue::Carray),
+            _ => Err(format!(
+                "Unsupported type {} of field {}",
+                field_type, field_id
+            )),
+        }
+    }
+
+    fn get_converted<T: Default>(
+        &self,
+        field_id: i32,
+        occ: i32,
+        usrtype: c_int,
+    ) -> Result<T, String> {
+        let mut value = T::default();
+        let mut len = std::mem::size_of::<T>() as c_int;
+
+        let result = unsafe {
+            ffi::CBget(
+                self.ptr,
+                field_id,
+                occ,
+                &mut value as *mut T as *mut c_char,
+                &mut len,
+                usrtype,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!("Failed to get field {} at occ {}", field_id, occ));
+        }
+
+        Ok(value)
+    }
+
+    /// Get a carray (binary) field, including any embedded NULs
+    pub fn get_carray(&self, field_id: i32, occ: i32) -> Result<Vec<u8>, String> {
+        // A field can't be larger than the used part of the buffer
+        let mut buf = vec![0u8; self.used().max(1)];
+        let mut len = buf.len() as c_int;
+
+        let result = unsafe {
+            ffi::CBget(
+                self.ptr,
+                field_id,
+                occ,
+                buf.as_mut_ptr() as *mut c_char,
+                &mut len,
+                ffi::BFLD_CARRAY,
+            )
+        };
+
+        if result == -1 {
+            return Err(format!(
+                "Failed to get carray field {} at occ {}",
+                field_id, occ
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// Number of occurrences of a field (0 when absent)
+    pub fn occurrences(&self, field_id: i32) -> Result<i32, String> {
+        let result = unsafe { ffi::Boccur(self.ptr, field_id) };
+
+        if result == -1 {
+            return Err(format!("Failed to count occurrences of field {}", field_id));
+        }
+
+        Ok(result)
+    }
+
+    /// Occurrence i of `first` paired with occurrence i of `second`
+    ///
+    /// For parallel repeating fields, e.g. validation errors carried as
+    /// `T_ERROR_CODE_FLD`/`T_ERROR_MSG_FLD` groups. Fails when the two
+    /// fields don't have the same number of occurrences.
+    pub fn get_string_pairs(
+        &self,
+        first: i32,
+        second: i32,
+    ) -> Result<Vec<(String, String)>, String> {
+        let count = self.paired_occurrences(first, second)?;
+        (0..count)
+            .map(|occ| Ok((self.get_string(first, occ)?, self.get_string(second, occ)?)))
+            .collect()
+    }
+
+    /// Appends each pair as the next occurrence of `first` and `second`
+    ///
+    /// On error the pairs added before the failing one stay, and the fields
+    /// stay paired.
+    pub fn add_string_pairs<A: AsRef<str>, B: AsRef<str>>(
+        &mut self,
+        first: i32,
+        second: i32,
+        pairs: &[(A, B)],
+    ) -> Result<(), String> {
+        let count = self.paired_occurrences(first, second)?;
+        for (occ, (a, b)) in (count..).zip(pairs) {
+            self.add_string(first, a.as_ref())?;
+            if let Err(e) = self.add_string(second, b.as_ref()) {
+                // Don't leave `first` one occurrence ahead of `second`
+                self.delete(first, occ)?;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Common occurrence count of two parallel fields
+    fn paired_occurrences(&self, first: i32, second: i32) -> Result<i32, String> {
+        let (count, other) = (self.occurrences(first)?, self.occurrences(second)?);
+        if count != other {
+            return Err(format!(
+                "Fields {} and {} are not paired: {} and {} occurrences",
+                first, second, count, other
+            ));
+        }
+        Ok(count)
+    }
+
+    /// Check if field is present
+    pub fn is_present(&self, field_id: i32, occ: i32) -> bool {
+        unsafe { ffi::Bpres(self.ptr, field_id, occ) == 1 }
+    }
+
+    /// Delete a field occurrence
+    ///
+    /// `Bdel` shifts higher occurrences down by one, so deleting several
+    /// occurrences in ascending order skips elements. Use
+    /// `delete_occurrences` for that.
+    pub fn delete(&mut self, field_id: i32, occ: i32) -> Result<(), String> {
+        let result = unsafe { ffi::Bdel(self.ptr, field_id, occ) };
+
+        if result == -1 {
+            return Err(format!(
+                "Failed to delete field {} at occ {}",
+                field_id, occ
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Delete several occurrences of a field
+    ///
+    /// `occs` refer to the occurrence indices before any deletion; they are
+    /// deleted in descending order so the shifting done by `Bdel` doesn't
+    /// invalidate the remaining indices. Duplicates are ignored.
+    pub fn delete_occurrences(&mut self, field_id: i32, occs: &[i32]) -> Result<(), String> {
+        let mut sorted = occs.to_vec();
+        sorted.sort_unstable_by(|a, b| b.cmp(a));
+        sorted.dedup();
+
+        for occ in sorted {
+            self.delete(field_id, occ)?;
+        }
+
         Ok(())
     }
 
@@ -219,12 +1053,59 @@ impl UbfBuffer {
         Ok(c_str.to_string_lossy().into_owned())
     }
 
+    /// Type of a field id (`Bfldtype`)
+    pub fn field_type(field_id: i32) -> Option<FieldType> {
+        FieldType::from_code(unsafe { ffi::Bfldtype(field_id) })
+    }
+
+    /// Checks the buffer against `schema` of `(field_id, type, required)`
+    ///
+    /// Reports every violation at once: required fields without an
+    /// occurrence, schema entries whose field id is of another type in the
+    /// field table, and fields in the buffer the schema doesn't list.
+    pub fn validate_against(
+        &self,
+        schema: &[(i32, FieldType, bool)],
+    ) -> Result<(), Vec<SchemaViolation>> {
+        let mut violations = Vec::new();
+
+        for &(field_id, expected, required) in schema {
+            let actual = Self::field_type(field_id);
+            if actual != Some(expected) {
+                violations.push(SchemaViolation::WrongType {
+                    field_id,
+                    expected,
+                    actual,
+                });
+            }
+            if required && !self.is_present(field_id, 0) {
+                violations.push(SchemaViolation::MissingRequired { field_id });
+            }
+        }
+
+        let mut seen = Vec::new();
+        for field in self.fields() {
+            let listed = schema.iter().any(|&(id, _, _)| id == field.id);
+            if !listed && !seen.contains(&field.id) {
+                seen.push(field.id);
+                violations.push(SchemaViolation::Unexpected { field_id: field.id });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
     /// Get field ID by name
     pub fn field_id(field_name: &str) -> Result<i32, String> {
-        let c_name = CString::new(field_name).map_err(|e| e.to_string())?;
+        let c_name = c_string(field_name, "Field name")?;
         let field_id = unsafe { ffi::Bfldid(c_name.as_ptr()) };
 
-        if field_id == -1 {
+        // Unknown names give BBADFLDID (0)
+        if field_id <= 0 {
             return Err(format!("Field name '{}' not found", field_name));
         }
 
@@ -246,6 +1127,123 @@ impl UbfBuffer {
         unsafe { ffi::Bsizeof(self.ptr) as usize }
     }
 
+    /// Builds a field index with room for `items` entries (`Bindex`)
+    ///
+    /// Kept for code ported from FML. Enduro/X already indexes UBF buffers
+    /// by field type and binary-searches fixed-size fields, so `Bindex` is
+    /// a no-op there and indexing never pays off: reads behave the same
+    /// with or without it.
+    pub fn build_index(&mut self, items: i32) -> Result<(), String> {
+        let result = unsafe { ffi::Bindex(self.ptr, items) };
+
+        if result == -1 {
+            return Err("Failed to index UBF buffer".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Drops the index built by `build_index` (`Bunindex`)
+    ///
+    /// Returns the number of index entries removed, 0 on Enduro/X.
+    pub fn drop_index(&mut self) -> Result<i32, String> {
+        let result = unsafe { ffi::Bunindex(self.ptr) };
+
+        if result == -1 {
+            return Err("Failed to unindex UBF buffer".to_string());
+        }
+
+        Ok(result)
+    }
+
+    /// Bytes used by the index (`Bidxused`)
+    pub fn index_used(&self) -> usize {
+        unsafe { ffi::Bidxused(self.ptr).max(0) as usize }
+    }
+
+    /// Resize the buffer with tprealloc, keeping all fields
+    ///
+    /// The buffer may move, so the stored pointer and size are updated together.
+    /// On failure the original buffer is left untouched.
+    pub fn realloc(&mut self, new_size: usize) -> Result<(), String> {
+        let new_ptr = unsafe { ffi::tprealloc(self.ptr, new_size as c_long) };
+
+        if new_ptr.is_null() {
+            return Err(format!(
+                "Failed to reallocate UBF buffer to {} bytes",
+                new_size
+            ));
+        }
+
+        self.ptr = new_ptr;
+        self.size = new_size;
+        Ok(())
+    }
+
+    /// Set how `reserve` grows the buffer
+    pub fn set_growth(&mut self, policy: GrowthPolicy) {
+        self.growth = policy;
+    }
+
+    pub fn growth(&self) -> GrowthPolicy {
+        self.growth
+    }
+
+    /// Make room for at least `additional` more bytes of fields
+    ///
+    /// Reallocates according to the growth policy only when the free space
+    /// is not enough.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), String> {
+        if self.unused() >= additional {
+            return Ok(());
+        }
+
+        let required = self.used() + additional;
+        let new_size = self.growth.next_size(self.size, required);
+        self.realloc(new_size)
+    }
+
+    /// Remove all fields, keeping the allocation
+    pub fn clear(&mut self) -> Result<(), String> {
+        let result = unsafe { ffi::Binit(self.ptr, self.size as c_long) };
+
+        if result == -1 {
+            return Err("Failed to reinitialize UBF buffer".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Applies `f` to the buffer all-or-nothing
+    ///
+    /// `f` works on a copy of the buffer, which is copied back only if `f`
+    /// returns `Ok`; a failed `add_*` midway leaves the buffer unchanged.
+    /// The buffer keeps its allocation, so a copy that grew past it fails
+    /// the transaction instead of being committed.
+    pub fn transaction<T, F>(&mut self, f: F) -> Result<T, String>
+    where
+        F: FnOnce(&mut UbfBuffer) -> Result<T, String>,
+    {
+        let mut txn = UbfBuffer::new(self.size)?;
+        txn.growth = self.growth;
+        if unsafe { ffi::Bcpy(txn.ptr, self.ptr) } == -1 {
+            return Err("Failed to copy UBF buffer".to_string());
+        }
+
+        let value = f(&mut txn)?;
+        if txn.used() > self.size {
+            return Err(format!(
+                "Transaction result of {} bytes doesn't fit the {} byte buffer",
+                txn.used(),
+                self.size
+            ));
+        }
+        if unsafe { ffi::Bcpy(self.ptr, txn.ptr) } == -1 {
+            return Err("Failed to copy UBF buffer".to_string());
+        }
+        Ok(value)
+    }
+
     /// Print buffer to stdout (for debugging)
     pub fn print(&self) -> Result<(), String> {
         let result = unsafe { ffi::Bprint(self.ptr) };
@@ -257,19 +1255,166 @@ impl UbfBuffer {
         Ok(())
     }
 
+    /// Iterate over all fields with their raw values
+    pub fn fields(&self) -> UbfFields<'_> {
+        UbfFields {
+            buffer: self,
+            scratch: vec![0u8; self.used().max(1)],
+            current_field_id: 0,
+            current_occ: 0,
+        }
+    }
+
+    /// Total number of field occurrences in the buffer
+    ///
+    /// One `Bnext` pass through a single scratch buffer, without copying
+    /// each value out like `fields()` does.
+    pub fn field_count(&self) -> usize {
+        // No value is longer than the used part of the buffer
+        let mut scratch = vec![0u8; self.used().max(1)];
+        let mut field_id = 0; // BFIRSTFLDID
+        let mut occ = 0;
+        let mut count = 0;
+
+        loop {
+            let mut len = scratch.len() as c_int;
+            let result = unsafe {
+                ffi::Bnext(
+                    self.ptr,
+                    &mut field_id,
+                    &mut occ,
+                    scratch.as_mut_ptr() as *mut c_char,
+                    &mut len,
+                )
+            };
+            if result != 1 {
+                return count;
+            }
+            count += 1;
+        }
+    }
+
+    /// New buffer holding only the fields in `field_ids`, all occurrences
+    ///
+    /// Unlike projecting in place, `self` is left untouched, e.g. to forward
+    /// a filtered view while the full request is still logged.
+    pub fn retained(&self, field_ids: &[i32]) -> Result<UbfBuffer, String> {
+        // A subset of the fields always fits in a buffer of the same size
+        let out = UbfBuffer::new(self.size())?;
+
+        for field in self.fields().filter(|f| field_ids.contains(&f.id)) {
+            // Raw values keep their type; the length only matters for CARRAY
+            let result = unsafe {
+                ffi::Badd(
+                    out.ptr,
+                    field.id,
+                    field.raw.as_ptr() as *const c_char,
+                    field.raw.len() as c_int,
+                )
+            };
+            if result == -1 {
+                return Err(format!(
+                    "Failed to copy field {} at occ {}",
+                    field.id, field.occ
+                ));
+            }
+        }
+
+        Ok(out)
+    }
+
     /// Get raw pointer (for FFI)
     pub fn as_ptr(&self) -> *mut c_char {
         self.ptr
     }
 
+    /// Raw `UBFH*` handle for calling UBF functions this crate doesn't wrap
+    ///
+    /// The buffer stays owned by `self`. The handle must not be freed or
+    /// reallocated through FFI, and must not be used after `self` is dropped.
+    ///
+    /// ```no_run
+    /// use endurox_sys::ubf::UbfBuffer;
+    /// use libc::{c_char, c_int};
+    ///
+    /// extern "C" {
+    ///     fn Bnum(p_ub: *mut c_char) -> c_int;
+    /// }
+    ///
+    /// let buf = UbfBuffer::new(1024).unwrap();
+    /// let count = unsafe { Bnum(buf.raw_ptr()) };
+    /// assert_eq!(count, 0);
+    /// ```
+    pub fn raw_ptr(&self) -> *mut c_char {
+        self.ptr
+    }
+
     /// Get buffer as byte slice
     pub fn as_bytes(&self) -> &[u8] {
         let used_size = self.used();
         unsafe { std::slice::from_raw_parts(self.ptr as *const u8, used_size) }
     }
 
+    /// Buffer in the Enduro/X JSON form (`tpubftojson`)
+    ///
+    /// Fields are keyed by name, e.g. `{"T_NAME_FLD":"alice","T_ID_FLD":[1,2]}`,
+    /// the form other Enduro/X components (such as the REST bridge) use.
+    /// Needs the field tables and an ATMI environment.
+    pub fn to_ndrx_json(&self) -> Result<String, String> {
+        // Field names and escaping make the JSON larger than the buffer;
+        // tpubftojson fails instead of truncating, so retry with more room
+        let max_size = self.used() * 16 + 1024;
+        let mut size = self.used() * 2 + 1024;
+        loop {
+            let mut out = vec![0u8; size];
+            let result = unsafe {
+                ffi::tpubftojson(self.ptr, out.as_mut_ptr() as *mut c_char, size as c_int)
+            };
+
+            if result != -1 {
+                let json = CStr::from_bytes_until_nul(&out).map_err(|e| e.to_string())?;
+                return Ok(json.to_string_lossy().into_owned());
+            }
+            if size >= max_size {
+                return Err(format!("tpubftojson failed: {}", TpError::last()));
+            }
+            size = (size * 2).min(max_size);
+        }
+    }
+
+    /// Parses the Enduro/X JSON form (`tpjsontoubf`), see `to_ndrx_json`
+    ///
+    /// Keys that are not field names in the loaded tables are skipped by
+    /// Enduro/X, not reported.
+    pub fn from_ndrx_json(json: &str) -> Result<Self, String> {
+        let c_json = c_string(json, "JSON")?;
+        let max_size = json.len() * 16 + DEFAULT_BUFFER_SIZE;
+        let mut size = json.len().max(DEFAULT_BUFFER_SIZE);
+        loop {
+            let buf = UbfBuffer::new(size)?;
+            let result = unsafe { ffi::tpjsontoubf(buf.ptr, c_json.as_ptr() as *mut c_char) };
+
+            if result != -1 {
+                return Ok(buf);
+            }
+            // Numbers take more room as UBF fields than as JSON text
+            if last_ubf_errno() != ffi::BNOSPACE || size >= max_size {
+                return Err(format!("tpjsontoubf failed: {}", TpError::last()));
+            }
+            size = (size * 2).min(max_size);
+        }
+    }
+
     /// Create UbfBuffer from byte slice
+    ///
+    /// The copy gets the sender's allocation size from the header, so fields
+    /// can still be added to it.
     pub fn from_bytes(data: &[u8]) -> Result<Self, String> {
+        Self::copy_bytes(data, usize::MAX)
+    }
+
+    /// `from_bytes` with the room of the copy capped at `max_size`
+    fn copy_bytes(data: &[u8], max_size: usize) -> Result<Self, String> {
         let size = data.len();
         let ubf_type = CString::new("UBF").map_err(|e| e.to_string())?;
         let ptr = unsafe { ffi::tpalloc(ubf_type.as_ptr(), ptr::null(), size as c_long) };
@@ -283,7 +1428,52 @@ impl UbfBuffer {
             std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, size);
         }
 
-        Ok(UbfBuffer { ptr, size })
+        // Freed on the error paths below
+        let buf = UbfBuffer {
+            ptr,
+            size,
+            growth: GrowthPolicy::default(),
+        };
+
+        // A STRING or JSON reply copied here has no UBF header, and reading
+        // it with Bnext/Bget is undefined
+        if unsafe { ffi::Bisubf(ptr) } != 1 {
+            return Err("Data is not a UBF buffer (bad header)".to_string());
+        }
+
+        let used = unsafe { ffi::Bused(ptr) };
+        if used < 0 || used as usize > size {
+            return Err(format!(
+                "Truncated UBF buffer: header claims {} bytes, got {}",
+                used, size
+            ));
+        }
+
+        // The copied header still holds the sender's allocation size, which
+        // Badd trusts. Bcpy keeps the destination's own size in its header.
+        let declared = unsafe { ffi::Bsizeof(ptr) }.max(0) as usize;
+        let copy = UbfBuffer::new(declared.min(max_size).max(size))?;
+        if unsafe { ffi::Bcpy(copy.ptr, buf.ptr) } == -1 {
+            return Err("Failed to copy UBF buffer".to_string());
+        }
+
+        Ok(copy)
+    }
+
+    /// Like `from_bytes`, but rejects payloads over `max_size` before allocating
+    ///
+    /// Use it for replies from services that aren't trusted to bound their size.
+    /// The copy is also allocated no larger than `max_size`.
+    pub fn from_bytes_bounded(data: &[u8], max_size: usize) -> Result<Self, String> {
+        if data.len() > max_size {
+            return Err(format!(
+                "UBF payload of {} bytes exceeds the limit of {} bytes",
+                data.len(),
+                max_size
+            ));
+        }
+
+        Self::copy_bytes(data, max_size)
     }
 
     /// Get raw pointer and consume the buffer (for tpreturn)
@@ -295,15 +1485,280 @@ impl UbfBuffer {
 
     /// Create UbfBuffer from raw pointer (unsafe - caller must ensure validity)
     ///
+    /// The allocation size is taken from `Bsizeof`, which matches the real
+    /// allocation only if the buffer was initialized to its full `tpalloc`
+    /// size. Use `from_raw_with_size` when the allocation size is known.
+    ///
     /// # Safety
     ///
     /// The caller must ensure that `ptr` is a valid pointer to a UBF buffer allocated by Balloc or tpalloc.
     pub unsafe fn from_raw(ptr: *mut c_char) -> Self {
         let size = ffi::Bsizeof(ptr) as usize;
-        UbfBuffer { ptr, size }
+        UbfBuffer {
+            ptr,
+            size,
+            growth: GrowthPolicy::default(),
+        }
+    }
+
+    /// `from_raw` for a buffer of unknown type, checked with `tptypes`
+    ///
+    /// A STRING or JSON buffer has no UBF header, so `Bsizeof` on it returns
+    /// garbage. Such a buffer is rejected and stays owned by the caller.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is null or a buffer allocated by tpalloc.
+    pub unsafe fn try_from_raw(ptr: *mut c_char) -> Result<Self, String> {
+        if ptr.is_null() {
+            return Err("Null buffer pointer".to_string());
+        }
+
+        let typ = buffer_type(ptr);
+        Self::from_typed(typ.as_deref(), ptr)
+    }
+
+    /// `try_from_raw` once the buffer type is known
+    unsafe fn from_typed(typ: Option<&str>, ptr: *mut c_char) -> Result<Self, String> {
+        match typ {
+            Some("UBF" | "FML" | "FML32") => Ok(Self::from_raw(ptr)),
+            Some(typ) => Err(format!("Expected a UBF buffer, got {}", typ)),
+            None => Err("Pointer is not an XATMI buffer".to_string()),
+        }
+    }
+
+    /// Wrap a UBF buffer owned elsewhere without taking ownership
+    ///
+    /// Unlike `from_raw`, dropping the result does not `tpfree` the buffer,
+    /// so a pointer received from custom FFI (or a service request) can use
+    /// the safe API without a double free.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is a valid UBF buffer that outlives
+    /// the returned handle.
+    pub unsafe fn from_raw_ptr_borrowed<'a>(ptr: *mut c_char) -> BorrowedUbfBuffer<'a> {
+        BorrowedUbfBuffer {
+            buffer: ManuallyDrop::new(Self::from_raw(ptr)),
+            _owner: PhantomData,
+        }
+    }
+
+    /// Create UbfBuffer from raw pointer with a known `tpalloc` size
+    ///
+    /// Invariant: the UBF header (`Bsizeof`) never claims more than
+    /// `tpalloc_size`, otherwise `Badd` could write past the allocation.
+    /// On error the buffer is not taken over and stays owned by the caller.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is a valid pointer to a UBF buffer allocated by tpalloc
+    /// with at least `tpalloc_size` bytes.
+    pub unsafe fn from_raw_with_size(
+        ptr: *mut c_char,
+        tpalloc_size: usize,
+    ) -> Result<Self, String> {
+        let ubf_size = ffi::Bsizeof(ptr);
+        if ubf_size < 0 {
+            return Err("Pointer is not a valid UBF buffer".to_string());
+        }
+
+        if ubf_size as usize > tpalloc_size {
+            return Err(format!(
+                "UBF header size {} exceeds allocation size {}",
+                ubf_size, tpalloc_size
+            ));
+        }
+
+        Ok(UbfBuffer {
+            ptr,
+            size: tpalloc_size,
+            growth: GrowthPolicy::default(),
+        })
+    }
+
+    /// Allocation size as requested from tpalloc/tprealloc
+    pub fn allocated_size(&self) -> usize {
+        self.size
+    }
+}
+
+/// UBF buffer borrowed from a raw pointer, see `UbfBuffer::from_raw_ptr_borrowed`
+pub struct BorrowedUbfBuffer<'a> {
+    buffer: ManuallyDrop<UbfBuffer>,
+    _owner: PhantomData<&'a mut c_char>,
+}
+
+impl Deref for BorrowedUbfBuffer<'_> {
+    type Target = UbfBuffer;
+
+    fn deref(&self) -> &UbfBuffer {
+        &self.buffer
     }
 }
 
+// No DerefMut: `realloc`, `reserve` or `mem::replace` through a
+// `&mut UbfBuffer` would free or move the owner's buffer. Only mutators that
+// write within the current allocation are exposed.
+impl BorrowedUbfBuffer<'_> {
+    /// See `UbfBuffer::add_string`
+    pub fn add_string(&mut self, field_id: i32, value: &str) -> Result<(), String> {
+        self.buffer.add_string(field_id, value)
+    }
+
+    /// See `UbfBuffer::add_long`
+    pub fn add_long(&mut self, field_id: i32, value: i64) -> Result<(), String> {
+        self.buffer.add_long(field_id, value)
+    }
+
+    /// See `UbfBuffer::add_double`
+    pub fn add_double(&mut self, field_id: i32, value: f64) -> Result<(), String> {
+        self.buffer.add_double(field_id, value)
+    }
+
+    /// See `UbfBuffer::add_float`
+    pub fn add_float(&mut self, field_id: i32, value: f32) -> Result<(), String> {
+        self.buffer.add_float(field_id, value)
+    }
+
+    /// See `UbfBuffer::add_carray`
+    pub fn add_carray(&mut self, field_id: i32, value: &[u8]) -> Result<(), String> {
+        self.buffer.add_carray(field_id, value)
+    }
+
+    /// See `UbfBuffer::add_value`
+    pub fn add_value(&mut self, field_id: i32, value: &UbfValue) -> Result<(), String> {
+        self.buffer.add_value(field_id, value)
+    }
+
+    /// See `UbfBuffer::set_value`
+    pub fn set_value(&mut self, field_id: i32, value: &UbfValue) -> Result<(), String> {
+        self.buffer.set_value(field_id, value)
+    }
+
+    /// See `UbfBuffer::change_string`
+    pub fn change_string(&mut self, field_id: i32, occ: i32, value: &str) -> Result<(), String> {
+        self.buffer.change_string(field_id, occ, value)
+    }
+
+    /// See `UbfBuffer::change_long`
+    pub fn change_long(&mut self, field_id: i32, occ: i32, value: i64) -> Result<(), String> {
+        self.buffer.change_long(field_id, occ, value)
+    }
+
+    /// See `UbfBuffer::change_double`
+    pub fn change_double(&mut self, field_id: i32, occ: i32, value: f64) -> Result<(), String> {
+        self.buffer.change_double(field_id, occ, value)
+    }
+
+    /// See `UbfBuffer::change_float`
+    pub fn change_float(&mut self, field_id: i32, occ: i32, value: f32) -> Result<(), String> {
+        self.buffer.change_float(field_id, occ, value)
+    }
+
+    /// See `UbfBuffer::delete`
+    pub fn delete(&mut self, field_id: i32, occ: i32) -> Result<(), String> {
+        self.buffer.delete(field_id, occ)
+    }
+}
+
+/// C string of a string field value
+///
+/// A NUL inside a "string" value usually means the data is binary, so the
+/// error names the field and points to `add_carray`.
+pub(crate) fn field_c_string(field_id: i32, value: &str) -> Result<CString, UbfError> {
+    CString::new(value).map_err(|e| {
+        let field = UbfBuffer::field_name(field_id)
+            .map(|name| format!("{} ({})", name, field_id))
+            .unwrap_or_else(|_| field_id.to_string());
+        UbfError::InvalidValue(format!(
+            "field {} value contains a NUL byte at position {}; use add_carray for binary data",
+            field,
+            e.nul_position()
+        ))
+    })
+}
+
+/// Berror of the last failed UBF call on this thread
+pub(crate) fn last_ubf_errno() -> c_int {
+    unsafe { *ffi::ndrx_Bget_Ferror_addr() }
+}
+
+/// XATMI type of a tpalloc'ed buffer (`tptypes`)
+///
+/// # Safety
+///
+/// `ptr` must be a buffer allocated by tpalloc.
+pub(crate) unsafe fn buffer_type(ptr: *mut c_char) -> Option<String> {
+    // XATMI_TYPE_LEN and XATMI_SUBTYPE_LEN plus NUL
+    let mut typ = [0 as c_char; 9];
+    let mut subtyp = [0 as c_char; 17];
+
+    if ffi::tptypes(ptr, typ.as_mut_ptr(), subtyp.as_mut_ptr()) == -1 {
+        return None;
+    }
+
+    Some(CStr::from_ptr(typ.as_ptr()).to_string_lossy().into_owned())
+}
+
+/// Takes over a reply buffer, freeing it if it isn't UBF
+///
+/// # Safety
+///
+/// `recv_buf` must be a buffer allocated by tpalloc, owned by the caller.
+pub(crate) unsafe fn reply_buffer(recv_buf: *mut c_char) -> Result<UbfBuffer, TpError> {
+    reply_buffer_via(buffer_type, recv_buf)
+}
+
+/// `buffer_type` of a buffer, passed in where unit tests can't call tptypes
+pub(crate) type Tptypes = unsafe fn(*mut c_char) -> Option<String>;
+
+/// `reply_buffer` with the type probe passed in
+///
+/// # Safety
+///
+/// Same as `reply_buffer`.
+pub(crate) unsafe fn reply_buffer_via(
+    tptypes: Tptypes,
+    recv_buf: *mut c_char,
+) -> Result<UbfBuffer, TpError> {
+    let checked = if recv_buf.is_null() {
+        Err("Null buffer pointer".to_string())
+    } else {
+        UbfBuffer::from_typed(tptypes(recv_buf).as_deref(), recv_buf)
+    };
+
+    checked.map_err(|e| {
+        if !recv_buf.is_null() {
+            ffi::tpfree(recv_buf);
+        }
+        crate::tplog_error(&format!("Unexpected reply buffer: {}", e));
+        TpError::Xatmi {
+            errno: ffi::TPEOTYPE,
+            message: e,
+        }
+    })
+}
+
+/// Converts the current Berror into a UbfError
+fn last_ubf_error(field_id: i32, occ: i32) -> UbfError {
+    let code = last_ubf_errno();
+    if code == ffi::BNOTPRES {
+        return UbfError::NotPresent { field_id, occ };
+    }
+
+    let msg_ptr = unsafe { ffi::Bstrerror(code) };
+    let message = if !msg_ptr.is_null() {
+        unsafe { CStr::from_ptr(msg_ptr) }
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        format!("field {} at occ {}", field_id, occ)
+    };
+
+    UbfError::Ubf { code, message }
+}
+
 impl Drop for UbfBuffer {
     fn drop(&mut self) {
         if !self.ptr.is_null() {
@@ -367,9 +1822,95 @@ impl Iterator for UbfIterator {
     }
 }
 
+/// Field occurrence with its raw value as stored by UBF
+#[derive(Debug, Clone, PartialEq)]
+pub struct UbfField {
+    pub id: i32,
+    pub occ: i32,
+    /// Exactly the `len` bytes reported by `Bnext` (strings include the NUL)
+    pub raw: Vec<u8>,
+}
+
+/// UBF field iterator returning values, see `UbfBuffer::fields`
+pub struct UbfFields<'a> {
+    buffer: &'a UbfBuffer,
+    scratch: Vec<u8>,
+    current_field_id: c_int,
+    current_occ: c_int,
+}
+
+impl Iterator for UbfFields<'_> {
+    type Item = UbfField;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Bnext writes back the actual field length, which is the only way
+        // to know where a CARRAY value with embedded NULs ends
+        let mut len = self.scratch.len() as c_int;
+
+        let result = unsafe {
+            ffi::Bnext(
+                self.buffer.ptr,
+                &mut self.current_field_id,
+                &mut self.current_occ,
+                self.scratch.as_mut_ptr() as *mut c_char,
+                &mut len,
+            )
+        };
+
+        if result == 1 {
+            Some(UbfField {
+                id: self.current_field_id,
+                occ: self.current_occ,
+                raw: self.scratch[..len as usize].to_vec(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ubf_fields::*;
+
+    #[test]
+    fn test_field_count_includes_occurrences() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        assert_eq!(buf.field_count(), 0);
+
+        buf.add_string(T_NAME_FLD, "alice").unwrap();
+        buf.add_long(T_AMOUNT_FLD, 100).unwrap();
+        buf.add_long(T_AMOUNT_FLD, 200).unwrap();
+        buf.add_double(T_BALANCE_FLD, 1.5).unwrap();
+        buf.add_string(T_NAME_FLD, "bob").unwrap();
+
+        assert_eq!(buf.field_count(), 5);
+        assert_eq!(buf.field_count(), buf.fields().count());
+    }
+
+    #[test]
+    fn test_retained_copies_only_listed_fields() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "alice").unwrap();
+        buf.add_long(T_AMOUNT_FLD, 100).unwrap();
+        buf.add_long(T_AMOUNT_FLD, 200).unwrap();
+        buf.add_double(T_BALANCE_FLD, 1.5).unwrap();
+        buf.add_carray(T_CARRAY_FLD, b"a\0b").unwrap();
+
+        let copy = buf.retained(&[T_AMOUNT_FLD, T_CARRAY_FLD]).unwrap();
+
+        assert_eq!(copy.field_count(), 3);
+        assert_eq!(copy.get_long(T_AMOUNT_FLD, 0).unwrap(), 100);
+        assert_eq!(copy.get_long(T_AMOUNT_FLD, 1).unwrap(), 200);
+        assert_eq!(copy.get_carray(T_CARRAY_FLD, 0).unwrap(), b"a\0b");
+        assert!(!copy.is_present(T_NAME_FLD, 0));
+        assert!(!copy.is_present(T_BALANCE_FLD, 0));
+
+        assert_eq!(buf.field_count(), 5);
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "alice");
+        assert_eq!(buf.get_double(T_BALANCE_FLD, 0).unwrap(), 1.5);
+    }
 
     #[test]
     fn test_ubf_buffer_creation() {
@@ -381,9 +1922,755 @@ mod tests {
         assert_eq!(buf.size(), 1024);
     }
 
+    #[test]
+    fn test_get_string_into_reuses_scratch() {
+        let long = "x".repeat(5000);
+        let mut buf = UbfBuffer::new(8192).unwrap();
+        buf.add_string(T_NAME_FLD, "Alice").unwrap();
+        buf.add_string(T_DESC_FLD, &long).unwrap();
+        buf.add_string(T_STATUS_FLD, "OK").unwrap();
+
+        let mut scratch = Vec::new();
+        assert_eq!(
+            buf.get_string_into(T_NAME_FLD, 0, &mut scratch).unwrap(),
+            "Alice"
+        );
+        // Grows past the default 1024 bytes for the long field...
+        assert_eq!(
+            buf.get_string_into(T_DESC_FLD, 0, &mut scratch).unwrap(),
+            long
+        );
+        assert_eq!(buf.get_string(T_DESC_FLD, 0).unwrap(), long);
+
+        // ...and later reads reuse it without stale bytes
+        let capacity = scratch.capacity();
+        assert_eq!(
+            buf.get_string_into(T_STATUS_FLD, 0, &mut scratch).unwrap(),
+            "OK"
+        );
+        assert_eq!(
+            buf.get_string_into(T_NAME_FLD, 0, &mut scratch).unwrap(),
+            "Alice"
+        );
+        assert_eq!(scratch.capacity(), capacity);
+
+        assert!(buf.get_string_into(T_NAME_FLD, 1, &mut scratch).is_err());
+    }
+
     #[test]
     fn test_ubf_add_get_string() {
         // This test requires UBF field tables to be loaded
         // Will work in integration tests with proper Enduro/X setup
     }
+
+    #[test]
+    fn test_add_many_mixed_types() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_many(&[
+            (T_NAME_FLD, UbfValue::String("Alice".to_string())),
+            (T_ID_FLD, UbfValue::Long(42)),
+            (T_PRICE_FLD, UbfValue::Double(9.5)),
+        ])
+        .unwrap();
+
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "Alice");
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 42);
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 9.5);
+
+        // The offending field id is reported and later fields are skipped
+        let err = buf
+            .add_many(&[
+                (T_COUNT_FLD, UbfValue::Short(3)),
+                (0, UbfValue::Long(1)),
+                (T_STATUS_FLD, UbfValue::String("skipped".to_string())),
+            ])
+            .unwrap_err();
+        assert!(err.contains("field 0"), "{}", err);
+        assert_eq!(buf.get_long(T_COUNT_FLD, 0).unwrap(), 3);
+        assert!(!buf.is_present(T_STATUS_FLD, 0));
+    }
+
+    #[test]
+    fn test_float_field_round_trip() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_float(T_FLOAT_FLD, 1.25).unwrap();
+        buf.add_float(T_FLOAT_FLD, f32::MAX).unwrap();
+        assert_eq!(buf.get_float(T_FLOAT_FLD, 0).unwrap(), 1.25);
+        assert_eq!(buf.get_float(T_FLOAT_FLD, 1).unwrap(), f32::MAX);
+        assert_eq!(buf.get_raw(T_FLOAT_FLD, 0).unwrap(), 1.25f32.to_ne_bytes());
+
+        buf.change_float(T_FLOAT_FLD, 0, -0.5).unwrap();
+        assert_eq!(
+            buf.get_value(T_FLOAT_FLD, 0).unwrap(),
+            UbfValue::Float(-0.5)
+        );
+        assert!(buf.get_float(T_FLOAT_FLD, 2).is_err());
+    }
+
+    #[test]
+    fn test_json_number_stored_in_field_type() {
+        let json = serde_json::json!({ "price": 42, "id": 3.0 });
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_many(&[(T_PRICE_FLD, UbfValue::Long(json["price"].as_i64().unwrap()))])
+            .unwrap();
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 42.0);
+
+        buf.set_value(T_PRICE_FLD, &UbfValue::Long(7)).unwrap();
+        assert_eq!(buf.occurrences(T_PRICE_FLD).unwrap(), 1);
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 7.0);
+
+        buf.set_value(T_ID_FLD, &UbfValue::Double(json["id"].as_f64().unwrap()))
+            .unwrap();
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 3);
+
+        buf.set_value(T_NAME_FLD, &UbfValue::Long(15)).unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "15");
+    }
+
+    #[test]
+    fn test_borrowed_buffer_does_not_free() {
+        let mut owner = UbfBuffer::new(1024).unwrap();
+
+        {
+            let mut borrowed = unsafe { UbfBuffer::from_raw_ptr_borrowed(owner.raw_ptr()) };
+            borrowed.add_string(T_NAME_FLD, "via borrow").unwrap();
+            assert_eq!(borrowed.raw_ptr(), owner.as_ptr());
+        }
+
+        // Still valid after the borrowed handle is dropped
+        assert_eq!(owner.get_string(T_NAME_FLD, 0).unwrap(), "via borrow");
+        owner.add_long(T_ID_FLD, 1).unwrap();
+    }
+
+    #[test]
+    fn test_ubf_value_json() {
+        let cases = [
+            (UbfValue::Short(-3), r#"{"type":"short","value":-3}"#, "-3"),
+            (UbfValue::Long(5), r#"{"type":"long","value":5}"#, "5"),
+            (UbfValue::Char(b'A'), r#"{"type":"char","value":"A"}"#, "A"),
+            (
+                UbfValue::Float(1.5),
+                r#"{"type":"float","value":1.5}"#,
+                "1.5",
+            ),
+            (
+                UbfValue::Double(-0.25),
+                r#"{"type":"double","value":-0.25}"#,
+                "-0.25",
+            ),
+            (
+                UbfValue::String("x\"y".to_string()),
+                r#"{"type":"string","value":"x\"y"}"#,
+                "x\"y",
+            ),
+            (
+                UbfValue::Carray(vec![0, 1, 2, 255]),
+                r#"{"type":"carray","value":"AAEC/w=="}"#,
+                "AAEC/w==",
+            ),
+        ];
+
+        for (value, json, text) in cases {
+            assert_eq!(serde_json::to_string(&value).unwrap(), json);
+            assert_eq!(serde_json::from_str::<UbfValue>(json).unwrap(), value);
+            assert_eq!(value.to_string(), text);
+        }
+
+        // Bytes above 0x7f survive as Latin-1 characters
+        let high = UbfValue::Char(0xe9);
+        let json = serde_json::to_string(&high).unwrap();
+        assert_eq!(serde_json::from_str::<UbfValue>(&json).unwrap(), high);
+
+        assert!(serde_json::from_str::<UbfValue>(r#"{"type":"char","value":"€"}"#).is_err());
+        assert!(serde_json::from_str::<UbfValue>(r#"{"type":"carray","value":"!!"}"#).is_err());
+    }
+
+    #[test]
+    fn test_builder() {
+        let buf = UbfBuffer::builder().build().unwrap();
+        assert_eq!(buf.size(), DEFAULT_BUFFER_SIZE);
+        assert_eq!(buf.growth(), GrowthPolicy::Double);
+
+        let mut buf = UbfBuffer::builder()
+            .size(512)
+            .growth(GrowthPolicy::Exact)
+            .build()
+            .unwrap();
+        assert_eq!(buf.size(), 512);
+        assert_eq!(buf.growth(), GrowthPolicy::Exact);
+        buf.add_string(T_NAME_FLD, "built").unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "built");
+    }
+
+    #[test]
+    fn test_growth_policy_next_size() {
+        assert_eq!(GrowthPolicy::default(), GrowthPolicy::Double);
+
+        assert_eq!(GrowthPolicy::Double.next_size(1024, 1100), 2048);
+        assert_eq!(GrowthPolicy::Double.next_size(1024, 5000), 5000);
+        assert_eq!(
+            GrowthPolicy::Double.next_size(4 * MAX_DOUBLING_STEP, 4 * MAX_DOUBLING_STEP + 1),
+            5 * MAX_DOUBLING_STEP
+        );
+
+        assert_eq!(GrowthPolicy::Fixed(512).next_size(1024, 1100), 1536);
+        assert_eq!(GrowthPolicy::Fixed(512).next_size(1024, 2049), 2560);
+        assert_eq!(GrowthPolicy::Fixed(0).next_size(1024, 1100), 1100);
+
+        assert_eq!(GrowthPolicy::Exact.next_size(1024, 1100), 1100);
+
+        // Enough room already
+        assert_eq!(GrowthPolicy::Exact.next_size(1024, 1000), 1024);
+    }
+
+    #[test]
+    #[ignore] // Run only with Enduro/X environment
+    fn test_reserve_follows_growth_policy() {
+        for (policy, expected) in [
+            (GrowthPolicy::Double, 2048),
+            (GrowthPolicy::Fixed(512), 1536),
+            (GrowthPolicy::Exact, 1024 + 100),
+        ] {
+            let mut buf = UbfBuffer::new(1024).unwrap();
+            buf.set_growth(policy);
+
+            // Needs 100 bytes beyond the current allocation
+            let additional = buf.unused() + 100;
+            buf.reserve(additional).unwrap();
+            assert_eq!(buf.size(), expected, "{:?}", policy);
+            assert!(buf.unused() >= additional);
+
+            // No realloc when there is room
+            buf.reserve(1).unwrap();
+            assert_eq!(buf.size(), expected);
+        }
+    }
+
+    #[test]
+    fn test_set_long_upserts() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.set_long(T_ID_FLD, 1).unwrap();
+        buf.set_long(T_ID_FLD, 2).unwrap();
+
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 2);
+        assert!(!buf.is_present(T_ID_FLD, 1));
+
+        buf.set_string(T_NAME_FLD, "a").unwrap();
+        buf.set_string(T_NAME_FLD, "b").unwrap();
+        buf.set_double(T_PRICE_FLD, 1.5).unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "b");
+        assert!(!buf.is_present(T_NAME_FLD, 1));
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_change_numeric_occurrence_in_place() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        for id in [10, 20, 30, 40] {
+            buf.add_long(T_ID_FLD, id).unwrap();
+        }
+        buf.add_double(T_PRICE_FLD, 1.5).unwrap();
+        buf.add_double(T_PRICE_FLD, 2.5).unwrap();
+
+        buf.change_long(T_ID_FLD, 2, 33).unwrap();
+        buf.change_double(T_PRICE_FLD, 1, 9.75).unwrap();
+
+        let ids: Vec<i64> = (0..4)
+            .map(|occ| buf.get_long(T_ID_FLD, occ).unwrap())
+            .collect();
+        assert_eq!(ids, vec![10, 20, 33, 40]);
+        assert_eq!(buf.occurrences(T_ID_FLD).unwrap(), 4);
+        assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 1.5);
+        assert_eq!(buf.get_double(T_PRICE_FLD, 1).unwrap(), 9.75);
+    }
+
+    #[test]
+    fn test_index_keeps_reads() {
+        let mut buf = UbfBuffer::new(4096).unwrap();
+        for i in 0..50 {
+            buf.add_long(T_ID_FLD, i).unwrap();
+        }
+        buf.add_string(T_NAME_FLD, "indexed").unwrap();
+
+        buf.build_index(64).unwrap();
+        assert_eq!(buf.get_long(T_ID_FLD, 37).unwrap(), 37);
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "indexed");
+
+        buf.drop_index().unwrap();
+        assert_eq!(buf.index_used(), 0);
+        assert_eq!(buf.get_long(T_ID_FLD, 49).unwrap(), 49);
+    }
+
+    #[test]
+    fn test_validate_against_reports_all_violations() {
+        let schema = [
+            (T_TRANS_ID_FLD, FieldType::String, true),
+            (T_AMOUNT_FLD, FieldType::Long, true),
+            (T_DESC_FLD, FieldType::String, false),
+            (T_PRICE_FLD, FieldType::Long, false),
+        ];
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_TRANS_ID_FLD, "TXN-1").unwrap();
+        buf.add_long(T_AMOUNT_FLD, 100).unwrap();
+        buf.add_double(T_PRICE_FLD, 1.5).unwrap();
+        buf.add_string(T_NAME_FLD, "a").unwrap();
+        buf.add_string(T_NAME_FLD, "b").unwrap();
+        buf.delete(T_AMOUNT_FLD, 0).unwrap();
+
+        let violations = buf.validate_against(&schema).unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                SchemaViolation::MissingRequired {
+                    field_id: T_AMOUNT_FLD
+                },
+                SchemaViolation::WrongType {
+                    field_id: T_PRICE_FLD,
+                    expected: FieldType::Long,
+                    actual: Some(FieldType::Double),
+                },
+                SchemaViolation::Unexpected {
+                    field_id: T_NAME_FLD
+                },
+            ]
+        );
+        // The name is only shown when field tables are loaded
+        assert!(violations[1]
+            .to_string()
+            .ends_with(" is double, expected long"));
+
+        buf.delete_occurrences(T_NAME_FLD, &[0, 1]).unwrap();
+        buf.add_long(T_AMOUNT_FLD, 100).unwrap();
+        assert!(buf.validate_against(&schema[..3]).is_err());
+        buf.delete(T_PRICE_FLD, 0).unwrap();
+        assert_eq!(buf.validate_against(&schema[..3]), Ok(()));
+    }
+
+    struct Row {
+        id: String,
+        amount: i64,
+        description: Option<String>,
+    }
+
+    impl ToUbf for Row {
+        fn ubf_fields(&self) -> Vec<(i32, UbfValue)> {
+            let mut fields = vec![
+                (T_TRANS_ID_FLD, self.id.as_str().into()),
+                (T_AMOUNT_FLD, self.amount.into()),
+            ];
+            if let Some(ref description) = self.description {
+                fields.push((T_DESC_FLD, description.as_str().into()));
+            }
+            fields
+        }
+    }
+
+    #[test]
+    fn test_to_ubf_row() {
+        let row = Row {
+            id: "TXN-7".to_string(),
+            amount: 1250,
+            description: Some("x".repeat(900)),
+        };
+        let buf = row.to_ubf_buffer().unwrap();
+        assert_eq!(buf.get_string(T_TRANS_ID_FLD, 0).unwrap(), "TXN-7");
+        assert_eq!(buf.get_long(T_AMOUNT_FLD, 0).unwrap(), 1250);
+        assert_eq!(buf.get_string(T_DESC_FLD, 0).unwrap().len(), 900);
+
+        let row = Row {
+            description: None,
+            ..row
+        };
+        let buf = row.to_ubf_buffer().unwrap();
+        assert!(!buf.is_present(T_DESC_FLD, 0));
+        assert!(buf.size() < DEFAULT_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn test_get_value_each_type() {
+        let fields = [
+            (T_SHORT_FLD, UbfValue::Short(-12)),
+            (T_ID_FLD, UbfValue::Long(1_234_567_890_123)),
+            (T_CHAR_FLD, UbfValue::Char(b'Y')),
+            (T_FLOAT_FLD, UbfValue::Float(2.5)),
+            (T_PRICE_FLD, UbfValue::Double(99.99)),
+            (T_NAME_FLD, UbfValue::String("Alice".to_string())),
+            (T_CARRAY_FLD, UbfValue::Carray(vec![0, 1, 0, 255])),
+        ];
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_many(&fields).unwrap();
+
+        for (field_id, expected) in &fields {
+            assert_eq!(&buf.get_value(*field_id, 0).unwrap(), expected);
+        }
+
+        assert!(buf.get_value(T_NAME_FLD, 1).is_err());
+    }
+
+    #[test]
+    fn test_add_string_with_nul() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        let err = buf.add_string(T_NAME_FLD, "ab\0cd").unwrap_err();
+
+        assert!(err.contains(&T_NAME_FLD.to_string()), "{}", err);
+        assert!(err.contains("position 2"), "{}", err);
+        assert!(err.contains("add_carray"), "{}", err);
+        assert!(!buf.is_present(T_NAME_FLD, 0));
+
+        assert!(matches!(
+            field_c_string(T_NAME_FLD, "\0"),
+            Err(UbfError::InvalidValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_non_ubf() {
+        for data in [&b"{\"status\":\"OK\"}"[..], &b"plain STRING reply"[..]] {
+            let err = UbfBuffer::from_bytes(data).unwrap_err();
+            assert!(err.contains("not a UBF buffer"), "{}", err);
+        }
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "truncated").unwrap();
+        let data = buf.as_bytes();
+        let err = UbfBuffer::from_bytes(&data[..data.len() - 4]).unwrap_err();
+        assert!(err.contains("Truncated"), "{}", err);
+    }
+
+    #[test]
+    fn test_from_typed_rejects_non_ubf_buffer() {
+        let typ = CString::new("STRING").unwrap();
+        let ptr = unsafe { ffi::tpalloc(typ.as_ptr(), ptr::null(), 128) };
+        assert!(!ptr.is_null());
+        let text = c"plain STRING reply";
+        let len = text.to_bytes_with_nul().len();
+        unsafe { ptr::copy_nonoverlapping(text.as_ptr(), ptr, len) };
+
+        let err = unsafe { UbfBuffer::from_typed(Some("STRING"), ptr) }.unwrap_err();
+        assert_eq!(err, "Expected a UBF buffer, got STRING");
+        let err = unsafe { UbfBuffer::from_typed(Some("JSON"), ptr) }.unwrap_err();
+        assert_eq!(err, "Expected a UBF buffer, got JSON");
+        let err = unsafe { UbfBuffer::from_typed(None, ptr) }.unwrap_err();
+        assert_eq!(err, "Pointer is not an XATMI buffer");
+        // Not taken over, still ours to free
+        unsafe { ffi::tpfree(ptr) };
+
+        assert!(unsafe { UbfBuffer::try_from_raw(ptr::null_mut()) }.is_err());
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "ubf reply").unwrap();
+        let wrapped = unsafe { UbfBuffer::from_typed(Some("UBF"), buf.into_raw()) }.unwrap();
+        assert_eq!(wrapped.get_string(T_NAME_FLD, 0).unwrap(), "ubf reply");
+    }
+
+    #[test]
+    fn test_from_bytes_copy_can_grow() {
+        let mut buf = UbfBuffer::new(4096).unwrap();
+        buf.add_string(T_NAME_FLD, "round trip").unwrap();
+
+        // as_bytes holds only the used part, the header says 4096
+        let data = buf.as_bytes();
+        let mut copy = UbfBuffer::from_bytes(data).unwrap();
+        assert_eq!(copy.size, unsafe { ffi::Bsizeof(copy.ptr) } as usize);
+
+        let long = "x".repeat(1024);
+        copy.add_string(T_NAME_FLD, &long).unwrap();
+        assert_eq!(copy.get_string(T_NAME_FLD, 0).unwrap(), "round trip");
+        assert_eq!(copy.get_string(T_NAME_FLD, 1).unwrap(), long);
+
+        // Capped at the limit, out of room rather than past the allocation
+        let mut bounded = UbfBuffer::from_bytes_bounded(data, data.len()).unwrap();
+        assert_eq!(bounded.size, unsafe { ffi::Bsizeof(bounded.ptr) } as usize);
+        assert!(bounded.add_string(T_NAME_FLD, &long).is_err());
+        assert_eq!(bounded.get_string(T_NAME_FLD, 0).unwrap(), "round trip");
+    }
+
+    #[test]
+    fn test_from_bytes_bounded() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "bounded").unwrap();
+        let data = buf.as_bytes();
+
+        let copy = UbfBuffer::from_bytes_bounded(data, data.len()).unwrap();
+        assert_eq!(copy.get_string(T_NAME_FLD, 0).unwrap(), "bounded");
+
+        let err = UbfBuffer::from_bytes_bounded(data, data.len() - 1).unwrap_err();
+        assert!(err.contains("exceeds the limit"), "{}", err);
+    }
+
+    #[test]
+    fn test_get_raw_matches_added_bytes() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        let binary = [0u8, 7, 0, 255];
+        buf.add_long(T_ID_FLD, 0x0102_0304).unwrap();
+        buf.add_double(T_PRICE_FLD, 12.5).unwrap();
+        buf.add_string(T_NAME_FLD, "raw").unwrap();
+        buf.add_carray(T_CARRAY_FLD, &binary).unwrap();
+
+        assert_eq!(
+            buf.get_raw(T_ID_FLD, 0).unwrap(),
+            (0x0102_0304 as c_long).to_ne_bytes()
+        );
+        assert_eq!(buf.get_raw(T_PRICE_FLD, 0).unwrap(), 12.5f64.to_ne_bytes());
+        assert_eq!(buf.get_raw(T_NAME_FLD, 0).unwrap(), b"raw\0");
+        assert_eq!(buf.get_raw(T_CARRAY_FLD, 0).unwrap(), binary);
+        assert!(buf.get_raw(T_NAME_FLD, 1).is_err());
+    }
+
+    #[test]
+    fn test_ubf_clear() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        let empty_used = buf.used();
+
+        buf.add_string(T_NAME_FLD, "to be cleared").unwrap();
+        buf.add_long(T_ID_FLD, 7).unwrap();
+        assert!(buf.used() > empty_used);
+
+        buf.clear().unwrap();
+
+        assert_eq!(buf.used(), empty_used);
+        assert_eq!(buf.size(), 1024);
+        assert!(!buf.is_present(T_NAME_FLD, 0));
+        assert!(!buf.is_present(T_ID_FLD, 0));
+
+        // The cleared buffer is reusable
+        buf.add_string(T_NAME_FLD, "reused").unwrap();
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "reused");
+    }
+
+    #[test]
+    fn test_get_long_not_present() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_long(T_ID_FLD, 1).unwrap();
+        buf.add_long(T_ID_FLD, 2).unwrap();
+
+        // Reading occurrences until NotPresent
+        let mut values = Vec::new();
+        let mut occ = 0;
+        loop {
+            match buf.get_long(T_ID_FLD, occ) {
+                Ok(v) => values.push(v),
+                Err(UbfError::NotPresent { .. }) => break,
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+            occ += 1;
+        }
+        assert_eq!(values, vec![1, 2]);
+
+        // Field never added
+        assert!(matches!(
+            buf.get_long(T_COUNT_FLD, 0),
+            Err(UbfError::NotPresent {
+                field_id: T_COUNT_FLD,
+                occ: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_get_long_bad_field_is_not_not_present() {
+        let buf = UbfBuffer::new(1024).unwrap();
+
+        match buf.get_long(0, 0) {
+            Err(UbfError::Ubf { code, .. }) => assert_eq!(code, ffi::BBADFLD),
+            other => panic!("expected UBF error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_fields_keep_carray_length() {
+        let binary = [0x01u8, 0x00, 0x02, 0x00, 0x00, 0xff];
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "abc").unwrap();
+        buf.add_carray(T_CARRAY_FLD, &binary).unwrap();
+        buf.add_carray(T_CARRAY_FLD, &[]).unwrap();
+
+        let fields: Vec<UbfField> = buf.fields().collect();
+        assert_eq!(fields.len(), 3);
+
+        let name = fields.iter().find(|f| f.id == T_NAME_FLD).unwrap();
+        assert_eq!(name.raw, b"abc\0");
+
+        let carrays: Vec<&UbfField> = fields.iter().filter(|f| f.id == T_CARRAY_FLD).collect();
+        assert_eq!(carrays[0].occ, 0);
+        assert_eq!(carrays[0].raw, binary);
+        assert_eq!(carrays[1].occ, 1);
+        assert!(carrays[1].raw.is_empty());
+
+        assert_eq!(buf.get_carray(T_CARRAY_FLD, 0).unwrap(), binary);
+    }
+
+    #[test]
+    fn test_transaction_rolls_back_on_failure() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "keep").unwrap();
+        let before = buf.as_bytes().to_vec();
+
+        let result = buf.transaction(|txn| {
+            txn.add_string(T_STRING_FLD, "partial")?;
+            txn.add_long(T_LONG_FLD, 42)?;
+            // Fails with BNOSPACE, add_* doesn't grow the buffer
+            txn.add_string(T_DATA_FLD, &"x".repeat(4096))?;
+            txn.add_double(T_DOUBLE_FLD, 1.5)
+        });
+        assert!(result.is_err());
+        assert_eq!(buf.as_bytes(), &before[..]);
+        assert!(!buf.is_present(T_STRING_FLD, 0));
+        assert_eq!(buf.size(), 1024);
+
+        let count = buf
+            .transaction(|txn| {
+                txn.add_string(T_STRING_FLD, "done")?;
+                txn.add_long(T_LONG_FLD, 42)?;
+                txn.occurrences(T_NAME_FLD)
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "keep");
+        assert_eq!(buf.get_string(T_STRING_FLD, 0).unwrap(), "done");
+        assert_eq!(buf.get_long(T_LONG_FLD, 0).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_transaction_commits_in_place() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "keep").unwrap();
+        let ptr = buf.as_ptr();
+
+        buf.transaction(|txn| txn.add_string(T_STRING_FLD, "done"))
+            .unwrap();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.size(), 1024);
+        assert_eq!(buf.get_string(T_STRING_FLD, 0).unwrap(), "done");
+
+        // A result larger than the buffer isn't committed
+        let before = buf.as_bytes().to_vec();
+        let err = buf
+            .transaction(|txn| {
+                let mut grown = UbfBuffer::new(8192)?;
+                grown.add_string(T_DATA_FLD, &"x".repeat(4096))?;
+                *txn = grown;
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(err.contains("doesn't fit"), "{}", err);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.as_bytes(), &before[..]);
+    }
+
+    #[test]
+    fn test_string_pairs_round_trip() {
+        let errors = [
+            ("MISSING_FIELD", "T_ACCOUNT_FLD is required"),
+            ("WRONG_TYPE", "T_AMOUNT_FLD must be long"),
+            ("INVALID_VALUE", "T_CURRENCY_FLD: unknown currency"),
+        ];
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD, &errors)
+            .unwrap();
+
+        let pairs = buf
+            .get_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD)
+            .unwrap();
+        assert_eq!(pairs.len(), 3);
+        for (pair, (code, message)) in pairs.iter().zip(errors) {
+            assert_eq!(pair, &(code.to_string(), message.to_string()));
+        }
+
+        // A stray code without its message breaks the pairing
+        buf.add_string(T_ERROR_CODE_FLD, "ORPHAN").unwrap();
+        assert!(buf
+            .get_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD)
+            .is_err());
+        assert!(buf
+            .add_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD, &[("A", "B")])
+            .is_err());
+
+        let empty = UbfBuffer::new(256).unwrap();
+        assert!(empty
+            .get_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_failed_string_pair_is_not_half_added() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        let pairs = [("FIRST", "kept"), ("SECOND", "bad\0message")];
+
+        assert!(buf
+            .add_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD, &pairs)
+            .is_err());
+
+        let added = buf
+            .get_string_pairs(T_ERROR_CODE_FLD, T_ERROR_MSG_FLD)
+            .unwrap();
+        assert_eq!(added, vec![("FIRST".to_string(), "kept".to_string())]);
+    }
+
+    #[test]
+    fn test_delete_occurrences() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        for value in ["a", "b", "c", "d", "e"] {
+            buf.add_string(T_NAME_FLD, value).unwrap();
+        }
+
+        buf.delete_occurrences(T_NAME_FLD, &[1, 3]).unwrap();
+
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "a");
+        assert_eq!(buf.get_string(T_NAME_FLD, 1).unwrap(), "c");
+        assert_eq!(buf.get_string(T_NAME_FLD, 2).unwrap(), "e");
+        assert!(!buf.is_present(T_NAME_FLD, 3));
+    }
+
+    #[test]
+    fn test_from_raw_with_size_rejects_short_allocation() {
+        let ptr = UbfBuffer::new(1024).unwrap().into_raw();
+
+        let err = unsafe { UbfBuffer::from_raw_with_size(ptr, 512) }.unwrap_err();
+        assert!(err.contains("exceeds allocation size"));
+
+        let buf = unsafe { UbfBuffer::from_raw_with_size(ptr, 1024) }.unwrap();
+        assert_eq!(buf.allocated_size(), 1024);
+        assert_eq!(buf.size(), 1024);
+    }
+
+    #[test]
+    #[ignore] // Run only with Enduro/X environment (tprealloc needs ATMI config)
+    fn test_ubf_grow_after_from_raw_with_size() {
+        let mut buf = UbfBuffer::new(512).unwrap();
+        buf.add_string(T_NAME_FLD, "request").unwrap();
+
+        let mut buf = unsafe { UbfBuffer::from_raw_with_size(buf.into_raw(), 512) }.unwrap();
+        buf.realloc(8192).unwrap();
+
+        assert_eq!(buf.allocated_size(), 8192);
+        assert_eq!(buf.size(), buf.allocated_size());
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "request");
+
+        buf.add_string(T_MESSAGE_FLD, &"y".repeat(4000)).unwrap();
+        assert_eq!(buf.unused(), buf.size() - buf.used());
+    }
+
+    #[test]
+    #[ignore] // Run only with Enduro/X environment (tprealloc needs ATMI config)
+    fn test_ubf_realloc_keeps_fields() {
+        let mut buf = UbfBuffer::new(256).unwrap();
+        buf.add_string(T_NAME_FLD, "before realloc").unwrap();
+        buf.add_long(T_ID_FLD, 42).unwrap();
+
+        buf.realloc(4096).expect("realloc should succeed");
+
+        assert_eq!(buf.size(), 4096);
+        assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "before realloc");
+        assert_eq!(buf.get_long(T_ID_FLD, 0).unwrap(), 42);
+
+        // The grown buffer accepts more data
+        buf.add_string(T_MESSAGE_FLD, &"x".repeat(2000)).unwrap();
+    }
 }
@@ -0,0 +1,69 @@
+//! `chrono::NaiveDateTime` in UBF string fields
+//!
+//! Timestamps are stored as ISO 8601 text without a zone, e.g.
+//! `2024-03-01T12:30:05.250`: readable in `ud` dumps and JSON, ordered the
+//! same as the instants, and exact to the nanosecond, which an epoch long
+//! would not be. The fraction is written only when non-zero. Use a string
+//! field; the value is whatever wall-clock time the caller keeps, typically
+//! UTC as stored by the database.
+
+use crate::ubf::UbfBuffer;
+use chrono::NaiveDateTime;
+
+/// Format `add_datetime` writes and `get_datetime` reads
+pub const DATETIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+impl UbfBuffer {
+    /// Add a timestamp as ISO 8601 text
+    pub fn add_datetime(&mut self, field_id: i32, value: NaiveDateTime) -> Result<(), String> {
+        self.add_string(field_id, &value.format(DATETIME_FORMAT).to_string())
+    }
+
+    /// Get a timestamp written by `add_datetime`
+    pub fn get_datetime(&self, field_id: i32, occ: i32) -> Result<NaiveDateTime, String> {
+        let text = self.get_string(field_id, occ).map_err(|e| e.to_string())?;
+        NaiveDateTime::parse_from_str(&text, DATETIME_FORMAT).map_err(|e| {
+            format!(
+                "Field {} at occ {} is not a timestamp ({:?}): {}",
+                field_id, occ, text, e
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ubf_fields::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_datetime_round_trip() {
+        let whole = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(12, 30, 5)
+            .unwrap();
+        let fractional = NaiveDate::from_ymd_opt(1999, 12, 31)
+            .unwrap()
+            .and_hms_nano_opt(23, 59, 59, 123_456_789)
+            .unwrap();
+
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_datetime(T_STRING_FLD, whole).unwrap();
+        buf.add_datetime(T_STRING_FLD, fractional).unwrap();
+
+        assert_eq!(
+            buf.get_string(T_STRING_FLD, 0).unwrap(),
+            "2024-03-01T12:30:05"
+        );
+        assert_eq!(
+            buf.get_string(T_STRING_FLD, 1).unwrap(),
+            "1999-12-31T23:59:59.123456789"
+        );
+        assert_eq!(buf.get_datetime(T_STRING_FLD, 0).unwrap(), whole);
+        assert_eq!(buf.get_datetime(T_STRING_FLD, 1).unwrap(), fractional);
+
+        buf.add_string(T_NAME_FLD, "yesterday").unwrap();
+        assert!(buf.get_datetime(T_NAME_FLD, 0).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+//! Arithmetic over the occurrences of a numeric UBF field
+//!
+//! Each helper makes one `Bnext` pass over the buffer and only looks at the
+//! occurrences of the requested field. The field type comes from `Bfldtype`:
+//! the `*_long` helpers accept short, long and char fields, the `*_double`
+//! helpers any numeric field; other types are an error.
+
+use crate::ubf::{FieldType, UbfBuffer};
+use libc::c_long;
+use std::mem;
+use std::ptr;
+
+impl UbfBuffer {
+    /// Sum of all occurrences of an integer field (0 when absent)
+    ///
+    /// Fails if the sum doesn't fit in an `i64`.
+    pub fn sum_long(&self, field_id: i32) -> Result<i64, String> {
+        self.longs(field_id)?.try_fold(0i64, |sum, value| {
+            sum.checked_add(value?)
+                .ok_or_else(|| format!("Sum of field {} overflows i64", field_id))
+        })
+    }
+
+    /// Largest occurrence of an integer field, `None` when absent
+    pub fn max_long(&self, field_id: i32) -> Result<Option<i64>, String> {
+        fold(self.longs(field_id)?, i64::max)
+    }
+
+    /// Smallest occurrence of an integer field, `None` when absent
+    pub fn min_long(&self, field_id: i32) -> Result<Option<i64>, String> {
+        fold(self.longs(field_id)?, i64::min)
+    }
+
+    /// Sum of all occurrences of a numeric field (0.0 when absent)
+    pub fn sum_double(&self, field_id: i32) -> Result<f64, String> {
+        self.doubles(field_id)?.sum()
+    }
+
+    /// Largest occurrence of a numeric field, `None` when absent
+    pub fn max_double(&self, field_id: i32) -> Result<Option<f64>, String> {
+        fold(self.doubles(field_id)?, f64::max)
+    }
+
+    /// Smallest occurrence of a numeric field, `None` when absent
+    pub fn min_double(&self, field_id: i32) -> Result<Option<f64>, String> {
+        fold(self.doubles(field_id)?, f64::min)
+    }
+
+    fn longs(
+        &self,
+        field_id: i32,
+    ) -> Result<impl Iterator<Item = Result<i64, String>> + '_, String> {
+        let field_type = match UbfBuffer::field_type(field_id) {
+            Some(t @ (FieldType::Short | FieldType::Long | FieldType::Char)) => t,
+            _ => return Err(format!("Field {} is not an integer field", field_id)),
+        };
+
+        Ok(self
+            .raw_values(field_id)
+            .map(move |raw| integer(field_type, &raw)))
+    }
+
+    fn doubles(
+        &self,
+        field_id: i32,
+    ) -> Result<impl Iterator<Item = Result<f64, String>> + '_, String> {
+        let field_type = match UbfBuffer::field_type(field_id) {
+            Some(FieldType::String | FieldType::Carray) | None => {
+                return Err(format!("Field {} is not a numeric field", field_id))
+            }
+            Some(t) => t,
+        };
+
+        Ok(self.raw_values(field_id).map(move |raw| match field_type {
+            FieldType::Float => read::<f32>(&raw).map(f64::from),
+            FieldType::Double => read::<f64>(&raw),
+            _ => integer(field_type, &raw).map(|value| value as f64),
+        }))
+    }
+
+    fn raw_values(&self, field_id: i32) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.fields()
+            .filter(move |field| field.id == field_id)
+            .map(|field| field.raw)
+    }
+}
+
+/// Largest/smallest value by `pick`, stopping at the first bad value
+fn fold<T>(
+    mut values: impl Iterator<Item = Result<T, String>>,
+    pick: fn(T, T) -> T,
+) -> Result<Option<T>, String> {
+    values.try_fold(None, |acc, value| {
+        let value = value?;
+        Ok(Some(match acc {
+            Some(acc) => pick(acc, value),
+            None => value,
+        }))
+    })
+}
+
+fn integer(field_type: FieldType, raw: &[u8]) -> Result<i64, String> {
+    match field_type {
+        FieldType::Short => read::<i16>(raw).map(i64::from),
+        FieldType::Char => read::<u8>(raw).map(i64::from),
+        // c_long is 32-bit on some targets
+        #[allow(clippy::unnecessary_cast)]
+        _ => read::<c_long>(raw).map(|value| value as i64),
+    }
+}
+
+// Values come from Bnext in native layout, exactly the size of the type
+fn read<T: Copy>(raw: &[u8]) -> Result<T, String> {
+    if raw.len() < mem::size_of::<T>() {
+        return Err(format!(
+            "Value of {} bytes, expected {}",
+            raw.len(),
+            mem::size_of::<T>()
+        ));
+    }
+    Ok(unsafe { ptr::read_unaligned(raw.as_ptr() as *const T) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ubf_fields::*;
+
+    #[test]
+    fn test_sum_long_occurrences() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        assert_eq!(buf.sum_long(T_AMOUNT_FLD).unwrap(), 0);
+        assert_eq!(buf.max_long(T_AMOUNT_FLD).unwrap(), None);
+
+        for amount in [100, -30, 250] {
+            buf.add_long(T_AMOUNT_FLD, amount).unwrap();
+        }
+        // Other fields don't count
+        buf.add_long(T_ID_FLD, 1000).unwrap();
+
+        assert_eq!(buf.sum_long(T_AMOUNT_FLD).unwrap(), 320);
+        assert_eq!(buf.max_long(T_AMOUNT_FLD).unwrap(), Some(250));
+        assert_eq!(buf.min_long(T_AMOUNT_FLD).unwrap(), Some(-30));
+    }
+
+    #[test]
+    fn test_sum_double_occurrences() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        assert_eq!(buf.sum_double(T_BALANCE_FLD).unwrap(), 0.0);
+        assert_eq!(buf.min_double(T_BALANCE_FLD).unwrap(), None);
+
+        for balance in [10.5, 0.25, -4.0] {
+            buf.add_double(T_BALANCE_FLD, balance).unwrap();
+        }
+
+        assert_eq!(buf.sum_double(T_BALANCE_FLD).unwrap(), 6.75);
+        assert_eq!(buf.max_double(T_BALANCE_FLD).unwrap(), Some(10.5));
+        assert_eq!(buf.min_double(T_BALANCE_FLD).unwrap(), Some(-4.0));
+
+        // Integer fields widen to f64
+        buf.add_long(T_AMOUNT_FLD, 3).unwrap();
+        buf.add_long(T_AMOUNT_FLD, 4).unwrap();
+        assert_eq!(buf.sum_double(T_AMOUNT_FLD).unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_non_numeric_field_rejected() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_NAME_FLD, "alice").unwrap();
+
+        assert!(buf.sum_long(T_NAME_FLD).is_err());
+        assert!(buf.sum_double(T_NAME_FLD).is_err());
+        // Doubles aren't silently truncated
+        assert!(buf.sum_long(T_BALANCE_FLD).is_err());
+    }
+
+    #[test]
+    fn test_sum_long_overflow_rejected() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_long(T_AMOUNT_FLD, i64::MAX).unwrap();
+        buf.add_long(T_AMOUNT_FLD, 1).unwrap();
+
+        let err = buf.sum_long(T_AMOUNT_FLD).unwrap_err();
+        assert!(err.contains("overflows"), "{}", err);
+        assert_eq!(buf.max_long(T_AMOUNT_FLD).unwrap(), Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_short_value_rejected() {
+        assert_eq!(read::<i16>(&[1, 0]), Ok(1));
+        assert!(read::<f64>(&[0; 4]).is_err());
+        assert!(integer(FieldType::Long, &[]).is_err());
+    }
+}
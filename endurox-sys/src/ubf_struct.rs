@@ -17,7 +17,50 @@ pub trait UbfStruct: Sized {
     fn to_ubf(&self) -> Result<UbfBuffer, UbfError>;
 
     /// Update existing UBF buffer with struct data
+    ///
+    /// Fields are changed in place at occurrence 0, so updating the same
+    /// buffer again leaves a single occurrence with the latest values.
     fn update_ubf(&self, buf: &mut UbfBuffer) -> Result<(), UbfError>;
+
+    /// Convert from the fields at occurrence `occ` (one record of a repeating group)
+    ///
+    /// Only occurrence 0 is supported unless the implementation overrides it;
+    /// `#[derive(UbfStruct)]` does.
+    fn from_ubf_occ(buf: &UbfBuffer, occ: i32) -> Result<Self, UbfError> {
+        if occ == 0 {
+            return Self::from_ubf(buf);
+        }
+        Err(UbfError::InvalidValue(format!(
+            "{} cannot be read at occurrence {}",
+            std::any::type_name::<Self>(),
+            occ
+        )))
+    }
+
+    /// Write the struct's fields at occurrence `occ`, replacing what is there
+    fn update_ubf_occ(&self, buf: &mut UbfBuffer, occ: i32) -> Result<(), UbfError> {
+        if occ == 0 {
+            return self.update_ubf(buf);
+        }
+        Err(UbfError::InvalidValue(format!(
+            "{} cannot be written at occurrence {}",
+            std::any::type_name::<Self>(),
+            occ
+        )))
+    }
+
+    /// Delete the struct's fields at occurrence `occ`, where present
+    ///
+    /// Used to clear an optional record set to `None` and the records of a
+    /// repeating group before it is rewritten. Not supported unless the
+    /// implementation overrides it; `#[derive(UbfStruct)]` does.
+    fn delete_ubf_occ(_buf: &mut UbfBuffer, occ: i32) -> Result<(), UbfError> {
+        Err(UbfError::InvalidValue(format!(
+            "{} cannot be deleted at occurrence {}",
+            std::any::type_name::<Self>(),
+            occ
+        )))
+    }
 }
 
 /// UBF conversion errors
@@ -31,6 +74,10 @@ pub enum UbfError {
     AllocationError(String),
     /// Invalid field value
     InvalidValue(String),
+    /// Field or occurrence not present in buffer (BNOTPRES)
+    NotPresent { field_id: i32, occ: i32 },
+    /// UBF call failed with Berror
+    Ubf { code: i32, message: String },
 }
 
 impl fmt::Display for UbfError {
@@ -40,12 +87,73 @@ impl fmt::Display for UbfError {
             UbfError::TypeError(msg) => write!(f, "Type error: {}", msg),
             UbfError::AllocationError(msg) => write!(f, "Allocation error: {}", msg),
             UbfError::InvalidValue(msg) => write!(f, "Invalid value: {}", msg),
+            UbfError::NotPresent { field_id, occ } => {
+                write!(f, "Field {} occurrence {} not present", field_id, occ)
+            }
+            UbfError::Ubf { code, message } => write!(f, "UBF error {}: {}", code, message),
+        }
+    }
+}
+
+impl UbfError {
+    /// Stable code of the error kind, e.g. for API error responses
+    pub fn code(&self) -> &'static str {
+        match self {
+            UbfError::FieldNotFound(_) => "FIELD_NOT_FOUND",
+            UbfError::TypeError(_) => "TYPE_ERROR",
+            UbfError::AllocationError(_) => "ALLOCATION_ERROR",
+            UbfError::InvalidValue(_) => "INVALID_VALUE",
+            UbfError::NotPresent { .. } => "FIELD_NOT_PRESENT",
+            UbfError::Ubf { .. } => "UBF_ERROR",
         }
     }
 }
 
 impl std::error::Error for UbfError {}
 
+/// Message for a failed read of a derived field, with the `Berror` reason
+///
+/// Names the Rust field, the UBF field and its table type, and the type it
+/// was read as. Used by `#[derive(UbfStruct)]` right after the failed call.
+#[doc(hidden)]
+pub fn field_read_error(
+    field: &str,
+    field_id: i32,
+    read_as: &str,
+    err: impl fmt::Display,
+) -> String {
+    // Taken first, the lookups below may overwrite it
+    let code = crate::ubf::last_ubf_errno();
+    let message = field_value_error(field, field_id, read_as, err);
+    if code == 0 {
+        return message;
+    }
+
+    let reason = unsafe { crate::ffi::Bstrerror(code) };
+    if reason.is_null() {
+        return format!("{} (Berror {})", message, code);
+    }
+    let reason = unsafe { std::ffi::CStr::from_ptr(reason) }.to_string_lossy();
+    // Bstrerror text starts with the code, e.g. "5:Field not present"
+    format!("{} (Berror {})", message, reason)
+}
+
+/// `field_read_error` for a value that was read but is not valid
+#[doc(hidden)]
+pub fn field_value_error(
+    field: &str,
+    field_id: i32,
+    read_as: &str,
+    err: impl fmt::Display,
+) -> String {
+    let name = UbfBuffer::field_name(field_id).unwrap_or_else(|_| field_id.to_string());
+    let table_type = UbfBuffer::field_type(field_id).map_or("unknown", |t| t.name());
+    format!(
+        "Field {} ({}, {} field {}, read as {}): {}",
+        field, name, table_type, field_id, read_as, err
+    )
+}
+
 /// Marshal Rust value to UBF buffer
 ///
 /// Converts a Rust type to UBF buffer. For structs with #[ubf] attributes,
@@ -145,17 +253,17 @@ impl UbfStruct for UserData {
     }
 
     fn update_ubf(&self, buf: &mut UbfBuffer) -> Result<(), UbfError> {
-        buf.add_string(T_NAME_FLD, &self.name)
+        buf.set_string(T_NAME_FLD, &self.name)
             .map_err(|e| UbfError::TypeError(format!("name: {}", e)))?;
 
-        buf.add_long(T_ID_FLD, self.id)
+        buf.set_long(T_ID_FLD, self.id)
             .map_err(|e| UbfError::TypeError(format!("id: {}", e)))?;
 
-        buf.add_double(T_PRICE_FLD, self.balance)
+        buf.set_double(T_PRICE_FLD, self.balance)
             .map_err(|e| UbfError::TypeError(format!("balance: {}", e)))?;
 
         if self.active {
-            buf.add_long(T_FLAG_FLD, 1)
+            buf.set_long(T_FLAG_FLD, 1)
                 .map_err(|e| UbfError::TypeError(format!("active: {}", e)))?;
         }
 
@@ -272,16 +380,16 @@ impl UbfStruct for Transaction {
     }
 
     fn update_ubf(&self, buf: &mut UbfBuffer) -> Result<(), UbfError> {
-        buf.add_string(T_NAME_FLD, &self.name)
+        buf.set_string(T_NAME_FLD, &self.name)
             .map_err(|e| UbfError::TypeError(format!("name: {}", e)))?;
 
-        buf.add_long(T_ID_FLD, self.id)
+        buf.set_long(T_ID_FLD, self.id)
             .map_err(|e| UbfError::TypeError(format!("id: {}", e)))?;
 
-        buf.add_double(T_PRICE_FLD, self.amount)
+        buf.set_double(T_PRICE_FLD, self.amount)
             .map_err(|e| UbfError::TypeError(format!("amount: {}", e)))?;
 
-        buf.add_string(T_STATUS_FLD, &self.status)
+        buf.set_string(T_STATUS_FLD, &self.status)
             .map_err(|e| UbfError::TypeError(format!("status: {}", e)))?;
 
         Ok(())
@@ -292,6 +400,34 @@ impl UbfStruct for Transaction {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_error_codes() {
+        let cases = [
+            (UbfError::FieldNotFound("x".into()), "FIELD_NOT_FOUND"),
+            (UbfError::TypeError("x".into()), "TYPE_ERROR"),
+            (UbfError::AllocationError("x".into()), "ALLOCATION_ERROR"),
+            (UbfError::InvalidValue("x".into()), "INVALID_VALUE"),
+            (
+                UbfError::NotPresent {
+                    field_id: 1,
+                    occ: 0,
+                },
+                "FIELD_NOT_PRESENT",
+            ),
+            (
+                UbfError::Ubf {
+                    code: 6,
+                    message: "x".into(),
+                },
+                "UBF_ERROR",
+            ),
+        ];
+
+        for (error, code) in cases {
+            assert_eq!(error.code(), code, "{:?}", error);
+        }
+    }
+
     #[test]
     fn test_ubf_struct_builder() {
         let result = UbfStructBuilder::new(1024)
@@ -0,0 +1,168 @@
+//! UBF field table generation
+//!
+//! Renders `.fd` and `.fd.h` field tables from Rust definitions, so the
+//! schema can be kept in Rust (usually via `#[derive(UbfFieldTable)]`) and
+//! the C-side table generated from it.
+
+/// UBF field type names as used in `.fd` files, indexed by type code
+pub const FIELD_TYPES: [&str; 7] = [
+    "short", "long", "char", "float", "double", "string", "carray",
+];
+
+/// Bit offset of the type code in a BFLDID32 field ID
+const FIELD_TYPE_SHIFT: i32 = 25;
+
+/// Type code of a `.fd` type name
+pub fn field_type_code(field_type: &str) -> Option<i32> {
+    FIELD_TYPES
+        .iter()
+        .position(|t| *t == field_type)
+        .map(|pos| pos as i32)
+}
+
+/// Single entry of a field table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UbfFieldDef {
+    /// Field name, e.g. `T_NAME_FLD`
+    pub name: &'static str,
+    /// Absolute field number (base already applied)
+    pub number: i32,
+    /// `.fd` type name, e.g. `string`
+    pub field_type: &'static str,
+}
+
+impl UbfFieldDef {
+    /// Compiled BFLDID32 field ID (type code in the upper bits)
+    pub fn field_id(&self) -> i32 {
+        let type_code = field_type_code(self.field_type).unwrap_or_else(|| {
+            panic!(
+                "Unknown UBF field type '{}' for {}",
+                self.field_type, self.name
+            )
+        });
+        (type_code << FIELD_TYPE_SHIFT) | self.number
+    }
+}
+
+/// Types describing a UBF field table
+pub trait UbfFieldTable {
+    /// Table name, used for the header guard (`_<NAME>_FD`)
+    const TABLE_NAME: &'static str;
+
+    /// `*base` of the table; field numbers in `.fd` output are relative to it
+    const BASE: i32 = 0;
+
+    /// Fields of the table
+    fn fields() -> &'static [UbfFieldDef];
+
+    /// Renders the `.fd` source table
+    fn fd_table() -> String {
+        let guard = header_guard(Self::TABLE_NAME);
+        let mut out = format!("$#ifndef {}\n$#define {}\n\n", guard, guard);
+        out.push_str(&format!("*base {}\n\n", Self::BASE));
+
+        for field in Self::fields() {
+            out.push_str(&format!(
+                "{}\t{}\t{}\t-\n",
+                field.name,
+                field.number - Self::BASE,
+                field.field_type
+            ));
+        }
+
+        out.push_str("\n$#endif\n");
+        out
+    }
+
+    /// Renders the `.fd.h` header in the format produced by `mkfldhdr32`
+    ///
+    /// The `#define` lines match what `build.rs` parses into constants.
+    fn fd_header() -> String {
+        let guard = header_guard(Self::TABLE_NAME);
+        let mut out = format!("#ifndef {}\n#define {}\n", guard, guard);
+        out.push_str("/*\tfname\tbfldid            */\n");
+        out.push_str("/*\t-----\t-----            */\n");
+
+        for field in Self::fields() {
+            out.push_str(&format!(
+                "#define\t{}\t((BFLDID32){})\t/* number: {}\t type: {} */\n",
+                field.name,
+                field.field_id(),
+                field.number,
+                field.field_type
+            ));
+        }
+
+        out.push_str("#endif\n");
+        out
+    }
+}
+
+fn header_guard(table_name: &str) -> String {
+    format!("_{}_FD", table_name.to_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sample;
+
+    impl UbfFieldTable for Sample {
+        const TABLE_NAME: &'static str = "sample";
+        const BASE: i32 = 1000;
+
+        fn fields() -> &'static [UbfFieldDef] {
+            &[
+                UbfFieldDef {
+                    name: "T_NAME_FLD",
+                    number: 1002,
+                    field_type: "string",
+                },
+                UbfFieldDef {
+                    name: "T_ID_FLD",
+                    number: 1012,
+                    field_type: "long",
+                },
+                UbfFieldDef {
+                    name: "T_SHORT_FLD",
+                    number: 1030,
+                    field_type: "short",
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn test_field_id_encoding() {
+        let fields = Sample::fields();
+        assert_eq!(fields[0].field_id(), 167773162);
+        assert_eq!(fields[1].field_id(), 33555444);
+        assert_eq!(fields[2].field_id(), 1030);
+    }
+
+    #[test]
+    fn test_fd_header() {
+        let expected = "#ifndef _SAMPLE_FD\n\
+                        #define _SAMPLE_FD\n\
+                        /*\tfname\tbfldid            */\n\
+                        /*\t-----\t-----            */\n\
+                        #define\tT_NAME_FLD\t((BFLDID32)167773162)\t/* number: 1002\t type: string */\n\
+                        #define\tT_ID_FLD\t((BFLDID32)33555444)\t/* number: 1012\t type: long */\n\
+                        #define\tT_SHORT_FLD\t((BFLDID32)1030)\t/* number: 1030\t type: short */\n\
+                        #endif\n";
+        assert_eq!(Sample::fd_header(), expected);
+    }
+
+    #[test]
+    fn test_fd_table() {
+        let expected = "$#ifndef _SAMPLE_FD\n\
+                        $#define _SAMPLE_FD\n\n\
+                        *base 1000\n\n\
+                        T_NAME_FLD\t2\tstring\t-\n\
+                        T_ID_FLD\t12\tlong\t-\n\
+                        T_SHORT_FLD\t30\tshort\t-\n\
+                        \n$#endif\n";
+        assert_eq!(Sample::fd_table(), expected);
+    }
+}
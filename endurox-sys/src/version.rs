@@ -0,0 +1,29 @@
+//! Version of the Enduro/X install the crate was built against
+
+/// Enduro/X version string, e.g. `Enduro/X 8.0.10`
+///
+/// Taken from `ndrx_config.h` at build time, as Enduro/X has no runtime
+/// version call. Returns `"unknown"` when the headers were not found.
+pub fn endurox_version() -> String {
+    #[cfg(endurox_version)]
+    {
+        env!("ENDUROX_VERSION").to_string()
+    }
+
+    #[cfg(not(endurox_version))]
+    {
+        "unknown".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_not_empty() {
+        let version = endurox_version();
+        assert!(!version.is_empty());
+        assert!(!version.contains('"'));
+    }
+}
@@ -0,0 +1,103 @@
+//! Global (XA) transactions and calls made from inside them
+
+#[cfg(all(feature = "client", feature = "ubf"))]
+use crate::client::EnduroxClient;
+use crate::error::TpError;
+use crate::flags::TpFlags;
+#[cfg(all(feature = "client", feature = "ubf"))]
+use crate::ubf::UbfBuffer;
+use libc::{c_int, c_long, c_ulong};
+
+/// Transaction timeout used by [`begin_transaction`], in seconds
+pub const DEFAULT_TRANSACTION_TIMEOUT: u32 = 60;
+
+type Tpbegin = unsafe extern "C" fn(c_ulong, c_long) -> c_int;
+
+/// Starts a global transaction with the default 60 second timeout
+pub fn begin_transaction() -> Result<(), TpError> {
+    begin_transaction_with(DEFAULT_TRANSACTION_TIMEOUT, TpFlags::empty())
+}
+
+/// Starts a global transaction that tmsrv aborts after `timeout_secs`
+///
+/// The timeout bounds how long the transaction may stay active before it is
+/// prepared or committed; `0` uses tmsrv's default maximum. `flags` is
+/// reserved by Enduro/X and must be empty.
+///
+/// `TPNOTIME` on calls inside the transaction only lifts the per-call
+/// blocking timeout (`NDRX_TOUT`). It does not extend the transaction: a
+/// call that outlives `timeout_secs` still finds the transaction rolled
+/// back, so long bulk work needs a larger timeout here, not just `TPNOTIME`.
+pub fn begin_transaction_with(timeout_secs: u32, flags: TpFlags) -> Result<(), TpError> {
+    begin_via(crate::ffi::tpbegin, timeout_secs, flags)
+}
+
+// `begin_transaction_with` with the tpbegin passed in; unit tests have no tmsrv
+fn begin_via(tpbegin: Tpbegin, timeout_secs: u32, flags: TpFlags) -> Result<(), TpError> {
+    let ret = unsafe { tpbegin(timeout_secs as c_ulong, flags.raw()) };
+    if ret == -1 {
+        return Err(TpError::last());
+    }
+    Ok(())
+}
+
+/// Whether the caller runs inside a global transaction
+pub fn in_transaction() -> bool {
+    unsafe { crate::ffi::tpgetlev() > 0 }
+}
+
+/// Flags of a read-only call: `TPNOTRAN` inside a global transaction
+pub fn readonly_flags() -> TpFlags {
+    readonly_flags_for(in_transaction())
+}
+
+fn readonly_flags_for(in_transaction: bool) -> TpFlags {
+    if in_transaction {
+        TpFlags::NOTRAN
+    } else {
+        TpFlags::empty()
+    }
+}
+
+/// Calls a read-only service without enlisting it in the current transaction
+///
+/// Inside a global transaction the call passes `TPNOTRAN`, so the callee's
+/// resource manager stays out of the two-phase commit. Outside one it is a
+/// plain call. `buf` is copied, the caller keeps it.
+#[cfg(all(feature = "client", feature = "ubf"))]
+pub fn call_readonly(
+    client: &EnduroxClient,
+    service: &str,
+    buf: &UbfBuffer,
+) -> Result<UbfBuffer, TpError> {
+    let send = UbfBuffer::from_bytes(buf.as_bytes()).map_err(TpError::InvalidArgument)?;
+    client.call_raw_ubf_with_flags(service, send, readonly_flags())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notran_only_inside_transaction() {
+        assert_eq!(readonly_flags_for(false), TpFlags::empty());
+        assert_eq!(readonly_flags_for(true), TpFlags::NOTRAN);
+    }
+
+    thread_local! {
+        static TPBEGIN_ARGS: std::cell::Cell<Option<(c_ulong, c_long)>> =
+            const { std::cell::Cell::new(None) };
+    }
+
+    // Records its arguments instead of starting a transaction
+    unsafe extern "C" fn fake_tpbegin(timeout: c_ulong, flags: c_long) -> c_int {
+        TPBEGIN_ARGS.with(|args| args.set(Some((timeout, flags))));
+        0
+    }
+
+    #[test]
+    fn test_begin_transaction_passes_timeout() {
+        begin_via(fake_tpbegin, 600, TpFlags::empty()).unwrap();
+        assert_eq!(TPBEGIN_ARGS.with(|args| args.get()), Some((600, 0)));
+    }
+}
@@ -0,0 +1,88 @@
+//! EnduroxClient::call_raw_ubf against samplesvr_rust
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "client", feature = "ubf"))]
+
+use endurox_sys::client::EnduroxClient;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use endurox_sys::TpFlags;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_raw_ubf_round_trip() {
+    let client = EnduroxClient::new().unwrap();
+
+    // Small send buffer so the reply has to be received into a grown buffer
+    let mut request = UbfBuffer::new(128).unwrap();
+    request.add_long(T_LONG_FLD, 0).unwrap();
+
+    let reply = client.call_raw_ubf("SLEEP", request).unwrap();
+    assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_raw_ubf_unknown_service() {
+    let client = EnduroxClient::new().unwrap();
+
+    let request = UbfBuffer::new(128).unwrap();
+    assert!(client.call_raw_ubf("NO_SUCH_SERVICE", request).is_err());
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment, with NDRX_TOUT below 2s
+fn test_call_ubf_no_timeout_outlives_block_timeout() {
+    let client = EnduroxClient::new().unwrap();
+
+    let mut request = UbfBuffer::new(128).unwrap();
+    request.add_long(T_LONG_FLD, 2000).unwrap();
+
+    let reply = client.call_ubf_no_timeout("SLEEP", &request).unwrap();
+    assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment (with tpadmsv booted)
+fn test_is_service_available() {
+    let client = EnduroxClient::new().unwrap();
+
+    assert!(client.is_service_available("ECHO"));
+    assert!(!client.is_service_available("NO_SUCH_SERVICE"));
+    // Cached answer
+    assert!(!client.is_service_available("NO_SUCH_SERVICE"));
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_from_thread_without_context() {
+    use endurox_sys::TpError;
+
+    let client = EnduroxClient::new().unwrap();
+
+    let result = std::thread::scope(|s| {
+        s.spawn(|| {
+            client
+                .call_raw_ubf("SLEEP", UbfBuffer::new(128).unwrap())
+                .map(|_| ())
+        })
+        .join()
+        .unwrap()
+    });
+    assert_eq!(result, Err(TpError::NotInitialized));
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_raw_ubf_with_composed_flags() {
+    let client = EnduroxClient::new().unwrap();
+
+    let mut request = UbfBuffer::new(128).unwrap();
+    request.add_long(T_LONG_FLD, 0).unwrap();
+
+    let flags = TpFlags::NOTIME | TpFlags::NOTRAN;
+    let reply = client
+        .call_raw_ubf_with_flags("SLEEP", request, flags)
+        .unwrap();
+    assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+}
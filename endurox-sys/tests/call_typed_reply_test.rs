@@ -0,0 +1,54 @@
+//! EnduroxClient::call_typed_reply against samplesvr_rust
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "client", feature = "ubf"))]
+
+use endurox_sys::client::EnduroxClient;
+use endurox_sys::typed_buffer::TypedBuffer;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_typed_reply_ubf() {
+    let client = EnduroxClient::new().unwrap();
+
+    let mut request = UbfBuffer::new(128).unwrap();
+    request.add_long(T_LONG_FLD, 0).unwrap();
+
+    let (reply, urcode) = client
+        .call_typed_reply("SLEEP", TypedBuffer::Ubf(request))
+        .unwrap();
+    assert_eq!(urcode, 0);
+    match reply {
+        TypedBuffer::Ubf(buf) => assert_eq!(buf.get_string(T_STATUS_FLD, 0).unwrap(), "OK"),
+        other => panic!("expected a UBF reply, got {}", other.type_name()),
+    }
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_typed_reply_string() {
+    let client = EnduroxClient::new().unwrap();
+
+    let (reply, _) = client
+        .call_typed_reply("ECHO", TypedBuffer::String("ping".to_string()))
+        .unwrap();
+    match reply {
+        TypedBuffer::String(text) => assert_eq!(text, "Echoed: ECHO"),
+        other => panic!("expected a STRING reply, got {}", other.type_name()),
+    }
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_typed_reply_string_to_ubf_request() {
+    let client = EnduroxClient::new().unwrap();
+
+    // ECHO answers STRING whatever it is sent
+    let request = UbfBuffer::new(128).unwrap();
+    let (reply, _) = client
+        .call_typed_reply("ECHO", TypedBuffer::Ubf(request))
+        .unwrap();
+    assert_eq!(reply.type_name(), "STRING");
+}
@@ -0,0 +1,80 @@
+//! Deadline calls against the SLEEP service of samplesvr_rust
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "client", feature = "ubf"))]
+
+use endurox_sys::client::EnduroxClient;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use endurox_sys::TpError;
+use std::time::{Duration, Instant};
+
+fn sleep_request(millis: i64) -> UbfBuffer {
+    let mut buf = UbfBuffer::new(256).unwrap();
+    buf.add_long(T_LONG_FLD, millis).unwrap();
+    buf
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_call_ubf_deadline_times_out() {
+    let client = EnduroxClient::new().unwrap();
+    let request = sleep_request(2000);
+
+    let started = Instant::now();
+    let result = client.call_ubf_deadline("SLEEP", &request, Duration::from_millis(200));
+
+    assert_eq!(result.unwrap_err(), TpError::Timeout);
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(200));
+    assert!(elapsed < Duration::from_millis(1000));
+}
+
+#[test]
+#[ignore]
+fn test_call_ubf_deadline_replies_in_time() {
+    let client = EnduroxClient::new().unwrap();
+    let request = sleep_request(50);
+
+    let reply = client
+        .call_ubf_deadline("SLEEP", &request, Duration::from_millis(2000))
+        .unwrap();
+
+    assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+}
+
+#[test]
+#[ignore]
+fn test_drain_replies_collects_all_in_time() {
+    let client = EnduroxClient::new().unwrap();
+    let descriptors: Vec<_> = [30, 10, 20]
+        .iter()
+        .map(|millis| client.acall("SLEEP", &sleep_request(*millis)).unwrap())
+        .collect();
+
+    let results = client.drain_replies(descriptors.clone(), Duration::from_millis(2000));
+
+    // Results keep the order of the descriptors
+    let returned: Vec<_> = results.iter().map(|(cd, _)| *cd).collect();
+    assert_eq!(returned, descriptors);
+    for (_, reply) in results {
+        let reply = UbfBuffer::from_bytes(&reply.unwrap()).unwrap();
+        assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+    }
+}
+
+#[test]
+#[ignore]
+fn test_drain_replies_cancels_stragglers() {
+    let client = EnduroxClient::new().unwrap();
+    let fast = client.acall("SLEEP", &sleep_request(10)).unwrap();
+    let slow = client.acall("SLEEP", &sleep_request(2000)).unwrap();
+
+    let started = Instant::now();
+    let results = client.drain_replies(vec![fast, slow], Duration::from_millis(300));
+    assert!(started.elapsed() < Duration::from_millis(1000));
+
+    assert_eq!(results[0].0, fast);
+    assert!(results[0].1.is_ok());
+    assert_eq!(results[1], (slow, Err(TpError::Timeout)));
+}
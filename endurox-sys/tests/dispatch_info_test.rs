@@ -0,0 +1,17 @@
+//! Server id reported by samplesvr_rust's STATUS service
+//!
+//! Requires a running Enduro/X application booted from conf/ndrxconfig.xml,
+//! where samplesvr_rust runs with srvid 2.
+#![cfg(feature = "client")]
+
+use endurox_sys::client::EnduroxClient;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_status_reports_server_id() {
+    let client = EnduroxClient::new().unwrap();
+
+    let reply = client.call_service_blocking("STATUS", "").unwrap();
+    assert!(reply.ends_with("srvid 2)"), "unexpected reply: {}", reply);
+    assert!(!reply.contains("node 0,"), "node id not set: {}", reply);
+}
@@ -0,0 +1,8 @@
+#ifndef _BILLING_FD
+#define _BILLING_FD
+/*	fname	bfldid            */
+/*	-----	-----            */
+#define	T_NAME_FLD	((BFLDID32)167773162)	/* number: 1002	 type: string */
+#define	T_ORDER_ID_FLD	((BFLDID32)33558432)	/* number: 4000	 type: long */
+#define	T_INVOICE_FLD	((BFLDID32)167776162)	/* number: 4002	 type: string */
+#endif
@@ -0,0 +1,7 @@
+#ifndef _ORDERS_FD
+#define _ORDERS_FD
+/*	fname	bfldid            */
+/*	-----	-----            */
+#define	T_NAME_FLD	((BFLDID32)167773162)	/* number: 1002	 type: string */
+#define	T_ORDER_ID_FLD	((BFLDID32)33557432)	/* number: 3000	 type: long */
+#endif
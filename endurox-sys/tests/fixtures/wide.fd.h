@@ -0,0 +1,7 @@
+#ifndef _WIDE_FD
+#define _WIDE_FD
+/*	fname	bfldid            */
+/*	-----	-----            */
+#define	T_WIDE_FLD	((BFLDID32)2181038082)	/* out of i32 range */
+#define	T_SMALL_FLD	((BFLDID32)33557433)	/* number: 3001	 type: long */
+#endif
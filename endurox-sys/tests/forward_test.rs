@@ -0,0 +1,25 @@
+//! ServiceResult::forward through samplesvr_rust's FORWARD service
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "client", feature = "ubf"))]
+
+use endurox_sys::client::EnduroxClient;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_forward_reaches_target() {
+    let client = EnduroxClient::new().unwrap();
+
+    let mut request = UbfBuffer::new(256).unwrap();
+    request.add_long(T_LONG_FLD, 0).unwrap();
+
+    // FORWARD hands the request to SLEEP, which answers with T_STATUS_FLD=OK
+    let reply = client
+        .call_service_ubf_blocking("FORWARD", request.as_bytes())
+        .unwrap();
+    let reply = UbfBuffer::from_bytes(&reply).unwrap();
+
+    assert_eq!(reply.get_string(T_STATUS_FLD, 0).unwrap(), "OK");
+}
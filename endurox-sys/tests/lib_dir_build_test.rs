@@ -0,0 +1,65 @@
+// Tests for the NDRX_HOME library probe used by build.rs
+
+#[path = "../build/lib_dir.rs"]
+mod lib_dir;
+
+use lib_dir::find_lib_dir;
+use std::fs;
+use std::path::PathBuf;
+
+/// Fresh fake install prefix with the given library files
+fn fake_home(name: &str, files: &[&str]) -> PathBuf {
+    let home =
+        std::env::temp_dir().join(format!("endurox-lib-dir-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&home);
+    for file in files {
+        let path = home.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"").unwrap();
+    }
+    fs::create_dir_all(&home).unwrap();
+    home
+}
+
+#[test]
+fn test_finds_lib_and_lib64() {
+    let home = fake_home("lib", &["lib/libatmi.so", "lib/libubf.so"]);
+    assert_eq!(find_lib_dir(&home, None).unwrap(), home.join("lib"));
+
+    let home = fake_home("lib64", &["lib64/libatmi.so", "lib64/libubf.a"]);
+    assert_eq!(find_lib_dir(&home, None).unwrap(), home.join("lib64"));
+
+    let home = fake_home(
+        "multiarch",
+        &[
+            "lib/x86_64-linux-gnu/libatmi.so",
+            "lib/x86_64-linux-gnu/libubf.so",
+        ],
+    );
+    assert_eq!(
+        find_lib_dir(&home, Some("x86_64-linux-gnu")).unwrap(),
+        home.join("lib/x86_64-linux-gnu")
+    );
+}
+
+#[test]
+fn test_reports_missing_libraries() {
+    let home = fake_home("partial", &["lib/libatmi.so"]);
+    let err = find_lib_dir(&home, None).unwrap_err();
+    assert!(err.contains(&home.display().to_string()), "{}", err);
+    assert!(err.contains("missing libubf"), "{}", err);
+    assert!(!err.contains("libatmi,"), "{}", err);
+
+    let home = fake_home("empty", &[]);
+    let err = find_lib_dir(&home, None).unwrap_err();
+    assert!(err.contains("missing libatmi, libubf"), "{}", err);
+    assert!(
+        err.contains(&home.join("lib64").display().to_string()),
+        "{}",
+        err
+    );
+
+    let home = fake_home("split", &["lib/libatmi.so", "lib64/libubf.so"]);
+    let err = find_lib_dir(&home, None).unwrap_err();
+    assert!(err.contains("not in the same directory"), "{}", err);
+}
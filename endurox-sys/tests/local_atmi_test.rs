@@ -0,0 +1,43 @@
+//! LocalAtmi on a current-thread runtime
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "async", feature = "ubf"))]
+
+use endurox_sys::local::LocalAtmi;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use tokio::task::LocalSet;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_local_atmi_spawn_local_calls() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let replies = rt
+        .block_on(LocalAtmi::run(LocalSet::new(), |atmi| async move {
+            let tasks: Vec<_> = (0..10)
+                .map(|i| {
+                    let atmi = atmi.clone();
+                    tokio::task::spawn_local(async move {
+                        let mut buf = UbfBuffer::new(256).unwrap();
+                        buf.add_long(T_LONG_FLD, 0).unwrap();
+                        buf.add_long(T_ID_FLD, i).unwrap();
+                        atmi.call("SLEEP", buf.as_bytes()).await
+                    })
+                })
+                .collect();
+
+            let mut replies = Vec::new();
+            for task in tasks {
+                replies.push(task.await.unwrap());
+            }
+            replies
+        }))
+        .unwrap();
+
+    assert_eq!(replies.len(), 10);
+    assert!(replies.iter().all(|reply| reply.is_ok()));
+}
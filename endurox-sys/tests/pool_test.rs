@@ -0,0 +1,42 @@
+//! BlockingAtmiPool under concurrent load
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "async", feature = "ubf"))]
+
+use endurox_sys::pool::BlockingAtmiPool;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use std::sync::Arc;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+#[ignore] // Run only with Enduro/X environment
+async fn test_pool_concurrent_calls() {
+    let pool = Arc::new(BlockingAtmiPool::new(4).unwrap());
+    assert_eq!(pool.size(), 4);
+
+    let mut tasks = Vec::new();
+    for i in 0..500 {
+        let pool = Arc::clone(&pool);
+        tasks.push(tokio::spawn(async move {
+            // UbfBuffer is not Send, so only its bytes cross the await
+            let data = {
+                let mut buf = UbfBuffer::new(256).unwrap();
+                buf.add_long(T_LONG_FLD, 0).unwrap();
+                buf.add_long(T_ID_FLD, i).unwrap();
+                buf.as_bytes().to_vec()
+            };
+
+            pool.call_async("SLEEP", data).await
+        }));
+    }
+
+    for task in tasks {
+        let reply = task.await.unwrap().expect("call on pool thread failed");
+        assert!(!reply.is_empty());
+    }
+}
+
+#[test]
+fn test_pool_rejects_zero_size() {
+    assert!(BlockingAtmiPool::new(0).is_err());
+}
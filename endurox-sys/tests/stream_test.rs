@@ -0,0 +1,39 @@
+//! EnduroxClient::stream_ubf against samplesvr_rust's STREAM service
+//!
+//! Requires a running Enduro/X application with samplesvr_rust booted.
+#![cfg(all(feature = "client", feature = "ubf"))]
+
+use endurox_sys::client::EnduroxClient;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_stream_n_buffers() {
+    let client = EnduroxClient::new().unwrap();
+
+    let mut request = UbfBuffer::new(256).unwrap();
+    request.add_long(T_COUNT_FLD, 25).unwrap();
+
+    let ids: Vec<i64> = client
+        .stream_ubf("STREAM", &request)
+        .unwrap()
+        .map(|row| row.unwrap().get_long(T_ID_FLD, 0).unwrap())
+        .collect();
+    assert_eq!(ids, (0..25).collect::<Vec<_>>());
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment
+fn test_stream_dropped_early() {
+    let client = EnduroxClient::new().unwrap();
+
+    let request = UbfBuffer::new(256).unwrap();
+    let mut stream = client.stream_ubf("STREAM", &request).unwrap();
+    assert!(stream.next().unwrap().is_ok());
+    drop(stream);
+
+    // The client is still usable after the disconnect
+    let request = UbfBuffer::new(256).unwrap();
+    assert_eq!(client.stream_ubf("STREAM", &request).unwrap().count(), 10);
+}
@@ -0,0 +1,353 @@
+//! #[derive(UbfStruct)] field encodings
+#![cfg(all(feature = "derive", feature = "ubf"))]
+
+use endurox_sys::money::Money;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::*;
+use endurox_sys::ubf_struct::UbfStruct;
+use endurox_sys::UbfStruct;
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Payment {
+    #[ubf(field = T_NAME_FLD)]
+    account: String,
+
+    // T_DATA_FLD and T_STRING_FLD are STRING fields in test.fd
+    #[ubf(field = T_DATA_FLD, as = "string")]
+    amount: i64,
+
+    #[ubf(field = T_STRING_FLD, as = "string")]
+    rate: Option<f64>,
+}
+
+#[test]
+fn test_numeric_through_string_field() {
+    let payment = Payment {
+        account: "ACC-1".to_string(),
+        amount: -1_234_567_890_123,
+        rate: Some(0.125),
+    };
+
+    let buf = payment.to_ubf().unwrap();
+    assert_eq!(buf.get_string(T_DATA_FLD, 0).unwrap(), "-1234567890123");
+    assert_eq!(buf.get_string(T_STRING_FLD, 0).unwrap(), "0.125");
+
+    assert_eq!(Payment::from_ubf(&buf).unwrap(), payment);
+}
+
+#[test]
+fn test_string_encoded_optional_and_invalid() {
+    let mut buf = UbfBuffer::new(1024).unwrap();
+    buf.add_string(T_NAME_FLD, "ACC-2").unwrap();
+    buf.add_string(T_DATA_FLD, "42").unwrap();
+
+    let payment = Payment::from_ubf(&buf).unwrap();
+    assert_eq!(payment.amount, 42);
+    assert_eq!(payment.rate, None);
+
+    let mut bad = UbfBuffer::new(1024).unwrap();
+    bad.add_string(T_NAME_FLD, "ACC-3").unwrap();
+    bad.add_string(T_DATA_FLD, "not a number").unwrap();
+    assert!(Payment::from_ubf(&bad).is_err());
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct LineItem {
+    #[ubf(field = T_NAME_FLD)]
+    product: String,
+
+    #[ubf(field = T_COUNT_FLD)]
+    quantity: i64,
+
+    #[ubf(field = T_PRICE_FLD)]
+    price: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Order {
+    #[ubf(field = T_ID_FLD)]
+    id: i64,
+
+    // One LineItem per occurrence of T_NAME_FLD
+    #[ubf(field = T_NAME_FLD)]
+    items: Vec<LineItem>,
+}
+
+#[test]
+fn test_repeating_group_round_trip() {
+    let order = Order {
+        id: 7,
+        items: vec![
+            LineItem {
+                product: "widget".to_string(),
+                quantity: 3,
+                price: Some(2.25),
+            },
+            LineItem {
+                product: "gadget".to_string(),
+                quantity: 1,
+                price: None,
+            },
+        ],
+    };
+
+    let buf = order.to_ubf().unwrap();
+    assert_eq!(buf.occurrences(T_NAME_FLD).unwrap(), 2);
+    assert_eq!(buf.get_string(T_NAME_FLD, 1).unwrap(), "gadget");
+    assert_eq!(buf.get_long(T_COUNT_FLD, 1).unwrap(), 1);
+    assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), 2.25);
+    assert!(!buf.is_present(T_PRICE_FLD, 1));
+
+    assert_eq!(Order::from_ubf(&buf).unwrap(), order);
+}
+
+#[test]
+fn test_empty_repeating_group() {
+    let mut buf = UbfBuffer::new(1024).unwrap();
+    buf.add_long(T_ID_FLD, 8).unwrap();
+
+    let order = Order::from_ubf(&buf).unwrap();
+    assert!(order.items.is_empty());
+}
+
+#[test]
+fn test_update_ubf_twice_keeps_single_occurrences() {
+    let mut buf = UbfBuffer::new(1024).unwrap();
+
+    let first = Payment {
+        account: "ACC-3".to_string(),
+        amount: 100,
+        rate: Some(0.5),
+    };
+    first.update_ubf(&mut buf).unwrap();
+
+    let second = Payment {
+        account: "ACC-4".to_string(),
+        amount: 200,
+        rate: Some(0.75),
+    };
+    second.update_ubf(&mut buf).unwrap();
+
+    for field in [T_NAME_FLD, T_DATA_FLD, T_STRING_FLD] {
+        assert_eq!(buf.occurrences(field).unwrap(), 1);
+    }
+    assert_eq!(Payment::from_ubf(&buf).unwrap(), second);
+}
+
+#[test]
+fn test_update_ubf_clears_field_set_to_none() {
+    let mut buf = UbfBuffer::new(1024).unwrap();
+
+    let mut item = LineItem {
+        product: "widget".to_string(),
+        quantity: 3,
+        price: Some(2.25),
+    };
+    item.update_ubf(&mut buf).unwrap();
+
+    item.price = None;
+    item.update_ubf(&mut buf).unwrap();
+
+    assert!(!buf.is_present(T_PRICE_FLD, 0));
+    assert_eq!(LineItem::from_ubf(&buf).unwrap(), item);
+}
+
+#[test]
+fn test_update_ubf_shrinks_repeating_group() {
+    let item = |product: &str, price| LineItem {
+        product: product.to_string(),
+        quantity: 1,
+        price,
+    };
+    let mut order = Order {
+        id: 9,
+        items: vec![
+            item("widget", None),
+            item("gadget", Some(1.5)),
+            item("gizmo", Some(4.0)),
+        ],
+    };
+    let mut buf = order.to_ubf().unwrap();
+
+    order.items.truncate(1);
+    order.update_ubf(&mut buf).unwrap();
+
+    assert_eq!(buf.occurrences(T_NAME_FLD).unwrap(), 1);
+    assert_eq!(buf.occurrences(T_COUNT_FLD).unwrap(), 1);
+    assert_eq!(buf.occurrences(T_PRICE_FLD).unwrap(), 0);
+    assert_eq!(Order::from_ubf(&buf).unwrap(), order);
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Reading {
+    #[ubf(field = T_ID_FLD)]
+    sensor: i64,
+
+    // T_FLOAT_FLD is a BFLD_FLOAT field
+    #[ubf(field = T_FLOAT_FLD)]
+    value: f32,
+
+    #[ubf(field = T_PRICE_FLD)]
+    limit: Option<f32>,
+}
+
+#[test]
+fn test_f32_field_round_trip() {
+    let reading = Reading {
+        sensor: 4,
+        value: 0.1,
+        limit: Some(-3.75),
+    };
+
+    let buf = reading.to_ubf().unwrap();
+    assert_eq!(buf.get_float(T_FLOAT_FLD, 0).unwrap(), 0.1f32);
+    assert_eq!(buf.get_double(T_PRICE_FLD, 0).unwrap(), -3.75);
+
+    assert_eq!(Reading::from_ubf(&buf).unwrap(), reading);
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Settlement {
+    #[ubf(field = T_TRANS_ID_FLD)]
+    id: String,
+
+    total: Money,
+}
+
+#[test]
+fn test_money_field_round_trip() {
+    let settlement = Settlement {
+        id: "STL-1".to_string(),
+        total: Money::new(i64::MAX - 1, "JPY"),
+    };
+
+    let buf = settlement.to_ubf().unwrap();
+    assert_eq!(buf.get_long(T_AMOUNT_FLD, 0).unwrap(), i64::MAX - 1);
+    assert_eq!(buf.get_string(T_CURRENCY_FLD, 0).unwrap(), "JPY");
+
+    assert_eq!(Settlement::from_ubf(&buf).unwrap(), settlement);
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Address {
+    #[ubf(field = T_NAME_FLD)]
+    city: String,
+
+    #[ubf(field = T_COUNT_FLD)]
+    zip: i64,
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Shipment {
+    #[ubf(field = T_ID_FLD)]
+    id: i64,
+
+    origin: Address,
+
+    #[ubf(occ_base = 10)]
+    destination: Address,
+
+    #[ubf(occ_base = 20)]
+    return_to: Option<Address>,
+}
+
+#[test]
+fn test_nested_structs_at_occ_base() {
+    let shipment = Shipment {
+        id: 7,
+        origin: Address {
+            city: "Riga".to_string(),
+            zip: 1010,
+        },
+        destination: Address {
+            city: "Vilnius".to_string(),
+            zip: 2020,
+        },
+        return_to: None,
+    };
+
+    let mut buf = shipment.to_ubf().unwrap();
+    assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "Riga");
+    assert_eq!(buf.get_string(T_NAME_FLD, 10).unwrap(), "Vilnius");
+    assert_eq!(buf.get_long(T_COUNT_FLD, 10).unwrap(), 2020);
+    assert!(!buf.is_present(T_NAME_FLD, 20));
+    assert_eq!(Shipment::from_ubf(&buf).unwrap(), shipment);
+
+    // Writing again changes the same occurrences
+    let returned = Shipment {
+        return_to: Some(Address {
+            city: "Tallinn".to_string(),
+            zip: 3030,
+        }),
+        ..shipment
+    };
+    returned.update_ubf(&mut buf).unwrap();
+    assert_eq!(buf.get_string(T_NAME_FLD, 20).unwrap(), "Tallinn");
+    assert_eq!(Shipment::from_ubf(&buf).unwrap(), returned);
+}
+
+const fn pick(ids: [i32; 2], index: usize) -> i32 {
+    ids[index]
+}
+
+#[derive(Debug, PartialEq, UbfStruct)]
+struct Ticket {
+    // Commas inside the expression used to split the attribute apart
+    #[ubf(field = pick([T_ID_FLD, T_COUNT_FLD], 1))]
+    seats: i64,
+
+    #[ubf( field=T_NAME_FLD , default = "open, unassigned" )]
+    state: String,
+}
+
+#[test]
+fn test_attributes_with_commas_and_expressions() {
+    let mut buf = UbfBuffer::new(1024).unwrap();
+    buf.add_long(T_COUNT_FLD, 3).unwrap();
+
+    let ticket = Ticket::from_ubf(&buf).unwrap();
+    assert_eq!(
+        ticket,
+        Ticket {
+            seats: 3,
+            state: "open, unassigned".to_string(),
+        }
+    );
+
+    let written = ticket.to_ubf().unwrap();
+    assert_eq!(written.get_long(T_COUNT_FLD, 0).unwrap(), 3);
+    assert!(!written.is_present(T_ID_FLD, 0));
+    assert_eq!(
+        written.get_string(T_NAME_FLD, 0).unwrap(),
+        "open, unassigned"
+    );
+}
+
+#[test]
+fn test_decode_error_names_field_and_reason() {
+    // account (T_NAME_FLD) is missing
+    let mut buf = UbfBuffer::new(1024).unwrap();
+    buf.add_string(T_DATA_FLD, "100").unwrap();
+
+    let err = Payment::from_ubf(&buf).unwrap_err().to_string();
+    assert!(err.contains("Field account"), "{}", err);
+    assert!(err.contains("read as string"), "{}", err);
+    assert!(err.contains("Berror"), "{}", err);
+
+    // Present but not a number: no stale Berror in the message
+    buf.add_string(T_NAME_FLD, "ACC-1").unwrap();
+    buf.change_string(T_DATA_FLD, 0, "lots").unwrap();
+    let err = Payment::from_ubf(&buf).unwrap_err().to_string();
+    assert!(err.contains("Field amount"), "{}", err);
+    assert!(err.contains("invalid digit"), "{}", err);
+    assert!(!err.contains("Berror"), "{}", err);
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment (FLDTBLDIR/FIELDTBLS for Bfname)
+fn test_decode_error_names_ubf_field() {
+    let buf = UbfBuffer::new(1024).unwrap();
+
+    let err = Payment::from_ubf(&buf).unwrap_err().to_string();
+    assert!(err.contains("T_NAME_FLD, string field"), "{}", err);
+}
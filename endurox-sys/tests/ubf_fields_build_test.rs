@@ -0,0 +1,64 @@
+// Tests for the field constant generator used by build.rs
+
+#[path = "../build/ubf_fields.rs"]
+mod ubf_fields;
+
+use ubf_fields::{parse_ubf_header, render_ubf_constants};
+
+fn fixture(name: &str) -> (String, String) {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    let content = std::fs::read_to_string(&path).expect("fixture exists");
+    (name.to_string(), content)
+}
+
+#[test]
+fn test_parse_ubf_header() {
+    let (_, content) = fixture("orders.fd.h");
+    assert_eq!(
+        parse_ubf_header(&content),
+        vec![
+            ("T_NAME_FLD".to_string(), "167773162".to_string()),
+            ("T_ORDER_ID_FLD".to_string(), "33557432".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_colliding_tables_emit_compile_error() {
+    let (code, conflicts) =
+        render_ubf_constants(&[fixture("orders.fd.h"), fixture("billing.fd.h")]);
+
+    assert_eq!(
+        conflicts,
+        vec!["UBF field T_ORDER_ID_FLD is 33557432 in orders.fd.h but 33558432 in billing.fd.h"]
+    );
+    assert!(code.contains(
+        "compile_error!(\"UBF field T_ORDER_ID_FLD is 33557432 in orders.fd.h but 33558432 in billing.fd.h\");"
+    ));
+    // Same name with the same id is emitted once
+    assert_eq!(code.matches("pub const T_NAME_FLD: i32").count(), 1);
+    assert_eq!(code.matches("pub const T_ORDER_ID_FLD: i32").count(), 1);
+    assert!(code.contains("pub const T_INVOICE_FLD: i32 = 167776162;"));
+}
+
+#[test]
+fn test_single_table_has_no_conflicts() {
+    let (code, conflicts) = render_ubf_constants(&[fixture("orders.fd.h")]);
+    assert!(conflicts.is_empty());
+    assert!(!code.contains("compile_error!"));
+}
+
+#[test]
+fn test_out_of_range_id_emits_compile_error() {
+    let (code, errors) = render_ubf_constants(&[fixture("wide.fd.h")]);
+
+    assert_eq!(
+        errors,
+        vec!["UBF field T_WIDE_FLD in wide.fd.h has id 2181038082, which doesn't fit i32"]
+    );
+    assert!(code.contains(
+        "compile_error!(\"UBF field T_WIDE_FLD in wide.fd.h has id 2181038082, which doesn't fit i32\");"
+    ));
+    assert!(!code.contains("pub const T_WIDE_FLD"));
+    assert!(code.contains("pub const T_SMALL_FLD: i32 = 33557433;"));
+}
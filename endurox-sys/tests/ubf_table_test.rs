@@ -0,0 +1,75 @@
+//! Field tables generated with #[derive(UbfFieldTable)]
+#![cfg(feature = "derive")]
+
+use endurox_sys::ubf_table::UbfFieldTable;
+use endurox_sys::UbfFieldTable;
+
+#[allow(dead_code)]
+#[derive(UbfFieldTable)]
+#[ubf_table(name = "test", base = 1000)]
+struct TestTable {
+    #[ubf(field = 1002, name = "T_NAME_FLD")]
+    name: String,
+
+    #[ubf(field = 1012, name = "T_ID_FLD")]
+    id: i64,
+
+    #[ubf(field = 1021, name = "T_PRICE_FLD")]
+    price: f64,
+
+    #[ubf(field = 1030, name = "T_SHORT_FLD", type = "short")]
+    short: i32,
+
+    #[ubf(field = 1040, name = "T_CHAR_FLD")]
+    flag: Option<char>,
+}
+
+#[allow(dead_code)]
+#[derive(UbfFieldTable)]
+struct Defaults {
+    #[ubf(field = 1)]
+    status: Option<String>,
+}
+
+#[test]
+fn test_header_matches_known_table() {
+    let known = include_str!("../../ubftab/test.fd.h");
+    let generated = TestTable::fd_header();
+
+    assert!(generated.starts_with("#ifndef _TEST_FD\n#define _TEST_FD\n"));
+
+    let defines: Vec<&str> = generated
+        .lines()
+        .filter(|line| line.starts_with("#define\t"))
+        .collect();
+    assert_eq!(defines.len(), 5);
+
+    for line in defines {
+        assert!(
+            known.lines().any(|known_line| known_line == line),
+            "generated line not in test.fd.h: {}",
+            line
+        );
+    }
+}
+
+#[test]
+fn test_fd_table_relative_numbers() {
+    let table = TestTable::fd_table();
+
+    assert!(table.contains("*base 1000\n"));
+    assert!(table.contains("T_NAME_FLD\t2\tstring\t-\n"));
+    assert!(table.contains("T_SHORT_FLD\t30\tshort\t-\n"));
+    assert!(table.contains("T_CHAR_FLD\t40\tchar\t-\n"));
+}
+
+#[test]
+fn test_default_name_and_type() {
+    assert_eq!(Defaults::TABLE_NAME, "defaults");
+    assert_eq!(Defaults::BASE, 0);
+
+    let fields = Defaults::fields();
+    assert_eq!(fields[0].name, "T_STATUS_FLD");
+    assert_eq!(fields[0].field_type, "string");
+    assert_eq!(fields[0].field_id(), (5 << 25) | 1);
+}
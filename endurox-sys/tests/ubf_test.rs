@@ -15,3 +15,63 @@ fn test_ubf_add_get_string() {
     // This test requires UBF field tables to be loaded
     // Will work in integration tests with proper Enduro/X setup
 }
+
+#[test]
+#[ignore] // Run only with Enduro/X environment (field tables in FLDTBLDIR/FIELDTBLS)
+fn test_ndrx_json_round_trip() {
+    use endurox_sys::ubf_fields::*;
+
+    let mut buf = UbfBuffer::new(1024).unwrap();
+    buf.add_string(T_NAME_FLD, "alice \"quoted\"").unwrap();
+    buf.add_long(T_AMOUNT_FLD, 42).unwrap();
+    buf.add_long(T_AMOUNT_FLD, 43).unwrap();
+
+    let json = buf.to_ndrx_json().unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["T_NAME_FLD"], "alice \"quoted\"");
+    assert_eq!(value["T_AMOUNT_FLD"], serde_json::json!([42, 43]));
+
+    let back = UbfBuffer::from_ndrx_json(&json).unwrap();
+    assert_eq!(back.get_string(T_NAME_FLD, 0).unwrap(), "alice \"quoted\"");
+    assert_eq!(back.occurrences(T_AMOUNT_FLD).unwrap(), 2);
+    assert_eq!(back.get_long(T_AMOUNT_FLD, 1).unwrap(), 43);
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment (field tables in FLDTBLDIR/FIELDTBLS)
+fn test_ndrx_json_larger_than_default_buffer() {
+    let long = "x".repeat(5000);
+    let buf = UbfBuffer::from_ndrx_json(&format!(r#"{{"T_MESSAGE_FLD":"{}"}}"#, long)).unwrap();
+    assert!(buf.used() > 5000);
+
+    let json = buf.to_ndrx_json().unwrap();
+    assert!(json.contains(&long));
+
+    assert!(UbfBuffer::from_ndrx_json("not json").is_err());
+}
+
+#[test]
+#[ignore] // Run only with Enduro/X environment (field tables in FLDTBLDIR/FIELDTBLS)
+fn test_from_map_resolves_field_names() {
+    use endurox_sys::ubf_fields::*;
+    use std::collections::HashMap;
+
+    let map = HashMap::from([
+        ("T_NAME_FLD", UbfValue::String("alice".to_string())),
+        ("T_AMOUNT_FLD", UbfValue::Long(42)),
+        ("T_BALANCE_FLD", UbfValue::Double(1.5)),
+    ]);
+
+    let buf = UbfBuffer::from_map(&map).unwrap();
+    assert_eq!(buf.get_string(T_NAME_FLD, 0).unwrap(), "alice");
+    assert_eq!(buf.get_long(T_AMOUNT_FLD, 0).unwrap(), 42);
+    assert_eq!(buf.get_double(T_BALANCE_FLD, 0).unwrap(), 1.5);
+
+    let mut bad = map.clone();
+    bad.insert("T_NO_SUCH_FLD", UbfValue::Long(1));
+    bad.insert("T_ALSO_MISSING_FLD", UbfValue::Long(2));
+    assert_eq!(
+        UbfBuffer::from_map(&bad).unwrap_err(),
+        "Unknown UBF field(s): T_ALSO_MISSING_FLD, T_NO_SUCH_FLD"
+    );
+}
@@ -0,0 +1,125 @@
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use std::fmt;
+
+/// Database failure of a transaction service, reported to callers by a
+/// stable code in `T_ERROR_CODE_FLD`
+#[derive(Debug, Clone, PartialEq)]
+pub enum DbServiceError {
+    /// Query matched no row
+    NotFound,
+    /// Insert or update hit a unique constraint
+    UniqueViolation(String),
+    /// Database unreachable or the connection dropped
+    Connection(String),
+    /// Any other database error
+    Query(String),
+}
+
+// Oracle errors diesel-oci reports without a specific kind
+const ORA_UNIQUE_VIOLATION: &str = "ORA-00001";
+const ORA_CONNECTION_ERRORS: &[&str] = &[
+    "ORA-03113", // end-of-file on communication channel
+    "ORA-03114", // not connected to Oracle
+    "ORA-12541", // no listener
+    "ORA-12170", // connect timeout
+];
+
+impl DbServiceError {
+    /// Code written to `T_ERROR_CODE_FLD`
+    pub fn code(&self) -> &'static str {
+        match self {
+            DbServiceError::NotFound => "NOT_FOUND",
+            DbServiceError::UniqueViolation(_) => "DB_CONFLICT",
+            DbServiceError::Connection(_) => "DB_UNAVAILABLE",
+            DbServiceError::Query(_) => "DB_ERROR",
+        }
+    }
+}
+
+impl fmt::Display for DbServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbServiceError::NotFound => write!(f, "Transaction not found"),
+            DbServiceError::UniqueViolation(msg) => write!(f, "Duplicate transaction: {}", msg),
+            DbServiceError::Connection(msg) => write!(f, "Database unavailable: {}", msg),
+            DbServiceError::Query(msg) => write!(f, "Database error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DbServiceError {}
+
+impl From<DieselError> for DbServiceError {
+    fn from(e: DieselError) -> Self {
+        match e {
+            DieselError::NotFound => DbServiceError::NotFound,
+            DieselError::DatabaseError(kind, info) => {
+                let msg = info.message().to_string();
+                match kind {
+                    DatabaseErrorKind::UniqueViolation => DbServiceError::UniqueViolation(msg),
+                    DatabaseErrorKind::ClosedConnection => DbServiceError::Connection(msg),
+                    _ if msg.starts_with(ORA_UNIQUE_VIOLATION) => {
+                        DbServiceError::UniqueViolation(msg)
+                    }
+                    _ if ORA_CONNECTION_ERRORS
+                        .iter()
+                        .any(|code| msg.starts_with(code)) =>
+                    {
+                        DbServiceError::Connection(msg)
+                    }
+                    _ => DbServiceError::Query(msg),
+                }
+            }
+            other => DbServiceError::Query(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn database_error(kind: DatabaseErrorKind, msg: &str) -> DieselError {
+        DieselError::DatabaseError(kind, Box::new(msg.to_string()))
+    }
+
+    #[test]
+    fn test_unique_violation_code() {
+        let err = DbServiceError::from(database_error(
+            DatabaseErrorKind::UniqueViolation,
+            "duplicate key value violates unique constraint \"transactions_pkey\"",
+        ));
+        assert!(matches!(err, DbServiceError::UniqueViolation(_)));
+        assert_eq!(err.code(), "DB_CONFLICT");
+
+        let err = DbServiceError::from(database_error(
+            DatabaseErrorKind::Unknown,
+            "ORA-00001: unique constraint (APP.TRANSACTIONS_PK) violated",
+        ));
+        assert_eq!(err.code(), "DB_CONFLICT");
+    }
+
+    #[test]
+    fn test_other_error_codes() {
+        assert_eq!(
+            DbServiceError::from(DieselError::NotFound).code(),
+            "NOT_FOUND"
+        );
+        assert_eq!(
+            DbServiceError::from(database_error(
+                DatabaseErrorKind::Unknown,
+                "ORA-03113: end-of-file on communication channel",
+            ))
+            .code(),
+            "DB_UNAVAILABLE"
+        );
+        assert_eq!(
+            DbServiceError::from(database_error(
+                DatabaseErrorKind::CheckViolation,
+                "check failed"
+            ))
+            .code(),
+            "DB_ERROR"
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
+use endurox_sys::ubf::{ToUbf, UbfValue};
+use endurox_sys::ubf_fields::*;
 use serde::{Deserialize, Serialize};
 
 use crate::schema::transactions;
@@ -22,6 +24,34 @@ pub struct Transaction {
     pub updated_at: NaiveDateTime,
 }
 
+// Row encoding shared by the services replying with transactions
+impl ToUbf for Transaction {
+    fn ubf_fields(&self) -> Vec<(i32, UbfValue)> {
+        let mut fields = vec![
+            (T_TRANS_ID_FLD, self.id.as_str().into()),
+            (T_TRANS_TYPE_FLD, self.transaction_type.as_str().into()),
+            (T_ACCOUNT_FLD, self.account.as_str().into()),
+            (T_AMOUNT_FLD, self.amount.into()),
+            (T_CURRENCY_FLD, self.currency.as_str().into()),
+            (T_STATUS_FLD, self.status.as_str().into()),
+        ];
+
+        let optional = [
+            (T_DESC_FLD, &self.description),
+            (T_MESSAGE_FLD, &self.message),
+            (T_ERROR_CODE_FLD, &self.error_code),
+            (T_ERROR_MSG_FLD, &self.error_message),
+        ];
+        for (field_id, value) in optional {
+            if let Some(value) = value {
+                fields.push((field_id, value.as_str().into()));
+            }
+        }
+
+        fields
+    }
+}
+
 // Diesel Insertable model for creating new records
 #[derive(Debug, Clone, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = transactions)]
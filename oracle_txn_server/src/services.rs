@@ -1,14 +1,16 @@
 use diesel::prelude::*;
-use endurox_sys::server::tpreturn_fail;
-use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::log::with_fields;
+use endurox_sys::money::Money;
+use endurox_sys::service::{ServiceRequest, ServiceResult};
+use endurox_sys::ubf::ToUbf;
 use endurox_sys::ubf_fields::*;
 use endurox_sys::ubf_struct::UbfStruct;
 use endurox_sys::UbfStruct as UbfStructDerive;
-use endurox_sys::{tplog_error, tplog_info, TpSvcInfoRaw};
+use endurox_sys::{tplog_error, tplog_info};
 use serde::{Deserialize, Serialize};
-use std::ffi::CStr;
 
 use crate::db::{DbConnection, DbPool};
+use crate::error::DbServiceError;
 use crate::models::{NewTransaction, Transaction};
 use crate::schema::transactions;
 
@@ -22,186 +24,6 @@ macro_rules! execute_db {
     };
 }
 
-#[derive(Debug)]
-pub struct ServiceRequest {
-    pub service_name: String,
-    pub ubf_buffer: Option<UbfBuffer>,
-}
-
-impl ServiceRequest {
-    pub fn from_raw(rqst: *mut TpSvcInfoRaw) -> Result<Self, String> {
-        let service_name = unsafe {
-            let name_array = &(*rqst).name;
-            CStr::from_ptr(name_array.as_ptr())
-                .to_str()
-                .map_err(|e| format!("Invalid UTF-8 in service name: {}", e))?
-                .to_string()
-        };
-
-        let ubf_buffer = unsafe {
-            let req = &*rqst;
-            if !req.data.is_null() && req.len > 0 {
-                let buffer_data =
-                    std::slice::from_raw_parts(req.data as *const u8, req.len as usize);
-                UbfBuffer::from_bytes(buffer_data).ok()
-            } else {
-                None
-            }
-        };
-
-        Ok(ServiceRequest {
-            service_name,
-            ubf_buffer,
-        })
-    }
-
-    pub fn service_name(&self) -> String {
-        self.service_name.clone()
-    }
-}
-
-#[derive(Debug)]
-pub struct ServiceResult {
-    pub success: bool,
-    pub message: String,
-    pub ubf_buffer: Option<UbfBuffer>,
-}
-
-impl ServiceResult {
-    #[allow(dead_code)]
-    pub fn success(message: &str) -> Self {
-        ServiceResult {
-            success: true,
-            message: message.to_string(),
-            ubf_buffer: None,
-        }
-    }
-
-    pub fn success_ubf(ubf_buffer: UbfBuffer) -> Self {
-        ServiceResult {
-            success: true,
-            message: String::new(),
-            ubf_buffer: Some(ubf_buffer),
-        }
-    }
-
-    pub fn error(message: &str) -> Self {
-        ServiceResult {
-            success: false,
-            message: message.to_string(),
-            ubf_buffer: None,
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn error_ubf(ubf_buffer: UbfBuffer) -> Self {
-        ServiceResult {
-            success: false,
-            message: String::new(),
-            ubf_buffer: Some(ubf_buffer),
-        }
-    }
-
-    pub fn send_response(&self, rqst: *mut TpSvcInfoRaw) -> Result<(), String> {
-        unsafe {
-            if self.success {
-                use endurox_sys::ffi;
-                use libc::c_long;
-                use std::ffi::CString;
-
-                let req = &*rqst;
-
-                if let Some(ref ubf_buf) = self.ubf_buffer {
-                    tplog_info("Service responded successfully with UBF buffer");
-
-                    let buffer_data = ubf_buf.as_bytes();
-                    let needed_len = buffer_data.len();
-
-                    let ret_buf = if req.data.is_null() {
-                        let ubf_type = CString::new("UBF").unwrap();
-                        ffi::tpalloc(ubf_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                    } else {
-                        ffi::tprealloc(req.data, needed_len as c_long)
-                    };
-
-                    if ret_buf.is_null() {
-                        tplog_error("Failed to allocate UBF return buffer");
-                        tpreturn_fail(rqst);
-                        return Ok(());
-                    }
-
-                    std::ptr::copy_nonoverlapping(
-                        buffer_data.as_ptr(),
-                        ret_buf as *mut u8,
-                        buffer_data.len(),
-                    );
-
-                    ffi::tpreturn(ffi::TPSUCCESS, 0, ret_buf, buffer_data.len() as c_long, 0);
-                } else {
-                    tplog_info(&format!("Service responded successfully: {}", self.message));
-
-                    let msg_bytes = self.message.as_bytes();
-                    let needed_len = msg_bytes.len() + 1;
-
-                    let ret_buf = if req.data.is_null() {
-                        let string_type = CString::new("STRING").unwrap();
-                        ffi::tpalloc(string_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                    } else {
-                        ffi::tprealloc(req.data, needed_len as c_long)
-                    };
-
-                    if ret_buf.is_null() {
-                        tplog_error("Failed to allocate return buffer");
-                        tpreturn_fail(rqst);
-                        return Ok(());
-                    }
-
-                    std::ptr::copy_nonoverlapping(
-                        msg_bytes.as_ptr(),
-                        ret_buf as *mut u8,
-                        msg_bytes.len(),
-                    );
-                    *ret_buf.add(msg_bytes.len()) = 0;
-
-                    ffi::tpreturn(ffi::TPSUCCESS, 0, ret_buf, msg_bytes.len() as c_long, 0);
-                }
-            } else if let Some(ref ubf_buf) = self.ubf_buffer {
-                tplog_error("Service responded with UBF error");
-
-                use endurox_sys::ffi;
-                use libc::c_long;
-                use std::ffi::CString;
-
-                let req = &*rqst;
-                let buffer_data = ubf_buf.as_bytes();
-                let needed_len = buffer_data.len();
-
-                let ret_buf = if req.data.is_null() {
-                    let ubf_type = CString::new("UBF").unwrap();
-                    ffi::tpalloc(ubf_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                } else {
-                    ffi::tprealloc(req.data, needed_len as c_long)
-                };
-
-                if !ret_buf.is_null() {
-                    std::ptr::copy_nonoverlapping(
-                        buffer_data.as_ptr(),
-                        ret_buf as *mut u8,
-                        buffer_data.len(),
-                    );
-                    ffi::tpreturn(ffi::TPFAIL, 0, ret_buf, buffer_data.len() as c_long, 0);
-                } else {
-                    tpreturn_fail(rqst);
-                }
-            } else {
-                tplog_error(&format!("Service responded with error: {}", self.message));
-                tpreturn_fail(rqst);
-            }
-        }
-        Ok(())
-    }
-}
-
 // UBF Request/Response structures
 #[derive(Debug, Deserialize, Serialize, UbfStructDerive)]
 struct CreateTransactionRequest {
@@ -214,11 +36,8 @@ struct CreateTransactionRequest {
     #[ubf(field = T_ACCOUNT_FLD)]
     account: String,
 
-    #[ubf(field = T_AMOUNT_FLD)]
-    amount: i64,
-
-    #[ubf(field = T_CURRENCY_FLD)]
-    currency: String,
+    #[serde(flatten)]
+    money: Money,
 
     #[ubf(field = T_DESC_FLD)]
     description: Option<String>,
@@ -250,6 +69,12 @@ struct GetTransactionRequest {
 
 /// CREATE_TXN - Create new transaction in Oracle DB
 pub fn create_transaction_service(request: &ServiceRequest, pool: &DbPool) -> ServiceResult {
+    with_fields([("txn_id", request.transaction_id())], || {
+        create_transaction(request, pool)
+    })
+}
+
+fn create_transaction(request: &ServiceRequest, pool: &DbPool) -> ServiceResult {
     tplog_info("CREATE_TXN service called");
 
     let ubf_buf = match &request.ubf_buffer {
@@ -270,7 +95,7 @@ pub fn create_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Se
 
     tplog_info(&format!(
         "Creating transaction: id={}, type={}, account={}, amount={}",
-        req.transaction_id, req.transaction_type, req.account, req.amount
+        req.transaction_id, req.transaction_type, req.account, req.money
     ));
 
     // Validate transaction type
@@ -294,7 +119,7 @@ pub fn create_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Se
         Ok(conn) => conn,
         Err(e) => {
             tplog_error(&format!("Failed to get DB connection: {}", e));
-            return create_error_response(&req.transaction_id, "DB_ERROR", &e);
+            return create_db_error_response(&req.transaction_id, DbServiceError::Connection(e));
         }
     };
 
@@ -305,8 +130,8 @@ pub fn create_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Se
         id: req.transaction_id.clone(),
         transaction_type: req.transaction_type,
         account: req.account,
-        amount: req.amount,
-        currency: req.currency,
+        amount: req.money.amount,
+        currency: req.money.currency,
         description: req.description,
         status: "SUCCESS".to_string(),
         message: Some(message.clone()),
@@ -331,13 +156,19 @@ pub fn create_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Se
         }
         Err(e) => {
             tplog_error(&format!("Failed to insert transaction: {}", e));
-            create_error_response(&req.transaction_id, "DB_INSERT_ERROR", &e.to_string())
+            create_db_error_response(&req.transaction_id, e.into())
         }
     }
 }
 
 /// GET_TXN - Get transaction from Oracle DB
 pub fn get_transaction_service(request: &ServiceRequest, pool: &DbPool) -> ServiceResult {
+    with_fields([("txn_id", request.transaction_id())], || {
+        get_transaction(request, pool)
+    })
+}
+
+fn get_transaction(request: &ServiceRequest, pool: &DbPool) -> ServiceResult {
     tplog_info("GET_TXN service called");
 
     let ubf_buf = match &request.ubf_buffer {
@@ -362,7 +193,7 @@ pub fn get_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Servi
         Ok(conn) => conn,
         Err(e) => {
             tplog_error(&format!("Failed to get DB connection: {}", e));
-            return create_error_response(&req.transaction_id, "DB_ERROR", &e);
+            return create_db_error_response(&req.transaction_id, DbServiceError::Connection(e));
         }
     };
 
@@ -381,15 +212,21 @@ pub fn get_transaction_service(request: &ServiceRequest, pool: &DbPool) -> Servi
                 "Transaction {} found: status={}",
                 txn.id, txn.status
             ));
-            create_success_response(&txn.id, &txn.message.unwrap_or_else(|| "OK".to_string()))
+            match txn.to_ubf_buffer() {
+                Ok(buf) => ServiceResult::success_ubf(buf),
+                Err(e) => {
+                    tplog_error(&format!("Failed to encode transaction: {}", e));
+                    ServiceResult::error(&format!("Failed to encode response: {}", e))
+                }
+            }
         }
         Err(diesel::result::Error::NotFound) => {
             tplog_error(&format!("Transaction {} not found", req.transaction_id));
-            create_error_response(&req.transaction_id, "NOT_FOUND", "Transaction not found")
+            create_db_error_response(&req.transaction_id, DbServiceError::NotFound)
         }
         Err(e) => {
             tplog_error(&format!("Failed to query transaction: {}", e));
-            create_error_response(&req.transaction_id, "DB_QUERY_ERROR", &e.to_string())
+            create_db_error_response(&req.transaction_id, e.into())
         }
     }
 }
@@ -402,7 +239,7 @@ pub fn list_transactions_service(_request: &ServiceRequest, pool: &DbPool) -> Se
         Ok(conn) => conn,
         Err(e) => {
             tplog_error(&format!("Failed to get DB connection: {}", e));
-            return create_error_response("", "DB_ERROR", &e);
+            return create_db_error_response("", DbServiceError::Connection(e));
         }
     };
 
@@ -425,7 +262,7 @@ pub fn list_transactions_service(_request: &ServiceRequest, pool: &DbPool) -> Se
         }
         Err(e) => {
             tplog_error(&format!("Failed to list transactions: {}", e));
-            create_error_response("", "DB_QUERY_ERROR", &e.to_string())
+            create_db_error_response("", e.into())
         }
     }
 }
@@ -440,20 +277,11 @@ fn create_success_response(transaction_id: &str, message: &str) -> ServiceResult
         error_message: None,
     };
 
-    let mut response_buf = match UbfBuffer::new(1024) {
-        Ok(buf) => buf,
-        Err(e) => {
-            tplog_error(&format!("Failed to create response buffer: {}", e));
-            return ServiceResult::error("Failed to create response buffer");
-        }
-    };
-
-    if let Err(e) = response.update_ubf(&mut response_buf) {
-        tplog_error(&format!("Failed to encode response: {}", e));
-        return ServiceResult::error(&format!("Failed to encode response: {}", e));
-    }
+    ServiceResult::ubf_sized(&response, 1024)
+}
 
-    ServiceResult::success_ubf(response_buf)
+fn create_db_error_response(transaction_id: &str, error: DbServiceError) -> ServiceResult {
+    create_error_response(transaction_id, error.code(), &error.to_string())
 }
 
 fn create_error_response(
@@ -469,19 +297,6 @@ fn create_error_response(
         error_message: Some(error_message.to_string()),
     };
 
-    let mut response_buf = match UbfBuffer::new(1024) {
-        Ok(buf) => buf,
-        Err(e) => {
-            tplog_error(&format!("Failed to create error buffer: {}", e));
-            return ServiceResult::error("Failed to create error buffer");
-        }
-    };
-
-    if let Err(e) = response.update_ubf(&mut response_buf) {
-        tplog_error(&format!("Failed to encode error response: {}", e));
-        return ServiceResult::error(&format!("Encode error: {}", e));
-    }
-
     // Return success with error details inside UBF, like TRANSACTION service does
-    ServiceResult::success_ubf(response_buf)
+    ServiceResult::ubf_sized(&response, 1024)
 }
@@ -1,17 +1,23 @@
 use endurox_sys::ffi;
+use endurox_sys::TpFlags;
 use endurox_sys::{tplog_error, tplog_info};
 
-/// Start an XA transaction
+/// Start an XA transaction with the default 60 second timeout
 pub fn begin_transaction() -> Result<(), String> {
-    let ret = unsafe { ffi::tpbegin(60, 0) }; // 60 second timeout
-    
-    if ret == -1 {
-        let err = unsafe { ffi::tperrno };
-        tplog_error(&format!("Failed to begin transaction: error={}", err));
-        return Err(format!("tpbegin failed with error {}", err));
+    begin_transaction_with(endurox_sys::xa::DEFAULT_TRANSACTION_TIMEOUT)
+}
+
+/// Start an XA transaction that may stay active for `timeout_secs`
+///
+/// Long bulk operations need a larger timeout: `TPNOTIME` on the inner
+/// calls does not stop tmsrv from rolling the transaction back.
+pub fn begin_transaction_with(timeout_secs: u32) -> Result<(), String> {
+    if let Err(e) = endurox_sys::xa::begin_transaction_with(timeout_secs, TpFlags::empty()) {
+        tplog_error(&format!("Failed to begin transaction: {}", e));
+        return Err(format!("tpbegin failed: {}", e));
     }
-    
-    tplog_info("XA transaction started");
+
+    tplog_info(&format!("XA transaction started (timeout {}s)", timeout_secs));
     Ok(())
 }
 
@@ -1,46 +1,101 @@
+use actix_web::http::StatusCode;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
 use endurox_sys::client::EnduroxClient;
+use endurox_sys::client_pool::ClientPool;
+use endurox_sys::money::Money;
 use endurox_sys::ubf::UbfBuffer;
 use endurox_sys::ubf_fields::*;
-use endurox_sys::ubf_struct::UbfStruct;
+use endurox_sys::ubf_struct::{UbfError, UbfStruct};
 use endurox_sys::UbfStruct as UbfStructDerive;
-use endurox_sys::{tplog_error, tplog_info};
+use endurox_sys::{tplog_error, tplog_info, TpFlags};
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 
 thread_local! {
-    static CLIENT: RefCell<Option<EnduroxClient>> = const { RefCell::new(None) };
-}
-
-fn get_client() -> Result<(), String> {
-    CLIENT.with(|c| {
-        if c.borrow().is_none() {
-            match EnduroxClient::new() {
-                Ok(client) => {
-                    *c.borrow_mut() = Some(client);
-                    Ok(())
-                }
-                Err(e) => Err(e),
-            }
-        } else {
-            Ok(())
-        }
-    })
+    // Each worker thread's client, reconnected after an Enduro/X restart
+    static CLIENTS: ClientPool = ClientPool::atmi();
 }
 
 fn with_client<F, R>(f: F) -> Result<R, String>
 where
     F: FnOnce(&EnduroxClient) -> Result<R, String>,
 {
-    get_client()?;
-    CLIENT.with(|c| {
-        let client_ref = c.borrow();
-        let client = client_ref.as_ref().unwrap();
-        f(client)
+    CLIENTS.with(|pool| {
+        let client = pool.checkout()?;
+        f(&client)
     })
 }
 
-struct AppState {}
+struct AppState {
+    /// Largest UBF reply accepted from a service
+    max_response_size: usize,
+    /// Service behind each `/api/call/{route}`
+    generic_routes: HashMap<String, String>,
+}
+
+/// Default for `REST_MAX_RESPONSE_SIZE`
+const DEFAULT_MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Parses `REST_GENERIC_ROUTES`: `route=SERVICE` pairs separated by commas
+fn parse_generic_routes(spec: &str) -> HashMap<String, String> {
+    spec.split(',')
+        .filter_map(|pair| {
+            let (route, service) = pair.split_once('=')?;
+            let (route, service) = (route.trim(), service.trim());
+            if route.is_empty() || service.is_empty() {
+                tplog_error(&format!("Ignoring generic route entry '{}'", pair));
+                return None;
+            }
+            Some((route.to_string(), service.to_string()))
+        })
+        .collect()
+}
+
+/// Failure of a generic call, mapped to the HTTP status of the reply
+#[derive(Debug)]
+enum GenericCallError {
+    /// The body isn't UBF JSON for the known field tables
+    InvalidRequest(String),
+    /// The call or the reply conversion failed
+    Service(String),
+}
+
+impl fmt::Display for GenericCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenericCallError::InvalidRequest(e) => write!(f, "Invalid request: {}", e),
+            GenericCallError::Service(e) => write!(f, "Service call failed: {}", e),
+        }
+    }
+}
+
+/// Calls `service` with a request in Enduro/X UBF JSON and returns the reply
+/// in the same format
+///
+/// Field names come from the field tables in `FIELDTBLS`, so any UBF service
+/// can be exposed without a request/response struct.
+fn call_generic(
+    client: &EnduroxClient,
+    service: &str,
+    body_json: &str,
+    max_response_size: usize,
+) -> Result<String, GenericCallError> {
+    let request = UbfBuffer::from_ndrx_json(body_json).map_err(GenericCallError::InvalidRequest)?;
+
+    let reply = client
+        .call_raw_ubf(service, request)
+        .map_err(|e| GenericCallError::Service(e.to_string()))?;
+    if reply.used() > max_response_size {
+        return Err(GenericCallError::Service(format!(
+            "Reply of {} bytes exceeds the limit of {} bytes",
+            reply.used(),
+            max_response_size
+        )));
+    }
+
+    reply.to_ndrx_json().map_err(GenericCallError::Service)
+}
 
 #[derive(Debug, Deserialize)]
 struct HelloRequest {
@@ -66,11 +121,9 @@ struct TransactionRequest {
     #[ubf(field = T_ACCOUNT_FLD)]
     account: String,
 
-    #[ubf(field = T_AMOUNT_FLD)]
-    amount: i64,
-
-    #[ubf(field = T_CURRENCY_FLD)]
-    currency: String,
+    // Flattened: the JSON keeps its amount and currency keys
+    #[serde(flatten)]
+    money: Money,
 
     #[ubf(field = T_DESC_FLD)]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -79,13 +132,14 @@ struct TransactionRequest {
 
 #[derive(Debug, Serialize, Deserialize, UbfStructDerive)]
 struct TransactionResponse {
-    #[ubf(field = T_TRANS_ID_FLD)]
+    // Minimal error replies may carry only status and error code
+    #[ubf(field = T_TRANS_ID_FLD, default = "")]
     transaction_id: String,
 
     #[ubf(field = T_STATUS_FLD)]
     status: String,
 
-    #[ubf(field = T_MESSAGE_FLD)]
+    #[ubf(field = T_MESSAGE_FLD, default = "")]
     message: String,
 
     #[ubf(field = T_ERROR_CODE_FLD)]
@@ -97,6 +151,33 @@ struct TransactionResponse {
     error_message: Option<String>,
 }
 
+impl TransactionResponse {
+    /// JSON reply, filling fields a minimal error reply may leave out
+    fn into_json(self, fallback_transaction_id: &str) -> TransactionJsonResponse {
+        let transaction_id = if self.transaction_id.is_empty() {
+            fallback_transaction_id.to_string()
+        } else {
+            self.transaction_id
+        };
+
+        let message = if self.message.is_empty() {
+            self.error_message.clone().unwrap_or_default()
+        } else {
+            self.message
+        };
+
+        TransactionJsonResponse {
+            transaction_id,
+            status: self.status,
+            message,
+            error: self.error_code.map(|code| ErrorDetail {
+                code,
+                message: self.error_message.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct TransactionJsonResponse {
     transaction_id: String,
@@ -112,6 +193,28 @@ struct ErrorDetail {
     message: String,
 }
 
+/// HTTP status for a service error code
+///
+/// Codes without a dedicated status keep the 200 reply with the error in
+/// the body.
+fn status_for_error_code(code: Option<&str>) -> StatusCode {
+    match code {
+        Some("NOT_FOUND") => StatusCode::NOT_FOUND,
+        Some("DB_CONFLICT") => StatusCode::CONFLICT,
+        Some("DB_UNAVAILABLE") => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::OK,
+    }
+}
+
+impl From<UbfError> for ErrorDetail {
+    fn from(e: UbfError) -> Self {
+        ErrorDetail {
+            code: e.code().to_string(),
+            message: e.to_string(),
+        }
+    }
+}
+
 // Get transaction request
 #[derive(Debug, Deserialize, Serialize, UbfStructDerive)]
 struct GetTransactionRequest {
@@ -208,7 +311,10 @@ async fn call_dataproc(_data: web::Data<AppState>, body: String) -> impl Respond
         body.len()
     ));
 
-    match with_client(|client| client.call_service_blocking("DATAPROC", &body)) {
+    // DATAPROC may run longer than NDRX_TOUT, so the block timeout is off
+    match with_client(|client| {
+        client.call_service_blocking_with_flags("DATAPROC", &body, TpFlags::NOTIME)
+    }) {
         Ok(result) => {
             let result = result.trim_end_matches('\0').to_string();
             HttpResponse::Ok().json(ServiceResponse {
@@ -228,7 +334,7 @@ async fn call_dataproc(_data: web::Data<AppState>, body: String) -> impl Respond
 
 // Oracle CREATE_TXN service endpoint
 async fn create_oracle_transaction(
-    _data: web::Data<AppState>,
+    data: web::Data<AppState>,
     payload: web::Json<TransactionRequest>,
 ) -> impl Responder {
     let transaction_id = payload.transaction_id.clone();
@@ -260,18 +366,19 @@ async fn create_oracle_transaction(
             transaction_id: transaction_id.clone(),
             status: "ERROR".to_string(),
             message: "Failed to encode request".to_string(),
-            error: Some(ErrorDetail {
-                code: "ENCODING_ERROR".to_string(),
-                message: e.to_string(),
-            }),
+            error: Some(ErrorDetail::from(e)),
         });
     }
 
     // Call CREATE_TXN service with UBF buffer
-    let buffer_data = ubf_buf.as_bytes().to_vec();
+    let buffer_data = ubf_buf.as_bytes();
 
-    match with_client(|client| client.call_service_ubf_blocking("CREATE_TXN", &buffer_data)) {
-        Ok(response_data) => process_transaction_response(&response_data, &transaction_id),
+    match with_client(|client| {
+        client.call_service_ubf_bounded("CREATE_TXN", buffer_data, data.max_response_size)
+    }) {
+        Ok(response_data) => {
+            process_transaction_response(&response_data, &transaction_id, data.max_response_size)
+        }
         Err(e) => {
             tplog_error(&format!("CREATE_TXN call failed: {}", e));
             HttpResponse::InternalServerError().json(TransactionJsonResponse {
@@ -289,7 +396,7 @@ async fn create_oracle_transaction(
 
 // Oracle GET_TXN service endpoint
 async fn get_oracle_transaction(
-    _data: web::Data<AppState>,
+    data: web::Data<AppState>,
     payload: web::Json<GetTransactionRequest>,
 ) -> impl Responder {
     let transaction_id = payload.transaction_id.clone();
@@ -321,18 +428,19 @@ async fn get_oracle_transaction(
             transaction_id: transaction_id.clone(),
             status: "ERROR".to_string(),
             message: "Failed to encode request".to_string(),
-            error: Some(ErrorDetail {
-                code: "ENCODING_ERROR".to_string(),
-                message: e.to_string(),
-            }),
+            error: Some(ErrorDetail::from(e)),
         });
     }
 
     // Call GET_TXN service with UBF buffer
-    let buffer_data = ubf_buf.as_bytes().to_vec();
+    let buffer_data = ubf_buf.as_bytes();
 
-    match with_client(|client| client.call_service_ubf_blocking("GET_TXN", &buffer_data)) {
-        Ok(response_data) => process_transaction_response(&response_data, &transaction_id),
+    match with_client(|client| {
+        client.call_service_ubf_bounded("GET_TXN", buffer_data, data.max_response_size)
+    }) {
+        Ok(response_data) => {
+            process_transaction_response(&response_data, &transaction_id, data.max_response_size)
+        }
         Err(e) => {
             tplog_error(&format!("GET_TXN call failed: {}", e));
             HttpResponse::InternalServerError().json(TransactionJsonResponse {
@@ -349,7 +457,7 @@ async fn get_oracle_transaction(
 }
 
 // Oracle LIST_TXN service endpoint
-async fn list_oracle_transactions(_data: web::Data<AppState>) -> impl Responder {
+async fn list_oracle_transactions(data: web::Data<AppState>) -> impl Responder {
     tplog_info("REST API: Listing Oracle transactions");
 
     // Call LIST_TXN service with empty UBF buffer
@@ -369,10 +477,14 @@ async fn list_oracle_transactions(_data: web::Data<AppState>) -> impl Responder
         }
     };
 
-    let buffer_data = ubf_buf.as_bytes().to_vec();
+    let buffer_data = ubf_buf.as_bytes();
 
-    match with_client(|client| client.call_service_ubf_blocking("LIST_TXN", &buffer_data)) {
-        Ok(response_data) => process_transaction_response(&response_data, ""),
+    match with_client(|client| {
+        client.call_service_ubf_bounded("LIST_TXN", buffer_data, data.max_response_size)
+    }) {
+        Ok(response_data) => {
+            process_transaction_response(&response_data, "", data.max_response_size)
+        }
         Err(e) => {
             tplog_error(&format!("LIST_TXN call failed: {}", e));
             HttpResponse::InternalServerError().json(TransactionJsonResponse {
@@ -388,13 +500,54 @@ async fn list_oracle_transactions(_data: web::Data<AppState>) -> impl Responder
     }
 }
 
+// Generic UBF endpoint, the route maps to a service in REST_GENERIC_ROUTES
+async fn call_generic_route(
+    data: web::Data<AppState>,
+    route: web::Path<String>,
+    body: String,
+) -> impl Responder {
+    let Some(service) = data.generic_routes.get(route.as_str()) else {
+        return HttpResponse::NotFound().json(ServiceResponse {
+            result: String::new(),
+            error: Some(format!("No service configured for route '{}'", route)),
+        });
+    };
+    tplog_info(&format!(
+        "REST API: Generic call of {} via /api/call/{}",
+        service, route
+    ));
+
+    let result =
+        with_client(|client| Ok(call_generic(client, service, &body, data.max_response_size)));
+    let error = match result {
+        Ok(Ok(reply_json)) => {
+            return HttpResponse::Ok()
+                .content_type("application/json")
+                .body(reply_json)
+        }
+        Ok(Err(e)) => e,
+        Err(e) => GenericCallError::Service(e),
+    };
+
+    tplog_error(&format!("{} call failed: {}", service, error));
+    let status = match error {
+        GenericCallError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+        GenericCallError::Service(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    HttpResponse::build(status).json(ServiceResponse {
+        result: String::new(),
+        error: Some(error.to_string()),
+    })
+}
+
 // Helper function to process transaction response
 fn process_transaction_response(
     response_data: &[u8],
     fallback_transaction_id: &str,
+    max_response_size: usize,
 ) -> HttpResponse {
     // Decode UBF response
-    let response_buf = match UbfBuffer::from_bytes(response_data) {
+    let response_buf = match UbfBuffer::from_bytes_bounded(response_data, max_response_size) {
         Ok(buf) => buf,
         Err(e) => {
             tplog_error(&format!("Failed to parse UBF response: {}", e));
@@ -418,31 +571,18 @@ fn process_transaction_response(
                 transaction_id: fallback_transaction_id.to_string(),
                 status: "ERROR".to_string(),
                 message: "Failed to decode response".to_string(),
-                error: Some(ErrorDetail {
-                    code: "DECODING_ERROR".to_string(),
-                    message: e.to_string(),
-                }),
+                error: Some(ErrorDetail::from(e)),
             });
         }
     };
 
-    // Convert to JSON response
-    let json_response = TransactionJsonResponse {
-        transaction_id: trans_response.transaction_id,
-        status: trans_response.status,
-        message: trans_response.message,
-        error: match (trans_response.error_code, trans_response.error_message) {
-            (Some(code), Some(msg)) => Some(ErrorDetail { code, message: msg }),
-            _ => None,
-        },
-    };
-
-    HttpResponse::Ok().json(json_response)
+    let status = status_for_error_code(trans_response.error_code.as_deref());
+    HttpResponse::build(status).json(trans_response.into_json(fallback_transaction_id))
 }
 
 // TRANSACTION service endpoint with UBF (legacy, calls samplesvr_rust)
 async fn call_transaction(
-    _data: web::Data<AppState>,
+    data: web::Data<AppState>,
     payload: web::Json<TransactionRequest>,
 ) -> impl Responder {
     let transaction_id = payload.transaction_id.clone();
@@ -474,34 +614,34 @@ async fn call_transaction(
             transaction_id: transaction_id.clone(),
             status: "ERROR".to_string(),
             message: "Failed to encode request".to_string(),
-            error: Some(ErrorDetail {
-                code: "ENCODING_ERROR".to_string(),
-                message: e.to_string(),
-            }),
+            error: Some(ErrorDetail::from(e)),
         });
     }
 
     // Call TRANSACTION service with UBF buffer
-    let buffer_data = ubf_buf.as_bytes().to_vec();
+    let buffer_data = ubf_buf.as_bytes();
 
-    match with_client(|client| client.call_service_ubf_blocking("TRANSACTION", &buffer_data)) {
+    match with_client(|client| {
+        client.call_service_ubf_bounded("TRANSACTION", buffer_data, data.max_response_size)
+    }) {
         Ok(response_data) => {
             // Decode UBF response
-            let response_buf = match UbfBuffer::from_bytes(&response_data) {
-                Ok(buf) => buf,
-                Err(e) => {
-                    tplog_error(&format!("Failed to parse UBF response: {}", e));
-                    return HttpResponse::InternalServerError().json(TransactionJsonResponse {
-                        transaction_id: transaction_id.clone(),
-                        status: "ERROR".to_string(),
-                        message: "Failed to parse response".to_string(),
-                        error: Some(ErrorDetail {
-                            code: "PARSING_ERROR".to_string(),
-                            message: e.to_string(),
-                        }),
-                    });
-                }
-            };
+            let response_buf =
+                match UbfBuffer::from_bytes_bounded(&response_data, data.max_response_size) {
+                    Ok(buf) => buf,
+                    Err(e) => {
+                        tplog_error(&format!("Failed to parse UBF response: {}", e));
+                        return HttpResponse::InternalServerError().json(TransactionJsonResponse {
+                            transaction_id: transaction_id.clone(),
+                            status: "ERROR".to_string(),
+                            message: "Failed to parse response".to_string(),
+                            error: Some(ErrorDetail {
+                                code: "PARSING_ERROR".to_string(),
+                                message: e.to_string(),
+                            }),
+                        });
+                    }
+                };
 
             let trans_response = match TransactionResponse::from_ubf(&response_buf) {
                 Ok(resp) => resp,
@@ -511,26 +651,12 @@ async fn call_transaction(
                         transaction_id: transaction_id.clone(),
                         status: "ERROR".to_string(),
                         message: "Failed to decode response".to_string(),
-                        error: Some(ErrorDetail {
-                            code: "DECODING_ERROR".to_string(),
-                            message: e.to_string(),
-                        }),
+                        error: Some(ErrorDetail::from(e)),
                     });
                 }
             };
 
-            // Convert to JSON response
-            let json_response = TransactionJsonResponse {
-                transaction_id: trans_response.transaction_id,
-                status: trans_response.status,
-                message: trans_response.message,
-                error: match (trans_response.error_code, trans_response.error_message) {
-                    (Some(code), Some(msg)) => Some(ErrorDetail { code, message: msg }),
-                    _ => None,
-                },
-            };
-
-            HttpResponse::Ok().json(json_response)
+            HttpResponse::Ok().json(trans_response.into_json(&transaction_id))
         }
         Err(e) => {
             tplog_error(&format!("TRANSACTION call failed: {}", e));
@@ -551,7 +677,22 @@ async fn call_transaction(
 async fn main() -> std::io::Result<()> {
     tplog_info("REST Gateway starting...");
 
-    let app_state = web::Data::new(AppState {});
+    let max_response_size = std::env::var("REST_MAX_RESPONSE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_RESPONSE_SIZE);
+
+    let generic_routes = std::env::var("REST_GENERIC_ROUTES")
+        .map(|spec| parse_generic_routes(&spec))
+        .unwrap_or_default();
+    for (route, service) in &generic_routes {
+        tplog_info(&format!("Generic route /api/call/{} -> {}", route, service));
+    }
+
+    let app_state = web::Data::new(AppState {
+        max_response_size,
+        generic_routes,
+    });
 
     // Get number of workers from environment or use default
     let workers = std::env::var("REST_WORKERS")
@@ -582,9 +723,129 @@ async fn main() -> std::io::Result<()> {
             )
             .route("/api/oracle/get", web::post().to(get_oracle_transaction))
             .route("/api/oracle/list", web::get().to(list_oracle_transactions))
+            // Services exposed through REST_GENERIC_ROUTES
+            .route("/api/call/{route}", web::post().to(call_generic_route))
     })
     .workers(workers)
     .bind(("0.0.0.0", 8080))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test as actix_test;
+
+    fn generic_app_state(routes: &str) -> web::Data<AppState> {
+        web::Data::new(AppState {
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            generic_routes: parse_generic_routes(routes),
+        })
+    }
+
+    #[test]
+    fn test_parse_generic_routes() {
+        let routes = parse_generic_routes(" ubftest = UBFTEST,orders=ORDER_SVC,broken,=X");
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes["ubftest"], "UBFTEST");
+        assert_eq!(routes["orders"], "ORDER_SVC");
+        assert!(parse_generic_routes("").is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_generic_route() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(generic_app_state("ubftest=UBFTEST"))
+                .route("/api/call/{route}", web::post().to(call_generic_route)),
+        )
+        .await;
+
+        let request = actix_test::TestRequest::post()
+            .uri("/api/call/missing")
+            .set_payload("{}")
+            .to_request();
+        let response = actix_test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    #[ignore] // Run only with Enduro/X environment
+    async fn test_generic_route_calls_ubftest() {
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(generic_app_state("ubftest=UBFTEST"))
+                .route("/api/call/{route}", web::post().to(call_generic_route)),
+        )
+        .await;
+
+        let request = actix_test::TestRequest::post()
+            .uri("/api/call/ubftest")
+            .set_payload(r#"{"T_NAME_FLD":"World"}"#)
+            .to_request();
+        let reply: serde_json::Value = actix_test::call_and_read_body_json(&app, request).await;
+
+        assert_eq!(reply["T_MESSAGE_FLD"], "Hello, World!");
+        assert_eq!(reply["T_STATUS_FLD"], "OK");
+    }
+
+    #[test]
+    fn test_decode_response_without_message() {
+        let mut buf = UbfBuffer::new(1024).unwrap();
+        buf.add_string(T_STATUS_FLD, "ERROR").unwrap();
+        buf.add_string(T_ERROR_CODE_FLD, "ACCOUNT_LOCKED").unwrap();
+
+        let response = TransactionResponse::from_ubf(&buf).unwrap();
+        assert_eq!(response.status, "ERROR");
+        assert_eq!(response.message, "");
+
+        let json = response.into_json("TXN-42");
+        assert_eq!(json.transaction_id, "TXN-42");
+        assert_eq!(json.status, "ERROR");
+        assert_eq!(json.error.unwrap().code, "ACCOUNT_LOCKED");
+    }
+
+    #[test]
+    fn test_large_amount_reaches_ubf_exactly() {
+        // 2^53 + 1 doesn't survive a conversion to f64
+        let body = r#"{"transaction_type":"sale","transaction_id":"TXN-9","account":"ACC-1","amount":9007199254740993,"currency":"USD"}"#;
+        let request: TransactionRequest = serde_json::from_str(body).unwrap();
+        assert_eq!(request.money, Money::new(9_007_199_254_740_993, "USD"));
+
+        let buf = request.to_ubf().unwrap();
+        assert_eq!(
+            buf.get_long(T_AMOUNT_FLD, 0).unwrap(),
+            9_007_199_254_740_993
+        );
+
+        let decoded = TransactionRequest::from_ubf(&buf).unwrap();
+        assert_eq!(decoded.money, request.money);
+        let json: serde_json::Value = serde_json::to_value(&decoded).unwrap();
+        assert_eq!(json["amount"], 9_007_199_254_740_993i64);
+        assert_eq!(json["currency"], "USD");
+    }
+
+    #[test]
+    fn test_string_reply_rejected() {
+        let response = process_transaction_response(b"Status: OK", "TXN-7", 65536);
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn test_status_for_error_code() {
+        assert_eq!(status_for_error_code(None), StatusCode::OK);
+        assert_eq!(
+            status_for_error_code(Some("DB_CONFLICT")),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            status_for_error_code(Some("DB_UNAVAILABLE")),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status_for_error_code(Some("ACCOUNT_LOCKED")),
+            StatusCode::OK
+        );
+    }
+}
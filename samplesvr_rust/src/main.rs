@@ -1,114 +1,69 @@
-#![allow(static_mut_refs)]
+use endurox_sys::ffi::TPSUCCESS;
 use endurox_sys::server::*;
+use endurox_sys::service::ServiceRequest;
+use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::ubf_fields::{T_COUNT_FLD, T_ID_FLD};
 use endurox_sys::{self, tplog_error, tplog_info, TpSvcInfoRaw};
 
 mod services;
 use services::*;
-use std::collections::HashMap;
 
-// Type alias for service handler to reduce complexity
-type ServiceHandler = fn(&ServiceRequest) -> ServiceResult;
+endurox_sys::endurox_server! {
+    name: "samplesvr_rust",
+    services: {
+        "ECHO" => echo_service,
+        "HELLO" => hello_service,
+        "STATUS" => status_service,
+        "DATAPROC" => dataproc_service,
+        "TRANSACTION" => transaction_service,
+        "SLEEP" => sleep_service,
+        "FORWARD" => forward_service,
+    },
+    raw_services: {
+        "STREAM" => stream_dispatcher,
+    },
+    init: |args| {
+        tplog_info(&format!("samplesvr_rust app args: {:?}", args));
+        Ok(())
+    },
+    done: log_metrics_summary,
+}
 
-// Service registry
-static mut SERVICE_REGISTRY: Option<HashMap<String, ServiceHandler>> = None;
+// Conversational service: streams T_COUNT_FLD rows (default 10), one
+// buffer per row with T_ID_FLD set to the row number
+extern "C" fn stream_dispatcher(rqst: *mut TpSvcInfoRaw) {
+    let count = unsafe { ServiceRequest::from_raw(rqst) }
+        .ok()
+        .and_then(|req| req.ubf_buffer?.get_long(T_COUNT_FLD, 0).ok())
+        .unwrap_or(10)
+        .max(0);
 
-// Initialize service registry
-fn init_services() {
-    let mut registry = HashMap::new();
-    registry.insert(
-        "ECHO".to_string(),
-        echo_service as fn(&ServiceRequest) -> ServiceResult,
-    );
-    registry.insert(
-        "HELLO".to_string(),
-        hello_service as fn(&ServiceRequest) -> ServiceResult,
-    );
-    registry.insert(
-        "STATUS".to_string(),
-        status_service as fn(&ServiceRequest) -> ServiceResult,
-    );
-    registry.insert(
-        "DATAPROC".to_string(),
-        dataproc_service as fn(&ServiceRequest) -> ServiceResult,
-    );
-    registry.insert(
-        "TRANSACTION".to_string(),
-        transaction_service as fn(&ServiceRequest) -> ServiceResult,
-    );
+    let rows = (0..count).filter_map(|id| {
+        let mut row = UbfBuffer::new(256).ok()?;
+        row.add_long(T_ID_FLD, id).ok()?;
+        Some(row)
+    });
 
-    // Safe assignment with proper synchronization would be better in production
     unsafe {
-        SERVICE_REGISTRY = Some(registry);
-    }
-}
-
-// Generic service dispatcher
-extern "C" fn service_dispatcher(rqst: *mut TpSvcInfoRaw) {
-    let request = match ServiceRequest::from_raw(rqst) {
-        Ok(req) => req,
-        Err(e) => {
-            tplog_error(&format!("Failed to parse service request: {}", e));
-            unsafe {
+        match send_ubf_stream(rqst, rows) {
+            Ok(sent) => tpreturn_string(rqst, TPSUCCESS, &format!("sent {}", sent)),
+            Err(e) => {
+                tplog_error(&format!("STREAM failed: {}", e));
                 tpreturn_fail(rqst);
             }
-            return;
         }
-    };
-
-    let service_name = request.service_name();
-    let result = unsafe {
-        let registry_ptr = &raw const SERVICE_REGISTRY;
-        match (*registry_ptr).as_ref() {
-            Some(registry) => match registry.get(&service_name) {
-                Some(handler) => handler(&request),
-                None => {
-                    tplog_error(&format!("Unknown service: {}", service_name));
-                    ServiceResult::error("Service not found")
-                }
-            },
-            None => {
-                tplog_error("Service registry not initialized");
-                ServiceResult::error("Registry error")
-            }
-        }
-    };
-
-    match result.send_response(rqst) {
-        Ok(_) => {}
-        Err(e) => tplog_error(&format!("Failed to send response: {}", e)),
     }
 }
 
-// Server initialization
-#[no_mangle]
-pub extern "C" fn tpsvrinit(_argc: libc::c_int, _argv: *mut *mut libc::c_char) -> libc::c_int {
-    tplog_info("samplesvr_rust starting...");
-
-    init_services();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::raw::{c_char, c_int};
 
-    let services = ["ECHO", "HELLO", "STATUS", "DATAPROC", "TRANSACTION"];
-
-    for service in &services {
-        match advertise_service(service, service_dispatcher) {
-            Ok(_) => tplog_info(&format!("Successfully advertised {}", service)),
-            Err(e) => {
-                tplog_error(&format!("Failed to advertise {}: {}", service, e));
-                return -1;
-            }
-        }
+    #[test]
+    fn test_entry_points_generated() {
+        let _init: extern "C" fn(c_int, *mut *mut c_char) -> c_int = tpsvrinit;
+        let _done: extern "C" fn() = tpsvrdone;
+        let _main: fn() -> ! = main;
     }
-
-    tplog_info("samplesvr_rust initialized successfully");
-    0
-}
-
-// Server shutdown
-#[no_mangle]
-pub extern "C" fn tpsvrdone() {
-    tplog_info("samplesvr_rust shutting down...");
-}
-
-// Main function - uses endurox_sys::server::run_server
-fn main() -> ! {
-    run_server(tpsvrinit, tpsvrdone)
 }
@@ -1,198 +1,12 @@
-use endurox_sys::server::tpreturn_fail;
-use endurox_sys::ubf::UbfBuffer;
+use endurox_sys::log::with_fields;
+use endurox_sys::money::Money;
+use endurox_sys::service::{ServiceRequest, ServiceResult};
+use endurox_sys::ubf::{FieldType, UbfBuffer};
 use endurox_sys::ubf_fields::*;
-use endurox_sys::ubf_struct::UbfStruct;
+use endurox_sys::ubf_struct::{UbfError, UbfStruct};
 use endurox_sys::UbfStruct as UbfStructDerive;
-use endurox_sys::{tplog_error, tplog_info, TpSvcInfoRaw};
+use endurox_sys::{tplog_error, tplog_info};
 use serde::{Deserialize, Serialize};
-use std::ffi::CStr;
-
-#[derive(Debug)]
-pub struct ServiceRequest {
-    pub service_name: String,
-    pub ubf_buffer: Option<UbfBuffer>,
-}
-
-impl ServiceRequest {
-    pub fn from_raw(rqst: *mut TpSvcInfoRaw) -> Result<Self, String> {
-        // Parse the service name from the TpSvcInfoRaw structure
-        let service_name = unsafe {
-            let name_array = &(*rqst).name;
-            CStr::from_ptr(name_array.as_ptr())
-                .to_str()
-                .map_err(|e| format!("Invalid UTF-8 in service name: {}", e))?
-                .to_string()
-        };
-
-        // Try to get UBF buffer if data is present
-        let ubf_buffer = unsafe {
-            let req = &*rqst;
-            if !req.data.is_null() && req.len > 0 {
-                let buffer_data =
-                    std::slice::from_raw_parts(req.data as *const u8, req.len as usize);
-                UbfBuffer::from_bytes(buffer_data).ok()
-            } else {
-                None
-            }
-        };
-
-        Ok(ServiceRequest {
-            service_name,
-            ubf_buffer,
-        })
-    }
-
-    pub fn service_name(&self) -> String {
-        self.service_name.clone()
-    }
-}
-
-#[derive(Debug)]
-pub struct ServiceResult {
-    pub success: bool,
-    pub message: String,
-    pub ubf_buffer: Option<UbfBuffer>,
-}
-
-impl ServiceResult {
-    pub fn success(message: &str) -> Self {
-        ServiceResult {
-            success: true,
-            message: message.to_string(),
-            ubf_buffer: None,
-        }
-    }
-
-    pub fn success_ubf(ubf_buffer: UbfBuffer) -> Self {
-        ServiceResult {
-            success: true,
-            message: String::new(),
-            ubf_buffer: Some(ubf_buffer),
-        }
-    }
-
-    pub fn error(message: &str) -> Self {
-        ServiceResult {
-            success: false,
-            message: message.to_string(),
-            ubf_buffer: None,
-        }
-    }
-
-    pub fn error_ubf(ubf_buffer: UbfBuffer) -> Self {
-        ServiceResult {
-            success: false,
-            message: String::new(),
-            ubf_buffer: Some(ubf_buffer),
-        }
-    }
-
-    pub fn send_response(&self, rqst: *mut TpSvcInfoRaw) -> Result<(), String> {
-        unsafe {
-            if self.success {
-                use endurox_sys::ffi;
-                use libc::c_long;
-                use std::ffi::CString;
-
-                let req = &*rqst;
-
-                // Check if we have UBF buffer to send
-                if let Some(ref ubf_buf) = self.ubf_buffer {
-                    tplog_info("Service responded successfully with UBF buffer");
-
-                    let buffer_data = ubf_buf.as_bytes();
-                    let needed_len = buffer_data.len();
-
-                    // Allocate or reallocate UBF buffer
-                    let ret_buf = if req.data.is_null() {
-                        let ubf_type = CString::new("UBF").unwrap();
-                        ffi::tpalloc(ubf_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                    } else {
-                        ffi::tprealloc(req.data, needed_len as c_long)
-                    };
-
-                    if ret_buf.is_null() {
-                        tplog_error("Failed to allocate UBF return buffer");
-                        tpreturn_fail(rqst);
-                        return Ok(());
-                    }
-
-                    // Copy UBF data to buffer
-                    std::ptr::copy_nonoverlapping(
-                        buffer_data.as_ptr(),
-                        ret_buf as *mut u8,
-                        buffer_data.len(),
-                    );
-
-                    ffi::tpreturn(ffi::TPSUCCESS, 0, ret_buf, buffer_data.len() as c_long, 0);
-                } else {
-                    // String response
-                    tplog_info(&format!("Service responded successfully: {}", self.message));
-
-                    let msg_bytes = self.message.as_bytes();
-                    let needed_len = msg_bytes.len() + 1;
-
-                    let ret_buf = if req.data.is_null() {
-                        let string_type = CString::new("STRING").unwrap();
-                        ffi::tpalloc(string_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                    } else {
-                        ffi::tprealloc(req.data, needed_len as c_long)
-                    };
-
-                    if ret_buf.is_null() {
-                        tplog_error("Failed to allocate return buffer");
-                        tpreturn_fail(rqst);
-                        return Ok(());
-                    }
-
-                    std::ptr::copy_nonoverlapping(
-                        msg_bytes.as_ptr(),
-                        ret_buf as *mut u8,
-                        msg_bytes.len(),
-                    );
-                    *ret_buf.add(msg_bytes.len()) = 0;
-
-                    ffi::tpreturn(ffi::TPSUCCESS, 0, ret_buf, msg_bytes.len() as c_long, 0);
-                }
-            } else {
-                // Error case
-                if let Some(ref ubf_buf) = self.ubf_buffer {
-                    tplog_error("Service responded with UBF error");
-
-                    use endurox_sys::ffi;
-                    use libc::c_long;
-                    use std::ffi::CString;
-
-                    let req = &*rqst;
-                    let buffer_data = ubf_buf.as_bytes();
-                    let needed_len = buffer_data.len();
-
-                    let ret_buf = if req.data.is_null() {
-                        let ubf_type = CString::new("UBF").unwrap();
-                        ffi::tpalloc(ubf_type.as_ptr(), std::ptr::null(), needed_len as c_long)
-                    } else {
-                        ffi::tprealloc(req.data, needed_len as c_long)
-                    };
-
-                    if !ret_buf.is_null() {
-                        std::ptr::copy_nonoverlapping(
-                            buffer_data.as_ptr(),
-                            ret_buf as *mut u8,
-                            buffer_data.len(),
-                        );
-                        ffi::tpreturn(ffi::TPFAIL, 0, ret_buf, buffer_data.len() as c_long, 0);
-                    } else {
-                        tpreturn_fail(rqst);
-                    }
-                } else {
-                    tplog_error(&format!("Service responded with error: {}", self.message));
-                    tpreturn_fail(rqst);
-                }
-            }
-        }
-        Ok(())
-    }
-}
 
 pub fn echo_service(request: &ServiceRequest) -> ServiceResult {
     tplog_info(&format!("Echo service called with request: {:?}", request));
@@ -209,7 +23,59 @@ pub fn status_service(request: &ServiceRequest) -> ServiceResult {
         "Status service called with request: {:?}",
         request
     ));
-    ServiceResult::success("Status: OK")
+    ServiceResult::success(&format!(
+        "Status: OK (node {}, srvid {})",
+        request.node_id(),
+        request.server_id()
+    ))
+}
+
+/// Deliberately slow service for exercising client deadlines
+///
+/// Sleeps for T_LONG_FLD milliseconds (default 1000) before replying.
+pub fn sleep_service(request: &ServiceRequest) -> ServiceResult {
+    let millis = request
+        .ubf_buffer
+        .as_ref()
+        .and_then(|buf| buf.get_long(T_LONG_FLD, 0).ok())
+        .unwrap_or(1000)
+        .max(0) as u64;
+
+    tplog_info(&format!("Sleep service called, sleeping {} ms", millis));
+    std::thread::sleep(std::time::Duration::from_millis(millis));
+
+    let mut response_buf = match UbfBuffer::new(256) {
+        Ok(buf) => buf,
+        Err(e) => {
+            tplog_error(&format!("Failed to create response buffer: {}", e));
+            return ServiceResult::error("Failed to create response buffer");
+        }
+    };
+
+    if let Err(e) = response_buf.add_string(T_STATUS_FLD, "OK") {
+        tplog_error(&format!("Failed to encode response: {}", e));
+        return ServiceResult::error(&format!("Failed to encode response: {}", e));
+    }
+
+    ServiceResult::success_ubf(response_buf)
+}
+
+/// Forwards the request unchanged to SLEEP, which replies to the caller
+pub fn forward_service(request: &ServiceRequest) -> ServiceResult {
+    tplog_info("Forward service called, forwarding to SLEEP");
+
+    let ubf_buf = match &request.ubf_buffer {
+        Some(buf) => buf,
+        None => return ServiceResult::error("FORWARD requires UBF buffer"),
+    };
+
+    match UbfBuffer::from_bytes(ubf_buf.as_bytes()) {
+        Ok(buf) => ServiceResult::forward("SLEEP", buf),
+        Err(e) => {
+            tplog_error(&format!("Failed to copy request buffer: {}", e));
+            ServiceResult::error("Failed to copy request buffer")
+        }
+    }
 }
 
 pub fn dataproc_service(request: &ServiceRequest) -> ServiceResult {
@@ -232,11 +98,9 @@ struct TransactionRequest {
     #[ubf(field = T_ACCOUNT_FLD)]
     account: String,
 
-    #[ubf(field = T_AMOUNT_FLD)]
-    amount: i64,
-
-    #[ubf(field = T_CURRENCY_FLD)]
-    currency: String,
+    // T_AMOUNT_FLD and T_CURRENCY_FLD
+    #[serde(flatten)]
+    money: Money,
 
     #[ubf(field = T_DESC_FLD)]
     description: Option<String>,
@@ -260,7 +124,42 @@ struct TransactionResponse {
     error_message: Option<String>,
 }
 
-pub fn transaction_service(request: &ServiceRequest) -> ServiceResult {
+/// Fields TRANSACTION accepts, as (field, type, required)
+const TRANSACTION_SCHEMA: &[(i32, FieldType, bool)] = &[
+    (T_TRANS_TYPE_FLD, FieldType::String, true),
+    (T_TRANS_ID_FLD, FieldType::String, true),
+    (T_ACCOUNT_FLD, FieldType::String, true),
+    (T_AMOUNT_FLD, FieldType::Long, true),
+    (T_CURRENCY_FLD, FieldType::String, true),
+    (T_DESC_FLD, FieldType::String, false),
+];
+
+/// Error reply of TRANSACTION, encoded as a `TransactionResponse`
+fn transaction_error(
+    message: &str,
+    error_code: &str,
+    error_message: &str,
+) -> Result<ServiceResult, UbfError> {
+    let response = TransactionResponse {
+        transaction_id: "unknown".to_string(),
+        status: "ERROR".to_string(),
+        message: message.to_string(),
+        error_code: Some(error_code.to_string()),
+        error_message: Some(error_message.to_string()),
+    };
+
+    let mut error_buf = UbfBuffer::new(512).map_err(UbfError::AllocationError)?;
+    response.update_ubf(&mut error_buf)?;
+    Ok(ServiceResult::error_ubf(error_buf))
+}
+
+pub fn transaction_service(request: &ServiceRequest) -> Result<ServiceResult, UbfError> {
+    with_fields([("txn_id", request.transaction_id())], || {
+        process_transaction(request)
+    })
+}
+
+fn process_transaction(request: &ServiceRequest) -> Result<ServiceResult, UbfError> {
     tplog_info("Transaction service called");
 
     // Get UBF buffer from request
@@ -268,61 +167,36 @@ pub fn transaction_service(request: &ServiceRequest) -> ServiceResult {
         Some(buf) => buf,
         None => {
             tplog_error("Transaction service requires UBF buffer");
-
-            // Return error in UBF format
-            let mut error_buf = match UbfBuffer::new(512) {
-                Ok(buf) => buf,
-                Err(_) => return ServiceResult::error("Failed to create error buffer"),
-            };
-
-            let error_response = TransactionResponse {
-                transaction_id: "unknown".to_string(),
-                status: "ERROR".to_string(),
-                message: "UBF buffer required".to_string(),
-                error_code: Some("MISSING_BUFFER".to_string()),
-                error_message: Some("Request must contain UBF buffer".to_string()),
-            };
-
-            if error_response.update_ubf(&mut error_buf).is_ok() {
-                return ServiceResult::error_ubf(error_buf);
-            }
-            return ServiceResult::error("UBF buffer required");
+            return transaction_error(
+                "UBF buffer required",
+                "MISSING_BUFFER",
+                "Request must contain UBF buffer",
+            );
         }
     };
 
+    if let Err(violations) = ubf_buf.validate_against(TRANSACTION_SCHEMA) {
+        let details = violations
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join("; ");
+        tplog_error(&format!("Invalid transaction request: {}", details));
+        return transaction_error("Invalid request", "VALIDATION_ERROR", &details);
+    }
+
     // Decode transaction request
     let trans_req = match TransactionRequest::from_ubf(ubf_buf) {
         Ok(req) => req,
         Err(e) => {
             tplog_error(&format!("Failed to decode transaction request: {}", e));
-
-            let mut error_buf = match UbfBuffer::new(512) {
-                Ok(buf) => buf,
-                Err(_) => return ServiceResult::error("Failed to create error buffer"),
-            };
-
-            let error_response = TransactionResponse {
-                transaction_id: "unknown".to_string(),
-                status: "ERROR".to_string(),
-                message: "Failed to decode request".to_string(),
-                error_code: Some("DECODE_ERROR".to_string()),
-                error_message: Some(e.to_string()),
-            };
-
-            if error_response.update_ubf(&mut error_buf).is_ok() {
-                return ServiceResult::error_ubf(error_buf);
-            }
-            return ServiceResult::error(&format!("Decode error: {}", e));
+            return transaction_error("Failed to decode request", "DECODE_ERROR", &e.to_string());
         }
     };
 
     tplog_info(&format!(
-        "Processing transaction: id={}, type={}, account={}, amount={}, currency={}",
-        trans_req.transaction_id,
-        trans_req.transaction_type,
-        trans_req.account,
-        trans_req.amount,
-        trans_req.currency
+        "Processing transaction: id={}, type={}, account={}, amount={}",
+        trans_req.transaction_id, trans_req.transaction_type, trans_req.account, trans_req.money
     ));
 
     // Check if transaction type is "sale"
@@ -366,20 +240,7 @@ pub fn transaction_service(request: &ServiceRequest) -> ServiceResult {
         error_message,
     };
 
-    // Encode response to UBF
-    let mut response_buf = match UbfBuffer::new(1024) {
-        Ok(buf) => buf,
-        Err(e) => {
-            tplog_error(&format!("Failed to create response buffer: {}", e));
-            return ServiceResult::error("Failed to create response buffer");
-        }
-    };
-
-    if let Err(e) = response.update_ubf(&mut response_buf) {
-        tplog_error(&format!("Failed to encode response: {}", e));
-        return ServiceResult::error(&format!("Failed to encode response: {}", e));
-    }
-
-    // Always return SUCCESS - error details are in the UBF buffer
-    ServiceResult::success_ubf(response_buf)
+    // Validation errors are returned with TPSUCCESS and read from the
+    // buffer; ServiceResult::logical_error_ubf would fail the call instead
+    Ok(ServiceResult::ubf_sized(&response, 1024))
 }
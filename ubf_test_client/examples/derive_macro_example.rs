@@ -60,7 +60,7 @@ struct Customer {
     #[ubf(field = T_ID_FLD)] // Auto-generated constant
     customer_id: i64,
 
-    #[ubf(field = 0)] // Nested struct doesn't use a specific field ID
+    // Nested struct, read through its own field IDs
     address: Option<Address>,
 }
 
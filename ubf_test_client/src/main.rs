@@ -52,12 +52,8 @@ fn test_ubfadd(client: &EnduroxClient) {
         }
     };
 
-    let ptr = ubf.into_raw();
-
-    match unsafe { client.call_service_raw("UBFADD", ptr) } {
-        Ok(response_ptr) => {
-            let response = unsafe { UbfBuffer::from_raw(response_ptr) };
-
+    match client.call_raw_ubf("UBFADD", ubf) {
+        Ok(response) => {
             println!("  Response received:");
             println!("    Buffer size: {} bytes", response.size());
             println!("    Used: {} bytes", response.used());
@@ -110,12 +106,8 @@ fn test_ubftest(client: &EnduroxClient) {
 
     println!("  Sending: T_NAME_FLD=RustTester");
 
-    let ptr = ubf.into_raw();
-
-    match unsafe { client.call_service_raw("UBFTEST", ptr) } {
-        Ok(response_ptr) => {
-            let response = unsafe { UbfBuffer::from_raw(response_ptr) };
-
+    match client.call_raw_ubf("UBFTEST", ubf) {
+        Ok(response) => {
             println!("  Response received:");
 
             if let Ok(message) = response.get_string(T_MESSAGE_FLD, 0) {
@@ -148,12 +140,8 @@ fn test_ubfecho(client: &EnduroxClient) {
 
     println!("  Sending: T_NAME_FLD='Echo Test', T_ID_FLD=123");
 
-    let ptr = ubf.into_raw();
-
-    match unsafe { client.call_service_raw("UBFECHO", ptr) } {
-        Ok(response_ptr) => {
-            let response = unsafe { UbfBuffer::from_raw(response_ptr) };
-
+    match client.call_raw_ubf("UBFECHO", ubf) {
+        Ok(response) => {
             println!("  Response received:");
 
             if let Ok(name) = response.get_string(T_NAME_FLD, 0) {
@@ -187,12 +175,8 @@ fn test_ubfget(client: &EnduroxClient) {
 
     println!("  Sending: T_NAME_FLD='John Doe', T_ID_FLD=9999, T_PRICE_FLD=123.45");
 
-    let ptr = ubf.into_raw();
-
-    match unsafe { client.call_service_raw("UBFGET", ptr) } {
-        Ok(response_ptr) => {
-            let _response = unsafe { UbfBuffer::from_raw(response_ptr) };
-
+    match client.call_raw_ubf("UBFGET", ubf) {
+        Ok(_response) => {
             println!("  Response received - buffer echoed back");
             println!("  ✓ Test passed");
         }
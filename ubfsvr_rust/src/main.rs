@@ -1,4 +1,3 @@
-#![allow(static_mut_refs)]
 use endurox_sys::server::*;
 use endurox_sys::ubf::*;
 use endurox_sys::{self, tplog_error, tplog_info, TpSvcInfoRaw};
@@ -166,45 +165,12 @@ extern "C" fn service_ubfget(rqst: *mut TpSvcInfoRaw) {
     }
 }
 
-// Server initialization
-#[no_mangle]
-pub extern "C" fn tpsvrinit(_argc: libc::c_int, _argv: *mut *mut libc::c_char) -> libc::c_int {
-    tplog_info("ubfsvr_rust starting...");
-
-    let services = [
-        (
-            "UBFECHO",
-            service_ubfecho as extern "C" fn(*mut TpSvcInfoRaw),
-        ),
-        (
-            "UBFTEST",
-            service_ubftest as extern "C" fn(*mut TpSvcInfoRaw),
-        ),
-        ("UBFADD", service_ubfadd as extern "C" fn(*mut TpSvcInfoRaw)),
-        ("UBFGET", service_ubfget as extern "C" fn(*mut TpSvcInfoRaw)),
-    ];
-
-    for (service_name, handler) in &services {
-        match advertise_service(service_name, *handler) {
-            Ok(_) => tplog_info(&format!("Successfully advertised {}", service_name)),
-            Err(e) => {
-                tplog_error(&format!("Failed to advertise {}: {}", service_name, e));
-                return -1;
-            }
-        }
-    }
-
-    tplog_info("ubfsvr_rust initialized successfully");
-    0
-}
-
-// Server shutdown
-#[no_mangle]
-pub extern "C" fn tpsvrdone() {
-    tplog_info("ubfsvr_rust shutting down...");
-}
-
-// Main function
-fn main() -> ! {
-    run_server(tpsvrinit, tpsvrdone)
+endurox_sys::endurox_server! {
+    name: "ubfsvr_rust",
+    raw_services: {
+        "UBFECHO" => service_ubfecho,
+        "UBFTEST" => service_ubftest,
+        "UBFADD" => service_ubfadd,
+        "UBFGET" => service_ubfget,
+    },
 }
@@ -21,6 +21,7 @@
 #define	T_SHORT_FLD	((BFLDID32)1030)	/* number: 1030	 type: short */
 #define	T_FLAG_FLD	((BFLDID32)1031)	/* number: 1031	 type: short */
 #define	T_CHAR_FLD	((BFLDID32)67109904)	/* number: 1040	 type: char */
+#define	T_FLOAT_FLD	((BFLDID32)100664341)	/* number: 1045	 type: float */
 #define	T_TRANS_TYPE_FLD	((BFLDID32)167773210)	/* number: 1050	 type: string */
 #define	T_TRANS_ID_FLD	((BFLDID32)167773211)	/* number: 1051	 type: string */
 #define	T_ACCOUNT_FLD	((BFLDID32)167773212)	/* number: 1052	 type: string */
@@ -28,4 +29,6 @@
 #define	T_DESC_FLD	((BFLDID32)167773214)	/* number: 1054	 type: string */
 #define	T_ERROR_CODE_FLD	((BFLDID32)167773215)	/* number: 1055	 type: string */
 #define	T_ERROR_MSG_FLD	((BFLDID32)167773216)	/* number: 1056	 type: string */
+#define	T_IDEMPOTENCY_KEY_FLD	((BFLDID32)167773217)	/* number: 1057	 type: string */
+#define	T_CARRAY_FLD	((BFLDID32)201327652)	/* number: 1060	 type: carray */
 #endif